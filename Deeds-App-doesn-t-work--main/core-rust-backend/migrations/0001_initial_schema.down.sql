@@ -0,0 +1,9 @@
+-- The baseline cannot be reverted. It adopts tables an older build may already have created
+-- (`users` in particular), so dropping what it touches could destroy data it never owned.
+-- Revert to version 1 at the lowest; remove the schema by hand if it really has to go.
+
+DO $$
+BEGIN
+    RAISE EXCEPTION 'migration 0001 (initial_schema) is the baseline and cannot be reverted';
+END
+$$;
@@ -0,0 +1,307 @@
+-- Initial schema, matching the models the handlers read and write.
+--
+-- Databases set up by the old ad-hoc init_schema are reconciled in place: columns the models
+-- need are added to `users`, and the `cases`/`evidence` tables (UUID ids, which no handler could
+-- use) are renamed to legacy_cases/legacy_evidence so their rows can be recovered by hand.
+
+-- pgvector is optional; without it the embeddings table is created without a vector column
+DO $$
+BEGIN
+    CREATE EXTENSION IF NOT EXISTS vector;
+EXCEPTION WHEN OTHERS THEN
+    RAISE NOTICE 'pgvector is not available: %', SQLERRM;
+END
+$$;
+
+DO $$
+BEGIN
+    IF EXISTS (
+        SELECT 1 FROM information_schema.columns
+        WHERE table_schema = current_schema() AND table_name = 'evidence'
+          AND column_name = 'id' AND data_type = 'uuid'
+    ) THEN
+        ALTER TABLE evidence RENAME TO legacy_evidence;
+        ALTER TABLE legacy_evidence RENAME CONSTRAINT evidence_pkey TO legacy_evidence_pkey;
+        ALTER INDEX IF EXISTS idx_evidence_case_hash RENAME TO idx_legacy_evidence_case_hash;
+    END IF;
+
+    IF EXISTS (
+        SELECT 1 FROM information_schema.columns
+        WHERE table_schema = current_schema() AND table_name = 'cases'
+          AND column_name = 'id' AND data_type = 'uuid'
+    ) THEN
+        ALTER TABLE cases RENAME TO legacy_cases;
+        ALTER TABLE legacy_cases RENAME CONSTRAINT cases_pkey TO legacy_cases_pkey;
+    END IF;
+END
+$$;
+
+-- Users
+
+CREATE TABLE IF NOT EXISTS users (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    email VARCHAR(255) UNIQUE NOT NULL,
+    email_verified TIMESTAMPTZ,
+    hashed_password VARCHAR(255),
+    role VARCHAR(50) NOT NULL DEFAULT 'prosecutor',
+    is_active BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    first_name VARCHAR(255),
+    last_name VARCHAR(255),
+    name VARCHAR(255),
+    title VARCHAR(255),
+    department VARCHAR(255),
+    phone VARCHAR(64),
+    office_address TEXT,
+    avatar TEXT,
+    bio TEXT,
+    specializations JSONB NOT NULL DEFAULT '[]'
+);
+
+ALTER TABLE users
+    ADD COLUMN IF NOT EXISTS email_verified TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS hashed_password VARCHAR(255),
+    ADD COLUMN IF NOT EXISTS is_active BOOLEAN NOT NULL DEFAULT TRUE,
+    ADD COLUMN IF NOT EXISTS updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    ADD COLUMN IF NOT EXISTS first_name VARCHAR(255),
+    ADD COLUMN IF NOT EXISTS last_name VARCHAR(255),
+    ADD COLUMN IF NOT EXISTS name VARCHAR(255),
+    ADD COLUMN IF NOT EXISTS title VARCHAR(255),
+    ADD COLUMN IF NOT EXISTS department VARCHAR(255),
+    ADD COLUMN IF NOT EXISTS phone VARCHAR(64),
+    ADD COLUMN IF NOT EXISTS office_address TEXT,
+    ADD COLUMN IF NOT EXISTS avatar TEXT,
+    ADD COLUMN IF NOT EXISTS bio TEXT,
+    ADD COLUMN IF NOT EXISTS specializations JSONB NOT NULL DEFAULT '[]';
+
+UPDATE users SET created_at = NOW() WHERE created_at IS NULL;
+ALTER TABLE users ALTER COLUMN created_at SET NOT NULL;
+
+-- init_schema stored the hash as password_hash and required a username nobody sets
+DO $$
+BEGIN
+    IF EXISTS (
+        SELECT 1 FROM information_schema.columns
+        WHERE table_schema = current_schema() AND table_name = 'users' AND column_name = 'password_hash'
+    ) THEN
+        UPDATE users SET hashed_password = password_hash WHERE hashed_password IS NULL;
+        ALTER TABLE users ALTER COLUMN password_hash DROP NOT NULL;
+    END IF;
+
+    IF EXISTS (
+        SELECT 1 FROM information_schema.columns
+        WHERE table_schema = current_schema() AND table_name = 'users' AND column_name = 'username'
+    ) THEN
+        ALTER TABLE users ALTER COLUMN username DROP NOT NULL;
+    END IF;
+END
+$$;
+
+CREATE TABLE IF NOT EXISTS sessions (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    refresh_token_hash VARCHAR(64) NOT NULL,
+    user_agent TEXT,
+    ip_address VARCHAR(64),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_used_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL,
+    revoked_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_sessions_user_id ON sessions(user_id);
+
+CREATE TABLE IF NOT EXISTS revoked_tokens (
+    jti UUID PRIMARY KEY,
+    expires_at BIGINT NOT NULL,
+    revoked_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+-- Cases
+
+CREATE TABLE IF NOT EXISTS cases (
+    id SERIAL PRIMARY KEY,
+    case_number VARCHAR(100) UNIQUE NOT NULL,
+    title VARCHAR(255) NOT NULL,
+    description TEXT,
+    status VARCHAR(50) NOT NULL DEFAULT 'open',
+    priority VARCHAR(50) NOT NULL DEFAULT 'medium',
+    created_by UUID NOT NULL REFERENCES users(id),
+    assigned_to UUID REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    court_date TIMESTAMPTZ,
+    court_location TEXT,
+    judge_assigned VARCHAR(255),
+    case_type VARCHAR(100),
+    jurisdiction VARCHAR(255),
+    estimated_duration INTEGER,
+    case_value DOUBLE PRECISION,
+    statute_of_limitations TIMESTAMPTZ,
+    tags JSONB NOT NULL DEFAULT '[]',
+    notes TEXT,
+    archived BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE INDEX IF NOT EXISTS idx_cases_created_by ON cases(created_by);
+CREATE INDEX IF NOT EXISTS idx_cases_assigned_to ON cases(assigned_to);
+
+-- Explicit case sharing beyond the creator and assignee
+CREATE TABLE IF NOT EXISTS case_collaborators (
+    case_id INTEGER NOT NULL,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    added_by UUID,
+    added_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (case_id, user_id)
+);
+
+-- Append-only, hash-chained audit log of case and evidence mutations
+CREATE TABLE IF NOT EXISTS audit_log (
+    id BIGSERIAL PRIMARY KEY,
+    actor_id UUID NOT NULL,
+    action VARCHAR(50) NOT NULL,
+    entity_type VARCHAR(50) NOT NULL,
+    entity_id VARCHAR(64) NOT NULL,
+    case_id INTEGER,
+    changes JSONB NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL,
+    prev_hash VARCHAR(64) NOT NULL,
+    entry_hash VARCHAR(64) NOT NULL UNIQUE
+);
+
+CREATE INDEX IF NOT EXISTS idx_audit_log_case_id ON audit_log(case_id);
+
+-- Evidence
+
+CREATE TABLE IF NOT EXISTS evidence (
+    id SERIAL PRIMARY KEY,
+    case_id INTEGER REFERENCES cases(id) ON DELETE CASCADE,
+    criminal_id INTEGER,
+    title VARCHAR(255) NOT NULL,
+    description TEXT,
+    evidence_type VARCHAR(100) NOT NULL,
+    file_path TEXT,
+    file_size BIGINT,
+    file_type VARCHAR(255),
+    hash_sha256 VARCHAR(64),
+    uploaded_by UUID NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    anchor_points JSONB NOT NULL DEFAULT '[]'
+);
+
+CREATE INDEX IF NOT EXISTS idx_evidence_case_id ON evidence(case_id);
+
+-- A file may only be attached to a given case once
+CREATE UNIQUE INDEX IF NOT EXISTS idx_evidence_case_hash ON evidence(case_id, hash_sha256);
+
+-- Stored evidence objects, keyed by content hash, with the number of evidence rows using each
+CREATE TABLE IF NOT EXISTS evidence_blobs (
+    sha256 VARCHAR(64) PRIMARY KEY,
+    storage_key TEXT NOT NULL,
+    size BIGINT NOT NULL,
+    ref_count INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Wrapped per-object data keys for encrypted evidence (see encryption.rs)
+CREATE TABLE IF NOT EXISTS evidence_data_keys (
+    storage_key TEXT PRIMARY KEY,
+    cipher VARCHAR(32) NOT NULL,
+    kek_id VARCHAR(64) NOT NULL,
+    wrapped_key TEXT NOT NULL,
+    nonce_prefix VARCHAR(16) NOT NULL,
+    chunk_size INTEGER NOT NULL,
+    plaintext_size BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    rewrapped_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS idx_evidence_data_keys_kek_id ON evidence_data_keys(kek_id);
+
+-- Every download of an evidence file
+CREATE TABLE IF NOT EXISTS evidence_access_events (
+    id BIGSERIAL PRIMARY KEY,
+    evidence_id INTEGER NOT NULL,
+    case_id INTEGER,
+    user_id UUID NOT NULL,
+    accessed_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    range_start BIGINT,
+    range_end BIGINT,
+    bytes_served BIGINT NOT NULL,
+    watermarked BOOLEAN NOT NULL DEFAULT FALSE,
+    user_agent TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_evidence_access_events_evidence_id ON evidence_access_events(evidence_id);
+
+-- Resumable upload sessions and the chunks received so far
+CREATE TABLE IF NOT EXISTS upload_sessions (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    case_id INTEGER,
+    criminal_id INTEGER,
+    title VARCHAR(255) NOT NULL,
+    description TEXT,
+    evidence_type VARCHAR(100) NOT NULL,
+    file_name VARCHAR(255) NOT NULL,
+    content_type VARCHAR(255),
+    total_size BIGINT NOT NULL,
+    chunk_size INTEGER NOT NULL,
+    expected_sha256 VARCHAR(64),
+    status VARCHAR(20) NOT NULL DEFAULT 'open',
+    evidence_id INTEGER,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS upload_chunks (
+    session_id UUID NOT NULL REFERENCES upload_sessions(id) ON DELETE CASCADE,
+    chunk_index INTEGER NOT NULL,
+    size INTEGER NOT NULL,
+    sha256 VARCHAR(64) NOT NULL,
+    received_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (session_id, chunk_index)
+);
+
+-- Signed chain-of-custody events for evidence items
+CREATE TABLE IF NOT EXISTS custody_events (
+    id BIGSERIAL PRIMARY KEY,
+    evidence_id INTEGER NOT NULL,
+    event_type VARCHAR(32) NOT NULL,
+    holder VARCHAR(255) NOT NULL,
+    location TEXT,
+    reason TEXT,
+    file_hash VARCHAR(64),
+    recorded_by UUID NOT NULL,
+    occurred_at TIMESTAMPTZ NOT NULL,
+    signing_kid VARCHAR(64) NOT NULL,
+    signature VARCHAR(128) NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_custody_events_evidence_id ON custody_events(evidence_id);
+
+-- Embeddings; the vector column and its index need pgvector
+CREATE TABLE IF NOT EXISTS embeddings (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    content_id VARCHAR(255) NOT NULL,
+    content_type VARCHAR(100) NOT NULL,
+    content_text TEXT NOT NULL,
+    metadata JSONB DEFAULT '{}',
+    created_at TIMESTAMPTZ DEFAULT NOW(),
+    UNIQUE(content_id, content_type)
+);
+
+CREATE INDEX IF NOT EXISTS idx_embeddings_content_type ON embeddings(content_type);
+CREATE INDEX IF NOT EXISTS idx_embeddings_content_id ON embeddings(content_id);
+
+DO $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM pg_extension WHERE extname = 'vector') THEN
+        ALTER TABLE embeddings ADD COLUMN IF NOT EXISTS embedding_vector vector(1536);
+        CREATE INDEX IF NOT EXISTS idx_embeddings_vector
+            ON embeddings USING ivfflat (embedding_vector vector_cosine_ops) WITH (lists = 100);
+    END IF;
+END
+$$;
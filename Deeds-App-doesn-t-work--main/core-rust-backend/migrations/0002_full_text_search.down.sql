@@ -0,0 +1,6 @@
+DROP INDEX IF EXISTS idx_evidence_search;
+DROP INDEX IF EXISTS idx_cases_search;
+
+ALTER TABLE evidence DROP COLUMN IF EXISTS search_vector;
+ALTER TABLE cases DROP COLUMN IF EXISTS search_vector;
+ALTER TABLE evidence DROP COLUMN IF EXISTS extracted_text;
@@ -0,0 +1,21 @@
+-- Full-text search over cases and evidence (see search.rs)
+-- Weights: A = title, B = description, C = notes / extracted file text. Only the first 500,000
+-- characters of extracted text are indexed, which keeps every document under the tsvector size
+-- limit.
+
+ALTER TABLE evidence ADD COLUMN IF NOT EXISTS extracted_text TEXT;
+
+ALTER TABLE cases ADD COLUMN IF NOT EXISTS search_vector TSVECTOR GENERATED ALWAYS AS (
+    setweight(to_tsvector('english', coalesce(title, '')), 'A') ||
+    setweight(to_tsvector('english', coalesce(description, '')), 'B') ||
+    setweight(to_tsvector('english', coalesce(notes, '')), 'C')
+) STORED;
+
+ALTER TABLE evidence ADD COLUMN IF NOT EXISTS search_vector TSVECTOR GENERATED ALWAYS AS (
+    setweight(to_tsvector('english', coalesce(title, '')), 'A') ||
+    setweight(to_tsvector('english', coalesce(description, '')), 'B') ||
+    setweight(to_tsvector('english', left(coalesce(extracted_text, ''), 500000)), 'C')
+) STORED;
+
+CREATE INDEX IF NOT EXISTS idx_cases_search ON cases USING GIN (search_vector);
+CREATE INDEX IF NOT EXISTS idx_evidence_search ON evidence USING GIN (search_vector);
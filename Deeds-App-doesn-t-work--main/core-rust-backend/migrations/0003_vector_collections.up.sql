@@ -0,0 +1,25 @@
+-- Versioned Qdrant collections (see vector_collections.rs)
+-- Each collection holds vectors from one embedding model at one dimension; queries go through an
+-- alias that points at the active one. A re-embed job fills a `building` collection from Postgres
+-- and records how far it got, so it can resume after a restart, then moves the alias in one step.
+
+CREATE TABLE IF NOT EXISTS vector_collections (
+    name TEXT PRIMARY KEY,
+    alias TEXT NOT NULL,
+    model TEXT NOT NULL,
+    dimensions INTEGER NOT NULL CHECK (dimensions > 0),
+    status TEXT NOT NULL DEFAULT 'building'
+        CHECK (status IN ('building', 'ready', 'active', 'retired', 'failed')),
+    indexed_count INTEGER NOT NULL DEFAULT 0,
+    last_evidence_id INTEGER NOT NULL DEFAULT 0,
+    error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    activated_at TIMESTAMPTZ
+);
+
+-- At most one build and one active collection per alias
+CREATE UNIQUE INDEX IF NOT EXISTS idx_vector_collections_building
+    ON vector_collections (alias) WHERE status = 'building';
+CREATE UNIQUE INDEX IF NOT EXISTS idx_vector_collections_active
+    ON vector_collections (alias) WHERE status = 'active';
@@ -0,0 +1,6 @@
+DROP INDEX IF EXISTS idx_evidence_ai_tags;
+
+ALTER TABLE evidence DROP COLUMN IF EXISTS ai_extracted_at;
+ALTER TABLE evidence DROP COLUMN IF EXISTS ai_extraction;
+ALTER TABLE evidence DROP COLUMN IF EXISTS ai_summary;
+ALTER TABLE evidence DROP COLUMN IF EXISTS ai_tags;
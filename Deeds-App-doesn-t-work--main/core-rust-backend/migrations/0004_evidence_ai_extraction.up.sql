@@ -0,0 +1,10 @@
+-- Structured extraction from evidence text (see extraction.rs)
+-- `ai_tags` and `ai_summary` are what evidence listings show; `ai_extraction` keeps the whole
+-- validated result (charges, persons, dates, locations) and which extractor produced it.
+
+ALTER TABLE evidence ADD COLUMN IF NOT EXISTS ai_tags JSONB;
+ALTER TABLE evidence ADD COLUMN IF NOT EXISTS ai_summary TEXT;
+ALTER TABLE evidence ADD COLUMN IF NOT EXISTS ai_extraction JSONB;
+ALTER TABLE evidence ADD COLUMN IF NOT EXISTS ai_extracted_at TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS idx_evidence_ai_tags ON evidence USING GIN (ai_tags);
@@ -0,0 +1,5 @@
+-- The refresh token hash each session rotated away from last (see handlers/auth.rs `refresh`)
+-- Presenting it again means the token was copied, which ends the session; any other mismatch is
+-- just a bad token.
+
+ALTER TABLE sessions ADD COLUMN IF NOT EXISTS previous_refresh_token_hash VARCHAR(64);
@@ -0,0 +1,343 @@
+// Tamper-evident audit log for prosecutor-core
+// Every case/evidence mutation appends an entry inside the same transaction as the change.
+// Entries form a single SHA-256 hash chain: each entry hashes its own fields together with
+// the previous entry's hash, so editing or deleting any row breaks every link after it.
+
+use anyhow::Result;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::{database::DbConnection, models::AuditEntry};
+
+/// Hash used as `prev_hash` for the very first entry
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Arbitrary key for the advisory lock that serializes appends to the chain
+const AUDIT_CHAIN_LOCK: i64 = 0x6175_6469_745f_6c67;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Create,
+    Update,
+    Delete,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Create => "create",
+            AuditAction::Update => "update",
+            AuditAction::Delete => "delete",
+        }
+    }
+}
+
+/// An entry about to be appended. `before`/`after` are full snapshots; only the diff is stored.
+pub struct NewAuditEntry {
+    pub actor_id: Uuid,
+    pub action: AuditAction,
+    pub entity_type: &'static str,
+    pub entity_id: String,
+    pub case_id: Option<i32>,
+    pub before: Option<Value>,
+    pub after: Option<Value>,
+}
+
+impl NewAuditEntry {
+    pub fn new<T: Serialize>(
+        actor_id: Uuid,
+        action: AuditAction,
+        entity_type: &'static str,
+        entity_id: impl ToString,
+        case_id: Option<i32>,
+        before: Option<&T>,
+        after: Option<&T>,
+    ) -> Self {
+        Self {
+            actor_id,
+            action,
+            entity_type,
+            entity_id: entity_id.to_string(),
+            case_id,
+            before: before.and_then(|value| serde_json::to_value(value).ok()),
+            after: after.and_then(|value| serde_json::to_value(value).ok()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct BrokenLink {
+    pub entry_id: i64,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChainVerification {
+    pub entries_checked: u64,
+    pub valid: bool,
+    pub first_broken_link: Option<BrokenLink>,
+}
+
+/// Field-level diff of two JSON objects: `{ field: { "before": .., "after": .. } }` for changed fields.
+pub fn json_diff(before: Option<&Value>, after: Option<&Value>) -> Value {
+    let empty = Map::new();
+    let before = before.and_then(Value::as_object).unwrap_or(&empty);
+    let after = after.and_then(Value::as_object).unwrap_or(&empty);
+
+    let mut diff = Map::new();
+    for key in before.keys().chain(after.keys()) {
+        if diff.contains_key(key) {
+            continue;
+        }
+        let old = before.get(key).unwrap_or(&Value::Null);
+        let new = after.get(key).unwrap_or(&Value::Null);
+        if old != new {
+            diff.insert(key.clone(), json!({ "before": old, "after": new }));
+        }
+    }
+    Value::Object(diff)
+}
+
+/// The fields of an entry covered by its hash
+pub struct HashedFields<'a> {
+    pub actor_id: Uuid,
+    pub action: &'a str,
+    pub entity_type: &'a str,
+    pub entity_id: &'a str,
+    pub case_id: Option<i32>,
+    pub changes: &'a Value,
+    pub created_at: DateTime<Utc>,
+}
+
+impl<'a> From<&'a AuditEntry> for HashedFields<'a> {
+    fn from(entry: &'a AuditEntry) -> Self {
+        Self {
+            actor_id: entry.actor_id,
+            action: &entry.action,
+            entity_type: &entry.entity_type,
+            entity_id: &entry.entity_id,
+            case_id: entry.case_id,
+            changes: &entry.changes,
+            created_at: entry.created_at,
+        }
+    }
+}
+
+impl HashedFields<'_> {
+    /// Hash of this entry chained to `prev_hash`. `changes` is serialized with sorted keys.
+    pub fn hash(&self, prev_hash: &str) -> String {
+        let mut hasher = Sha256::new();
+        for field in [
+            prev_hash.to_string(),
+            self.actor_id.to_string(),
+            self.action.to_string(),
+            self.entity_type.to_string(),
+            self.entity_id.to_string(),
+            self.case_id.map(|id| id.to_string()).unwrap_or_default(),
+            self.changes.to_string(),
+            self.created_at.to_rfc3339_opts(SecondsFormat::Micros, true),
+        ] {
+            hasher.update(field.as_bytes());
+            hasher.update([0x1f]); // unit separator keeps field boundaries unambiguous
+        }
+        hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+/// Append an entry within the caller's transaction so it commits or rolls back with the change.
+pub async fn record(tx: &mut Transaction<'_, Postgres>, entry: NewAuditEntry) -> Result<AuditEntry> {
+    sqlx::query("SELECT pg_advisory_xact_lock($1)")
+        .bind(AUDIT_CHAIN_LOCK)
+        .execute(&mut **tx)
+        .await?;
+
+    let prev_hash: Option<(String,)> =
+        sqlx::query_as("SELECT entry_hash FROM audit_log ORDER BY id DESC LIMIT 1")
+            .fetch_optional(&mut **tx)
+            .await?;
+    let prev_hash = prev_hash
+        .map(|(hash,)| hash)
+        .unwrap_or_else(|| GENESIS_HASH.to_string());
+
+    // Postgres keeps microseconds; hash exactly what will be stored
+    let now = Utc::now();
+    let created_at = DateTime::from_timestamp_micros(now.timestamp_micros()).unwrap_or(now);
+    let changes = json_diff(entry.before.as_ref(), entry.after.as_ref());
+    let hash = HashedFields {
+        actor_id: entry.actor_id,
+        action: entry.action.as_str(),
+        entity_type: entry.entity_type,
+        entity_id: &entry.entity_id,
+        case_id: entry.case_id,
+        changes: &changes,
+        created_at,
+    }
+    .hash(&prev_hash);
+
+    let recorded = sqlx::query_as::<_, AuditEntry>(
+        r#"
+        INSERT INTO audit_log (
+            actor_id, action, entity_type, entity_id, case_id, changes, created_at, prev_hash, entry_hash
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        RETURNING *
+        "#
+    )
+    .bind(entry.actor_id)
+    .bind(entry.action.as_str())
+    .bind(entry.entity_type)
+    .bind(&entry.entity_id)
+    .bind(entry.case_id)
+    .bind(&changes)
+    .bind(created_at)
+    .bind(&prev_hash)
+    .bind(&hash)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    Ok(recorded)
+}
+
+/// Check a run of entries (ordered by id) starting after `prev_hash`.
+/// Returns the number of entries checked and the first broken link, if any.
+pub fn verify_entries<'a>(
+    mut prev_hash: String,
+    entries: impl IntoIterator<Item = &'a AuditEntry>,
+) -> (u64, Option<BrokenLink>) {
+    let mut checked = 0;
+    for entry in entries {
+        checked += 1;
+        if entry.prev_hash != prev_hash {
+            return (checked, Some(BrokenLink {
+                entry_id: entry.id,
+                reason: format!(
+                    "prev_hash {} does not match preceding entry hash {}",
+                    entry.prev_hash, prev_hash
+                ),
+            }));
+        }
+
+        let expected = HashedFields::from(entry).hash(&entry.prev_hash);
+        if entry.entry_hash != expected {
+            return (checked, Some(BrokenLink {
+                entry_id: entry.id,
+                reason: "entry contents do not match its hash".to_string(),
+            }));
+        }
+        prev_hash = entry.entry_hash.clone();
+    }
+    (checked, None)
+}
+
+/// Walk the whole chain in id order and report the first broken link.
+pub async fn verify_chain(db: &DbConnection) -> Result<ChainVerification> {
+    const BATCH: i64 = 1000;
+    let mut prev_hash = GENESIS_HASH.to_string();
+    let mut last_id = 0i64;
+    let mut entries_checked = 0u64;
+
+    loop {
+        let batch = sqlx::query_as::<_, AuditEntry>(
+            "SELECT * FROM audit_log WHERE id > $1 ORDER BY id LIMIT $2"
+        )
+        .bind(last_id)
+        .bind(BATCH)
+        .fetch_all(db.as_ref())
+        .await?;
+
+        let Some(last) = batch.last() else {
+            break;
+        };
+        last_id = last.id;
+
+        let (checked, broken) = verify_entries(prev_hash, &batch);
+        entries_checked += checked;
+        if let Some(broken) = broken {
+            tracing::error!("Audit chain broken at entry {}: {}", broken.entry_id, broken.reason);
+            return Ok(ChainVerification {
+                entries_checked,
+                valid: false,
+                first_broken_link: Some(broken),
+            });
+        }
+        prev_hash = last.entry_hash.clone();
+    }
+
+    Ok(ChainVerification {
+        entries_checked,
+        valid: true,
+        first_broken_link: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chain(len: usize) -> Vec<AuditEntry> {
+        let actor = Uuid::new_v4();
+        let mut prev = GENESIS_HASH.to_string();
+        (0..len)
+            .map(|i| {
+                let changes = json_diff(None, Some(&json!({ "title": format!("Case {}", i) })));
+                let created_at = DateTime::from_timestamp_micros(1_700_000_000_000_000 + i as i64).unwrap();
+                let mut entry = AuditEntry {
+                    id: i as i64 + 1,
+                    actor_id: actor,
+                    action: "create".to_string(),
+                    entity_type: "case".to_string(),
+                    entity_id: i.to_string(),
+                    case_id: Some(1),
+                    changes,
+                    created_at,
+                    prev_hash: prev.clone(),
+                    entry_hash: String::new(),
+                };
+                entry.entry_hash = HashedFields::from(&entry).hash(&prev);
+                prev = entry.entry_hash.clone();
+                entry
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_json_diff_only_reports_changes() {
+        let before = json!({ "title": "A", "status": "open", "notes": null });
+        let after = json!({ "title": "B", "status": "open", "notes": "x" });
+        let diff = json_diff(Some(&before), Some(&after));
+
+        assert_eq!(diff["title"], json!({ "before": "A", "after": "B" }));
+        assert_eq!(diff["notes"], json!({ "before": null, "after": "x" }));
+        assert!(diff.get("status").is_none());
+    }
+
+    #[test]
+    fn test_intact_chain_verifies() {
+        let entries = chain(5);
+        let (checked, broken) = verify_entries(GENESIS_HASH.to_string(), &entries);
+        assert_eq!(checked, 5);
+        assert!(broken.is_none());
+    }
+
+    #[test]
+    fn test_tampered_entry_is_first_broken_link() {
+        let mut entries = chain(5);
+        entries[2].changes = json!({ "title": { "before": null, "after": "Forged" } });
+
+        let (_, broken) = verify_entries(GENESIS_HASH.to_string(), &entries);
+        assert_eq!(broken.unwrap().entry_id, 3);
+    }
+
+    #[test]
+    fn test_deleted_entry_breaks_chain() {
+        let mut entries = chain(5);
+        entries.remove(1);
+
+        let (_, broken) = verify_entries(GENESIS_HASH.to_string(), &entries);
+        assert_eq!(broken.unwrap().entry_id, 3);
+    }
+}
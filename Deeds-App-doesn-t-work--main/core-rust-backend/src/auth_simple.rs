@@ -1,14 +1,25 @@
 use anyhow::Result;
-use base64::{engine::general_purpose::STANDARD, Engine};
+use base64::{
+    engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD},
+    Engine,
+};
 use chrono::{Duration, Utc};
+use hmac::{Hmac, Mac};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
 use uuid::Uuid;
 
-// Simple token structure without JWT (avoiding ring dependency)
+use crate::{config::Config, database::DbConnection};
+
+type HmacSha256 = Hmac<Sha256>;
+
+// Signed token payload (HS256, JWT compact serialization without the jsonwebtoken/ring dependency)
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SimpleToken {
+    pub jti: Uuid,
+    pub session_id: Uuid,
     pub user_id: Uuid,
     pub email: String,
     pub role: String,
@@ -17,9 +28,18 @@ pub struct SimpleToken {
     pub token_type: String,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+struct TokenHeader {
+    alg: String,
+    typ: String,
+    kid: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String, // User ID
+    pub jti: String, // Token ID, used for revocation
+    pub sid: String, // Session ID, revoked on logout
     pub email: String,
     pub role: String,
     pub exp: usize, // Expiration time
@@ -27,13 +47,15 @@ pub struct Claims {
 }
 
 impl Claims {
-    pub fn new(user_id: Uuid, email: String, role: String) -> Self {
+    pub fn new(user_id: Uuid, email: String, role: String, session_id: Uuid, ttl: Duration) -> Self {
         let now = Utc::now();
-        let exp = (now + Duration::days(30)).timestamp() as usize; // 30 days
+        let exp = (now + ttl).timestamp() as usize;
         let iat = now.timestamp() as usize;
 
         Self {
             sub: user_id.to_string(),
+            jti: Uuid::new_v4().to_string(),
+            sid: session_id.to_string(),
             email,
             role,
             exp,
@@ -42,26 +64,215 @@ impl Claims {
     }
 }
 
-// Simple password hashing using SHA256 (not production-ready, just for development)
-pub fn hash_password(password: &str, cost: u32) -> Result<String> {
-    // Generate a simple salt based on the cost parameter
-    let salt = format!("prosecutor_salt_{}", cost);
-    let mut hasher = Sha256::new();
-    hasher.update(password.as_bytes());
-    hasher.update(salt.as_bytes());
-    let result = hasher.finalize();
-    Ok(STANDARD.encode(result))
+/// Why a bearer token was rejected. Each variant maps to a distinct log reason.
+#[derive(Debug, thiserror::Error, PartialEq)]
+pub enum TokenError {
+    #[error("malformed token")]
+    Malformed,
+    #[error("unknown signing key id '{0}'")]
+    UnknownKey(String),
+    #[error("signature mismatch")]
+    BadSignature,
+    #[error("token expired at {0}")]
+    Expired(i64),
+    #[error("token {0} has been revoked")]
+    Revoked(Uuid),
+    #[error("session {0} has been revoked")]
+    SessionRevoked(Uuid),
+}
+
+impl TokenError {
+    /// Short, stable identifier for structured logs and metrics.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            TokenError::Malformed => "malformed",
+            TokenError::UnknownKey(_) => "unknown_key",
+            TokenError::BadSignature => "bad_signature",
+            TokenError::Expired(_) => "expired",
+            TokenError::Revoked(_) => "revoked",
+            TokenError::SessionRevoked(_) => "session_revoked",
+        }
+    }
+}
+
+/// HMAC key ring. New tokens are signed with the active key; retired keys are
+/// still accepted for verification so a secret can be rotated without logging everyone out.
+#[derive(Clone, Debug)]
+pub struct SigningKeys {
+    active_kid: String,
+    keys: HashMap<String, Vec<u8>>,
+}
+
+impl SigningKeys {
+    pub fn new(kid: &str, secret: &str) -> Self {
+        let mut keys = HashMap::new();
+        keys.insert(kid.to_string(), secret.as_bytes().to_vec());
+        Self {
+            active_kid: kid.to_string(),
+            keys,
+        }
+    }
+
+    pub fn with_retired_key(mut self, kid: &str, secret: &str) -> Self {
+        self.keys
+            .entry(kid.to_string())
+            .or_insert_with(|| secret.as_bytes().to_vec());
+        self
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        config
+            .jwt_retired_keys
+            .iter()
+            .fold(Self::new(&config.jwt_key_id, &config.jwt_secret), |keys, (kid, secret)| {
+                keys.with_retired_key(kid, secret)
+            })
+    }
+
+    /// Keys for custody records. Without `CUSTODY_SIGNING_KEY` the JWT keys are used, as older builds did,
+    /// so records signed before a custody key was configured keep verifying once it is listed as retired.
+    pub fn custody_from_config(config: &Config) -> Self {
+        let keys = match &config.custody_signing_key {
+            Some(secret) => Self::new(&config.custody_key_id, secret),
+            None => Self::from_config(config),
+        };
+        config
+            .custody_retired_keys
+            .iter()
+            .fold(keys, |keys, (kid, secret)| keys.with_retired_key(kid, secret))
+    }
+
+    pub fn active_kid(&self) -> &str {
+        &self.active_kid
+    }
+
+    /// Sign arbitrary data (e.g. custody records) with the active key: returns (kid, base64url signature).
+    pub fn sign(&self, message: &[u8]) -> Result<(String, String), TokenError> {
+        let mut mac = self.mac(&self.active_kid)?;
+        mac.update(message);
+        Ok((self.active_kid.clone(), URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())))
+    }
+
+    /// Check a signature produced by `sign`. Fails with `UnknownKey` once the key has been dropped.
+    pub fn verify(&self, kid: &str, message: &[u8], signature: &str) -> Result<bool, TokenError> {
+        let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| TokenError::Malformed)?;
+        let mut mac = self.mac(kid)?;
+        mac.update(message);
+        Ok(mac.verify_slice(&signature).is_ok())
+    }
+
+    fn mac(&self, kid: &str) -> Result<HmacSha256, TokenError> {
+        let secret = self
+            .keys
+            .get(kid)
+            .ok_or_else(|| TokenError::UnknownKey(kid.to_string()))?;
+        HmacSha256::new_from_slice(secret).map_err(|_| TokenError::UnknownKey(kid.to_string()))
+    }
 }
 
-pub fn verify_password(password: &str, hash: &str) -> Result<bool> {
-    // Use default cost for verification
-    let computed_hash = hash_password(password, 12)?;
-    Ok(computed_hash == hash)
+/// Server-side list of revoked token and session ids. Kept in memory for the middleware hot path
+/// and persisted (`revoked_tokens`, `sessions.revoked_at`) so revocations survive restarts.
+#[derive(Clone, Default)]
+pub struct RevocationList {
+    revoked: Arc<RwLock<HashMap<Uuid, i64>>>,
+    sessions: Arc<RwLock<HashMap<Uuid, i64>>>,
 }
 
-// Simple token generation (base64 encoded JSON, not secure for production)
-pub fn create_jwt(claims: &Claims, _secret: &str) -> Result<String> {
+impl RevocationList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn load(db: &DbConnection) -> Result<Self> {
+        let rows: Vec<(Uuid, i64)> = sqlx::query_as(
+            "SELECT jti, expires_at FROM revoked_tokens WHERE expires_at > $1",
+        )
+        .bind(Utc::now().timestamp())
+        .fetch_all(db.as_ref())
+        .await?;
+
+        let session_rows: Vec<(Uuid, i64)> = sqlx::query_as(
+            "SELECT id, EXTRACT(EPOCH FROM expires_at)::BIGINT FROM sessions
+             WHERE revoked_at IS NOT NULL AND expires_at > NOW()",
+        )
+        .fetch_all(db.as_ref())
+        .await?;
+
+        let list = Self::new();
+        if let Ok(mut revoked) = list.revoked.write() {
+            revoked.extend(rows);
+        }
+        if let Ok(mut sessions) = list.sessions.write() {
+            sessions.extend(session_rows);
+        }
+        tracing::info!("Loaded revocation list");
+        Ok(list)
+    }
+
+    /// Revoke a token in memory only. Use `revoke_persisted` from request handlers.
+    pub fn revoke(&self, jti: Uuid, expires_at: i64) {
+        if let Ok(mut revoked) = self.revoked.write() {
+            let now = Utc::now().timestamp();
+            // Entries past their expiry are rejected as expired anyway
+            revoked.retain(|_, exp| *exp > now);
+            revoked.insert(jti, expires_at);
+        }
+    }
+
+    pub async fn revoke_persisted(&self, db: &DbConnection, jti: Uuid, expires_at: i64) -> Result<()> {
+        sqlx::query(
+            "INSERT INTO revoked_tokens (jti, expires_at, revoked_at) VALUES ($1, $2, NOW())
+             ON CONFLICT (jti) DO NOTHING",
+        )
+        .bind(jti)
+        .bind(expires_at)
+        .execute(db.as_ref())
+        .await?;
+
+        self.revoke(jti, expires_at);
+        Ok(())
+    }
+
+    /// Mark a session as revoked in memory. The caller is responsible for setting
+    /// `sessions.revoked_at`; `expires_at` is the session expiry as a unix timestamp.
+    pub fn revoke_session(&self, session_id: Uuid, expires_at: i64) {
+        if let Ok(mut sessions) = self.sessions.write() {
+            let now = Utc::now().timestamp();
+            sessions.retain(|_, exp| *exp > now);
+            sessions.insert(session_id, expires_at);
+        }
+    }
+
+    pub fn is_session_revoked(&self, session_id: &Uuid) -> bool {
+        self.sessions
+            .read()
+            .map(|sessions| sessions.contains_key(session_id))
+            .unwrap_or(true)
+    }
+
+    pub fn is_revoked(&self, jti: &Uuid) -> bool {
+        self.revoked
+            .read()
+            .map(|revoked| revoked.contains_key(jti))
+            .unwrap_or(true) // Fail closed if the lock is poisoned
+    }
+
+    pub fn check(&self, token: &SimpleToken) -> Result<(), TokenError> {
+        if self.is_revoked(&token.jti) {
+            Err(TokenError::Revoked(token.jti))
+        } else if self.is_session_revoked(&token.session_id) {
+            Err(TokenError::SessionRevoked(token.session_id))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+// HS256 token: base64url(header).base64url(payload).base64url(hmac)
+pub fn create_jwt(claims: &Claims, keys: &SigningKeys) -> Result<String> {
     let token = SimpleToken {
+        jti: Uuid::parse_str(&claims.jti)?,
+        session_id: Uuid::parse_str(&claims.sid)?,
         user_id: Uuid::parse_str(&claims.sub)?,
         email: claims.email.clone(),
         role: claims.role.clone(),
@@ -69,24 +280,95 @@ pub fn create_jwt(claims: &Claims, _secret: &str) -> Result<String> {
         expires_at: claims.exp as i64,
         token_type: "Bearer".to_string(),
     };
-    
-    let json = serde_json::to_string(&token)?;
-    Ok(STANDARD.encode(json.as_bytes()))
+
+    let header = TokenHeader {
+        alg: "HS256".to_string(),
+        typ: "JWT".to_string(),
+        kid: keys.active_kid().to_string(),
+    };
+
+    let signing_input = format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header)?),
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(&token)?)
+    );
+
+    let mut mac = keys.mac(keys.active_kid())?;
+    mac.update(signing_input.as_bytes());
+    let signature = URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+
+    Ok(format!("{}.{}", signing_input, signature))
 }
 
-pub fn verify_jwt(token: &str, _secret: &str) -> Result<SimpleToken> {
-    let decoded = STANDARD.decode(token)?;
-    let json = String::from_utf8(decoded)?;
-    let token: SimpleToken = serde_json::from_str(&json)?;
-    
+pub fn verify_jwt(token: &str, keys: &SigningKeys) -> Result<SimpleToken, TokenError> {
+    let mut parts = token.split('.');
+    let (header_b64, payload_b64, signature_b64) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(h), Some(p), Some(s), None) => (h, p, s),
+        _ => return Err(TokenError::Malformed),
+    };
+
+    let header: TokenHeader = URL_SAFE_NO_PAD
+        .decode(header_b64)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .ok_or(TokenError::Malformed)?;
+
+    if header.alg != "HS256" {
+        return Err(TokenError::Malformed);
+    }
+
+    let signature = URL_SAFE_NO_PAD
+        .decode(signature_b64)
+        .map_err(|_| TokenError::Malformed)?;
+
+    // Check the signature before trusting anything in the payload
+    let mut mac = keys.mac(&header.kid)?;
+    mac.update(header_b64.as_bytes());
+    mac.update(b".");
+    mac.update(payload_b64.as_bytes());
+    mac.verify_slice(&signature)
+        .map_err(|_| TokenError::BadSignature)?;
+
+    let token: SimpleToken = URL_SAFE_NO_PAD
+        .decode(payload_b64)
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .ok_or(TokenError::Malformed)?;
+
     let now = Utc::now().timestamp();
     if token.expires_at < now {
-        return Err(anyhow::anyhow!("Token expired"));
+        return Err(TokenError::Expired(token.expires_at));
     }
-    
+
     Ok(token)
 }
 
+/// Opaque refresh token `<session_id>.<secret>` and the SHA-256 hex digest stored in `sessions`.
+/// Only the digest is persisted, so a database leak does not hand out live refresh tokens.
+pub fn generate_refresh_token(session_id: Uuid) -> (String, String) {
+    use rand::RngCore;
+    let mut secret = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut secret);
+    let secret = URL_SAFE_NO_PAD.encode(secret);
+    let digest = hash_refresh_secret(&secret);
+    (format!("{}.{}", session_id, secret), digest)
+}
+
+/// Split a refresh token into its session id and the digest of its secret.
+pub fn parse_refresh_token(token: &str) -> Option<(Uuid, String)> {
+    let (session_id, secret) = token.split_once('.')?;
+    let session_id = Uuid::parse_str(session_id).ok()?;
+    if secret.is_empty() {
+        return None;
+    }
+    Some((session_id, hash_refresh_secret(secret)))
+}
+
+fn hash_refresh_secret(secret: &str) -> String {
+    let digest = Sha256::digest(secret.as_bytes());
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
 // Generate a random salt
 pub fn generate_salt() -> String {
     use rand::Rng;
@@ -94,3 +376,108 @@ pub fn generate_salt() -> String {
     let salt: Vec<u8> = (0..16).map(|_| rng.gen()).collect();
     STANDARD.encode(salt)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claims() -> Claims {
+        Claims::new(
+            Uuid::new_v4(),
+            "da@example.gov".to_string(),
+            "prosecutor".to_string(),
+            Uuid::new_v4(),
+            Duration::minutes(15),
+        )
+    }
+
+    #[test]
+    fn test_token_roundtrip() {
+        let keys = SigningKeys::new("k1", "secret-one");
+        let claims = claims();
+        let token = create_jwt(&claims, &keys).unwrap();
+        let decoded = verify_jwt(&token, &keys).unwrap();
+
+        assert_eq!(decoded.user_id.to_string(), claims.sub);
+        assert_eq!(decoded.jti.to_string(), claims.jti);
+        assert_eq!(decoded.role, "prosecutor");
+    }
+
+    #[test]
+    fn test_tampered_token_rejected() {
+        let keys = SigningKeys::new("k1", "secret-one");
+        let token = create_jwt(&claims(), &keys).unwrap();
+
+        let mut parts: Vec<String> = token.split('.').map(String::from).collect();
+        let mut payload: serde_json::Value =
+            serde_json::from_slice(&URL_SAFE_NO_PAD.decode(&parts[1]).unwrap()).unwrap();
+        payload["role"] = "admin".into();
+        parts[1] = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&payload).unwrap());
+
+        assert_eq!(verify_jwt(&parts.join("."), &keys).unwrap_err(), TokenError::BadSignature);
+        assert_eq!(
+            verify_jwt(&token, &SigningKeys::new("k1", "other")).unwrap_err(),
+            TokenError::BadSignature
+        );
+        assert_eq!(verify_jwt("not-a-token", &keys).unwrap_err(), TokenError::Malformed);
+    }
+
+    #[test]
+    fn test_expired_token_rejected() {
+        let keys = SigningKeys::new("k1", "secret-one");
+        let mut claims = claims();
+        claims.exp = (Utc::now() - Duration::minutes(1)).timestamp() as usize;
+        let token = create_jwt(&claims, &keys).unwrap();
+
+        assert_eq!(verify_jwt(&token, &keys).unwrap_err().reason(), "expired");
+    }
+
+    #[test]
+    fn test_key_rotation() {
+        let old_keys = SigningKeys::new("k1", "secret-one");
+        let token = create_jwt(&claims(), &old_keys).unwrap();
+
+        let rotated = SigningKeys::new("k2", "secret-two").with_retired_key("k1", "secret-one");
+        assert!(verify_jwt(&token, &rotated).is_ok());
+
+        let dropped = SigningKeys::new("k2", "secret-two");
+        assert_eq!(
+            verify_jwt(&token, &dropped).unwrap_err(),
+            TokenError::UnknownKey("k1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_revocation_list() {
+        let keys = SigningKeys::new("k1", "secret-one");
+        let token = verify_jwt(&create_jwt(&claims(), &keys).unwrap(), &keys).unwrap();
+        let revocations = RevocationList::new();
+
+        assert!(revocations.check(&token).is_ok());
+        revocations.revoke(token.jti, token.expires_at);
+        assert_eq!(revocations.check(&token).unwrap_err(), TokenError::Revoked(token.jti));
+    }
+
+    #[test]
+    fn test_revoked_session_rejects_its_tokens() {
+        let keys = SigningKeys::new("k1", "secret-one");
+        let token = verify_jwt(&create_jwt(&claims(), &keys).unwrap(), &keys).unwrap();
+        let revocations = RevocationList::new();
+
+        revocations.revoke_session(token.session_id, token.expires_at);
+        assert_eq!(
+            revocations.check(&token).unwrap_err(),
+            TokenError::SessionRevoked(token.session_id)
+        );
+    }
+
+    #[test]
+    fn test_refresh_token_roundtrip() {
+        let session_id = Uuid::new_v4();
+        let (token, digest) = generate_refresh_token(session_id);
+
+        assert_eq!(parse_refresh_token(&token), Some((session_id, digest.clone())));
+        assert_ne!(generate_refresh_token(session_id).1, digest);
+        assert_eq!(parse_refresh_token("garbage"), None);
+    }
+}
@@ -0,0 +1,231 @@
+// Evidence text chunking
+// Extracted text is split into pages (form feeds, which PDF extraction puts between pages), each
+// page into paragraphs (blank lines), and the words of a page are packed into chunks of at most
+// `max_tokens` with `overlap_tokens` repeated between neighbours. A chunk prefers to end at a
+// paragraph, then a sentence, and never spans pages. Words stand in for model tokens.
+//
+// Chunk boundaries are stored as anchors in `evidence.anchor_points`, next to any other anchors a
+// client has recorded there. Offsets count characters, not bytes, so they line up with Postgres
+// substr() and with the text as a browser sees it.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Separates pages in extracted text
+pub const PAGE_BREAK: char = '\u{c}';
+
+#[derive(Debug, Clone, Copy)]
+pub struct ChunkOptions {
+    pub max_tokens: usize,
+    /// Words repeated at the start of a chunk from the end of the one before
+    pub overlap_tokens: usize,
+}
+
+impl Default for ChunkOptions {
+    fn default() -> Self {
+        Self { max_tokens: 200, overlap_tokens: 40 }
+    }
+}
+
+/// Where a chunk sits in the extracted text: 1-based page, and the half-open character range
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename = "text_chunk")]
+pub struct ChunkAnchor {
+    pub chunk: usize,
+    pub page: usize,
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextChunk {
+    pub anchor: ChunkAnchor,
+    pub text: String,
+}
+
+struct Word {
+    start: usize,
+    end: usize,
+    // First word of a paragraph
+    paragraph: bool,
+    sentence_end: bool,
+}
+
+/// Split `text` into overlapping chunks, in order
+pub fn chunk_text(text: &str, options: &ChunkOptions) -> Vec<TextChunk> {
+    let max = options.max_tokens.max(1);
+    let overlap = options.overlap_tokens.min(max - 1);
+
+    // Byte ranges first; converted to character offsets once all are known
+    let mut spans = Vec::new();
+    let mut page_start = 0;
+    for (page_index, page) in text.split(PAGE_BREAK).enumerate() {
+        let words = words(page, page_start);
+        let mut first = 0;
+        while first < words.len() {
+            let mut end = (first + max).min(words.len());
+            if end < words.len() {
+                // Cut at the last paragraph or sentence in the back half of the window, if any
+                let floor = first + max / 2 + 1;
+                if let Some(cut) = (floor..=end).rev().find(|&i| words[i].paragraph) {
+                    end = cut;
+                } else if let Some(cut) = (floor..=end).rev().find(|&i| words[i - 1].sentence_end) {
+                    end = cut;
+                }
+            }
+            spans.push((page_index + 1, words[first].start, words[end - 1].end));
+            if end == words.len() {
+                break;
+            }
+            first = end.saturating_sub(overlap).max(first + 1);
+        }
+        page_start += page.len() + PAGE_BREAK.len_utf8();
+    }
+
+    // Starts and ends each only move forward, so one cursor apiece does the conversion
+    let mut starts = CharCursor::new(text);
+    let mut ends = CharCursor::new(text);
+    spans
+        .into_iter()
+        .enumerate()
+        .map(|(chunk, (page, start, end))| TextChunk {
+            anchor: ChunkAnchor { chunk, page, start: starts.advance(start), end: ends.advance(end) },
+            text: text[start..end].to_string(),
+        })
+        .collect()
+}
+
+fn words(page: &str, offset: usize) -> Vec<Word> {
+    let mut words = Vec::new();
+    let mut word_start = None;
+    let mut newlines = 0;
+    for (i, c) in page.char_indices().chain(std::iter::once((page.len(), ' '))) {
+        match (c.is_whitespace(), word_start) {
+            (true, Some(start)) => {
+                let last = page[..i].chars().next_back().unwrap_or(' ');
+                words.push(Word {
+                    start: offset + start,
+                    end: offset + i,
+                    paragraph: words.is_empty() || newlines >= 2,
+                    sentence_end: matches!(last, '.' | '?' | '!' | ':' | ';'),
+                });
+                word_start = None;
+                newlines = usize::from(c == '\n');
+            }
+            (true, None) => newlines += usize::from(c == '\n'),
+            (false, None) => word_start = Some(i),
+            (false, Some(_)) => {}
+        }
+    }
+    words
+}
+
+struct CharCursor<'a> {
+    text: &'a str,
+    byte: usize,
+    chars: usize,
+}
+
+impl<'a> CharCursor<'a> {
+    fn new(text: &'a str) -> Self {
+        Self { text, byte: 0, chars: 0 }
+    }
+
+    fn advance(&mut self, byte: usize) -> usize {
+        self.chars += self.text[self.byte..byte].chars().count();
+        self.byte = byte;
+        self.chars
+    }
+}
+
+/// The text of each anchored chunk. Anchors that do not fit `text` are skipped.
+pub fn chunks_from_anchors(text: &str, anchors: &[ChunkAnchor]) -> Vec<TextChunk> {
+    anchors
+        .iter()
+        .filter_map(|anchor| {
+            let len = anchor.end.checked_sub(anchor.start)?;
+            let chunk: String = text.chars().skip(anchor.start).take(len).collect();
+            (chunk.chars().count() == len).then_some(TextChunk { anchor: *anchor, text: chunk })
+        })
+        .collect()
+}
+
+/// Anchors of the chunks of `text`, as stored in `anchor_points`
+pub fn chunk_anchors(text: &str, options: &ChunkOptions) -> Vec<ChunkAnchor> {
+    chunk_text(text, options).into_iter().map(|chunk| chunk.anchor).collect()
+}
+
+pub fn anchors_json(anchors: &[ChunkAnchor]) -> Value {
+    Value::Array(anchors.iter().map(|anchor| serde_json::to_value(anchor).unwrap_or_default()).collect())
+}
+
+/// The chunk anchors among `anchor_points`, in chunk order
+pub fn read_anchors(anchor_points: &Value) -> Vec<ChunkAnchor> {
+    let mut anchors: Vec<ChunkAnchor> = anchor_points
+        .as_array()
+        .map(|points| points.iter().filter_map(|point| ChunkAnchor::deserialize(point).ok()).collect())
+        .unwrap_or_default();
+    anchors.sort_by_key(|anchor| anchor.chunk);
+    anchors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn options(max_tokens: usize, overlap_tokens: usize) -> ChunkOptions {
+        ChunkOptions { max_tokens, overlap_tokens }
+    }
+
+    fn texts(chunks: &[TextChunk]) -> Vec<&str> {
+        chunks.iter().map(|chunk| chunk.text.as_str()).collect()
+    }
+
+    #[test]
+    fn test_chunks_stay_on_their_page() {
+        let text = "one two three\u{c}four five\u{c}\u{c}  six";
+        let chunks = chunk_text(text, &options(10, 2));
+        assert_eq!(texts(&chunks), vec!["one two three", "four five", "six"]);
+        assert_eq!(chunks.iter().map(|chunk| chunk.anchor.page).collect::<Vec<_>>(), vec![1, 2, 4]);
+        assert_eq!(chunks[2].anchor, ChunkAnchor { chunk: 2, page: 4, start: 27, end: 30 });
+    }
+
+    #[test]
+    fn test_budget_overlap_and_paragraph_preference() {
+        let words: Vec<String> = (1..=10).map(|n| format!("w{}", n)).collect();
+        let chunks = chunk_text(&words.join(" "), &options(4, 1));
+        assert_eq!(texts(&chunks), vec!["w1 w2 w3 w4", "w4 w5 w6 w7", "w7 w8 w9 w10"]);
+
+        // The window would end mid-paragraph; it stops at the paragraph break instead
+        let text = "a b c\n\nd e f g h";
+        assert_eq!(texts(&chunk_text(text, &options(4, 0))), vec!["a b c", "d e f g", "h"]);
+        assert_eq!(texts(&chunk_text(text, &options(4, 1))), vec!["a b c", "c\n\nd e f", "f g h"]);
+
+        // Without a paragraph break, the end of a sentence will do
+        let text = "Well, stop here. Then carry on further";
+        assert_eq!(texts(&chunk_text(text, &options(5, 0))), vec!["Well, stop here.", "Then carry on further"]);
+    }
+
+    #[test]
+    fn test_anchors_count_characters_and_round_trip() {
+        let text = "Café crème\n\nau lait\u{c}Über alles";
+        let chunks = chunk_text(text, &options(2, 0));
+        assert_eq!(texts(&chunks), vec!["Café crème", "au lait", "Über alles"]);
+        for chunk in &chunks {
+            let chars: String = text.chars().skip(chunk.anchor.start).take(chunk.anchor.end - chunk.anchor.start).collect();
+            assert_eq!(chars, chunk.text);
+        }
+
+        let mut stored = anchors_json(&chunk_anchors(text, &options(2, 0)));
+        assert_eq!(stored[1], json!({ "kind": "text_chunk", "chunk": 1, "page": 1, "start": 12, "end": 19 }));
+        // Other kinds of anchor are left alone
+        stored.as_array_mut().unwrap().insert(0, json!({ "kind": "region", "x": 1, "y": 2 }));
+        let anchors = read_anchors(&stored);
+        assert_eq!(anchors.len(), 3);
+        assert_eq!(chunks_from_anchors(text, &anchors), chunks);
+
+        let stale = ChunkAnchor { chunk: 0, page: 1, start: 20, end: 400 };
+        assert!(chunks_from_anchors(text, &[stale]).is_empty());
+    }
+}
@@ -0,0 +1,392 @@
+// Chain of custody for evidence items
+// Each custody event records who holds the item, where, why, and the SHA-256 of the stored file
+// at that moment. Events are HMAC-signed with the server signing keys, so a timeline can show
+// both edited records (bad signature) and modified files (hash changed between events).
+
+use anyhow::anyhow;
+use chrono::{DateTime, SecondsFormat, Utc};
+use sqlx::query_as;
+use std::str::FromStr;
+use uuid::Uuid;
+
+use crate::{
+    audit::{self, AuditAction, NewAuditEntry},
+    auth_simple::SigningKeys,
+    database::DbConnection,
+    evidence_store::EvidenceStore,
+    models::{CustodyEvent, CustodyEventView, CustodyTimeline, Evidence, RecordCustodyRequest},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CustodyEventType {
+    Collected,
+    Transferred,
+    CheckedOut,
+    Returned,
+    Sealed,
+}
+
+impl CustodyEventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CustodyEventType::Collected => "collected",
+            CustodyEventType::Transferred => "transferred",
+            CustodyEventType::CheckedOut => "checked_out",
+            CustodyEventType::Returned => "returned",
+            CustodyEventType::Sealed => "sealed",
+        }
+    }
+}
+
+impl FromStr for CustodyEventType {
+    type Err = CustodyError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "collected" => Ok(Self::Collected),
+            "transferred" => Ok(Self::Transferred),
+            "checked_out" => Ok(Self::CheckedOut),
+            "returned" => Ok(Self::Returned),
+            "sealed" => Ok(Self::Sealed),
+            other => Err(CustodyError::UnknownEventType(other.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CustodyError {
+    #[error("unknown custody event type '{0}'")]
+    UnknownEventType(String),
+    #[error("invalid custody transition: {0}")]
+    InvalidTransition(String),
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+impl From<sqlx::Error> for CustodyError {
+    fn from(e: sqlx::Error) -> Self {
+        CustodyError::Internal(e.into())
+    }
+}
+
+/// Where an item currently stands, derived by replaying its events in order
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct CustodyState {
+    pub started: bool,
+    pub holder: Option<String>,
+    pub checked_out: bool,
+    pub sealed: bool,
+}
+
+impl CustodyState {
+    pub fn from_events<'a>(events: impl IntoIterator<Item = &'a CustodyEvent>) -> Self {
+        let mut state = Self::default();
+        for event in events {
+            if let Ok(event_type) = event.event_type.parse() {
+                state.apply(event_type, &event.holder);
+            }
+        }
+        state
+    }
+
+    pub fn apply(&mut self, event_type: CustodyEventType, holder: &str) {
+        self.started = true;
+        self.holder = Some(holder.to_string());
+        match event_type {
+            CustodyEventType::CheckedOut => {
+                self.checked_out = true;
+                self.sealed = false; // Checking out a sealed item breaks the seal
+            }
+            CustodyEventType::Returned => self.checked_out = false,
+            CustodyEventType::Sealed => self.sealed = true,
+            CustodyEventType::Collected | CustodyEventType::Transferred => {}
+        }
+    }
+
+    pub fn validate(&self, next: CustodyEventType) -> Result<(), CustodyError> {
+        let invalid = |reason: &str| Err(CustodyError::InvalidTransition(reason.to_string()));
+        match next {
+            CustodyEventType::Collected if self.started => invalid("evidence has already been collected"),
+            _ if !self.started && next != CustodyEventType::Collected => {
+                invalid("the first custody event must be 'collected'")
+            }
+            CustodyEventType::CheckedOut if self.checked_out => invalid("evidence is already checked out"),
+            CustodyEventType::Returned if !self.checked_out => invalid("evidence is not checked out"),
+            CustodyEventType::Sealed if self.checked_out => invalid("evidence must be returned before sealing"),
+            CustodyEventType::Sealed if self.sealed => invalid("evidence is already sealed"),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Bytes covered by an event's signature (everything except the row id and the signature itself)
+pub fn signing_payload(event: &CustodyEvent) -> Vec<u8> {
+    [
+        event.evidence_id.to_string(),
+        event.event_type.clone(),
+        event.holder.clone(),
+        event.location.clone().unwrap_or_default(),
+        event.reason.clone().unwrap_or_default(),
+        event.file_hash.clone().unwrap_or_default(),
+        event.recorded_by.to_string(),
+        event.occurred_at.to_rfc3339_opts(SecondsFormat::Micros, true),
+    ]
+    .join("\u{1f}")
+    .into_bytes()
+}
+
+pub async fn load_events(db: &DbConnection, evidence_id: i32) -> anyhow::Result<Vec<CustodyEvent>> {
+    let events = query_as::<_, CustodyEvent>(
+        "SELECT * FROM custody_events WHERE evidence_id = $1 ORDER BY occurred_at, id"
+    )
+    .bind(evidence_id)
+    .fetch_all(db.as_ref())
+    .await?;
+    Ok(events)
+}
+
+async fn hash_evidence_file(store: &dyn EvidenceStore, evidence: &Evidence) -> Option<String> {
+    let key = evidence.file_path.as_deref()?;
+    match store.sha256(key).await {
+        Ok(hash) => hash,
+        Err(e) => {
+            tracing::warn!("Could not hash file for evidence {}: {}", evidence.id, e);
+            None
+        }
+    }
+}
+
+/// Hash, validate, sign and store a custody event; the check and the writes (with the audit entry) share one transaction.
+pub async fn record_event(
+    db: &DbConnection,
+    keys: &SigningKeys,
+    store: &dyn EvidenceStore,
+    evidence: &Evidence,
+    actor_id: Uuid,
+    request: RecordCustodyRequest,
+) -> Result<CustodyEvent, CustodyError> {
+    let event_type: CustodyEventType = request.event_type.parse()?;
+    if request.holder.trim().is_empty() {
+        return Err(CustodyError::InvalidTransition("holder is required".to_string()));
+    }
+
+    // Hash the file before taking the lock; a large file must not hold up other custody changes
+    let file_hash = hash_evidence_file(store, evidence).await;
+
+    let mut tx = db.begin().await?;
+
+    // Serialize custody changes per item
+    sqlx::query("SELECT id FROM evidence WHERE id = $1 FOR UPDATE")
+        .bind(evidence.id)
+        .execute(&mut *tx)
+        .await?;
+
+    let previous = query_as::<_, CustodyEvent>(
+        "SELECT * FROM custody_events WHERE evidence_id = $1 ORDER BY occurred_at, id"
+    )
+    .bind(evidence.id)
+    .fetch_all(&mut *tx)
+    .await?;
+    CustodyState::from_events(&previous).validate(event_type)?;
+
+    let now = Utc::now();
+    let mut event = CustodyEvent {
+        id: 0,
+        evidence_id: evidence.id,
+        event_type: event_type.as_str().to_string(),
+        holder: request.holder.trim().to_string(),
+        location: request.location,
+        reason: request.reason,
+        file_hash,
+        recorded_by: actor_id,
+        occurred_at: DateTime::from_timestamp_micros(now.timestamp_micros()).unwrap_or(now),
+        signing_kid: String::new(),
+        signature: String::new(),
+    };
+    let (kid, signature) = keys
+        .sign(&signing_payload(&event))
+        .map_err(|e| anyhow!("Failed to sign custody event: {}", e))?;
+    event.signing_kid = kid;
+    event.signature = signature;
+
+    let stored = query_as::<_, CustodyEvent>(
+        r#"
+        INSERT INTO custody_events (
+            evidence_id, event_type, holder, location, reason, file_hash,
+            recorded_by, occurred_at, signing_kid, signature
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+        RETURNING *
+        "#
+    )
+    .bind(event.evidence_id)
+    .bind(&event.event_type)
+    .bind(&event.holder)
+    .bind(&event.location)
+    .bind(&event.reason)
+    .bind(&event.file_hash)
+    .bind(event.recorded_by)
+    .bind(event.occurred_at)
+    .bind(&event.signing_kid)
+    .bind(&event.signature)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    audit::record(
+        &mut tx,
+        NewAuditEntry::new(actor_id, AuditAction::Create, "custody_event", stored.id, evidence.case_id, None, Some(&stored)),
+    )
+    .await?;
+
+    tx.commit().await?;
+    Ok(stored)
+}
+
+/// Build the timeline view: verify each signature, flag hash changes between events and
+/// compare the latest recorded hash against the file as it is now.
+pub fn build_timeline(
+    evidence_id: i32,
+    events: Vec<CustodyEvent>,
+    keys: &SigningKeys,
+    current_file_hash: Option<String>,
+) -> CustodyTimeline {
+    let state = CustodyState::from_events(&events);
+    let mut issues = Vec::new();
+    let mut last_hash: Option<String> = None;
+
+    let events: Vec<CustodyEventView> = events
+        .into_iter()
+        .map(|event| {
+            let signature_valid = keys
+                .verify(&event.signing_kid, &signing_payload(&event), &event.signature)
+                .ok();
+            if signature_valid == Some(false) {
+                issues.push(format!("custody event {} has an invalid signature", event.id));
+            }
+
+            let hash_changed = matches!(
+                (&last_hash, &event.file_hash),
+                (Some(previous), Some(current)) if previous != current
+            );
+            if hash_changed {
+                issues.push(format!(
+                    "file hash changed before custody event {} ({})",
+                    event.id, event.event_type
+                ));
+            }
+            if event.file_hash.is_some() {
+                last_hash = event.file_hash.clone();
+            }
+
+            CustodyEventView {
+                event,
+                signature_valid,
+                hash_changed,
+            }
+        })
+        .collect();
+
+    if let (Some(recorded), Some(current)) = (&last_hash, &current_file_hash) {
+        if recorded != current {
+            issues.push("stored file no longer matches the last recorded custody hash".to_string());
+        }
+    } else if last_hash.is_some() && current_file_hash.is_none() {
+        issues.push("stored file is missing or unreadable".to_string());
+    }
+
+    CustodyTimeline {
+        evidence_id,
+        current_holder: state.holder,
+        checked_out: state.checked_out,
+        sealed: state.sealed,
+        current_file_hash,
+        integrity_ok: issues.is_empty(),
+        issues,
+        events,
+    }
+}
+
+pub async fn timeline(
+    db: &DbConnection,
+    keys: &SigningKeys,
+    store: &dyn EvidenceStore,
+    evidence: &Evidence,
+) -> anyhow::Result<CustodyTimeline> {
+    let events = load_events(db, evidence.id).await?;
+    let current_file_hash = hash_evidence_file(store, evidence).await;
+    Ok(build_timeline(evidence.id, events, keys, current_file_hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(id: i64, event_type: &str, file_hash: Option<&str>, keys: &SigningKeys) -> CustodyEvent {
+        let mut event = CustodyEvent {
+            id,
+            evidence_id: 7,
+            event_type: event_type.to_string(),
+            holder: format!("Officer {}", id),
+            location: Some("Evidence room B".to_string()),
+            reason: None,
+            file_hash: file_hash.map(String::from),
+            recorded_by: Uuid::nil(),
+            occurred_at: DateTime::from_timestamp_micros(1_700_000_000_000_000 + id).unwrap(),
+            signing_kid: String::new(),
+            signature: String::new(),
+        };
+        let (kid, signature) = keys.sign(&signing_payload(&event)).unwrap();
+        event.signing_kid = kid;
+        event.signature = signature;
+        event
+    }
+
+    #[test]
+    fn test_transitions() {
+        let mut state = CustodyState::default();
+        assert!(state.validate(CustodyEventType::Transferred).is_err());
+        assert!(state.validate(CustodyEventType::Collected).is_ok());
+
+        state.apply(CustodyEventType::Collected, "Det. Ruiz");
+        assert!(state.validate(CustodyEventType::Collected).is_err());
+        assert!(state.validate(CustodyEventType::Returned).is_err());
+
+        state.apply(CustodyEventType::CheckedOut, "Lab");
+        assert!(state.validate(CustodyEventType::CheckedOut).is_err());
+        assert!(state.validate(CustodyEventType::Sealed).is_err());
+
+        state.apply(CustodyEventType::Returned, "Evidence room");
+        assert!(state.validate(CustodyEventType::Sealed).is_ok());
+    }
+
+    #[test]
+    fn test_clean_timeline() {
+        let keys = SigningKeys::new("k1", "secret");
+        let events = vec![
+            event(1, "collected", Some("aa"), &keys),
+            event(2, "checked_out", Some("aa"), &keys),
+        ];
+        let timeline = build_timeline(7, events, &keys, Some("aa".to_string()));
+
+        assert!(timeline.integrity_ok, "{:?}", timeline.issues);
+        assert!(timeline.checked_out);
+        assert_eq!(timeline.current_holder.as_deref(), Some("Officer 2"));
+    }
+
+    #[test]
+    fn test_timeline_flags_hash_change_and_forgery() {
+        let keys = SigningKeys::new("k1", "secret");
+        let mut forged = event(2, "transferred", Some("aa"), &keys);
+        forged.holder = "Someone else".to_string();
+        let events = vec![
+            event(1, "collected", Some("aa"), &keys),
+            forged,
+            event(3, "sealed", Some("bb"), &keys),
+        ];
+        let timeline = build_timeline(7, events, &keys, Some("cc".to_string()));
+
+        assert!(!timeline.integrity_ok);
+        assert_eq!(timeline.events[1].signature_valid, Some(false));
+        assert!(timeline.events[2].hash_changed);
+        assert_eq!(timeline.issues.len(), 3);
+    }
+}
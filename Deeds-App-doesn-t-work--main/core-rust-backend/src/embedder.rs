@@ -0,0 +1,387 @@
+// Text embedding providers for prosecutor-core
+// Everything that turns text into vectors (embedding search, hybrid search, indexing) goes through
+// `EmbeddingProvider`. The HTTP provider speaks the OpenAI `/embeddings` API, which Ollama, vLLM,
+// LocalAI and OpenAI itself all serve. The hashing provider is deterministic and needs nothing
+// running, for tests and development. With the `local-embeddings` feature a fastembed model can run
+// in-process. A provider reports its dimensions and every vector it returns is checked against them.
+
+use anyhow::{anyhow, Result};
+use axum::async_trait;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{sync::Arc, time::Duration};
+
+use crate::config::Config;
+
+#[async_trait]
+pub trait EmbeddingProvider: Send + Sync {
+    /// Provider and model, for logs and stored metadata (e.g. `openai:nomic-embed-text`)
+    fn name(&self) -> String;
+
+    /// Length of every vector this provider returns
+    fn dimensions(&self) -> usize;
+
+    /// One vector per text, in order
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>>;
+}
+
+/// Embed a single text
+pub async fn embed_one(provider: &dyn EmbeddingProvider, text: &str) -> Result<Vec<f32>> {
+    provider
+        .embed(&[text.to_string()])
+        .await?
+        .pop()
+        .ok_or_else(|| anyhow!("{} returned no embedding", provider.name()))
+}
+
+/// Fail unless vectors from `provider` fit a collection or column of `expected` dimensions
+pub fn check_dimensions(provider: &dyn EmbeddingProvider, expected: usize, collection: &str) -> Result<()> {
+    if provider.dimensions() != expected {
+        return Err(anyhow!(
+            "{} produces {}-dimensional embeddings but {} holds {} dimensions",
+            provider.name(),
+            provider.dimensions(),
+            collection,
+            expected
+        ));
+    }
+    Ok(())
+}
+
+fn check_vectors(name: &str, dimensions: usize, texts: usize, vectors: &[Vec<f32>]) -> Result<()> {
+    if vectors.len() != texts {
+        return Err(anyhow!("{} returned {} embeddings for {} texts", name, vectors.len(), texts));
+    }
+    if let Some(vector) = vectors.iter().find(|vector| vector.len() != dimensions) {
+        return Err(anyhow!("{} returned a {}-dimensional embedding, expected {}", name, vector.len(), dimensions));
+    }
+    Ok(())
+}
+
+/// The configured provider, or `None` when EMBEDDING_PROVIDER is `none`
+pub async fn from_config(config: &Config) -> Result<Option<Arc<dyn EmbeddingProvider>>> {
+    let provider: Arc<dyn EmbeddingProvider> = match config.embedding_provider.as_str() {
+        "none" => return Ok(None),
+        "openai" => {
+            let url = config
+                .embedding_url
+                .clone()
+                .ok_or_else(|| anyhow!("EMBEDDING_URL is required when EMBEDDING_PROVIDER=openai"))?;
+            let connected = OpenAiEmbeddingProvider::connect(OpenAiEmbeddingConfig {
+                url,
+                model: config.embedding_model.clone(),
+                api_key: config.embedding_api_key.clone(),
+                dimensions: config.embedding_dimensions,
+                batch_size: config.embedding_batch_size,
+            })
+            .await;
+            match connected {
+                Ok(provider) => Arc::new(provider),
+                // Only the dimension probe talks to the server here; a server that is down should not
+                // keep the rest of the API from starting
+                Err(e) => {
+                    tracing::warn!(
+                        "Embedding server unavailable, embeddings disabled until restart (set EMBEDDING_DIMENSIONS to skip the probe): {}",
+                        e
+                    );
+                    return Ok(None);
+                }
+            }
+        }
+        "hash" => Arc::new(HashEmbeddingProvider::new(config.embedding_dimensions.unwrap_or(DEFAULT_HASH_DIMENSIONS))),
+        "local" => local_provider(config)?,
+        other => {
+            return Err(anyhow!("Unknown EMBEDDING_PROVIDER '{}' (expected 'none', 'openai', 'hash' or 'local')", other))
+        }
+    };
+    tracing::info!("Embedding provider: {} ({} dimensions)", provider.name(), provider.dimensions());
+    Ok(Some(provider))
+}
+
+#[cfg(feature = "local-embeddings")]
+fn local_provider(config: &Config) -> Result<Arc<dyn EmbeddingProvider>> {
+    Ok(Arc::new(LocalEmbeddingProvider::load(&config.embedding_model, &config.llm_models_dir)?))
+}
+
+#[cfg(not(feature = "local-embeddings"))]
+fn local_provider(_config: &Config) -> Result<Arc<dyn EmbeddingProvider>> {
+    Err(anyhow!("EMBEDDING_PROVIDER=local requires the `local-embeddings` feature"))
+}
+
+#[derive(Debug, Clone)]
+pub struct OpenAiEmbeddingConfig {
+    /// Base URL of the API, e.g. `http://localhost:11434/v1` for Ollama
+    pub url: String,
+    pub model: String,
+    pub api_key: Option<String>,
+    /// Requested from the server and checked on every response; probed once when unset
+    pub dimensions: Option<usize>,
+    pub batch_size: usize,
+}
+
+#[derive(Serialize)]
+struct EmbeddingsRequest<'a> {
+    model: &'a str,
+    input: &'a [String],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dimensions: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingsResponse {
+    data: Vec<EmbeddingData>,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingData {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+/// An OpenAI-compatible `/embeddings` endpoint
+pub struct OpenAiEmbeddingProvider {
+    http: reqwest::Client,
+    config: OpenAiEmbeddingConfig,
+    dimensions: usize,
+}
+
+impl OpenAiEmbeddingProvider {
+    /// Connect to the endpoint. Without configured dimensions one probe request finds them out.
+    pub async fn connect(config: OpenAiEmbeddingConfig) -> Result<Self> {
+        let http = reqwest::Client::builder().timeout(Duration::from_secs(120)).build()?;
+        let dimensions = match config.dimensions {
+            Some(dimensions) => dimensions,
+            None => {
+                let probe = Self::request(&http, &config, &["dimension probe".to_string()]).await?;
+                probe.first().map(Vec::len).ok_or_else(|| anyhow!("embedding endpoint returned no data"))?
+            }
+        };
+        if dimensions == 0 {
+            return Err(anyhow!("embedding endpoint returned empty vectors"));
+        }
+        Ok(Self { http, config, dimensions })
+    }
+
+    async fn request(http: &reqwest::Client, config: &OpenAiEmbeddingConfig, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let url = format!("{}/embeddings", config.url.trim_end_matches('/'));
+        let mut request = http.post(&url).json(&EmbeddingsRequest {
+            model: &config.model,
+            input: texts,
+            dimensions: config.dimensions,
+        });
+        if let Some(api_key) = &config.api_key {
+            request = request.bearer_auth(api_key);
+        }
+
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow!("embedding request to {} failed with {}: {}", url, status, body.trim()));
+        }
+
+        // `data` is not guaranteed to come back in input order
+        let mut data = response.json::<EmbeddingsResponse>().await?.data;
+        data.sort_by_key(|item| item.index);
+        Ok(data.into_iter().map(|item| item.embedding).collect())
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for OpenAiEmbeddingProvider {
+    fn name(&self) -> String {
+        format!("openai:{}", self.config.model)
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let mut vectors = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(self.config.batch_size.max(1)) {
+            let embedded = Self::request(&self.http, &self.config, batch).await?;
+            check_vectors(&self.name(), self.dimensions, batch.len(), &embedded)?;
+            vectors.extend(embedded);
+        }
+        Ok(vectors)
+    }
+}
+
+pub const DEFAULT_HASH_DIMENSIONS: usize = 384;
+
+/// Deterministic feature-hashing embeddings: each lowercased word adds ±1 to a slot picked by its
+/// hash, and the result is normalised. Texts sharing words get similar vectors, which is enough to
+/// exercise retrieval in tests without a model.
+pub struct HashEmbeddingProvider {
+    dimensions: usize,
+}
+
+impl HashEmbeddingProvider {
+    pub fn new(dimensions: usize) -> Self {
+        Self { dimensions: dimensions.max(1) }
+    }
+
+    pub fn embed_text(&self, text: &str) -> Vec<f32> {
+        let mut vector = vec![0.0f32; self.dimensions];
+        for word in text.split(|c: char| !c.is_alphanumeric()).filter(|word| !word.is_empty()) {
+            let digest = Sha256::digest(word.to_lowercase().as_bytes());
+            let slot = u64::from_le_bytes(digest[..8].try_into().expect("digest has 32 bytes"));
+            let sign = if digest[8] & 1 == 0 { 1.0 } else { -1.0 };
+            vector[(slot % self.dimensions as u64) as usize] += sign;
+        }
+        let norm = vector.iter().map(|value| value * value).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            vector.iter_mut().for_each(|value| *value /= norm);
+        }
+        vector
+    }
+}
+
+#[async_trait]
+impl EmbeddingProvider for HashEmbeddingProvider {
+    fn name(&self) -> String {
+        "hash".to_string()
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        Ok(texts.iter().map(|text| self.embed_text(text)).collect())
+    }
+}
+
+/// A fastembed (ONNX) model run in-process. Models are downloaded into the cache directory on
+/// first use; EMBEDDING_MODEL names one by its model code, e.g. `BAAI/bge-small-en-v1.5`.
+#[cfg(feature = "local-embeddings")]
+pub struct LocalEmbeddingProvider {
+    model: Arc<fastembed::TextEmbedding>,
+    model_code: String,
+    dimensions: usize,
+}
+
+#[cfg(feature = "local-embeddings")]
+impl LocalEmbeddingProvider {
+    pub fn load(model_code: &str, cache_dir: &str) -> Result<Self> {
+        let info = fastembed::TextEmbedding::list_supported_models()
+            .into_iter()
+            .find(|info| info.model_code == model_code)
+            .ok_or_else(|| anyhow!("'{}' is not a supported local embedding model", model_code))?;
+        let model = fastembed::TextEmbedding::try_new(fastembed::InitOptions {
+            model_name: info.model,
+            cache_dir: std::path::PathBuf::from(cache_dir),
+            show_download_progress: false,
+            ..Default::default()
+        })?;
+        Ok(Self { model: Arc::new(model), model_code: model_code.to_string(), dimensions: info.dim })
+    }
+}
+
+#[cfg(feature = "local-embeddings")]
+#[async_trait]
+impl EmbeddingProvider for LocalEmbeddingProvider {
+    fn name(&self) -> String {
+        format!("local:{}", self.model_code)
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        // Inference is CPU-bound; keep it off the async workers
+        let model = self.model.clone();
+        let input = texts.to_vec();
+        let vectors = tokio::task::spawn_blocking(move || model.embed(input, None)).await??;
+        check_vectors(&self.name(), self.dimensions, texts.len(), &vectors)?;
+        Ok(vectors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cosine(a: &[f32], b: &[f32]) -> f32 {
+        a.iter().zip(b).map(|(x, y)| x * y).sum()
+    }
+
+    fn http_config(url: String, dimensions: Option<usize>) -> OpenAiEmbeddingConfig {
+        OpenAiEmbeddingConfig { url, model: "test-model".to_string(), api_key: Some("secret".to_string()), dimensions, batch_size: 2 }
+    }
+
+    #[tokio::test]
+    async fn test_hash_provider_is_deterministic_and_normalised() {
+        let provider = HashEmbeddingProvider::new(64);
+        let texts = vec!["White van near the harbor".to_string(), "white VAN, harbor".to_string(), "arson".to_string()];
+        let vectors = provider.embed(&texts).await.unwrap();
+
+        assert_eq!(vectors[0], provider.embed_text("White van near the harbor"));
+        assert!(vectors.iter().all(|vector| vector.len() == 64));
+        assert!((cosine(&vectors[0], &vectors[0]) - 1.0).abs() < 1e-5);
+        assert!(cosine(&vectors[0], &vectors[1]) > cosine(&vectors[0], &vectors[2]));
+        assert!(provider.embed_text("  ").iter().all(|value| *value == 0.0));
+    }
+
+    #[tokio::test]
+    async fn test_openai_provider_batches_and_orders_results() {
+        let mut server = mockito::Server::new_async().await;
+        let first = server
+            .mock("POST", "/v1/embeddings")
+            .match_header("authorization", "Bearer secret")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({"model": "test-model", "input": ["a", "b"], "dimensions": 2})))
+            .with_body(r#"{"data": [{"index": 1, "embedding": [0.0, 1.0]}, {"index": 0, "embedding": [1.0, 0.0]}]}"#)
+            .create_async()
+            .await;
+        let second = server
+            .mock("POST", "/v1/embeddings")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({"input": ["c"]})))
+            .with_body(r#"{"data": [{"index": 0, "embedding": [0.5, 0.5]}]}"#)
+            .create_async()
+            .await;
+
+        let provider = OpenAiEmbeddingProvider::connect(http_config(format!("{}/v1/", server.url()), Some(2))).await.unwrap();
+        let texts = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        let vectors = provider.embed(&texts).await.unwrap();
+
+        assert_eq!(vectors, vec![vec![1.0, 0.0], vec![0.0, 1.0], vec![0.5, 0.5]]);
+        first.assert_async().await;
+        second.assert_async().await;
+    }
+
+    #[tokio::test]
+    async fn test_openai_provider_probes_and_checks_dimensions() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/embeddings")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({"input": ["dimension probe"]})))
+            .with_body(r#"{"data": [{"index": 0, "embedding": [0.1, 0.2, 0.3]}]}"#)
+            .create_async()
+            .await;
+        server
+            .mock("POST", "/embeddings")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({"input": ["short"]})))
+            .with_body(r#"{"data": [{"index": 0, "embedding": [0.1]}]}"#)
+            .create_async()
+            .await;
+        server
+            .mock("POST", "/embeddings")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({"input": ["down"]})))
+            .with_status(503)
+            .with_body("model loading")
+            .create_async()
+            .await;
+
+        let provider = OpenAiEmbeddingProvider::connect(http_config(server.url(), None)).await.unwrap();
+        assert_eq!(provider.dimensions(), 3);
+        assert!(check_dimensions(&provider, 3, "embeddings").is_ok());
+        assert!(check_dimensions(&provider, 1536, "embeddings").is_err());
+
+        let short = embed_one(&provider, "short").await.unwrap_err().to_string();
+        assert!(short.contains("1-dimensional"), "{}", short);
+        let down = embed_one(&provider, "down").await.unwrap_err().to_string();
+        assert!(down.contains("503") && down.contains("model loading"), "{}", down);
+    }
+}
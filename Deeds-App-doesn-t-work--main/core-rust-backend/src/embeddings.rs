@@ -0,0 +1,221 @@
+// Embeddings stored in Postgres with pgvector
+// One row per (content_id, content_type). Vectors go over the wire as pgvector's text form and
+// are cast in SQL, so no pgvector client type is needed. Without the extension the table has no
+// vector column and every vector operation reports `Unavailable`.
+
+use sqlx::query_as;
+
+use crate::{
+    database::DbConnection,
+    models::{CreateEmbeddingRequest, Embedding, EmbeddingMatch},
+    permissions::{case_visibility_clause, CurrentUser},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum EmbeddingError {
+    #[error("pgvector is not installed in this database")]
+    Unavailable,
+    #[error("embedding has {actual} dimensions, the index expects {expected}")]
+    DimensionMismatch { expected: usize, actual: usize },
+    #[error("embedding values must be finite numbers")]
+    InvalidValue,
+    #[error("metadata filter must be a JSON object")]
+    InvalidFilter,
+    #[error(transparent)]
+    Database(sqlx::Error),
+}
+
+impl From<sqlx::Error> for EmbeddingError {
+    fn from(e: sqlx::Error) -> Self {
+        // undefined_column / undefined_object: the vector column or type is missing
+        match e.as_database_error().and_then(|db_err| db_err.code()) {
+            Some(code) if code == "42703" || code == "42704" => Self::Unavailable,
+            _ => Self::Database(e),
+        }
+    }
+}
+
+/// Options for a similarity search
+#[derive(Debug, Clone, Default)]
+pub struct EmbeddingQuery {
+    pub content_type: Option<String>,
+    /// Only rows whose metadata contains this JSON object
+    pub metadata: Option<serde_json::Value>,
+    pub limit: i64,
+    pub min_similarity: Option<f32>,
+}
+
+/// pgvector's text form: `[0.1,0.2,...]`
+pub fn vector_literal(vector: &[f32]) -> String {
+    let values: Vec<String> = vector.iter().map(|value| value.to_string()).collect();
+    format!("[{}]", values.join(","))
+}
+
+/// Check a vector against the dimensions of the index
+pub fn validate_vector(vector: &[f32], dimensions: usize) -> Result<(), EmbeddingError> {
+    if vector.len() != dimensions {
+        return Err(EmbeddingError::DimensionMismatch { expected: dimensions, actual: vector.len() });
+    }
+    if !vector.iter().all(|value| value.is_finite()) {
+        return Err(EmbeddingError::InvalidValue);
+    }
+    Ok(())
+}
+
+/// Dimensions of the `embedding_vector` column, or `Unavailable` without pgvector
+pub async fn vector_dimensions(db: &DbConnection) -> Result<usize, EmbeddingError> {
+    // For vector(n) columns the type modifier is n
+    let dimensions: Option<(i32,)> = query_as(
+        "SELECT atttypmod FROM pg_attribute
+         WHERE attrelid = to_regclass('embeddings') AND attname = 'embedding_vector' AND NOT attisdropped"
+    )
+    .fetch_optional(db.as_ref())
+    .await?;
+    match dimensions {
+        Some((dimensions,)) if dimensions > 0 => Ok(dimensions as usize),
+        _ => Err(EmbeddingError::Unavailable),
+    }
+}
+
+const EMBEDDING_COLUMNS: &str = "em.id, em.content_id, em.content_type, em.content_text, \
+    COALESCE(em.metadata, '{}'::JSONB) AS metadata, COALESCE(em.created_at, NOW()) AS created_at";
+
+/// Insert or replace the embedding of a piece of content
+pub async fn upsert(db: &DbConnection, request: &CreateEmbeddingRequest) -> Result<Embedding, EmbeddingError> {
+    validate_vector(&request.embedding_vector, vector_dimensions(db).await?)?;
+    let metadata = request.metadata.clone().unwrap_or_else(|| serde_json::json!({}));
+
+    let sql = format!(
+        r#"
+        INSERT INTO embeddings AS em (content_id, content_type, content_text, metadata, embedding_vector, created_at)
+        VALUES ($1, $2, $3, $4, $5::vector, NOW())
+        ON CONFLICT (content_id, content_type) DO UPDATE SET
+            content_text = EXCLUDED.content_text,
+            metadata = EXCLUDED.metadata,
+            embedding_vector = EXCLUDED.embedding_vector,
+            created_at = EXCLUDED.created_at
+        RETURNING {}
+        "#,
+        EMBEDDING_COLUMNS
+    );
+    let embedding = query_as::<_, Embedding>(&sql)
+        .bind(&request.content_id)
+        .bind(&request.content_type)
+        .bind(&request.content_text)
+        .bind(&metadata)
+        .bind(vector_literal(&request.embedding_vector))
+        .fetch_one(db.as_ref())
+        .await?;
+    Ok(embedding)
+}
+
+// Embeddings of cases and evidence follow case visibility; other content types are not tied to
+// a case. $1 is the viewer.
+fn visibility_joins(viewer: &CurrentUser) -> (&'static str, String) {
+    let joins = "LEFT JOIN evidence ev ON em.content_type = 'evidence' AND em.content_id = ev.id::TEXT
+        LEFT JOIN cases c ON c.id = CASE
+            WHEN em.content_type = 'evidence' THEN ev.case_id
+            WHEN em.content_type = 'case' AND em.content_id ~ '^[0-9]{1,9}$' THEN em.content_id::INTEGER
+        END";
+    let condition = format!("(c.id IS NULL OR (c.archived = false AND {}))", case_visibility_clause(viewer, 1));
+    (joins, condition)
+}
+
+pub async fn get(
+    db: &DbConnection,
+    viewer: &CurrentUser,
+    content_id: &str,
+    content_type: &str,
+) -> Result<Option<Embedding>, EmbeddingError> {
+    let (joins, visible) = visibility_joins(viewer);
+    let sql = format!(
+        "SELECT {} FROM embeddings em {} WHERE em.content_id = $2 AND em.content_type = $3 AND {}",
+        EMBEDDING_COLUMNS, joins, visible
+    );
+    let embedding = query_as::<_, Embedding>(&sql)
+        .bind(viewer.user_id)
+        .bind(content_id)
+        .bind(content_type)
+        .fetch_optional(db.as_ref())
+        .await?;
+    Ok(embedding)
+}
+
+/// Every embedding of a content id, newest first
+pub async fn for_content(db: &DbConnection, viewer: &CurrentUser, content_id: &str) -> Result<Vec<Embedding>, EmbeddingError> {
+    let (joins, visible) = visibility_joins(viewer);
+    let sql = format!(
+        "SELECT {} FROM embeddings em {} WHERE em.content_id = $2 AND {} ORDER BY em.created_at DESC",
+        EMBEDDING_COLUMNS, joins, visible
+    );
+    let embeddings = query_as::<_, Embedding>(&sql)
+        .bind(viewer.user_id)
+        .bind(content_id)
+        .fetch_all(db.as_ref())
+        .await?;
+    Ok(embeddings)
+}
+
+/// Nearest embeddings by cosine distance, most similar first
+pub async fn search(
+    db: &DbConnection,
+    viewer: &CurrentUser,
+    vector: &[f32],
+    query: &EmbeddingQuery,
+) -> Result<Vec<EmbeddingMatch>, EmbeddingError> {
+    validate_vector(vector, vector_dimensions(db).await?)?;
+    if query.metadata.as_ref().is_some_and(|filter| !filter.is_object()) {
+        return Err(EmbeddingError::InvalidFilter);
+    }
+
+    let (joins, visible) = visibility_joins(viewer);
+    // Ordering by the distance operator itself lets the ivfflat index serve the query
+    let sql = format!(
+        r#"
+        SELECT {columns}, (1 - (em.embedding_vector <=> $2::vector))::FLOAT8 AS similarity
+        FROM embeddings em
+        {joins}
+        WHERE em.embedding_vector IS NOT NULL
+          AND ($3::TEXT IS NULL OR em.content_type = $3)
+          AND ($4::JSONB IS NULL OR em.metadata @> $4)
+          AND ($5::FLOAT8 IS NULL OR 1 - (em.embedding_vector <=> $2::vector) >= $5)
+          AND {visible}
+        ORDER BY em.embedding_vector <=> $2::vector
+        LIMIT $6
+        "#,
+        columns = EMBEDDING_COLUMNS,
+        joins = joins,
+        visible = visible
+    );
+    let matches = query_as::<_, EmbeddingMatch>(&sql)
+        .bind(viewer.user_id)
+        .bind(vector_literal(vector))
+        .bind(&query.content_type)
+        .bind(&query.metadata)
+        .bind(query.min_similarity.map(f64::from))
+        .bind(query.limit)
+        .fetch_all(db.as_ref())
+        .await?;
+    Ok(matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vector_literal() {
+        assert_eq!(vector_literal(&[0.5, -1.0, 0.0]), "[0.5,-1,0]");
+        assert_eq!(vector_literal(&[]), "[]");
+    }
+
+    #[test]
+    fn test_validate_vector() {
+        assert!(validate_vector(&[0.1, 0.2, 0.3], 3).is_ok());
+        assert!(matches!(
+            validate_vector(&[0.1, 0.2], 3),
+            Err(EmbeddingError::DimensionMismatch { expected: 3, actual: 2 })
+        ));
+        assert!(matches!(validate_vector(&[0.1, f32::NAN, 0.3], 3), Err(EmbeddingError::InvalidValue)));
+    }
+}
@@ -0,0 +1,854 @@
+// Encryption at rest for evidence files (enabled by the `encryption` feature)
+// Envelope encryption: every stored object gets a random data key, which is itself encrypted
+// ("wrapped") under a master key and kept in `evidence_data_keys`. Rotating the master key only
+// re-wraps those small records; the files are untouched. Objects are sealed in fixed-size chunks
+// with the STREAM nonce construction (prefix || counter || last-chunk flag), so any plaintext
+// range can be decrypted from just the chunks that cover it, and reordered, dropped or truncated
+// chunks fail authentication.
+
+use aes_gcm::{
+    aead::{Aead, KeyInit, Payload},
+    Aes256Gcm,
+};
+use anyhow::{anyhow, Result};
+use axum::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::ChaCha20Poly1305;
+use rand::RngCore;
+use sqlx::FromRow;
+use std::{
+    collections::HashMap,
+    ffi::OsString,
+    io,
+    path::{Path, PathBuf},
+    pin::Pin,
+    str::FromStr,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::{
+    fs,
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt, ReadBuf},
+    sync::mpsc,
+};
+
+use crate::{
+    config::Config,
+    database::DbConnection,
+    evidence_store::{parse_content_key, EvidenceStore, StoreError, StoredObject},
+    models::ByteRange,
+};
+
+/// Plaintext bytes per sealed chunk
+pub const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+const TAG_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+const NONCE_PREFIX_LEN: usize = 7; // + 4-byte chunk counter + 1-byte last-chunk flag
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cipher {
+    Aes256Gcm,
+    ChaCha20Poly1305,
+}
+
+impl Cipher {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Cipher::Aes256Gcm => "aes-256-gcm",
+            Cipher::ChaCha20Poly1305 => "chacha20-poly1305",
+        }
+    }
+
+    fn instance(&self, key: &[u8; 32]) -> ChunkCipher {
+        match self {
+            Cipher::Aes256Gcm => ChunkCipher::Aes(Box::new(Aes256Gcm::new(key.into()))),
+            Cipher::ChaCha20Poly1305 => ChunkCipher::ChaCha(ChaCha20Poly1305::new(key.into())),
+        }
+    }
+}
+
+impl FromStr for Cipher {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        match value {
+            "aes-256-gcm" => Ok(Cipher::Aes256Gcm),
+            "chacha20-poly1305" => Ok(Cipher::ChaCha20Poly1305),
+            other => Err(anyhow!("Unknown evidence cipher '{}'", other)),
+        }
+    }
+}
+
+enum ChunkCipher {
+    Aes(Box<Aes256Gcm>), // Expanded AES key schedule is ~1KB
+    ChaCha(ChaCha20Poly1305),
+}
+
+impl ChunkCipher {
+    fn seal(&self, nonce: &[u8; NONCE_LEN], aad: &[u8], msg: &[u8]) -> Result<Vec<u8>, StoreError> {
+        let payload = Payload { msg, aad };
+        match self {
+            ChunkCipher::Aes(cipher) => cipher.encrypt(nonce.into(), payload),
+            ChunkCipher::ChaCha(cipher) => cipher.encrypt(nonce.into(), payload),
+        }
+        .map_err(|_| StoreError::Encryption("failed to seal chunk".to_string()))
+    }
+
+    fn open(&self, nonce: &[u8; NONCE_LEN], aad: &[u8], msg: &[u8]) -> Result<Vec<u8>, StoreError> {
+        let payload = Payload { msg, aad };
+        match self {
+            ChunkCipher::Aes(cipher) => cipher.decrypt(nonce.into(), payload),
+            ChunkCipher::ChaCha(cipher) => cipher.decrypt(nonce.into(), payload),
+        }
+        .map_err(|_| StoreError::Encryption("chunk failed authentication".to_string()))
+    }
+}
+
+fn chunk_nonce(prefix: &[u8], index: u64, last: bool) -> [u8; NONCE_LEN] {
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce[..NONCE_PREFIX_LEN].copy_from_slice(prefix);
+    nonce[NONCE_PREFIX_LEN..NONCE_LEN - 1].copy_from_slice(&(index as u32).to_be_bytes());
+    nonce[NONCE_LEN - 1] = last as u8;
+    nonce
+}
+
+/// An empty file is still one (empty, authenticated) chunk
+fn chunk_count(plaintext_size: u64, chunk_size: u64) -> u64 {
+    plaintext_size.div_ceil(chunk_size).max(1)
+}
+
+fn ciphertext_size(plaintext_size: u64, chunk_size: u64) -> u64 {
+    plaintext_size + chunk_count(plaintext_size, chunk_size) * TAG_LEN as u64
+}
+
+/// Master keys ("key encryption keys") that wrap the per-object data keys
+#[derive(Clone)]
+pub struct MasterKeys {
+    current_id: String,
+    keys: HashMap<String, [u8; 32]>,
+}
+
+impl MasterKeys {
+    pub fn new(current_id: &str, current: [u8; 32], retired: Vec<(String, [u8; 32])>) -> Self {
+        let mut keys: HashMap<String, [u8; 32]> = retired.into_iter().collect();
+        keys.insert(current_id.to_string(), current);
+        Self {
+            current_id: current_id.to_string(),
+            keys,
+        }
+    }
+
+    /// Keys are base64-encoded 32-byte values
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let current = config
+            .evidence_master_key
+            .as_deref()
+            .ok_or_else(|| anyhow!("EVIDENCE_MASTER_KEY is required when EVIDENCE_ENCRYPTION is enabled"))?;
+        let retired = config
+            .evidence_retired_master_keys
+            .iter()
+            .map(|(kid, key)| Ok((kid.clone(), decode_key(kid, key)?)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self::new(
+            &config.evidence_master_key_id,
+            decode_key(&config.evidence_master_key_id, current)?,
+            retired,
+        ))
+    }
+
+    pub fn current_id(&self) -> &str {
+        &self.current_id
+    }
+
+    pub fn retired_ids(&self) -> Vec<String> {
+        self.keys.keys().filter(|kid| **kid != self.current_id).cloned().collect()
+    }
+
+    /// Wrap a data key under the current master key: base64(nonce || ciphertext)
+    pub fn wrap(&self, data_key: &[u8; 32], aad: &[u8]) -> Result<(String, String), StoreError> {
+        let cipher = Aes256Gcm::new((&self.keys[&self.current_id]).into());
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let sealed = cipher
+            .encrypt((&nonce).into(), Payload { msg: data_key, aad })
+            .map_err(|_| StoreError::Encryption("failed to wrap data key".to_string()))?;
+
+        let mut wrapped = nonce.to_vec();
+        wrapped.extend_from_slice(&sealed);
+        Ok((self.current_id.clone(), STANDARD.encode(wrapped)))
+    }
+
+    pub fn unwrap(&self, kek_id: &str, wrapped: &str, aad: &[u8]) -> Result<[u8; 32], StoreError> {
+        let kek = self
+            .keys
+            .get(kek_id)
+            .ok_or_else(|| StoreError::Encryption(format!("unknown master key '{}'", kek_id)))?;
+        let wrapped = STANDARD
+            .decode(wrapped)
+            .map_err(|_| StoreError::Encryption("wrapped data key is not base64".to_string()))?;
+        if wrapped.len() < NONCE_LEN {
+            return Err(StoreError::Encryption("wrapped data key is truncated".to_string()));
+        }
+
+        let (nonce, sealed) = wrapped.split_at(NONCE_LEN);
+        let data_key = Aes256Gcm::new(kek.into())
+            .decrypt(nonce.into(), Payload { msg: sealed, aad })
+            .map_err(|_| StoreError::Encryption(format!("data key does not unwrap under '{}'", kek_id)))?;
+        data_key
+            .try_into()
+            .map_err(|_| StoreError::Encryption("data key has the wrong length".to_string()))
+    }
+}
+
+fn decode_key(kid: &str, encoded: &str) -> Result<[u8; 32]> {
+    STANDARD
+        .decode(encoded.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| anyhow!("Master key '{}' must be 32 bytes, base64-encoded", kid))
+}
+
+/// The wrapped data key and sealing parameters for one stored object
+#[derive(Debug, Clone, PartialEq, FromRow)]
+pub struct DataKeyRecord {
+    pub storage_key: String,
+    pub cipher: String,
+    pub kek_id: String,
+    pub wrapped_key: String,
+    pub nonce_prefix: String, // base64
+    pub chunk_size: i32,
+    pub plaintext_size: i64,
+}
+
+#[async_trait]
+pub trait DataKeyStore: Send + Sync {
+    async fn load(&self, storage_key: &str) -> Result<Option<DataKeyRecord>>;
+
+    /// Insert unless a record already exists; returns whichever record is stored.
+    async fn insert_if_absent(&self, record: DataKeyRecord) -> Result<DataKeyRecord>;
+
+    async fn delete(&self, storage_key: &str) -> Result<()>;
+
+    /// Records wrapped under one of `retired` master keys
+    async fn wrapped_under(&self, retired: &[String], limit: i64) -> Result<Vec<DataKeyRecord>>;
+
+    /// Replace the wrapped key if it is still wrapped under `old_kek_id`.
+    async fn rewrap(&self, storage_key: &str, old_kek_id: &str, new_kek_id: &str, wrapped_key: &str) -> Result<bool>;
+}
+
+pub struct PgDataKeyStore {
+    db: DbConnection,
+}
+
+impl PgDataKeyStore {
+    pub fn new(db: DbConnection) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl DataKeyStore for PgDataKeyStore {
+    async fn load(&self, storage_key: &str) -> Result<Option<DataKeyRecord>> {
+        let record = sqlx::query_as::<_, DataKeyRecord>(
+            r#"
+            SELECT storage_key, cipher, kek_id, wrapped_key, nonce_prefix, chunk_size, plaintext_size
+            FROM evidence_data_keys WHERE storage_key = $1
+            "#
+        )
+        .bind(storage_key)
+        .fetch_optional(self.db.as_ref())
+        .await?;
+        Ok(record)
+    }
+
+    async fn insert_if_absent(&self, record: DataKeyRecord) -> Result<DataKeyRecord> {
+        sqlx::query(
+            r#"
+            INSERT INTO evidence_data_keys
+                (storage_key, cipher, kek_id, wrapped_key, nonce_prefix, chunk_size, plaintext_size)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (storage_key) DO NOTHING
+            "#
+        )
+        .bind(&record.storage_key)
+        .bind(&record.cipher)
+        .bind(&record.kek_id)
+        .bind(&record.wrapped_key)
+        .bind(&record.nonce_prefix)
+        .bind(record.chunk_size)
+        .bind(record.plaintext_size)
+        .execute(self.db.as_ref())
+        .await?;
+
+        self.load(&record.storage_key)
+            .await?
+            .ok_or_else(|| anyhow!("Data key for {} vanished after insert", record.storage_key))
+    }
+
+    async fn delete(&self, storage_key: &str) -> Result<()> {
+        sqlx::query("DELETE FROM evidence_data_keys WHERE storage_key = $1")
+            .bind(storage_key)
+            .execute(self.db.as_ref())
+            .await?;
+        Ok(())
+    }
+
+    async fn wrapped_under(&self, retired: &[String], limit: i64) -> Result<Vec<DataKeyRecord>> {
+        let records = sqlx::query_as::<_, DataKeyRecord>(
+            r#"
+            SELECT storage_key, cipher, kek_id, wrapped_key, nonce_prefix, chunk_size, plaintext_size
+            FROM evidence_data_keys WHERE kek_id = ANY($1)
+            ORDER BY storage_key
+            LIMIT $2
+            "#
+        )
+        .bind(retired)
+        .bind(limit)
+        .fetch_all(self.db.as_ref())
+        .await?;
+        Ok(records)
+    }
+
+    async fn rewrap(&self, storage_key: &str, old_kek_id: &str, new_kek_id: &str, wrapped_key: &str) -> Result<bool> {
+        let updated = sqlx::query(
+            r#"
+            UPDATE evidence_data_keys SET kek_id = $3, wrapped_key = $4, rewrapped_at = NOW()
+            WHERE storage_key = $1 AND kek_id = $2
+            "#
+        )
+        .bind(storage_key)
+        .bind(old_kek_id)
+        .bind(new_kek_id)
+        .bind(wrapped_key)
+        .execute(self.db.as_ref())
+        .await?;
+        Ok(updated.rows_affected() > 0)
+    }
+}
+
+fn key_store_error(e: anyhow::Error) -> StoreError {
+    StoreError::Encryption(format!("data key store: {}", e))
+}
+
+/// Seals objects on the way into `inner` and opens them on the way out. Objects without a data
+/// key record (stored before encryption was enabled, or legacy file paths) pass through as-is.
+pub struct EncryptedEvidenceStore {
+    inner: Arc<dyn EvidenceStore>,
+    data_keys: Arc<dyn DataKeyStore>,
+    master_keys: MasterKeys,
+    cipher: Cipher,
+    chunk_size: usize,
+}
+
+impl EncryptedEvidenceStore {
+    pub fn new(
+        inner: Arc<dyn EvidenceStore>,
+        data_keys: Arc<dyn DataKeyStore>,
+        master_keys: MasterKeys,
+        cipher: Cipher,
+    ) -> Self {
+        Self {
+            inner,
+            data_keys,
+            master_keys,
+            cipher,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+
+    pub fn from_config(inner: Arc<dyn EvidenceStore>, config: &Config, db: &DbConnection) -> Result<Self> {
+        Ok(Self::new(
+            inner,
+            Arc::new(PgDataKeyStore::new(db.clone())),
+            MasterKeys::from_config(config)?,
+            config.evidence_cipher.parse()?,
+        ))
+    }
+
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    async fn data_key_for_new_object(&self, key: &str, plaintext_size: u64) -> Result<DataKeyRecord, StoreError> {
+        if let Some(existing) = self.data_keys.load(key).await.map_err(key_store_error)? {
+            return Ok(existing);
+        }
+
+        let mut data_key = [0u8; 32];
+        let mut nonce_prefix = [0u8; NONCE_PREFIX_LEN];
+        rand::thread_rng().fill_bytes(&mut data_key);
+        rand::thread_rng().fill_bytes(&mut nonce_prefix);
+        let (kek_id, wrapped_key) = self.master_keys.wrap(&data_key, key.as_bytes())?;
+
+        // Identical content shares a key; if another upload got here first, seal with its key
+        // so both writers produce the same ciphertext.
+        self.data_keys
+            .insert_if_absent(DataKeyRecord {
+                storage_key: key.to_string(),
+                cipher: self.cipher.as_str().to_string(),
+                kek_id,
+                wrapped_key,
+                nonce_prefix: STANDARD.encode(nonce_prefix),
+                chunk_size: self.chunk_size as i32,
+                plaintext_size: plaintext_size as i64,
+            })
+            .await
+            .map_err(key_store_error)
+    }
+
+    fn open_record(&self, record: &DataKeyRecord) -> Result<(ChunkCipher, Vec<u8>), StoreError> {
+        let cipher: Cipher = record
+            .cipher
+            .parse()
+            .map_err(|e: anyhow::Error| StoreError::Encryption(e.to_string()))?;
+        let data_key = self
+            .master_keys
+            .unwrap(&record.kek_id, &record.wrapped_key, record.storage_key.as_bytes())?;
+        let nonce_prefix = STANDARD
+            .decode(&record.nonce_prefix)
+            .ok()
+            .filter(|prefix| prefix.len() == NONCE_PREFIX_LEN)
+            .ok_or_else(|| StoreError::Encryption("invalid nonce prefix".to_string()))?;
+        Ok((cipher.instance(&data_key), nonce_prefix))
+    }
+}
+
+#[async_trait]
+impl EvidenceStore for EncryptedEvidenceStore {
+    fn name(&self) -> &'static str {
+        "encrypted"
+    }
+
+    async fn put_file(&self, key: &str, source: &Path) -> Result<(), StoreError> {
+        if parse_content_key(key).is_none() {
+            return Err(StoreError::InvalidKey(key.to_string()));
+        }
+        let plaintext_size = fs::metadata(source).await?.len();
+        let record = self.data_key_for_new_object(key, plaintext_size).await?;
+        let (cipher, nonce_prefix) = self.open_record(&record)?;
+
+        let mut sealed_path = OsString::from(source.as_os_str());
+        sealed_path.push(".sealed");
+        let sealed_path = PathBuf::from(sealed_path);
+
+        let result = async {
+            seal_file(source, &sealed_path, &cipher, &nonce_prefix, key, record.chunk_size as usize).await?;
+            self.inner.put_file(key, &sealed_path).await
+        }
+        .await;
+
+        let _ = fs::remove_file(&sealed_path).await;
+        let _ = fs::remove_file(source).await;
+        result
+    }
+
+    async fn get(&self, key: &str, range: Option<ByteRange>) -> Result<StoredObject, StoreError> {
+        let Some(record) = self.data_keys.load(key).await.map_err(key_store_error)? else {
+            return self.inner.get(key, range).await;
+        };
+        let (cipher, nonce_prefix) = self.open_record(&record)?;
+
+        let plaintext_size = record.plaintext_size as u64;
+        let chunk_size = record.chunk_size as u64;
+        let sealed_chunk = chunk_size + TAG_LEN as u64;
+        let range = range.unwrap_or(ByteRange { start: 0, end: plaintext_size });
+        let (start, end) = (range.start.min(plaintext_size), range.end.min(plaintext_size));
+        if start >= end {
+            return Ok(StoredObject {
+                total_size: plaintext_size,
+                reader: Box::pin(tokio::io::empty()),
+            });
+        }
+
+        let first = start / chunk_size;
+        let last = (end - 1) / chunk_size;
+        let chunks = chunk_count(plaintext_size, chunk_size);
+        let sealed_size = ciphertext_size(plaintext_size, chunk_size);
+        let sealed_range = ByteRange {
+            start: first * sealed_chunk,
+            end: ((last + 1) * sealed_chunk).min(sealed_size),
+        };
+        let mut sealed = self.inner.get(key, Some(sealed_range)).await?;
+
+        let (sender, receiver) = mpsc::channel(4);
+        let aad = key.to_string();
+        tokio::spawn(async move {
+            for index in first..=last {
+                let len = sealed_chunk.min(sealed_size - index * sealed_chunk) as usize;
+                let mut buffer = vec![0u8; len];
+                let opened = match sealed.reader.read_exact(&mut buffer).await {
+                    Ok(_) => cipher
+                        .open(&chunk_nonce(&nonce_prefix, index, index + 1 == chunks), aad.as_bytes(), &buffer)
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string())),
+                    Err(e) => Err(e),
+                };
+
+                let message = opened.map(|plain| {
+                    let offset = index * chunk_size;
+                    let from = start.saturating_sub(offset) as usize;
+                    let to = ((end - offset) as usize).min(plain.len());
+                    plain[from..to].to_vec()
+                });
+                let failed = message.is_err();
+                if sender.send(message).await.is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        Ok(StoredObject {
+            total_size: plaintext_size,
+            reader: Box::pin(ChannelReader::new(receiver)),
+        })
+    }
+
+    async fn exists(&self, key: &str) -> Result<bool, StoreError> {
+        self.inner.exists(key).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), StoreError> {
+        self.inner.delete(key).await?;
+        self.data_keys.delete(key).await.map_err(key_store_error)
+    }
+}
+
+async fn seal_file(
+    source: &Path,
+    destination: &Path,
+    cipher: &ChunkCipher,
+    nonce_prefix: &[u8],
+    aad: &str,
+    chunk_size: usize,
+) -> Result<(), StoreError> {
+    let mut input = fs::File::open(source).await?;
+    let plaintext_size = input.metadata().await?.len();
+    let mut output = fs::File::create(destination).await?;
+
+    let chunks = chunk_count(plaintext_size, chunk_size as u64);
+    let mut buffer = vec![0u8; chunk_size];
+    let mut remaining = plaintext_size;
+    for index in 0..chunks {
+        let len = remaining.min(chunk_size as u64) as usize;
+        input.read_exact(&mut buffer[..len]).await?;
+        remaining -= len as u64;
+
+        let nonce = chunk_nonce(nonce_prefix, index, index + 1 == chunks);
+        output.write_all(&cipher.seal(&nonce, aad.as_bytes(), &buffer[..len])?).await?;
+    }
+
+    output.flush().await?;
+    output.sync_all().await?;
+    Ok(())
+}
+
+/// `AsyncRead` over decrypted chunks sent by the decrypting task; errors surface to the reader.
+struct ChannelReader {
+    receiver: mpsc::Receiver<io::Result<Vec<u8>>>,
+    current: Vec<u8>,
+    position: usize,
+}
+
+impl ChannelReader {
+    fn new(receiver: mpsc::Receiver<io::Result<Vec<u8>>>) -> Self {
+        Self {
+            receiver,
+            current: Vec::new(),
+            position: 0,
+        }
+    }
+}
+
+impl AsyncRead for ChannelReader {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        while self.position >= self.current.len() {
+            match self.receiver.poll_recv(cx) {
+                Poll::Ready(Some(Ok(chunk))) => {
+                    self.current = chunk;
+                    self.position = 0;
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(e)),
+                Poll::Ready(None) => return Poll::Ready(Ok(())),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        let available = &self.current[self.position..];
+        let len = available.len().min(buf.remaining());
+        buf.put_slice(&available[..len]);
+        self.position += len;
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Re-wrap every data key still wrapped under a retired master key. Returns how many moved.
+pub async fn rewrap_data_keys(data_keys: &dyn DataKeyStore, master_keys: &MasterKeys) -> Result<usize> {
+    const BATCH: i64 = 200;
+    let retired = master_keys.retired_ids();
+    if retired.is_empty() {
+        return Ok(0);
+    }
+
+    let mut rewrapped = 0;
+    loop {
+        let batch = data_keys.wrapped_under(&retired, BATCH).await?;
+        if batch.is_empty() {
+            break;
+        }
+
+        let mut progressed = false;
+        for record in batch {
+            let aad = record.storage_key.as_bytes();
+            let data_key = match master_keys.unwrap(&record.kek_id, &record.wrapped_key, aad) {
+                Ok(data_key) => data_key,
+                Err(e) => {
+                    tracing::error!("Cannot re-wrap data key for {}: {}", record.storage_key, e);
+                    continue;
+                }
+            };
+            let (kek_id, wrapped_key) = master_keys.wrap(&data_key, aad)?;
+            if data_keys
+                .rewrap(&record.storage_key, &record.kek_id, &kek_id, &wrapped_key)
+                .await?
+            {
+                rewrapped += 1;
+            }
+            progressed = true;
+        }
+
+        // Every remaining record failed to unwrap; don't spin on them
+        if !progressed {
+            break;
+        }
+    }
+
+    Ok(rewrapped)
+}
+
+/// Re-wrap data keys in the background after startup, so rotating the master key is: configure
+/// the new key, move the old one to EVIDENCE_RETIRED_MASTER_KEYS, restart.
+pub fn spawn_rewrap_data_keys(db: DbConnection, master_keys: MasterKeys, delay: Duration) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        tokio::time::sleep(delay).await;
+        match rewrap_data_keys(&PgDataKeyStore::new(db), &master_keys).await {
+            Ok(0) => {}
+            Ok(count) => tracing::info!(
+                "Re-wrapped {} evidence data keys under master key '{}'",
+                count,
+                master_keys.current_id()
+            ),
+            Err(e) => tracing::error!("Evidence data key rotation failed: {}", e),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evidence_store::{content_key, LocalEvidenceStore};
+    use std::sync::Mutex;
+    use uuid::Uuid;
+
+    const HASH: &str = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+
+    #[derive(Default)]
+    struct MemoryDataKeyStore {
+        records: Mutex<HashMap<String, DataKeyRecord>>,
+    }
+
+    #[async_trait]
+    impl DataKeyStore for MemoryDataKeyStore {
+        async fn load(&self, storage_key: &str) -> Result<Option<DataKeyRecord>> {
+            Ok(self.records.lock().unwrap().get(storage_key).cloned())
+        }
+
+        async fn insert_if_absent(&self, record: DataKeyRecord) -> Result<DataKeyRecord> {
+            let mut records = self.records.lock().unwrap();
+            Ok(records.entry(record.storage_key.clone()).or_insert(record).clone())
+        }
+
+        async fn delete(&self, storage_key: &str) -> Result<()> {
+            self.records.lock().unwrap().remove(storage_key);
+            Ok(())
+        }
+
+        async fn wrapped_under(&self, retired: &[String], limit: i64) -> Result<Vec<DataKeyRecord>> {
+            let records = self.records.lock().unwrap();
+            Ok(records
+                .values()
+                .filter(|record| retired.contains(&record.kek_id))
+                .take(limit as usize)
+                .cloned()
+                .collect())
+        }
+
+        async fn rewrap(&self, storage_key: &str, old_kek_id: &str, new_kek_id: &str, wrapped_key: &str) -> Result<bool> {
+            let mut records = self.records.lock().unwrap();
+            match records.get_mut(storage_key) {
+                Some(record) if record.kek_id == old_kek_id => {
+                    record.kek_id = new_kek_id.to_string();
+                    record.wrapped_key = wrapped_key.to_string();
+                    Ok(true)
+                }
+                _ => Ok(false),
+            }
+        }
+    }
+
+    struct Fixture {
+        root: PathBuf,
+        inner: Arc<LocalEvidenceStore>,
+        data_keys: Arc<MemoryDataKeyStore>,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let root = std::env::temp_dir().join(format!("sealed-{}", Uuid::new_v4()));
+            Self {
+                inner: Arc::new(LocalEvidenceStore::new(&root)),
+                data_keys: Arc::new(MemoryDataKeyStore::default()),
+                root,
+            }
+        }
+
+        fn store(&self, master_keys: MasterKeys, cipher: Cipher) -> EncryptedEvidenceStore {
+            EncryptedEvidenceStore::new(self.inner.clone(), self.data_keys.clone(), master_keys, cipher)
+                .with_chunk_size(16)
+        }
+
+        fn source(&self, contents: &[u8]) -> PathBuf {
+            std::fs::create_dir_all(&self.root).unwrap();
+            let path = self.root.join(format!("source-{}", Uuid::new_v4()));
+            std::fs::write(&path, contents).unwrap();
+            path
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.root);
+        }
+    }
+
+    fn master(kid: &str, byte: u8) -> MasterKeys {
+        MasterKeys::new(kid, [byte; 32], Vec::new())
+    }
+
+    async fn read(store: &dyn EvidenceStore, key: &str, range: Option<ByteRange>) -> io::Result<Vec<u8>> {
+        let mut object = store.get(key, range).await.map_err(|e| io::Error::other(e.to_string()))?;
+        let mut bytes = Vec::new();
+        object.reader.read_to_end(&mut bytes).await?;
+        Ok(bytes)
+    }
+
+    const EXHIBIT: &[u8] = b"Dashcam footage, unit 14, 2024-03-02 21:14 to 21:52, sealed at intake.";
+
+    #[tokio::test]
+    async fn test_roundtrip_and_ranges_for_both_ciphers() {
+        for cipher in [Cipher::Aes256Gcm, Cipher::ChaCha20Poly1305] {
+            let fixture = Fixture::new();
+            let store = fixture.store(master("m1", 1), cipher);
+            let key = content_key(HASH);
+
+            store.put_file(&key, &fixture.source(EXHIBIT)).await.unwrap();
+
+            // The object at rest is ciphertext, one tag per 16-byte chunk
+            let at_rest = std::fs::read(fixture.inner.local_path(&key).unwrap()).unwrap();
+            assert_eq!(at_rest.len() as u64, ciphertext_size(EXHIBIT.len() as u64, 16));
+            assert!(!at_rest.windows(7).any(|window| window == b"Dashcam"));
+
+            assert_eq!(read(&store, &key, None).await.unwrap(), EXHIBIT);
+            for (start, end) in [(0, 5), (14, 18), (16, 32), (30, 71), (70, 500)] {
+                let expected = &EXHIBIT[start.min(EXHIBIT.len())..end.min(EXHIBIT.len())];
+                let range = ByteRange { start: start as u64, end: end as u64 };
+                assert_eq!(read(&store, &key, Some(range)).await.unwrap(), expected, "{:?} {}-{}", cipher, start, end);
+            }
+            assert_eq!(store.get(&key, None).await.unwrap().total_size, EXHIBIT.len() as u64);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tampered_or_truncated_objects_fail() {
+        let fixture = Fixture::new();
+        let store = fixture.store(master("m1", 1), Cipher::Aes256Gcm);
+        let key = content_key(HASH);
+        store.put_file(&key, &fixture.source(EXHIBIT)).await.unwrap();
+        let path = fixture.inner.local_path(&key).unwrap();
+        let original = std::fs::read(&path).unwrap();
+
+        let mut flipped = original.clone();
+        flipped[40] ^= 1;
+        std::fs::write(&path, &flipped).unwrap();
+        assert!(read(&store, &key, None).await.is_err());
+        // Chunks away from the damage still decrypt
+        assert_eq!(read(&store, &key, Some(ByteRange { start: 0, end: 16 })).await.unwrap(), &EXHIBIT[..16]);
+
+        // Dropping the final chunk must not pass as a shorter file
+        let sealed_chunk = 16 + TAG_LEN;
+        let last_chunk = (original.len() - 1) % sealed_chunk + 1;
+        std::fs::write(&path, &original[..original.len() - last_chunk]).unwrap();
+        assert!(read(&store, &key, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_identical_content_shares_one_data_key() {
+        let fixture = Fixture::new();
+        let store = fixture.store(master("m1", 1), Cipher::Aes256Gcm);
+        let key = content_key(HASH);
+
+        store.put_file(&key, &fixture.source(EXHIBIT)).await.unwrap();
+        let first = fixture.data_keys.load(&key).await.unwrap().unwrap();
+        store.put_file(&key, &fixture.source(EXHIBIT)).await.unwrap();
+        assert_eq!(fixture.data_keys.load(&key).await.unwrap().unwrap(), first);
+        assert_eq!(read(&store, &key, None).await.unwrap(), EXHIBIT);
+
+        store.delete(&key).await.unwrap();
+        assert!(fixture.data_keys.load(&key).await.unwrap().is_none());
+        assert!(!store.exists(&key).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_rotation_rewraps_without_touching_files() {
+        let fixture = Fixture::new();
+        let key = content_key(HASH);
+        fixture
+            .store(master("m1", 1), Cipher::ChaCha20Poly1305)
+            .put_file(&key, &fixture.source(EXHIBIT))
+            .await
+            .unwrap();
+        let path = fixture.inner.local_path(&key).unwrap();
+        let sealed_before = std::fs::read(&path).unwrap();
+
+        let rotated = MasterKeys::new("m2", [2; 32], vec![("m1".to_string(), [1; 32])]);
+        assert_eq!(rewrap_data_keys(fixture.data_keys.as_ref(), &rotated).await.unwrap(), 1);
+        assert_eq!(rewrap_data_keys(fixture.data_keys.as_ref(), &rotated).await.unwrap(), 0);
+
+        assert_eq!(fixture.data_keys.load(&key).await.unwrap().unwrap().kek_id, "m2");
+        assert_eq!(std::fs::read(&path).unwrap(), sealed_before);
+
+        // Once re-wrapped the old master key can be dropped entirely
+        let store = fixture.store(master("m2", 2), Cipher::Aes256Gcm);
+        assert_eq!(read(&store, &key, None).await.unwrap(), EXHIBIT);
+        assert!(read(&fixture.store(master("m1", 1), Cipher::Aes256Gcm), &key, None).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_objects_without_data_keys_pass_through() {
+        let fixture = Fixture::new();
+        let key = content_key(HASH);
+        fixture.inner.put_file(&key, &fixture.source(b"stored before encryption")).await.unwrap();
+
+        let store = fixture.store(master("m1", 1), Cipher::Aes256Gcm);
+        assert_eq!(read(&store, &key, None).await.unwrap(), b"stored before encryption");
+    }
+
+    #[test]
+    fn test_master_key_parsing() {
+        let encoded = STANDARD.encode([7u8; 32]);
+        assert_eq!(decode_key("m1", &encoded).unwrap(), [7u8; 32]);
+        assert!(decode_key("m1", &STANDARD.encode([7u8; 16])).is_err());
+        assert!(decode_key("m1", "not base64!").is_err());
+    }
+}
@@ -0,0 +1,359 @@
+// Structured extraction from evidence text
+// The model is asked for one JSON object matching `schema()`: tags, charges, persons, dates and
+// locations. Its reply is validated here rather than trusted; an invalid reply is sent back with the
+// validation error for another try, up to `MAX_ATTEMPTS`. Without a backend, or when every attempt
+// fails, the rule-based extractor answers instead, so callers always get a result.
+//
+// Results are stored on the evidence row (through the repository, so each run is audited):
+// `ai_tags` and `ai_summary` for listings, and the whole extraction in `ai_extraction`. Model calls
+// take a slot from the shared `llm_permits` first, so uploads cannot queue unbounded generations.
+
+use anyhow::Result;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use uuid::Uuid;
+
+use crate::{
+    llm::LLMService,
+    llm_backend::{GenerationRequest, LlmBackend},
+    repository::{EvidenceExtraction, EvidenceRepository},
+};
+
+/// Model replies tried before falling back to the rule-based extractor
+pub const MAX_ATTEMPTS: u32 = 3;
+const MAX_ITEMS: usize = 20;
+const MAX_ITEM_CHARS: usize = 200;
+/// Characters of evidence text sent to the model
+const INPUT_CHARS: usize = 16_000;
+
+const FIELDS: [&str; 5] = ["tags", "charges", "persons", "dates", "locations"];
+
+const SYSTEM_PROMPT: &str = "You extract structured facts from evidence in criminal cases. \
+Reply with a single JSON object and nothing else. Only include what the text states.";
+
+/// Offences the rule-based extractor recognises, as they appear in text
+const CHARGE_TERMS: &[&str] = &[
+    "arson", "assault", "burglary", "fraud", "homicide", "kidnapping", "manslaughter", "murder",
+    "robbery", "theft", "trafficking", "vandalism",
+];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExtractedFields {
+    /// Lowercase categorisation tags
+    pub tags: Vec<String>,
+    pub charges: Vec<String>,
+    pub persons: Vec<String>,
+    /// Dates as `YYYY-MM-DD`
+    pub dates: Vec<String>,
+    pub locations: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Extraction {
+    #[serde(flatten)]
+    pub fields: ExtractedFields,
+    /// `llm:<model>` or `rule-based`
+    pub extractor: String,
+    /// Model replies it took; 0 for the rule-based extractor
+    pub attempts: u32,
+}
+
+/// The JSON Schema the model's reply must match
+pub fn schema() -> Value {
+    let strings = |description: &str| {
+        json!({
+            "type": "array",
+            "description": description,
+            "maxItems": MAX_ITEMS,
+            "items": {"type": "string", "minLength": 1, "maxLength": MAX_ITEM_CHARS},
+        })
+    };
+    let mut dates = strings("Dates mentioned, as YYYY-MM-DD");
+    dates["items"]["format"] = json!("date");
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "type": "object",
+        "additionalProperties": false,
+        "required": FIELDS,
+        "properties": {
+            "tags": strings("Short lowercase tags for categorising the evidence"),
+            "charges": strings("Criminal offences the text describes or alleges"),
+            "persons": strings("Full names of people mentioned"),
+            "dates": dates,
+            "locations": strings("Places mentioned: addresses, streets, towns"),
+        },
+    })
+}
+
+/// Check a model reply against `schema()`. The error says what is wrong in words the model can act on.
+pub fn validate(reply: &str) -> Result<ExtractedFields, String> {
+    let body = json_body(reply).ok_or("the reply contains no JSON object")?;
+    let value: Value = serde_json::from_str(body).map_err(|e| format!("the reply is not valid JSON ({})", e))?;
+    let object = value.as_object().ok_or("the reply must be a JSON object")?;
+    if let Some(key) = object.keys().find(|key| !FIELDS.contains(&key.as_str())) {
+        return Err(format!("unexpected property \"{}\"", key));
+    }
+
+    let mut fields = ExtractedFields {
+        tags: string_list(object, "tags")?,
+        charges: string_list(object, "charges")?,
+        persons: string_list(object, "persons")?,
+        dates: string_list(object, "dates")?,
+        locations: string_list(object, "locations")?,
+    };
+    for (index, date) in fields.dates.iter().enumerate() {
+        if NaiveDate::parse_from_str(date, "%Y-%m-%d").is_err() {
+            return Err(format!("\"dates[{}]\" must be a date as YYYY-MM-DD, not \"{}\"", index, date));
+        }
+    }
+    fields.tags = dedup(fields.tags.iter().map(|tag| tag.to_lowercase()).collect());
+    Ok(fields)
+}
+
+/// The outermost `{...}` of a reply, which may be wrapped in prose or a code fence
+fn json_body(reply: &str) -> Option<&str> {
+    let start = reply.find('{')?;
+    let end = reply.rfind('}')?;
+    (start < end).then(|| &reply[start..=end])
+}
+
+fn string_list(object: &Map<String, Value>, field: &str) -> Result<Vec<String>, String> {
+    let value = object.get(field).ok_or_else(|| format!("missing required property \"{}\"", field))?;
+    let items = value.as_array().ok_or_else(|| format!("\"{}\" must be an array of strings", field))?;
+    if items.len() > MAX_ITEMS {
+        return Err(format!("\"{}\" has {} items; at most {} are allowed", field, items.len(), MAX_ITEMS));
+    }
+    let mut list = Vec::with_capacity(items.len());
+    for (index, item) in items.iter().enumerate() {
+        let item = item.as_str().ok_or_else(|| format!("\"{}[{}]\" must be a string", field, index))?.trim();
+        if item.chars().count() > MAX_ITEM_CHARS {
+            return Err(format!("\"{}[{}]\" is longer than {} characters", field, index, MAX_ITEM_CHARS));
+        }
+        if !item.is_empty() {
+            list.push(item.to_string());
+        }
+    }
+    Ok(dedup(list))
+}
+
+fn dedup(items: Vec<String>) -> Vec<String> {
+    let mut seen = std::collections::HashSet::new();
+    items.into_iter().filter(|item| seen.insert(item.to_lowercase())).collect()
+}
+
+fn prompt(text: &str) -> String {
+    format!(
+        "Extract the tags, charges, persons, dates and locations from the evidence text below. Reply with only a JSON object matching this JSON Schema, using empty arrays for anything the text does not mention:\n\n{}\n\nEvidence text:\n{}",
+        serde_json::to_string_pretty(&schema()).unwrap_or_default(),
+        text
+    )
+}
+
+/// Extract with `llm`, retrying invalid replies with the validation error, or with the rules
+pub async fn extract(llm: Option<&dyn LlmBackend>, text: &str) -> Extraction {
+    let Some(llm) = llm else { return rule_based(text) };
+    let text: String = text.chars().take(INPUT_CHARS).collect();
+    let first_prompt = prompt(&text);
+
+    let mut request = GenerationRequest {
+        system: Some(SYSTEM_PROMPT.to_string()),
+        prompt: first_prompt.clone(),
+        max_tokens: Some(800),
+        temperature: Some(0.0),
+    };
+    for attempt in 1..=MAX_ATTEMPTS {
+        let generation = match llm.generate(&request).await {
+            Ok(generation) => generation,
+            Err(e) => {
+                tracing::warn!("Extraction with {} failed: {}", llm.name(), e);
+                break;
+            }
+        };
+        match validate(&generation.text) {
+            Ok(fields) => {
+                return Extraction { fields, extractor: format!("llm:{}", generation.model), attempts: attempt };
+            }
+            Err(error) => {
+                tracing::debug!("Extraction attempt {} with {} was rejected: {}", attempt, llm.name(), error);
+                request.prompt = format!(
+                    "{}\n\nYour previous reply was:\n{}\n\nIt was rejected because {}. Reply again with only the corrected JSON object.",
+                    first_prompt,
+                    generation.text.trim(),
+                    error
+                );
+            }
+        }
+    }
+    tracing::warn!("No valid extraction from {}; using the rule-based extractor", llm.name());
+    rule_based(&text)
+}
+
+/// Keyword tags and charges, and ISO dates; persons and locations need a model
+pub fn rule_based(text: &str) -> Extraction {
+    let lower = text.to_lowercase();
+    let charges = CHARGE_TERMS.iter().filter(|&&term| lower.contains(term)).map(|term| term.to_string()).collect();
+    let dates = text
+        .split(|c: char| c.is_whitespace() || ",;()[]\"'".contains(c))
+        .map(|word| word.trim_end_matches('.'))
+        .filter(|word| word.len() == 10 && NaiveDate::parse_from_str(word, "%Y-%m-%d").is_ok())
+        .map(str::to_string)
+        .collect();
+    Extraction {
+        fields: ExtractedFields {
+            tags: dedup(LLMService::extract_basic_tags(text).iter().map(|tag| tag.to_lowercase()).collect()),
+            charges,
+            persons: Vec::new(),
+            dates: dedup(dates),
+            locations: Vec::new(),
+        },
+        extractor: "rule-based".to_string(),
+        attempts: 0,
+    }
+}
+
+/// Extract from an evidence row's text (or its description) and store the result with a summary,
+/// audited as a change by `actor`. `None` when the row does not exist or has no text.
+pub async fn extract_evidence(
+    evidence_repo: &dyn EvidenceRepository,
+    llm: Option<Arc<dyn LlmBackend>>,
+    permits: &Semaphore,
+    actor: Uuid,
+    evidence_id: i32,
+) -> Result<Option<Extraction>> {
+    let Some(evidence) = evidence_repo.get(evidence_id).await? else { return Ok(None) };
+    let text = evidence_repo.extracted_text(evidence_id).await?.filter(|text| !text.is_empty());
+    let Some(text) = text.or(evidence.description).filter(|text| !text.trim().is_empty()) else { return Ok(None) };
+
+    // The rule-based fallback needs no slot
+    let permit = match llm {
+        Some(_) => Some(permits.acquire().await?),
+        None => None,
+    };
+    let extraction = extract(llm.as_deref(), &text).await;
+    let summary_input: String = text.chars().take(INPUT_CHARS).collect();
+    let summary = match LLMService::new(llm).process_for_summary(&summary_input).await {
+        Ok(summary) => Some(summary),
+        Err(e) => {
+            tracing::warn!("Could not summarise evidence {}: {}", evidence_id, e);
+            None
+        }
+    };
+
+    drop(permit);
+
+    let stored = EvidenceExtraction {
+        tags: extraction.fields.tags.clone(),
+        summary,
+        extraction: serde_json::to_value(&extraction)?,
+    };
+    evidence_repo.save_extraction(actor, evidence_id, stored).await?;
+    Ok(Some(extraction))
+}
+
+/// Run `extract_evidence` in the background after an upload, on behalf of the uploader; a failure
+/// leaves the AI fields empty
+pub fn spawn_extract_evidence(
+    evidence_repo: Arc<dyn EvidenceRepository>,
+    llm: Option<Arc<dyn LlmBackend>>,
+    permits: Arc<Semaphore>,
+    uploader: Uuid,
+    evidence_id: i32,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        match extract_evidence(evidence_repo.as_ref(), llm, &permits, uploader, evidence_id).await {
+            Ok(Some(extraction)) => tracing::debug!("Extracted evidence {} with {}", evidence_id, extraction.extractor),
+            Ok(None) => tracing::debug!("Evidence {} has no text to extract from", evidence_id),
+            Err(e) => tracing::warn!("Could not extract evidence {}: {}", evidence_id, e),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm_backend::{Generation, MockLlmBackend, ModelInfo, TokenUsage};
+    use axum::async_trait;
+    use std::sync::Mutex;
+    use tokio::sync::mpsc;
+
+    /// Replies from a script in order, remembering the prompts it was sent
+    struct ScriptedBackend {
+        replies: Mutex<Vec<&'static str>>,
+        prompts: Mutex<Vec<String>>,
+    }
+
+    #[async_trait]
+    impl LlmBackend for ScriptedBackend {
+        fn name(&self) -> String {
+            "scripted".to_string()
+        }
+
+        async fn model_info(&self) -> Result<ModelInfo> {
+            Ok(ModelInfo { backend: self.name(), model: "scripted".to_string(), context_length: None })
+        }
+
+        async fn tokenize(&self, text: &str) -> Result<Vec<u32>> {
+            Ok(vec![0; text.split_whitespace().count()])
+        }
+
+        async fn generate(&self, request: &GenerationRequest) -> Result<Generation> {
+            self.prompts.lock().unwrap().push(request.prompt.clone());
+            let text = self.replies.lock().unwrap().remove(0).to_string();
+            Ok(Generation { text, model: "scripted".to_string(), usage: TokenUsage::default() })
+        }
+
+        async fn stream(&self, request: &GenerationRequest, _pieces: mpsc::Sender<String>) -> Result<Generation> {
+            self.generate(request).await
+        }
+    }
+
+    const VALID: &str = r#"{"tags": ["Vehicle", "vehicle", "cctv"], "charges": ["burglary"], "persons": ["Jane Roe"],
+        "dates": ["2024-03-01"], "locations": ["Elm Street"]}"#;
+
+    #[test]
+    fn test_validate_checks_the_schema() {
+        let fields = validate(&format!("Here you go:\n```json\n{}\n```", VALID)).unwrap();
+        assert_eq!(fields.tags, vec!["vehicle", "cctv"]);
+        assert_eq!(fields.persons, vec!["Jane Roe"]);
+        assert_eq!(fields.dates, vec!["2024-03-01"]);
+
+        let missing = r#"{"tags": [], "charges": [], "persons": [], "dates": []}"#;
+        assert_eq!(validate(missing).unwrap_err(), "missing required property \"locations\"");
+        let extra = VALID.replace("\"tags\"", "\"notes\": [], \"tags\"");
+        assert_eq!(validate(&extra).unwrap_err(), "unexpected property \"notes\"");
+        let bad_date = VALID.replace("2024-03-01", "March 1st");
+        assert!(validate(&bad_date).unwrap_err().contains("dates[0]"));
+        assert!(validate(&VALID.replace("[\"burglary\"]", "\"burglary\"")).unwrap_err().contains("\"charges\" must be an array"));
+        assert_eq!(validate("No facts found.").unwrap_err(), "the reply contains no JSON object");
+        assert!(schema()["required"].as_array().unwrap().len() == FIELDS.len());
+    }
+
+    #[tokio::test]
+    async fn test_extract_retries_with_the_validation_error() {
+        let backend = ScriptedBackend { replies: Mutex::new(vec![r#"{"tags": "van"}"#, VALID]), prompts: Mutex::new(Vec::new()) };
+        let extraction = extract(Some(&backend), "A burglary on Elm Street.").await;
+        assert_eq!(extraction.extractor, "llm:scripted");
+        assert_eq!(extraction.attempts, 2);
+        assert_eq!(extraction.fields.charges, vec!["burglary"]);
+
+        let prompts = backend.prompts.lock().unwrap();
+        assert!(prompts[1].contains(r#"{"tags": "van"}"#));
+        assert!(prompts[1].contains("\"tags\" must be an array of strings"), "{}", prompts[1]);
+    }
+
+    #[tokio::test]
+    async fn test_extract_falls_back_to_rules() {
+        let text = "Police report: assault outside the bar on 2024-02-29, weapon recovered (see 2024-03-02).";
+        let never_valid = MockLlmBackend::with_reply("I cannot help with that.");
+        let extraction = extract(Some(&never_valid), text).await;
+        assert_eq!(extraction, rule_based(text));
+        assert_eq!(extraction.extractor, "rule-based");
+        assert_eq!(extraction.fields.charges, vec!["assault"]);
+        assert_eq!(extraction.fields.dates, vec!["2024-02-29", "2024-03-02"]);
+        assert_eq!(extraction.fields.tags, vec!["police", "weapon", "assault"]);
+        assert_eq!(extract(None, text).await, extraction);
+    }
+}
@@ -1,7 +1,8 @@
 use anyhow::{anyhow, Result};
-use image::ImageFormat;
 use std::path::Path;
-use tracing::{debug, error, info, warn};
+use tracing::{debug, info, warn};
+
+use crate::chunker::PAGE_BREAK;
 
 #[derive(Debug, Clone)]
 pub struct ProcessedFile {
@@ -177,10 +178,11 @@ impl FileProcessor {
     }
 
     fn extract_text_from_pdf(&self, file_path: &str) -> Result<String> {
-        // Using pdf-extract crate
-        match pdf_extract::extract_text(file_path) {
-            Ok(text) => {
-                debug!("Extracted {} characters from PDF", text.len());
+        // Pages are kept apart with form feeds so chunks can point back at the page they came from
+        match pdf_extract::extract_text_by_pages(file_path) {
+            Ok(pages) => {
+                let text = pages.join(&PAGE_BREAK.to_string());
+                debug!("Extracted {} characters from {} PDF pages", text.len(), pages.len());
                 Ok(text)
             }
             Err(e) => {
@@ -287,10 +289,8 @@ impl FileProcessor {
             for entry in entries.flatten() {
                 if let Ok(metadata) = entry.metadata() {
                     if let Ok(modified) = metadata.modified() {
-                        if modified < cutoff_time {
-                            if std::fs::remove_file(entry.path()).is_ok() {
-                                cleaned_count += 1;
-                            }
+                        if modified < cutoff_time && std::fs::remove_file(entry.path()).is_ok() {
+                            cleaned_count += 1;
                         }
                     }
                 }
@@ -0,0 +1,44 @@
+use axum::{
+    extract::{Extension, Query},
+    http::StatusCode,
+    response::Json,
+};
+
+use crate::{
+    audit::ChainVerification,
+    models::{AuditEntry, AuditQuery},
+    permissions::{ensure_case_access, CurrentUser, Permission},
+    AppState,
+};
+
+pub async fn list_audit(
+    Extension(state): Extension<AppState>,
+    user: CurrentUser,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<Vec<AuditEntry>>, StatusCode> {
+    // Per-case history follows case visibility; the global log is for supervisors
+    match query.case_id {
+        Some(case_id) => ensure_case_access(state.cases.as_ref(), &user, case_id).await?,
+        None if !user.can(Permission::ViewAllCases) => return Err(StatusCode::FORBIDDEN),
+        None => {}
+    }
+
+    let limit = query.limit.unwrap_or(100).clamp(1, 500);
+
+    let entries = state.audit.list(query.case_id, query.before_id, limit).await?;
+
+    Ok(Json(entries))
+}
+
+pub async fn verify_audit(
+    Extension(state): Extension<AppState>,
+    user: CurrentUser,
+) -> Result<Json<ChainVerification>, StatusCode> {
+    if !user.can(Permission::ViewAllCases) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    let verification = state.audit.verify().await?;
+
+    Ok(Json(verification))
+}
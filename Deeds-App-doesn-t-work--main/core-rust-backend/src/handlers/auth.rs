@@ -1,33 +1,30 @@
 use axum::{
-    extract::{Extension, Request},
-    http::StatusCode,
+    extract::{Extension, Path},
+    http::{header::USER_AGENT, HeaderMap, StatusCode},
     response::Json,
 };
-use chrono::Utc;
+use chrono::{Duration, Utc};
 use serde_json::Value;
-use sqlx::query_as;
 use uuid::Uuid;
 
 use crate::{
-    auth_simple::{create_jwt, hash_password, verify_password, Claims},
-    models::{AuthResponse, CreateUserRequest, LoginRequest, User, UserResponse},
+    auth_simple::{create_jwt, generate_refresh_token, parse_refresh_token, Claims, SimpleToken},
+    models::{AuthResponse, CreateUserRequest, LoginRequest, RefreshRequest, SessionResponse, User, UserResponse},
+    password::{hash_password, verify_password},
+    repository::{NewSession, NewUser},
     AppState,
 };
 
+/// How long after a rotation the replaced refresh token counts as a concurrent refresh, not reuse
+const REFRESH_RACE_GRACE_SECONDS: i64 = 30;
+
 pub async fn register(
     Extension(state): Extension<AppState>,
+    headers: HeaderMap,
     Json(request): Json<CreateUserRequest>,
 ) -> Result<Json<AuthResponse>, StatusCode> {
     // Check if user already exists
-    let existing_user = query_as::<_, User>(
-        "SELECT * FROM users WHERE email = $1"
-    )
-    .bind(&request.email)
-    .fetch_optional(&state.db)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    if existing_user.is_some() {
+    if state.users.find_by_email(&request.email).await?.is_some() {
         return Err(StatusCode::CONFLICT);
     }
 
@@ -35,100 +32,254 @@ pub async fn register(
     let hashed_password = hash_password(&request.password, state.config.bcrypt_cost)
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
 
-    // Create user
-    let user_id = Uuid::new_v4();
-    let now = Utc::now();
-    
-    let name = match (&request.first_name, &request.last_name) {
-        (Some(first), Some(last)) => Some(format!("{} {}", first, last)),
-        (Some(first), None) => Some(first.clone()),
-        (None, Some(last)) => Some(last.clone()),
-        _ => None,
-    };
+    // Create user; a concurrent registration of the same email is a conflict too
+    let user = state
+        .users
+        .create(NewUser {
+            email: request.email,
+            hashed_password,
+            role: "prosecutor".to_string(),
+            first_name: request.first_name,
+            last_name: request.last_name,
+            title: request.title,
+            department: request.department,
+        })
+        .await?;
 
-    let user = query_as::<_, User>(
-        r#"
-        INSERT INTO users (
-            id, email, hashed_password, role, is_active, created_at, updated_at,
-            first_name, last_name, name, title, department, specializations
-        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
-        RETURNING *
-        "#
-    )
-    .bind(user_id)
-    .bind(&request.email)
-    .bind(&hashed_password)
-    .bind("prosecutor")
-    .bind(true)
-    .bind(now)
-    .bind(now)
-    .bind(&request.first_name)
-    .bind(&request.last_name)
-    .bind(&name)
-    .bind(&request.title)
-    .bind(&request.department)
-    .bind(serde_json::json!([]))
-    .fetch_one(&state.db)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    // Create JWT token
-    let claims = Claims::new(user.id, user.email.clone(), user.role.clone());
-    let token = create_jwt(&claims, &state.config.jwt_secret)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    // Start a session and issue access + refresh tokens
+    let response = start_session(&state, user, &headers).await?;
 
-    Ok(Json(AuthResponse {
-        token,
-        user: user.into(),
-    }))
+    Ok(Json(response))
 }
 
 pub async fn login(
     Extension(state): Extension<AppState>,
+    headers: HeaderMap,
     Json(request): Json<LoginRequest>,
 ) -> Result<Json<AuthResponse>, StatusCode> {
     // Find user by email
-    let user = query_as::<_, User>(
-        "SELECT * FROM users WHERE email = $1 AND is_active = true"
-    )
-    .bind(&request.email)
-    .fetch_optional(&state.db)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-    .ok_or(StatusCode::UNAUTHORIZED)?;
+    let user = state
+        .users
+        .find_by_email(&request.email)
+        .await?
+        .filter(|user| user.is_active)
+        .ok_or(StatusCode::UNAUTHORIZED)?;
 
     // Verify password
     let hashed_password = user.hashed_password.as_ref().ok_or(StatusCode::UNAUTHORIZED)?;
-    let valid = verify_password(&request.password, hashed_password)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let check = verify_password(&request.password, hashed_password, state.config.bcrypt_cost)
+        .map_err(|e| {
+            tracing::error!("Password verification failed for user {}: {}", user.id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
 
-    if !valid {
+    if !check.valid {
         return Err(StatusCode::UNAUTHORIZED);
     }
 
-    // Create JWT token
-    let claims = Claims::new(user.id, user.email.clone(), user.role.clone());
-    let token = create_jwt(&claims, &state.config.jwt_secret)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    // Upgrade legacy or outdated-cost hashes now that we have the plaintext
+    if check.needs_rehash {
+        match hash_password(&request.password, state.config.bcrypt_cost) {
+            Ok(new_hash) => {
+                if let Err(e) = state.users.set_password_hash(user.id, &new_hash).await {
+                    tracing::warn!("Failed to upgrade password hash for user {}: {}", user.id, e);
+                }
+            }
+            Err(e) => tracing::warn!("Failed to rehash password for user {}: {}", user.id, e),
+        }
+    }
 
-    Ok(Json(AuthResponse {
-        token,
-        user: user.into(),
-    }))
+    // Start a session and issue access + refresh tokens
+    let response = start_session(&state, user, &headers).await?;
+
+    Ok(Json(response))
 }
 
 pub async fn me(
     Extension(state): Extension<AppState>,
     Extension(user_id): Extension<Uuid>,
 ) -> Result<Json<UserResponse>, StatusCode> {
-    let user = query_as::<_, User>(
-        "SELECT * FROM users WHERE id = $1 AND is_active = true"
-    )
-    .bind(user_id)
-    .fetch_optional(&state.db)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-    .ok_or(StatusCode::NOT_FOUND)?;
+    let user = state
+        .users
+        .get(user_id)
+        .await?
+        .filter(|user| user.is_active)
+        .ok_or(StatusCode::NOT_FOUND)?;
 
     Ok(Json(user.into()))
 }
+
+pub async fn refresh(
+    Extension(state): Extension<AppState>,
+    Json(request): Json<RefreshRequest>,
+) -> Result<Json<AuthResponse>, StatusCode> {
+    let (session_id, presented_hash) =
+        parse_refresh_token(&request.refresh_token).ok_or(StatusCode::UNAUTHORIZED)?;
+
+    // Rotate the refresh token; the hash check makes concurrent refreshes race safely
+    let (refresh_token, new_hash) = generate_refresh_token(session_id);
+    let session = state
+        .users
+        .rotate_refresh_token(session_id, &presented_hash, &new_hash)
+        .await?;
+
+    let session = match session {
+        Some(session) => session,
+        None => {
+            // Only the token the last rotation replaced proves reuse; anything else is just a bad
+            // token, and the session id alone (readable in any access token) must not end a session
+            let rotated_out = state.users.find_rotated_out(session_id, &presented_hash).await?;
+            match rotated_out {
+                // Two tabs refreshing with the same token: the loser just logs in again
+                Some(session) if Utc::now() - session.last_used_at < Duration::seconds(REFRESH_RACE_GRACE_SECONDS) => {
+                    tracing::debug!("Concurrent refresh of session {}", session_id);
+                }
+                Some(_) => {
+                    if let Some(user_id) = revoke_session_row(&state, session_id, None).await? {
+                        tracing::warn!(
+                            "Refresh token reuse detected for session {} (user {}); session revoked",
+                            session_id,
+                            user_id
+                        );
+                    }
+                }
+                None => {}
+            }
+            return Err(StatusCode::UNAUTHORIZED);
+        }
+    };
+
+    let user = state
+        .users
+        .get(session.user_id)
+        .await?
+        .filter(|user| user.is_active)
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    let (token, expires_in) = issue_access_token(&state, &user, session.id)?;
+
+    Ok(Json(AuthResponse {
+        token,
+        refresh_token,
+        expires_in,
+        user: user.into(),
+    }))
+}
+
+pub async fn logout(
+    Extension(state): Extension<AppState>,
+    Extension(token): Extension<SimpleToken>,
+) -> Result<StatusCode, StatusCode> {
+    revoke_session_row(&state, token.session_id, Some(token.user_id)).await?;
+
+    // Also revoke the access token itself so it stops working immediately everywhere
+    state
+        .revoked_tokens
+        .revoke_persisted(&state.db, token.jti, token.expires_at)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn list_sessions(
+    Extension(state): Extension<AppState>,
+    Extension(token): Extension<SimpleToken>,
+) -> Result<Json<Vec<SessionResponse>>, StatusCode> {
+    let sessions = state.users.active_sessions(token.user_id).await?;
+
+    Ok(Json(
+        sessions
+            .into_iter()
+            .map(|session| SessionResponse::from_session(session, token.session_id))
+            .collect(),
+    ))
+}
+
+pub async fn revoke_session(
+    Extension(state): Extension<AppState>,
+    Extension(token): Extension<SimpleToken>,
+    Path(session_id): Path<Uuid>,
+) -> Result<StatusCode, StatusCode> {
+    revoke_session_row(&state, session_id, Some(token.user_id))
+        .await?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn revoke_other_sessions(
+    Extension(state): Extension<AppState>,
+    Extension(token): Extension<SimpleToken>,
+) -> Result<Json<Value>, StatusCode> {
+    let revoked = state.users.revoke_other_sessions(token.user_id, token.session_id).await?;
+
+    for session in &revoked {
+        state.revoked_tokens.revoke_session(session.session_id, session.expires_at);
+    }
+
+    Ok(Json(serde_json::json!({ "revoked": revoked.len() })))
+}
+
+async fn start_session(
+    state: &AppState,
+    user: User,
+    headers: &HeaderMap,
+) -> Result<AuthResponse, StatusCode> {
+    let session_id = Uuid::new_v4();
+    let (refresh_token, refresh_hash) = generate_refresh_token(session_id);
+
+    let user_agent = headers
+        .get(USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.chars().take(512).collect::<String>());
+    let ip_address = headers
+        .get("x-forwarded-for")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(',').next())
+        .map(|value| value.trim().to_string());
+
+    state
+        .users
+        .create_session(NewSession {
+            id: session_id,
+            user_id: user.id,
+            refresh_token_hash: refresh_hash,
+            user_agent,
+            ip_address,
+            expires_at: Utc::now() + Duration::days(state.config.refresh_token_ttl_days),
+        })
+        .await?;
+
+    let (token, expires_in) = issue_access_token(state, &user, session_id)?;
+
+    Ok(AuthResponse {
+        token,
+        refresh_token,
+        expires_in,
+        user: user.into(),
+    })
+}
+
+fn issue_access_token(state: &AppState, user: &User, session_id: Uuid) -> Result<(String, i64), StatusCode> {
+    let ttl = Duration::minutes(state.config.access_token_ttl_minutes);
+    let claims = Claims::new(user.id, user.email.clone(), user.role.clone(), session_id, ttl);
+    let token = create_jwt(&claims, &state.signing_keys)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok((token, ttl.num_seconds()))
+}
+
+/// Revoke a session (optionally only if it belongs to `owner`) and return its user id when a row was updated.
+async fn revoke_session_row(
+    state: &AppState,
+    session_id: Uuid,
+    owner: Option<Uuid>,
+) -> Result<Option<Uuid>, StatusCode> {
+    let revoked = state.users.revoke_session(session_id, owner).await?;
+
+    Ok(revoked.map(|revoked| {
+        state.revoked_tokens.revoke_session(session_id, revoked.expires_at);
+        revoked.user_id
+    }))
+}
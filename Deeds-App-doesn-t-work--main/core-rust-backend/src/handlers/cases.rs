@@ -3,232 +3,205 @@ use axum::{
     http::StatusCode,
     response::Json,
 };
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use serde::Deserialize;
-use sqlx::{query, query_as};
 use uuid::Uuid;
 
 use crate::{
-    models::{Case, CaseResponse, CreateCaseRequest, UpdateCaseRequest},
+    models::{CaseListResponse, CaseResponse, CreateCaseRequest, UpdateCaseRequest},
+    permissions::{
+        ensure_case_access, Authorized, CanArchiveCase, CanCreateCase, CanEditCase,
+        CanManageCollaborators, CurrentUser,
+    },
+    rag::{self, AskError, AskRequest, CaseAnswer},
+    repository::{CaseCursor, CaseFilter, CaseSort, SortDirection},
     AppState,
 };
 
 #[derive(Deserialize)]
 pub struct ListCasesQuery {
-    page: Option<u32>,
     limit: Option<u32>,
+    cursor: Option<String>,
+    sort: Option<CaseSort>,
+    direction: Option<SortDirection>,
     status: Option<String>,
     priority: Option<String>,
     assigned_to: Option<Uuid>,
+    case_type: Option<String>,
+    jurisdiction: Option<String>,
+    court_date_from: Option<DateTime<Utc>>,
+    court_date_to: Option<DateTime<Utc>>,
+    tags: Option<String>, // Comma-separated; cases must carry all of them
+    q: Option<String>,
 }
 
 pub async fn list_cases(
     Extension(state): Extension<AppState>,
-    Extension(user_id): Extension<Uuid>,
+    user: CurrentUser,
     Query(query): Query<ListCasesQuery>,
-) -> Result<Json<Vec<CaseResponse>>, StatusCode> {
-    let page = query.page.unwrap_or(1);
-    let limit = query.limit.unwrap_or(20).min(100); // Max 100 per page
-    let offset = (page - 1) * limit;
-
-    let mut sql = r#"
-        SELECT 
-            c.*,
-            creator.name as created_by_user,
-            assignee.name as assigned_to_user,
-            COALESCE(evidence_count.count, 0) as evidence_count
-        FROM cases c
-        LEFT JOIN users creator ON c.created_by = creator.id
-        LEFT JOIN users assignee ON c.assigned_to = assignee.id
-        LEFT JOIN (
-            SELECT case_id, COUNT(*) as count
-            FROM evidence
-            WHERE case_id IS NOT NULL
-            GROUP BY case_id
-        ) evidence_count ON c.id = evidence_count.case_id
-        WHERE c.archived = false
-    "#.to_string();
-
-    let mut bind_count = 0;
-    let mut binds: Vec<Box<dyn sqlx::Encode<'_, sqlx::Postgres> + Send + Sync>> = Vec::new();
-
-    // Add filters
-    if let Some(status) = &query.status {
-        bind_count += 1;
-        sql.push_str(&format!(" AND c.status = ${}", bind_count));
-        binds.push(Box::new(status.clone()));
-    }
-
-    if let Some(priority) = &query.priority {
-        bind_count += 1;
-        sql.push_str(&format!(" AND c.priority = ${}", bind_count));
-        binds.push(Box::new(priority.clone()));
-    }
-
-    if let Some(assigned_to) = &query.assigned_to {
-        bind_count += 1;
-        sql.push_str(&format!(" AND c.assigned_to = ${}", bind_count));
-        binds.push(Box::new(*assigned_to));
-    }
-
-    sql.push_str(" ORDER BY c.created_at DESC");
-    sql.push_str(&format!(" LIMIT {} OFFSET {}", limit, offset));
-
-    // For simplicity, let's use a simpler query for now
-    let cases = query_as::<_, Case>(
-        r#"
-        SELECT * FROM cases 
-        WHERE archived = false
-        ORDER BY created_at DESC
-        LIMIT $1 OFFSET $2
-        "#
-    )
-    .bind(limit as i64)
-    .bind(offset as i64)
-    .fetch_all(&state.db)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let case_responses: Vec<CaseResponse> = cases.into_iter().map(|case| case.into()).collect();
-
-    Ok(Json(case_responses))
+) -> Result<Json<CaseListResponse>, StatusCode> {
+    let limit = query.limit.unwrap_or(20).clamp(1, 100); // Max 100 per page
+    let sort = query.sort.unwrap_or_default();
+    let direction = query.direction.unwrap_or_else(|| sort.default_direction());
+
+    // A cursor only makes sense for the ordering it was issued for
+    let after = match query.cursor.as_deref() {
+        Some(cursor) => {
+            let cursor = CaseCursor::decode(cursor).ok_or(StatusCode::BAD_REQUEST)?;
+            if cursor.sort != sort || cursor.direction != direction {
+                return Err(StatusCode::BAD_REQUEST);
+            }
+            Some(cursor)
+        }
+        None => None,
+    };
+
+    let filter = CaseFilter {
+        status: query.status,
+        priority: query.priority,
+        assigned_to: query.assigned_to,
+        case_type: query.case_type,
+        jurisdiction: query.jurisdiction,
+        court_date_from: query.court_date_from,
+        court_date_to: query.court_date_to,
+        tags: query
+            .tags
+            .iter()
+            .flat_map(|tags| tags.split(','))
+            .map(str::trim)
+            .filter(|tag| !tag.is_empty())
+            .map(String::from)
+            .collect(),
+        search: query.q.map(|q| q.trim().to_string()).filter(|q| !q.is_empty()),
+        sort,
+        direction,
+        after,
+        limit: limit as i64,
+    };
+    let page = state.cases.list(&user, &filter).await?;
+
+    Ok(Json(CaseListResponse {
+        items: page.cases.into_iter().map(CaseResponse::from).collect(),
+        total: page.total,
+        next_cursor: page.next_cursor.map(|cursor| cursor.encode()),
+    }))
 }
 
 pub async fn get_case(
     Extension(state): Extension<AppState>,
-    Extension(user_id): Extension<Uuid>,
+    user: CurrentUser,
     Path(case_id): Path<i32>,
 ) -> Result<Json<CaseResponse>, StatusCode> {
-    let case = query_as::<_, Case>(
-        "SELECT * FROM cases WHERE id = $1 AND archived = false"
-    )
-    .bind(case_id)
-    .fetch_optional(&state.db)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-    .ok_or(StatusCode::NOT_FOUND)?;
+    ensure_case_access(state.cases.as_ref(), &user, case_id).await?;
+
+    let case = state.cases.get(case_id).await?.ok_or(StatusCode::NOT_FOUND)?;
 
     Ok(Json(case.into()))
 }
 
 pub async fn create_case(
     Extension(state): Extension<AppState>,
-    Extension(user_id): Extension<Uuid>,
+    auth: Authorized<CanCreateCase>,
     Json(request): Json<CreateCaseRequest>,
 ) -> Result<Json<CaseResponse>, StatusCode> {
-    let now = Utc::now();
-    let tags_json = serde_json::to_value(request.tags.unwrap_or_default())
-        .map_err(|_| StatusCode::BAD_REQUEST)?;
-
-    let case = query_as::<_, Case>(
-        r#"
-        INSERT INTO cases (
-            case_number, title, description, status, priority, created_by, assigned_to,
-            created_at, updated_at, court_date, court_location, judge_assigned,
-            case_type, jurisdiction, estimated_duration, case_value,
-            statute_of_limitations, tags, notes, archived
-        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20)
-        RETURNING *
-        "#
-    )
-    .bind(&request.case_number)
-    .bind(&request.title)
-    .bind(&request.description)
-    .bind(request.status.unwrap_or_else(|| "open".to_string()))
-    .bind(request.priority.unwrap_or_else(|| "medium".to_string()))
-    .bind(user_id)
-    .bind(&request.assigned_to)
-    .bind(now)
-    .bind(now)
-    .bind(&request.court_date)
-    .bind(&request.court_location)
-    .bind(&request.judge_assigned)
-    .bind(&request.case_type)
-    .bind(&request.jurisdiction)
-    .bind(&request.estimated_duration)
-    .bind(&request.case_value)
-    .bind(&request.statute_of_limitations)
-    .bind(&tags_json)
-    .bind(&request.notes)
-    .bind(false)
-    .fetch_one(&state.db)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let case = state.cases.create(auth.user_id(), request).await?;
 
     Ok(Json(case.into()))
 }
 
 pub async fn update_case(
     Extension(state): Extension<AppState>,
-    Extension(user_id): Extension<Uuid>,
+    auth: Authorized<CanEditCase>,
     Path(case_id): Path<i32>,
     Json(request): Json<UpdateCaseRequest>,
 ) -> Result<Json<CaseResponse>, StatusCode> {
-    let now = Utc::now();
-
-    // Build dynamic update query
-    let mut updates = Vec::new();
-    let mut bind_count = 1;
-
-    if request.title.is_some() { updates.push(format!("title = ${}", bind_count)); bind_count += 1; }
-    if request.description.is_some() { updates.push(format!("description = ${}", bind_count)); bind_count += 1; }
-    if request.status.is_some() { updates.push(format!("status = ${}", bind_count)); bind_count += 1; }
-    if request.priority.is_some() { updates.push(format!("priority = ${}", bind_count)); bind_count += 1; }
-    if request.assigned_to.is_some() { updates.push(format!("assigned_to = ${}", bind_count)); bind_count += 1; }
-    if request.court_date.is_some() { updates.push(format!("court_date = ${}", bind_count)); bind_count += 1; }
-    if request.court_location.is_some() { updates.push(format!("court_location = ${}", bind_count)); bind_count += 1; }
-    if request.judge_assigned.is_some() { updates.push(format!("judge_assigned = ${}", bind_count)); bind_count += 1; }
-    if request.case_type.is_some() { updates.push(format!("case_type = ${}", bind_count)); bind_count += 1; }
-    if request.jurisdiction.is_some() { updates.push(format!("jurisdiction = ${}", bind_count)); bind_count += 1; }
-    if request.estimated_duration.is_some() { updates.push(format!("estimated_duration = ${}", bind_count)); bind_count += 1; }
-    if request.case_value.is_some() { updates.push(format!("case_value = ${}", bind_count)); bind_count += 1; }
-    if request.statute_of_limitations.is_some() { updates.push(format!("statute_of_limitations = ${}", bind_count)); bind_count += 1; }
-    if request.tags.is_some() { updates.push(format!("tags = ${}", bind_count)); bind_count += 1; }
-    if request.notes.is_some() { updates.push(format!("notes = ${}", bind_count)); bind_count += 1; }
-
-    if updates.is_empty() {
+    ensure_case_access(state.cases.as_ref(), &auth.user, case_id).await?;
+
+    if request.is_empty() {
         return Err(StatusCode::BAD_REQUEST);
     }
 
-    updates.push(format!("updated_at = ${}", bind_count));
-
-    // For simplicity, let's just update a few common fields
-    let case = query_as::<_, Case>(
-        "UPDATE cases SET title = COALESCE($1, title), description = COALESCE($2, description), 
-         status = COALESCE($3, status), priority = COALESCE($4, priority), updated_at = $5
-         WHERE id = $6 AND archived = false
-         RETURNING *"
-    )
-    .bind(&request.title)
-    .bind(&request.description)
-    .bind(&request.status)
-    .bind(&request.priority)
-    .bind(now)
-    .bind(case_id)
-    .fetch_optional(&state.db)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-    .ok_or(StatusCode::NOT_FOUND)?;
+    let case = state.cases.update(auth.user_id(), case_id, request).await?;
 
     Ok(Json(case.into()))
 }
 
 pub async fn delete_case(
     Extension(state): Extension<AppState>,
-    Extension(user_id): Extension<Uuid>,
+    auth: Authorized<CanArchiveCase>,
     Path(case_id): Path<i32>,
 ) -> Result<Json<()>, StatusCode> {
-    let result = query(
-        "UPDATE cases SET archived = true WHERE id = $1"
-    )
-    .bind(case_id)
-    .execute(&state.db)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    if result.rows_affected() == 0 {
+    ensure_case_access(state.cases.as_ref(), &auth.user, case_id).await?;
+
+    state.cases.archive(auth.user_id(), case_id).await?;
+
+    Ok(Json(()))
+}
+
+/// Answer a question from the case's evidence, citing the chunks the answer rests on
+pub async fn ask_case(
+    Extension(state): Extension<AppState>,
+    user: CurrentUser,
+    Path(case_id): Path<i32>,
+    Json(request): Json<AskRequest>,
+) -> Result<Json<CaseAnswer>, StatusCode> {
+    ensure_case_access(state.cases.as_ref(), &user, case_id).await?;
+
+    let answer = rag::ask(&state.db, &state.qdrant, state.embedder.as_deref(), state.llm.as_deref(), case_id, request)
+        .await
+        .map_err(|e| match e {
+            AskError::Invalid(_) => StatusCode::BAD_REQUEST,
+            AskError::Unavailable(reason) => {
+                tracing::warn!("Case Q&A unavailable: {}", reason);
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            AskError::Other(e) => {
+                tracing::error!("Case Q&A failed for case {}: {}", case_id, e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+
+    Ok(Json(answer))
+}
+
+#[derive(Deserialize)]
+pub struct AddCollaboratorRequest {
+    user_id: Uuid,
+}
+
+pub async fn list_collaborators(
+    Extension(state): Extension<AppState>,
+    user: CurrentUser,
+    Path(case_id): Path<i32>,
+) -> Result<Json<Vec<Uuid>>, StatusCode> {
+    ensure_case_access(state.cases.as_ref(), &user, case_id).await?;
+
+    Ok(Json(state.cases.collaborators(case_id).await?))
+}
+
+pub async fn add_collaborator(
+    Extension(state): Extension<AppState>,
+    auth: Authorized<CanManageCollaborators>,
+    Path(case_id): Path<i32>,
+    Json(request): Json<AddCollaboratorRequest>,
+) -> Result<StatusCode, StatusCode> {
+    ensure_case_access(state.cases.as_ref(), &auth.user, case_id).await?;
+
+    state.cases.add_collaborator(case_id, request.user_id, auth.user_id()).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn remove_collaborator(
+    Extension(state): Extension<AppState>,
+    auth: Authorized<CanManageCollaborators>,
+    Path((case_id, collaborator_id)): Path<(i32, Uuid)>,
+) -> Result<StatusCode, StatusCode> {
+    ensure_case_access(state.cases.as_ref(), &auth.user, case_id).await?;
+
+    if !state.cases.remove_collaborator(case_id, collaborator_id).await? {
         return Err(StatusCode::NOT_FOUND);
     }
 
-    Ok(Json(()))
+    Ok(StatusCode::NO_CONTENT)
 }
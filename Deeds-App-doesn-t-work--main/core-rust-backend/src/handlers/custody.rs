@@ -0,0 +1,70 @@
+use axum::{
+    extract::{Extension, Path},
+    http::StatusCode,
+    response::Json,
+};
+
+use crate::{
+    custody::{self, CustodyError},
+    models::{CustodyEvent, CustodyTimeline, Evidence, RecordCustodyRequest},
+    permissions::{ensure_case_access, Authorized, CanRecordCustody, CurrentUser},
+    AppState,
+};
+
+async fn load_visible_evidence(state: &AppState, user: &CurrentUser, evidence_id: i32) -> Result<Evidence, StatusCode> {
+    let evidence = state.evidence.get(evidence_id).await?.ok_or(StatusCode::NOT_FOUND)?;
+
+    if let Some(case_id) = evidence.case_id {
+        ensure_case_access(state.cases.as_ref(), user, case_id).await?;
+    }
+    Ok(evidence)
+}
+
+pub async fn record_custody_event(
+    Extension(state): Extension<AppState>,
+    auth: Authorized<CanRecordCustody>,
+    Path(evidence_id): Path<i32>,
+    Json(payload): Json<RecordCustodyRequest>,
+) -> Result<Json<CustodyEvent>, StatusCode> {
+    let evidence = load_visible_evidence(&state, &auth.user, evidence_id).await?;
+
+    let event = custody::record_event(
+        &state.db,
+        &state.custody_keys,
+        state.evidence_store.as_ref(),
+        &evidence,
+        auth.user_id(),
+        payload,
+    )
+    .await
+    .map_err(|e| match e {
+        CustodyError::UnknownEventType(_) => StatusCode::BAD_REQUEST,
+        CustodyError::InvalidTransition(reason) => {
+            tracing::warn!("Rejected custody event for evidence {}: {}", evidence_id, reason);
+            StatusCode::CONFLICT
+        }
+        CustodyError::Internal(e) => {
+            tracing::error!("Failed to record custody event for evidence {}: {}", evidence_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    Ok(Json(event))
+}
+
+pub async fn get_custody_timeline(
+    Extension(state): Extension<AppState>,
+    user: CurrentUser,
+    Path(evidence_id): Path<i32>,
+) -> Result<Json<CustodyTimeline>, StatusCode> {
+    let evidence = load_visible_evidence(&state, &user, evidence_id).await?;
+
+    let timeline = custody::timeline(&state.db, &state.custody_keys, state.evidence_store.as_ref(), &evidence)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to load custody timeline for evidence {}: {}", evidence_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(timeline))
+}
@@ -1,227 +1,164 @@
 use axum::{
-    extract::{Extension, Path, Query},
+    extract::{Extension, Path},
     http::StatusCode,
     response::Json,
 };
-use rusqlite::params;
-use serde::{Deserialize, Serialize};
-use serde_json::Value;
-use std::collections::HashMap;
-use uuid::Uuid;
-
-use crate::AppState;
-
-#[derive(Deserialize)]
-pub struct CreateEmbeddingRequest {
-    pub content_id: String,
-    pub content_type: String, // "evidence", "case", "note", etc.
-    pub content_text: String,
-    pub embedding_vector: Vec<f32>, // The actual embedding vector
-    pub metadata: Option<Value>,
-}
+use serde::Serialize;
+
+use crate::{
+    embedder::embed_one,
+    embeddings::{self, EmbeddingError, EmbeddingQuery},
+    models::{CreateEmbeddingRequest, Embedding, EmbeddingMatch, SearchEmbeddingsRequest},
+    permissions::{ensure_case_access, Authorized, CanManageSearchIndex, CurrentUser},
+    vector_collections::{self, ReembedError, VectorCollection},
+    AppState,
+};
 
 #[derive(Serialize)]
-pub struct EmbeddingResponse {
-    pub id: i64,
-    pub content_id: String,
-    pub content_type: String,
-    pub content_text: String,
-    pub metadata: String, // JSON string for SQLite
-    pub created_at: String, // SQLite datetime string
-}
-
-#[derive(Deserialize)]
-pub struct SearchEmbeddingRequest {
-    pub query_embedding: Vec<f32>,
-    pub content_type: Option<String>,
-    pub limit: Option<i64>,
-    pub threshold: Option<f32>, // Similarity threshold
+pub struct VectorCollectionsResponse {
+    pub alias: String,
+    pub serving_dimensions: Option<usize>,
+    pub provider: Option<String>,
+    pub provider_dimensions: Option<usize>,
+    pub collections: Vec<VectorCollection>,
 }
 
-#[derive(Serialize)]
-pub struct SearchResult {
-    pub embedding: EmbeddingResponse,
-    pub similarity: f32,
+fn embedding_status(e: EmbeddingError) -> StatusCode {
+    match e {
+        EmbeddingError::DimensionMismatch { .. } | EmbeddingError::InvalidValue | EmbeddingError::InvalidFilter => {
+            StatusCode::BAD_REQUEST
+        }
+        EmbeddingError::Unavailable => {
+            tracing::warn!("Embedding request without pgvector installed");
+            StatusCode::SERVICE_UNAVAILABLE
+        }
+        EmbeddingError::Database(e) => {
+            tracing::error!("Database error in embeddings: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
 }
 
-// Create a new embedding
+// Create or replace the embedding of a piece of content. Embeddings of cases and evidence feed
+// everyone's similarity search, so writing one takes index rights and access to the case.
 pub async fn create_embedding(
     Extension(state): Extension<AppState>,
+    auth: Authorized<CanManageSearchIndex>,
     Json(request): Json<CreateEmbeddingRequest>,
-) -> Result<Json<EmbeddingResponse>, StatusCode> {
-    let db = &state.db;
-    
-    // Convert embedding vector to JSON string
-    let embedding_json = serde_json::to_string(&request.embedding_vector)
-        .map_err(|_| StatusCode::BAD_REQUEST)?;
-    
-    let metadata = serde_json::to_string(&request.metadata.unwrap_or_else(|| serde_json::json!({})))
-        .map_err(|_| StatusCode::BAD_REQUEST)?;
-
-    let conn = db.lock().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    
-    // Use REPLACE to handle upserts
-    let id = conn.execute(
-        "INSERT OR REPLACE INTO embeddings (content_id, content_type, content_text, embedding_vector, metadata)
-         VALUES (?1, ?2, ?3, ?4, ?5)",
-        params![
-            request.content_id,
-            request.content_type,
-            request.content_text,
-            embedding_json,
-            metadata
-        ],
-    ).map_err(|e| {
-        tracing::error!("Database error creating embedding: {}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
-
-    // Get the created/updated record
-    let mut stmt = conn.prepare(
-        "SELECT id, content_id, content_type, content_text, metadata, created_at
-         FROM embeddings 
-         WHERE content_id = ?1 AND content_type = ?2"
-    ).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+) -> Result<Json<Embedding>, StatusCode> {
+    if request.content_id.is_empty() || request.content_type.is_empty() {
+        return Err(StatusCode::BAD_REQUEST);
+    }
 
-    let result = stmt.query_row(
-        params![request.content_id, request.content_type],
-        |row| {
-            Ok(EmbeddingResponse {
-                id: row.get(0)?,
-                content_id: row.get(1)?,
-                content_type: row.get(2)?,
-                content_text: row.get(3)?,
-                metadata: row.get(4)?,
-                created_at: row.get(5)?,
-            })
+    let case_id = match request.content_type.as_str() {
+        "case" => Some(request.content_id.parse::<i32>().map_err(|_| StatusCode::BAD_REQUEST)?),
+        "evidence" => {
+            let evidence_id = request.content_id.parse::<i32>().map_err(|_| StatusCode::BAD_REQUEST)?;
+            state.evidence.get(evidence_id).await?.ok_or(StatusCode::NOT_FOUND)?.case_id
         }
-    ).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        _ => None,
+    };
+    if let Some(case_id) = case_id {
+        ensure_case_access(state.cases.as_ref(), &auth.user, case_id).await?;
+    }
 
-    Ok(Json(result))
+    let embedding = embeddings::upsert(&state.db, &request).await.map_err(embedding_status)?;
+    Ok(Json(embedding))
 }
 
 // Get embedding by content_id and content_type
 pub async fn get_embedding(
     Extension(state): Extension<AppState>,
+    user: CurrentUser,
     Path((content_id, content_type)): Path<(String, String)>,
-) -> Result<Json<EmbeddingResponse>, StatusCode> {
-    let db = &state.db;
-    let conn = db.lock().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let mut stmt = conn.prepare(
-        "SELECT id, content_id, content_type, content_text, metadata, created_at
-         FROM embeddings 
-         WHERE content_id = ?1 AND content_type = ?2"
-    ).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let result = stmt.query_row(
-        params![content_id, content_type],
-        |row| {
-            Ok(EmbeddingResponse {
-                id: row.get(0)?,
-                content_id: row.get(1)?,
-                content_type: row.get(2)?,
-                content_text: row.get(3)?,
-                metadata: row.get(4)?,
-                created_at: row.get(5)?,
-            })
-        }
-    ).map_err(|_| StatusCode::NOT_FOUND)?;
-
-    Ok(Json(result))
+) -> Result<Json<Embedding>, StatusCode> {
+    embeddings::get(&state.db, &user, &content_id, &content_type)
+        .await
+        .map_err(embedding_status)?
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
 }
 
 // Get all embeddings for a content_id
 pub async fn get_content_embeddings(
     Extension(state): Extension<AppState>,
+    user: CurrentUser,
     Path(content_id): Path<String>,
-) -> Result<Json<Vec<EmbeddingResponse>>, StatusCode> {
-    let db = &state.db;
-    let conn = db.lock().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let mut stmt = conn.prepare(
-        "SELECT id, content_id, content_type, content_text, metadata, created_at
-         FROM embeddings 
-         WHERE content_id = ?1
-         ORDER BY created_at DESC"
-    ).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let rows = stmt.query_map(params![content_id], |row| {
-        Ok(EmbeddingResponse {
-            id: row.get(0)?,
-            content_id: row.get(1)?,
-            content_type: row.get(2)?,
-            content_text: row.get(3)?,
-            metadata: row.get(4)?,
-            created_at: row.get(5)?,
-        })
-    }).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let results: Result<Vec<_>, _> = rows.collect();
-    let results = results.map_err(|e| {
-        tracing::error!("Database error fetching embeddings: {}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
-
+) -> Result<Json<Vec<Embedding>>, StatusCode> {
+    let results = embeddings::for_content(&state.db, &user, &content_id).await.map_err(embedding_status)?;
     Ok(Json(results))
 }
 
-// Search embeddings by similarity
+/// Nearest embeddings by cosine similarity, most similar first
 pub async fn search_embeddings(
     Extension(state): Extension<AppState>,
-    Query(params): Query<HashMap<String, String>>,
-    Json(request): Json<SearchEmbeddingRequest>,
-) -> Result<Json<Vec<SearchResult>>, StatusCode> {
-    let db = &state.db;
-    let conn = db.lock().map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-    
-    let limit = request.limit.unwrap_or(10).min(100); // Cap at 100 results
-    
-    // Basic implementation without vector similarity
-    // In production, you'd want to use a proper vector database like Qdrant
-    let mut query = "SELECT id, content_id, content_type, content_text, metadata, created_at
-                     FROM embeddings WHERE 1=1".to_string();
-    let mut query_params: Vec<Box<dyn rusqlite::ToSql>> = vec![];
-    
-    if let Some(content_type) = &request.content_type {
-        query.push_str(" AND content_type = ?");
-        query_params.push(Box::new(content_type.clone()));
-    }
-
-    query.push_str(" ORDER BY created_at DESC LIMIT ?");
-    query_params.push(Box::new(limit));
-
-    let mut stmt = conn.prepare(&query)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-    let param_refs: Vec<&dyn rusqlite::ToSql> = query_params.iter()
-        .map(|p| p.as_ref())
-        .collect();
-
-    let rows = stmt.query_map(&param_refs[..], |row| {
-        Ok(EmbeddingResponse {
-            id: row.get(0)?,
-            content_id: row.get(1)?,
-            content_type: row.get(2)?,
-            content_text: row.get(3)?,
-            metadata: row.get(4)?,
-            created_at: row.get(5)?,
-        })
-    }).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    user: CurrentUser,
+    Json(request): Json<SearchEmbeddingsRequest>,
+) -> Result<Json<Vec<EmbeddingMatch>>, StatusCode> {
+    let vector = match (request.query_embedding, request.query_text) {
+        (Some(vector), _) => vector,
+        (None, Some(text)) if !text.trim().is_empty() => {
+            // Text queries are embedded here, so they need a configured provider
+            let embedder = state.embedder.as_deref().ok_or(StatusCode::NOT_IMPLEMENTED)?;
+            if !state.pgvector_search {
+                tracing::warn!("Text embedding search requested but {} does not fit the pgvector column", embedder.name());
+                return Err(StatusCode::SERVICE_UNAVAILABLE);
+            }
+            embed_one(embedder, &text).await.map_err(|e| {
+                tracing::error!("Failed to embed search query: {}", e);
+                StatusCode::BAD_GATEWAY
+            })?
+        }
+        _ => return Err(StatusCode::BAD_REQUEST),
+    };
+
+    let query = EmbeddingQuery {
+        content_type: request.content_type,
+        metadata: request.metadata,
+        limit: request.limit.unwrap_or(10).clamp(1, 100),
+        min_similarity: request.min_similarity,
+    };
+    let matches = embeddings::search(&state.db, &user, &vector, &query).await.map_err(embedding_status)?;
+    Ok(Json(matches))
+}
 
-    let embeddings: Result<Vec<_>, _> = rows.collect();
-    let embeddings = embeddings.map_err(|e| {
-        tracing::error!("Database error searching embeddings: {}", e);
+/// The Qdrant collections behind the alias, newest first, with build progress
+pub async fn list_vector_collections(
+    Extension(state): Extension<AppState>,
+    _auth: Authorized<CanManageSearchIndex>,
+) -> Result<Json<VectorCollectionsResponse>, StatusCode> {
+    let collections = vector_collections::list(&state.db, state.qdrant.alias()).await.map_err(|e| {
+        tracing::error!("Failed to list vector collections: {}", e);
         StatusCode::INTERNAL_SERVER_ERROR
     })?;
 
-    let search_results: Vec<SearchResult> = embeddings
-        .into_iter()
-        .map(|embedding| SearchResult {
-            embedding,
-            similarity: 1.0, // Placeholder similarity score
-        })
-        .collect();
+    Ok(Json(VectorCollectionsResponse {
+        alias: state.qdrant.alias().to_string(),
+        serving_dimensions: state.qdrant.serving_dimensions(),
+        provider: state.embedder.as_ref().map(|embedder| embedder.name()),
+        provider_dimensions: state.embedder.as_ref().map(|embedder| embedder.dimensions()),
+        collections,
+    }))
+}
 
-    Ok(Json(search_results))
+/// Start filling a new collection with the current embedding model. The alias moves to it once
+/// every evidence row is embedded; poll `GET /api/vector-collections` for progress.
+pub async fn reembed(
+    Extension(state): Extension<AppState>,
+    _auth: Authorized<CanManageSearchIndex>,
+) -> Result<(StatusCode, Json<VectorCollection>), StatusCode> {
+    let embedder = state.embedder.clone().ok_or(StatusCode::NOT_IMPLEMENTED)?;
+
+    let collection = vector_collections::start_reembed(&state.db, &state.qdrant, embedder.as_ref())
+        .await
+        .map_err(|e| match e {
+            ReembedError::AlreadyRunning(_) => StatusCode::CONFLICT,
+            ReembedError::Other(e) => {
+                tracing::error!("Failed to start re-embed: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+    vector_collections::spawn_reembed(state.db.clone(), state.qdrant.clone(), embedder, collection.clone());
+
+    Ok((StatusCode::ACCEPTED, Json(collection)))
 }
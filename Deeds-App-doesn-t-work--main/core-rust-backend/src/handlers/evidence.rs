@@ -1,33 +1,51 @@
 use axum::{
-    extract::{Extension, Multipart, Path},
-    http::StatusCode,
-    response::Json,
+    body::Body,
+    extract::{Extension, Multipart, Path, Query},
+    http::{header, HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Json, Response,
+    },
 };
 use chrono::Utc;
-use sqlx::{query, query_as};
-use std::{fs, path::PathBuf};
-use uuid::Uuid;
+use serde::Deserialize;
+use std::path::PathBuf;
+use tokio::io::AsyncReadExt;
+use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
+use tokio_util::io::ReaderStream;
 
 use crate::{
-    models::{Evidence, EvidenceResponse, UploadEvidenceRequest},
+    chunker::{self, ChunkOptions},
+    download::{self, content_disposition, content_type_for, parse_range, AccessEvent, RangeRequest},
+    evidence_store::{self, content_key, parse_content_key, StoreError},
+    extraction::{self, Extraction},
+    integrity::{verify_evidence, IntegrityReport},
+    llm::LLMService,
+    llm_stream::StreamEvent,
+    models::{EvidenceAccessEvent, EvidenceResponse},
+    permissions::{ensure_case_access, Authorized, CanDeleteEvidence, CanEditEvidence, CanUploadEvidence, CurrentUser, Permission},
+    repository::NewEvidence,
+    search,
+    upload::{StagedUpload, UploadError},
+    vector_collections,
+    watermark::{self, WatermarkError, WatermarkKind},
     AppState,
 };
 
 pub async fn upload_evidence(
     Extension(state): Extension<AppState>,
-    Extension(user_id): Extension<Uuid>,
+    auth: Authorized<CanUploadEvidence>,
     mut multipart: Multipart,
 ) -> Result<Json<EvidenceResponse>, StatusCode> {
+    let upload_dir = PathBuf::from(&state.config.upload_dir);
     let mut title = String::new();
     let mut description: Option<String> = None;
     let mut evidence_type = String::new();
     let mut case_id: Option<i32> = None;
     let mut criminal_id: Option<i32> = None;
-    let mut file_data: Option<Vec<u8>> = None;
-    let mut file_name: Option<String> = None;
-    let mut file_type: Option<String> = None;
+    let mut staged: Option<StagedUpload> = None;
 
-    // Process multipart form data
+    // Process multipart form data; the file streams to a staging file rather than into memory
     while let Some(field) = multipart.next_field().await.map_err(|_| StatusCode::BAD_REQUEST)? {
         let name = field.name().unwrap_or("").to_string();
         
@@ -50,9 +68,17 @@ pub async fn upload_evidence(
                 criminal_id = text.parse().ok();
             }
             "file" => {
-                file_name = field.file_name().map(|s| s.to_string());
-                file_type = field.content_type().map(|s| s.to_string());
-                file_data = Some(field.bytes().await.map_err(|_| StatusCode::BAD_REQUEST)?.to_vec());
+                let upload = StagedUpload::from_field(field, &upload_dir, state.config.max_file_size)
+                    .await
+                    .map_err(|e| match e {
+                        UploadError::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+                        UploadError::Malformed(_) => StatusCode::BAD_REQUEST,
+                        UploadError::Io(e) => {
+                            tracing::error!("Failed to stage evidence upload: {}", e);
+                            StatusCode::INTERNAL_SERVER_ERROR
+                        }
+                    })?;
+                staged = Some(upload);
             }
             _ => {}
         }
@@ -62,112 +88,422 @@ pub async fn upload_evidence(
         return Err(StatusCode::BAD_REQUEST);
     }
 
-    // Handle file upload if present
-    let mut file_path: Option<String> = None;
-    let mut file_size: Option<i64> = None;
+    if let Some(case_id) = case_id {
+        ensure_case_access(state.cases.as_ref(), &auth.user, case_id).await?;
 
-    if let Some(data) = file_data {
-        if data.len() > state.config.max_file_size {
-            return Err(StatusCode::PAYLOAD_TOO_LARGE);
+        // The same file can only be attached to a case once
+        if let Some(upload) = &staged {
+            if let Some(existing_id) = state.evidence.find_by_hash(case_id, &upload.sha256).await? {
+                tracing::info!("Rejected duplicate upload to case {}: matches evidence {}", case_id, existing_id);
+                return Err(StatusCode::CONFLICT);
+            }
         }
+    }
 
-        // Create upload directory if it doesn't exist
-        let upload_dir = PathBuf::from(&state.config.upload_dir);
-        if !upload_dir.exists() {
-            fs::create_dir_all(&upload_dir).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    // Extract text for search while the file is still staged. Prefer the sniffed type; fall back to
+    // what processing infers from the file name.
+    let mut mime_type = staged.as_ref().map(|upload| upload.mime_type.clone());
+    let mut extracted_text = None;
+    let mut chunk_anchors = Vec::new();
+    if let Some(upload) = &staged {
+        let original_name = upload.original_name.as_deref().unwrap_or_default();
+        match state.file_processor.process_file(&upload.temp_path().to_string_lossy(), original_name).await {
+            Ok(processed) => {
+                if upload.mime_type == mime::APPLICATION_OCTET_STREAM.as_ref() {
+                    mime_type = Some(processed.metadata.mime_type);
+                }
+                extracted_text = search::indexable_text(&processed.extracted_text);
+                chunk_anchors = extracted_text
+                    .as_deref()
+                    .map(|text| chunker::chunk_anchors(text, &ChunkOptions::default()))
+                    .unwrap_or_default();
+            }
+            Err(e) => tracing::warn!("Could not process uploaded file {:?}: {}", original_name, e),
         }
+    }
 
-        // Generate unique filename
-        let file_id = Uuid::new_v4();
-        let extension = file_name.as_ref()
-            .and_then(|name| PathBuf::from(name).extension())
-            .and_then(|ext| ext.to_str())
-            .unwrap_or("bin");
-        
-        let stored_filename = format!("{}_{}.{}", Utc::now().format("%Y%m%d_%H%M%S"), file_id, extension);
-        let stored_path = upload_dir.join(&stored_filename);
-
-        // Save file
-        fs::write(&stored_path, &data).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    // The row records the content key; the file only moves into the store once the row has committed.
+    // A concurrent upload of the same file to the same case loses with a conflict.
+    let evidence = state
+        .evidence
+        .create(
+            auth.user_id(),
+            NewEvidence {
+                case_id,
+                criminal_id,
+                title,
+                description,
+                evidence_type,
+                file_path: staged.as_ref().map(|upload| content_key(&upload.sha256)),
+                file_size: staged.as_ref().map(|upload| upload.size as i64),
+                file_type: mime_type,
+                hash_sha256: staged.as_ref().map(|upload| upload.sha256.clone()),
+                uploaded_by: auth.user_id(),
+                extracted_text,
+                chunk_anchors,
+            },
+        )
+        .await?;
 
-        file_path = Some(stored_path.to_string_lossy().to_string());
-        file_size = Some(data.len() as i64);
+    if let Some(upload) = staged {
+        // Identical content already in the store is reused. If storing fails the row is taken back
+        // out, releasing its blob reference, so the client can simply retry.
+        if let Err(e) = evidence_store::store_staged(state.evidence_store.as_ref(), upload).await {
+            tracing::error!("Failed to store file for evidence {}: {}", evidence.id, e);
+            if let Err(e) = state.evidence.delete(auth.user_id(), evidence.id).await {
+                tracing::error!("Failed to remove evidence {} after its file could not be stored: {}", evidence.id, e);
+            }
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
     }
 
-    // Insert evidence record
-    let evidence = query_as::<_, Evidence>(
-        r#"
-        INSERT INTO evidence (
-            case_id, criminal_id, title, description, evidence_type,
-            file_path, file_size, file_type, uploaded_by, created_at
-        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
-        RETURNING *
-        "#
-    )
-    .bind(case_id)
-    .bind(criminal_id)
-    .bind(&title)
-    .bind(&description)
-    .bind(&evidence_type)
-    .bind(&file_path)
-    .bind(file_size)
-    .bind(&file_type)
-    .bind(user_id)
-    .bind(Utc::now())
-    .fetch_one(&state.db)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if let Some(embedder) = state.embedder.clone() {
+        vector_collections::spawn_index_evidence(state.db.clone(), state.qdrant.clone(), embedder, evidence.id);
+    }
+    extraction::spawn_extract_evidence(
+        state.evidence.clone(),
+        state.llm.clone(),
+        state.llm_permits.clone(),
+        auth.user_id(),
+        evidence.id,
+    );
 
     Ok(Json(evidence.into()))
 }
 
 pub async fn get_evidence(
     Extension(state): Extension<AppState>,
-    Extension(user_id): Extension<Uuid>,
+    user: CurrentUser,
     Path(evidence_id): Path<i32>,
 ) -> Result<Json<EvidenceResponse>, StatusCode> {
-    let evidence = query_as::<_, Evidence>(
-        "SELECT * FROM evidence WHERE id = $1"
+    let evidence = state.evidence.get(evidence_id).await?.ok_or(StatusCode::NOT_FOUND)?;
+
+    if let Some(case_id) = evidence.case_id {
+        ensure_case_access(state.cases.as_ref(), &user, case_id).await?;
+    }
+
+    Ok(Json(evidence.into()))
+}
+
+/// Characters of extracted text handed to the model for a summary, to stay inside small context windows
+const SUMMARY_INPUT_CHARS: usize = 16_000;
+
+/// Summarize an evidence item's extracted text as server-sent events: `token` events as the model
+/// writes, then `done` with the usage, or `error`. Closing the connection stops the generation.
+pub async fn stream_evidence_summary(
+    Extension(state): Extension<AppState>,
+    user: CurrentUser,
+    Path(evidence_id): Path<i32>,
+) -> Result<Sse<impl Stream<Item = Result<Event, axum::Error>>>, StatusCode> {
+    let evidence = state.evidence.get(evidence_id).await?.ok_or(StatusCode::NOT_FOUND)?;
+    if let Some(case_id) = evidence.case_id {
+        ensure_case_access(state.cases.as_ref(), &user, case_id).await?;
+    }
+
+    let text = state.evidence.extracted_text(evidence_id).await?;
+    let Some(text) = text.or(evidence.description).filter(|text| !text.trim().is_empty()) else {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    };
+    let content: String = text.chars().take(SUMMARY_INPUT_CHARS).collect();
+
+    // Held until the stream ends or the client goes away; the rule-based fallback needs no slot
+    let permit = match state.llm {
+        Some(_) => Some(state.llm_permits.clone().acquire_owned().await.map_err(|_| StatusCode::SERVICE_UNAVAILABLE)?),
+        None => None,
+    };
+    let events = LLMService::new(state.llm.clone()).stream_summary(&content);
+    let stream = ReceiverStream::new(events).map(move |event| {
+        let _permit = &permit;
+        let name = match &event {
+            StreamEvent::Token { .. } => "token",
+            StreamEvent::Done(_) => "done",
+            StreamEvent::Error { .. } => "error",
+        };
+        Event::default().event(name).json_data(&event)
+    });
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// Re-run structured extraction on an evidence item now and return the stored result
+pub async fn extract_evidence(
+    Extension(state): Extension<AppState>,
+    auth: Authorized<CanEditEvidence>,
+    Path(evidence_id): Path<i32>,
+) -> Result<Json<Extraction>, StatusCode> {
+    let evidence = state.evidence.get(evidence_id).await?.ok_or(StatusCode::NOT_FOUND)?;
+    if let Some(case_id) = evidence.case_id {
+        ensure_case_access(state.cases.as_ref(), &auth.user, case_id).await?;
+    }
+
+    let extraction = extraction::extract_evidence(
+        state.evidence.as_ref(),
+        state.llm.clone(),
+        &state.llm_permits,
+        auth.user_id(),
+        evidence_id,
+    )
+        .await
+        .map_err(|e| {
+            tracing::error!("Extraction failed for evidence {}: {}", evidence_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?
+        .ok_or(StatusCode::UNPROCESSABLE_ENTITY)?;
+
+    Ok(Json(extraction))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EvidenceContentQuery {
+    #[serde(default)]
+    pub watermark: bool,
+    #[serde(default)]
+    pub download: bool,
+}
+
+fn store_error_status(evidence_id: i32, e: StoreError) -> StatusCode {
+    match e {
+        StoreError::NotFound(_) => {
+            tracing::error!("Stored file for evidence {} is missing", evidence_id);
+            StatusCode::NOT_FOUND
+        }
+        e => {
+            tracing::error!("Failed to read stored file for evidence {}: {}", evidence_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// Stream an evidence file, honouring single `Range` requests. `?watermark=true` serves a
+/// discovery copy of a PDF or image stamped with the requesting user and the time.
+pub async fn get_evidence_content(
+    Extension(state): Extension<AppState>,
+    user: CurrentUser,
+    Path(evidence_id): Path<i32>,
+    Query(params): Query<EvidenceContentQuery>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let evidence = state.evidence.get(evidence_id).await?.ok_or(StatusCode::NOT_FOUND)?;
+
+    if let Some(case_id) = evidence.case_id {
+        ensure_case_access(state.cases.as_ref(), &user, case_id).await?;
+    }
+
+    let storage_key = evidence.file_path.clone().ok_or(StatusCode::NOT_FOUND)?;
+    let content_type = content_type_for(&evidence);
+    let range_header = headers.get(header::RANGE).and_then(|value| value.to_str().ok());
+    let if_range = headers.get(header::IF_RANGE).and_then(|value| value.to_str().ok());
+    let user_agent = headers.get(header::USER_AGENT).and_then(|value| value.to_str().ok());
+
+    // Discovery copies are rendered per request, so there is no stable validator to range against
+    let marked = if params.watermark {
+        let kind = WatermarkKind::for_content_type(&content_type).ok_or(StatusCode::UNSUPPORTED_MEDIA_TYPE)?;
+        if evidence.file_size.unwrap_or_default() as usize > state.config.max_file_size {
+            return Err(StatusCode::PAYLOAD_TOO_LARGE);
+        }
+
+        let mut object = state
+            .evidence_store
+            .get(&storage_key, None)
+            .await
+            .map_err(|e| store_error_status(evidence_id, e))?;
+        let mut original = Vec::with_capacity(object.total_size as usize);
+        object.reader.read_to_end(&mut original).await.map_err(|e| {
+            tracing::error!("Failed to read stored file for evidence {}: {}", evidence_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+        let recipient = state
+            .users
+            .get(user.user_id)
+            .await?
+            .map(|recipient| recipient.email)
+            .unwrap_or_else(|| user.user_id.to_string());
+        let text = watermark::watermark_text(evidence.id, &recipient, Utc::now());
+
+        let marked = tokio::task::spawn_blocking(move || watermark::apply(kind, &original, &text))
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+            .map_err(|e| match e {
+                WatermarkError::Unsupported(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                WatermarkError::Malformed(e) => {
+                    tracing::warn!("Could not watermark evidence {}: {}", evidence_id, e);
+                    StatusCode::UNPROCESSABLE_ENTITY
+                }
+            })?;
+        Some(marked)
+    } else {
+        None
+    };
+
+    let etag = match (&marked, &evidence.hash_sha256) {
+        (None, Some(hash)) => Some(format!("\"{}\"", hash)),
+        _ => None,
+    };
+    let total_size = match (&marked, evidence.file_size) {
+        (Some(bytes), _) => bytes.len() as u64,
+        (None, Some(size)) => size as u64,
+        (None, None) => state
+            .evidence_store
+            .get(&storage_key, None)
+            .await
+            .map_err(|e| store_error_status(evidence_id, e))?
+            .total_size,
+    };
+
+    // A stale If-Range means the client's partial copy is of something else: send it all
+    let range_applies = match if_range {
+        None => true,
+        Some(validator) => etag.as_deref() == Some(validator),
+    };
+    let range = match if range_applies { parse_range(range_header, total_size) } else { RangeRequest::Full } {
+        RangeRequest::Full => None,
+        RangeRequest::Partial(range) => Some(range),
+        RangeRequest::Unsatisfiable => {
+            return Response::builder()
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{}", total_size))
+                .body(Body::empty())
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+    let served = range.map_or(total_size, |range| range.end - range.start);
+
+    download::record_access(
+        &state.db,
+        AccessEvent {
+            evidence: &evidence,
+            user_id: user.user_id,
+            range,
+            bytes_served: served,
+            watermarked: marked.is_some(),
+            user_agent,
+        },
     )
-    .bind(evidence_id)
-    .fetch_optional(&state.db)
     .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-    .ok_or(StatusCode::NOT_FOUND)?;
+    .map_err(|e| {
+        tracing::error!("Failed to record access to evidence {}: {}", evidence_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
 
-    Ok(Json(evidence.into()))
+    let body = match marked.as_deref() {
+        Some(bytes) => {
+            let slice = range.map_or(bytes, |range| &bytes[range.start as usize..range.end as usize]);
+            Body::from(slice.to_vec())
+        }
+        None => {
+            let object = state
+                .evidence_store
+                .get(&storage_key, range)
+                .await
+                .map_err(|e| store_error_status(evidence_id, e))?;
+            Body::from_stream(ReaderStream::new(object.reader))
+        }
+    };
+
+    let mut response = Response::builder()
+        .status(if range.is_some() { StatusCode::PARTIAL_CONTENT } else { StatusCode::OK })
+        .header(header::CONTENT_TYPE, content_type.as_ref())
+        .header(header::CONTENT_LENGTH, served)
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::CONTENT_DISPOSITION, content_disposition(&evidence, &content_type, params.download, marked.is_some()))
+        .header(header::CACHE_CONTROL, "private, no-store")
+        .header(header::X_CONTENT_TYPE_OPTIONS, "nosniff");
+    if let Some(range) = range {
+        response = response.header(header::CONTENT_RANGE, format!("bytes {}-{}/{}", range.start, range.end - 1, total_size));
+    }
+    if let Some(etag) = &etag {
+        response = response.header(header::ETAG, etag);
+    }
+
+    response.body(body).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+/// Download history for an evidence item, newest first
+pub async fn get_evidence_access_log(
+    Extension(state): Extension<AppState>,
+    user: CurrentUser,
+    Path(evidence_id): Path<i32>,
+) -> Result<Json<Vec<EvidenceAccessEvent>>, StatusCode> {
+    let evidence = state.evidence.get(evidence_id).await?.ok_or(StatusCode::NOT_FOUND)?;
+
+    if let Some(case_id) = evidence.case_id {
+        ensure_case_access(state.cases.as_ref(), &user, case_id).await?;
+    }
+
+    let events = download::access_log(&state.db, evidence_id).await.map_err(|e| {
+        tracing::error!("Failed to load access log for evidence {}: {}", evidence_id, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(events))
 }
 
 pub async fn delete_evidence(
     Extension(state): Extension<AppState>,
-    Extension(user_id): Extension<Uuid>,
+    auth: Authorized<CanDeleteEvidence>,
     Path(evidence_id): Path<i32>,
 ) -> Result<Json<()>, StatusCode> {
-    // Get evidence to check if file needs to be deleted
-    let evidence = query_as::<_, Evidence>(
-        "SELECT * FROM evidence WHERE id = $1"
-    )
-    .bind(evidence_id)
-    .fetch_optional(&state.db)
-    .await
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-    .ok_or(StatusCode::NOT_FOUND)?;
+    let evidence = state.evidence.get(evidence_id).await?.ok_or(StatusCode::NOT_FOUND)?;
 
-    // Delete from database
-    let result = query("DELETE FROM evidence WHERE id = $1")
-        .bind(evidence_id)
-        .execute(&state.db)
-        .await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    if let Some(case_id) = evidence.case_id {
+        ensure_case_access(state.cases.as_ref(), &auth.user, case_id).await?;
+    }
+
+    // Deletes the row, releases its file reference and records the removal in the audit log
+    let evidence = state.evidence.delete(auth.user_id(), evidence_id).await?;
+    let stored_hash = evidence
+        .file_path
+        .as_deref()
+        .and_then(parse_content_key)
+        .map(str::to_string);
 
-    if result.rows_affected() == 0 {
-        return Err(StatusCode::NOT_FOUND);
+    // Object removal is best effort: an orphaned object is harmless, a failed delete is logged
+    match (stored_hash, evidence.file_path) {
+        (Some(hash), _) => {
+            if let Err(e) = evidence_store::collect_blob(&state.db, state.evidence_store.as_ref(), &hash).await {
+                tracing::warn!("Failed to remove stored object {}: {}", hash, e);
+            }
+        }
+        // Uploaded before content addressing; the file belongs to this row alone
+        (None, Some(file_path)) => {
+            if let Err(e) = state.evidence_store.delete(&file_path).await {
+                tracing::warn!("Failed to delete file {}: {}", file_path, e);
+            }
+        }
+        (None, None) => {}
     }
 
-    // Delete file if it exists
-    if let Some(file_path) = evidence.file_path {
-        let _ = fs::remove_file(file_path); // Ignore errors for file deletion
+    if let Err(e) = vector_collections::remove_evidence(&state.db, &state.qdrant, evidence_id).await {
+        tracing::warn!("Failed to remove evidence {} from vector search: {}", evidence_id, e);
     }
 
     Ok(Json(()))
 }
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyEvidenceQuery {
+    pub case_id: Option<i32>,
+}
+
+/// Re-hash stored evidence files and report any that are missing or modified.
+pub async fn verify_evidence_integrity(
+    Extension(state): Extension<AppState>,
+    user: CurrentUser,
+    Query(query): Query<VerifyEvidenceQuery>,
+) -> Result<Json<IntegrityReport>, StatusCode> {
+    // A single case follows case visibility; a full sweep is for supervisors
+    match query.case_id {
+        Some(case_id) => ensure_case_access(state.cases.as_ref(), &user, case_id).await?,
+        None if !user.can(Permission::ViewAllCases) => return Err(StatusCode::FORBIDDEN),
+        None => {}
+    }
+
+    let report = verify_evidence(&state.db, state.evidence_store.as_ref(), query.case_id)
+        .await
+        .map_err(|e| {
+            tracing::error!("Evidence integrity check failed: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    Ok(Json(report))
+}
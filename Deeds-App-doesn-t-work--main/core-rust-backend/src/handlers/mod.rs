@@ -1,5 +1,9 @@
+pub mod audit;
 pub mod auth;
 pub mod cases;
+pub mod custody;
 pub mod evidence;
 pub mod embeddings;
 pub mod health;
+pub mod search;
+pub mod uploads;
@@ -0,0 +1,76 @@
+use axum::{
+    extract::{Extension, Query},
+    http::StatusCode,
+    response::Json,
+};
+use serde::Deserialize;
+
+use crate::{
+    permissions::{ensure_case_access, CurrentUser},
+    hybrid::{self, HybridResults, HybridSearchError, HybridSearchRequest},
+    search::{self, SearchKind, SearchRequest, SearchResults},
+    AppState,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    q: String,
+    kind: Option<SearchKind>,
+    case_id: Option<i32>,
+    limit: Option<u32>,
+    offset: Option<u32>,
+}
+
+pub async fn search(
+    Extension(state): Extension<AppState>,
+    user: CurrentUser,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<SearchResults>, StatusCode> {
+    if query.q.trim().is_empty() || query.q.len() > 1000 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if let Some(case_id) = query.case_id {
+        ensure_case_access(state.cases.as_ref(), &user, case_id).await?;
+    }
+
+    let request = SearchRequest {
+        query: query.q,
+        kind: query.kind,
+        case_id: query.case_id,
+        limit: query.limit.unwrap_or(20).clamp(1, 100) as i64,
+        offset: query.offset.unwrap_or(0) as i64,
+    };
+    let results = search::search(&state.db, &user, &request).await.map_err(|e| {
+        tracing::error!("Search failed: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(results))
+}
+
+/// Full-text and vector retrieval fused with reciprocal rank fusion. Each hit lists the
+/// retrievers that found it; `retrievers` says which ones ran.
+pub async fn hybrid_search(
+    Extension(state): Extension<AppState>,
+    user: CurrentUser,
+    Json(request): Json<HybridSearchRequest>,
+) -> Result<Json<HybridResults>, StatusCode> {
+    if request.query.trim().is_empty() || request.query.len() > 1000 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if let Some(case_id) = request.case_id {
+        ensure_case_access(state.cases.as_ref(), &user, case_id).await?;
+    }
+
+    let results = hybrid::hybrid_search(&state.db, &state.qdrant, state.embedder.as_deref(), &user, request)
+        .await
+        .map_err(|e| match e {
+            HybridSearchError::Invalid(_) => StatusCode::BAD_REQUEST,
+            HybridSearchError::Other(e) => {
+                tracing::error!("Hybrid search failed: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?;
+
+    Ok(Json(results))
+}
@@ -0,0 +1,151 @@
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Extension, Path},
+    http::{HeaderMap, StatusCode},
+    response::Json,
+};
+use uuid::Uuid;
+
+use crate::{
+    extraction,
+    models::{CreateUploadRequest, EvidenceResponse, UploadSessionResponse},
+    permissions::{ensure_case_access, Authorized, CanUploadEvidence, CurrentUser},
+    resumable::{self, ResumableError, UploadSettings},
+    upload::UploadError,
+    vector_collections,
+    AppState,
+};
+
+/// Optional per-chunk digest a client can send so corruption in transit is caught immediately
+const CHUNK_SHA256_HEADER: &str = "x-chunk-sha256";
+
+fn resumable_status(e: ResumableError) -> StatusCode {
+    match e {
+        ResumableError::NotFound => StatusCode::NOT_FOUND,
+        ResumableError::Invalid(reason) => {
+            tracing::warn!("Rejected resumable upload request: {}", reason);
+            StatusCode::BAD_REQUEST
+        }
+        ResumableError::Closed(_) => StatusCode::GONE,
+        ResumableError::Busy | ResumableError::Incomplete(_) | ResumableError::Duplicate(_) => StatusCode::CONFLICT,
+        ResumableError::ChecksumMismatch { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+        ResumableError::Upload(UploadError::TooLarge(_)) => StatusCode::PAYLOAD_TOO_LARGE,
+        ResumableError::Upload(UploadError::Malformed(_)) => StatusCode::BAD_REQUEST,
+        ResumableError::Upload(UploadError::Io(e)) => {
+            tracing::error!("Resumable upload I/O error: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+        ResumableError::Internal(e) => {
+            tracing::error!("Resumable upload failed: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+pub async fn create_upload(
+    Extension(state): Extension<AppState>,
+    auth: Authorized<CanUploadEvidence>,
+    Json(payload): Json<CreateUploadRequest>,
+) -> Result<Json<UploadSessionResponse>, StatusCode> {
+    if let Some(case_id) = payload.case_id {
+        ensure_case_access(state.cases.as_ref(), &auth.user, case_id).await?;
+    }
+
+    let settings = UploadSettings::from_config(&state.config);
+    let session = resumable::create_session(&state.db, &settings, auth.user_id(), payload)
+        .await
+        .map_err(resumable_status)?;
+
+    Ok(Json(resumable::session_response(&session, &[])))
+}
+
+pub async fn get_upload(
+    Extension(state): Extension<AppState>,
+    user: CurrentUser,
+    Path(session_id): Path<Uuid>,
+) -> Result<Json<UploadSessionResponse>, StatusCode> {
+    let session = resumable::load_session(&state.db, session_id, user.user_id)
+        .await
+        .map_err(resumable_status)?;
+    let chunks = resumable::load_chunks(&state.db, session_id)
+        .await
+        .map_err(resumable_status)?;
+
+    Ok(Json(resumable::session_response(&session, &chunks)))
+}
+
+pub async fn put_chunk(
+    Extension(state): Extension<AppState>,
+    auth: Authorized<CanUploadEvidence>,
+    Path((session_id, index)): Path<(Uuid, u32)>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Json<UploadSessionResponse>, StatusCode> {
+    let settings = UploadSettings::from_config(&state.config);
+    let session = resumable::load_session(&state.db, session_id, auth.user_id())
+        .await
+        .map_err(resumable_status)?;
+
+    // Chunks are bounded by the session's chunk size, so buffering one is fine
+    let data = to_bytes(body, session.chunk_size as usize)
+        .await
+        .map_err(|_| StatusCode::BAD_REQUEST)?;
+    let claimed_sha256 = headers
+        .get(CHUNK_SHA256_HEADER)
+        .and_then(|value| value.to_str().ok());
+
+    resumable::write_chunk(&state.db, &settings, &session, index, &data, claimed_sha256)
+        .await
+        .map_err(resumable_status)?;
+
+    let chunks = resumable::load_chunks(&state.db, session_id)
+        .await
+        .map_err(resumable_status)?;
+
+    Ok(Json(resumable::session_response(&session, &chunks)))
+}
+
+pub async fn finalize_upload(
+    Extension(state): Extension<AppState>,
+    auth: Authorized<CanUploadEvidence>,
+    Path(session_id): Path<Uuid>,
+) -> Result<Json<EvidenceResponse>, StatusCode> {
+    let settings = UploadSettings::from_config(&state.config);
+    let evidence = resumable::finalize(
+        &state.db,
+        state.evidence.as_ref(),
+        &settings,
+        &state.file_processor,
+        state.evidence_store.as_ref(),
+        session_id,
+        &auth.user,
+    )
+    .await
+    .map_err(resumable_status)?;
+
+    if let Some(embedder) = state.embedder.clone() {
+        vector_collections::spawn_index_evidence(state.db.clone(), state.qdrant.clone(), embedder, evidence.id);
+    }
+    extraction::spawn_extract_evidence(
+        state.evidence.clone(),
+        state.llm.clone(),
+        state.llm_permits.clone(),
+        evidence.uploaded_by,
+        evidence.id,
+    );
+
+    Ok(Json(evidence.into()))
+}
+
+pub async fn abort_upload(
+    Extension(state): Extension<AppState>,
+    user: CurrentUser,
+    Path(session_id): Path<Uuid>,
+) -> Result<Json<()>, StatusCode> {
+    let settings = UploadSettings::from_config(&state.config);
+    resumable::abort(&state.db, &settings, session_id, user.user_id)
+        .await
+        .map_err(resumable_status)?;
+
+    Ok(Json(()))
+}
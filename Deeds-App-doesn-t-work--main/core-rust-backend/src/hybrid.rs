@@ -0,0 +1,471 @@
+// Hybrid search: full-text and vector retrieval fused with reciprocal rank fusion
+// Each retriever produces its own ranked candidate list; a hit scores the weighted sum of
+// 1 / (RRF_K + rank) over the lists it appears in. Ranks, not raw scores, are combined, so
+// ts_rank and cosine similarity never have to be put on the same scale. Every hit records the
+// retrievers that found it and what each contributed. Evidence is indexed by chunk, so a vector
+// hit also says which chunk matched and on what page.
+
+use axum::async_trait;
+use serde::{Deserialize, Serialize};
+use sqlx::{query_as, FromRow};
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    chunker::ChunkAnchor,
+    database::DbConnection,
+    embedder::{embed_one, EmbeddingProvider},
+    permissions::{case_visibility_clause, CurrentUser},
+    search::{self, render_snippet, SearchKind, SearchRequest},
+};
+
+/// The usual RRF constant: dampens the gap between the top few ranks
+pub const RRF_K: f32 = 60.0;
+
+// Candidates taken from each retriever per requested hit, and the cap on that
+const CANDIDATES_PER_HIT: i64 = 4;
+const MAX_CANDIDATES: i64 = 200;
+
+/// One indexed point close to the query: a chunk of an evidence row's text, or the row itself
+#[derive(Debug, Clone)]
+pub struct Neighbour {
+    pub evidence_id: i32,
+    pub similarity: f32,
+    pub anchor: Option<ChunkAnchor>,
+}
+
+/// Nearest-neighbour lookup over indexed evidence
+#[async_trait]
+pub trait VectorIndex: Send + Sync {
+    /// Points closest to `vector`, best first, optionally only those of one case. One evidence row
+    /// may appear more than once.
+    async fn nearest_evidence(&self, vector: Vec<f32>, limit: u64, case_id: Option<i32>) -> anyhow::Result<Vec<Neighbour>>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Retriever {
+    Lexical,
+    Vector,
+}
+
+/// One retriever's candidates, best first, with the retriever's own scores
+#[derive(Debug, Clone)]
+pub struct RankedList {
+    pub retriever: Retriever,
+    pub weight: f32,
+    pub hits: Vec<(SearchKind, i32, f32)>,
+}
+
+/// How one retriever ranked a hit
+#[derive(Debug, Clone, Serialize)]
+pub struct Contribution {
+    pub retriever: Retriever,
+    /// 1-based position in that retriever's list
+    pub rank: usize,
+    /// The retriever's own score (ts_rank_cd or cosine similarity)
+    pub retriever_score: f32,
+    /// What this retriever added to the fused score
+    pub contribution: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct FusedHit {
+    pub kind: SearchKind,
+    pub id: i32,
+    pub score: f32,
+    pub sources: Vec<Contribution>,
+}
+
+/// Reciprocal rank fusion of the lists, best first. Lists with a zero weight are ignored.
+pub fn fuse(lists: &[RankedList]) -> Vec<FusedHit> {
+    let mut fused: HashMap<(SearchKind, i32), FusedHit> = HashMap::new();
+    for list in lists.iter().filter(|list| list.weight > 0.0) {
+        for (position, &(kind, id, retriever_score)) in list.hits.iter().enumerate() {
+            let rank = position + 1;
+            let contribution = list.weight / (RRF_K + rank as f32);
+            let hit = fused.entry((kind, id)).or_insert_with(|| FusedHit { kind, id, score: 0.0, sources: Vec::new() });
+            // A retriever listing the same hit twice only counts its best rank
+            if hit.sources.iter().any(|source| source.retriever == list.retriever) {
+                continue;
+            }
+            hit.score += contribution;
+            hit.sources.push(Contribution { retriever: list.retriever, rank, retriever_score, contribution });
+        }
+    }
+
+    let best_rank = |hit: &FusedHit| hit.sources.iter().map(|source| source.rank).min().unwrap_or(usize::MAX);
+    let mut hits: Vec<FusedHit> = fused.into_values().collect();
+    hits.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| best_rank(a).cmp(&best_rank(b)))
+            .then_with(|| (a.kind.as_str(), a.id).cmp(&(b.kind.as_str(), b.id)))
+    });
+    hits
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct HybridSearchRequest {
+    pub query: String,
+    /// Embedding of `query` from the same model as the index; when absent the query is embedded
+    /// with the configured provider, and without one only full-text runs
+    pub query_embedding: Option<Vec<f32>>,
+    /// Relative weight of each retriever, default 1. A weight of 0 turns a retriever off.
+    pub lexical_weight: Option<f32>,
+    pub vector_weight: Option<f32>,
+    pub kind: Option<SearchKind>,
+    pub case_id: Option<i32>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RetrieverStatus {
+    Ok,
+    Skipped,
+    Failed,
+}
+
+/// What each retriever did for this query, so a missing contribution can be told apart from a
+/// retriever that did not run
+#[derive(Debug, Clone, Serialize)]
+pub struct RetrieverReport {
+    pub retriever: Retriever,
+    pub weight: f32,
+    pub status: RetrieverStatus,
+    pub candidates: usize,
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HybridHit {
+    pub kind: SearchKind,
+    pub id: i32,
+    pub case_id: Option<i32>,
+    pub title: String,
+    /// HTML-escaped; full-text hits have their matches wrapped in `<mark>`
+    pub snippet: String,
+    /// The chunk of evidence text the vector retriever matched, with its page
+    pub anchor: Option<ChunkAnchor>,
+    pub score: f32,
+    pub sources: Vec<Contribution>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HybridResults {
+    pub hits: Vec<HybridHit>,
+    pub retrievers: Vec<RetrieverReport>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum HybridSearchError {
+    #[error("invalid request: {0}")]
+    Invalid(&'static str),
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+struct HitDetails {
+    case_id: Option<i32>,
+    title: String,
+    snippet: String,
+    anchor: Option<ChunkAnchor>,
+}
+
+#[derive(FromRow)]
+struct VisibleEvidence {
+    id: i32,
+    case_id: Option<i32>,
+    title: String,
+    excerpt: String,
+}
+
+fn weight(value: Option<f32>) -> Result<f32, HybridSearchError> {
+    match value.unwrap_or(1.0) {
+        weight if weight.is_finite() && weight >= 0.0 => Ok(weight),
+        _ => Err(HybridSearchError::Invalid("weights must be finite and not negative")),
+    }
+}
+
+/// Run the retrievers side by side and fuse their results. Vector hits are checked against case
+/// visibility here, since the index itself knows nothing about users. A failing vector index
+/// degrades to full-text results and is reported as `failed`.
+pub async fn hybrid_search(
+    db: &DbConnection,
+    index: &dyn VectorIndex,
+    embedder: Option<&dyn EmbeddingProvider>,
+    viewer: &CurrentUser,
+    request: HybridSearchRequest,
+) -> Result<HybridResults, HybridSearchError> {
+    let lexical_weight = weight(request.lexical_weight)?;
+    let vector_weight = weight(request.vector_weight)?;
+    if lexical_weight == 0.0 && vector_weight == 0.0 {
+        return Err(HybridSearchError::Invalid("at least one retriever needs a positive weight"));
+    }
+
+    let limit = request.limit.unwrap_or(20).clamp(1, 100) as usize;
+    let candidates = (limit as i64 * CANDIDATES_PER_HIT).min(MAX_CANDIDATES);
+
+    // The vector index only holds evidence
+    let embedding = request.query_embedding.as_ref().filter(|embedding| !embedding.is_empty());
+    let vector_skip_reason = if vector_weight == 0.0 {
+        Some("weight is 0")
+    } else if request.kind == Some(SearchKind::Case) {
+        Some("only evidence is embedded")
+    } else if embedding.is_none() && embedder.is_none() {
+        Some("no query embedding was supplied and no embedding provider is configured")
+    } else {
+        None
+    };
+
+    let lexical = async {
+        if lexical_weight == 0.0 {
+            return None;
+        }
+        let search_request = SearchRequest {
+            query: request.query.clone(),
+            kind: request.kind,
+            case_id: request.case_id,
+            limit: candidates,
+            offset: 0,
+        };
+        Some(search::search(db, viewer, &search_request).await)
+    };
+    let vector = async {
+        if vector_skip_reason.is_some() {
+            return None;
+        }
+        let embedding = match (embedding, embedder) {
+            (Some(embedding), _) => embedding.clone(),
+            (None, Some(embedder)) => match embed_one(embedder, &request.query).await {
+                Ok(embedding) => embedding,
+                Err(e) => return Some(Err(("the query could not be embedded", e))),
+            },
+            (None, None) => return None,
+        };
+        let hits = visible_vector_hits(db, index, viewer, embedding, candidates, request.case_id).await;
+        Some(hits.map_err(|e| ("the vector index could not be queried", e)))
+    };
+    let (lexical, vector) = tokio::join!(lexical, vector);
+
+    let mut lists = Vec::new();
+    let mut reports = Vec::new();
+    let mut details: HashMap<(SearchKind, i32), HitDetails> = HashMap::new();
+
+    match lexical {
+        Some(Ok(results)) => {
+            reports.push(RetrieverReport {
+                retriever: Retriever::Lexical,
+                weight: lexical_weight,
+                status: RetrieverStatus::Ok,
+                candidates: results.hits.len(),
+                detail: None,
+            });
+            let mut hits = Vec::with_capacity(results.hits.len());
+            for hit in results.hits {
+                hits.push((hit.kind, hit.id, hit.rank));
+                details.insert(
+                    (hit.kind, hit.id),
+                    HitDetails { case_id: hit.case_id, title: hit.title, snippet: hit.snippet, anchor: None },
+                );
+            }
+            lists.push(RankedList { retriever: Retriever::Lexical, weight: lexical_weight, hits });
+        }
+        // Full-text search runs against our own database; if it fails, so would everything else
+        Some(Err(e)) => return Err(e.into()),
+        None => reports.push(RetrieverReport {
+            retriever: Retriever::Lexical,
+            weight: lexical_weight,
+            status: RetrieverStatus::Skipped,
+            candidates: 0,
+            detail: Some("weight is 0".to_string()),
+        }),
+    }
+
+    match vector {
+        Some(Ok(visible)) => {
+            reports.push(RetrieverReport {
+                retriever: Retriever::Vector,
+                weight: vector_weight,
+                status: RetrieverStatus::Ok,
+                candidates: visible.len(),
+                detail: None,
+            });
+            let mut hits = Vec::with_capacity(visible.len());
+            for (evidence, neighbour) in visible {
+                hits.push((SearchKind::Evidence, evidence.id, neighbour.similarity));
+                // A full-text snippet is kept if there is one; the page comes from the vector match
+                let details = details.entry((SearchKind::Evidence, evidence.id)).or_insert(HitDetails {
+                    case_id: evidence.case_id,
+                    title: evidence.title,
+                    snippet: render_snippet(&evidence.excerpt),
+                    anchor: None,
+                });
+                details.anchor = neighbour.anchor;
+            }
+            lists.push(RankedList { retriever: Retriever::Vector, weight: vector_weight, hits });
+        }
+        Some(Err((detail, e))) => {
+            tracing::warn!("Vector retrieval failed, returning full-text results only: {}", e);
+            reports.push(RetrieverReport {
+                retriever: Retriever::Vector,
+                weight: vector_weight,
+                status: RetrieverStatus::Failed,
+                candidates: 0,
+                detail: Some(detail.to_string()),
+            });
+        }
+        None => reports.push(RetrieverReport {
+            retriever: Retriever::Vector,
+            weight: vector_weight,
+            status: RetrieverStatus::Skipped,
+            candidates: 0,
+            detail: vector_skip_reason.map(String::from),
+        }),
+    }
+
+    let hits = fuse(&lists)
+        .into_iter()
+        .take(limit)
+        .filter_map(|hit| {
+            let details = details.remove(&(hit.kind, hit.id))?;
+            Some(HybridHit {
+                kind: hit.kind,
+                id: hit.id,
+                case_id: details.case_id,
+                title: details.title,
+                snippet: details.snippet,
+                anchor: details.anchor,
+                score: hit.score,
+                sources: hit.sources,
+            })
+        })
+        .collect();
+
+    Ok(HybridResults { hits, retrievers: reports })
+}
+
+/// Nearest evidence the viewer may see, in index order, with an excerpt of the matching chunk (or
+/// of the description when the row itself matched) for display
+async fn visible_vector_hits(
+    db: &DbConnection,
+    index: &dyn VectorIndex,
+    viewer: &CurrentUser,
+    embedding: Vec<f32>,
+    candidates: i64,
+    case_id: Option<i32>,
+) -> anyhow::Result<Vec<(VisibleEvidence, Neighbour)>> {
+    // Over-fetch: some neighbours will be hidden from the viewer or outside the requested case, and
+    // several chunks of one row can match
+    let nearest = index.nearest_evidence(embedding, (candidates * 4) as u64, case_id).await?;
+
+    // Each row ranks by its best chunk, which the index lists first
+    let mut seen = HashSet::new();
+    let nearest: Vec<Neighbour> = nearest.into_iter().filter(|neighbour| seen.insert(neighbour.evidence_id)).collect();
+    let ids: Vec<i32> = nearest.iter().map(|neighbour| neighbour.evidence_id).collect();
+    let starts: Vec<Option<i32>> = nearest.iter().map(|neighbour| neighbour.anchor.map(|anchor| anchor.start as i32)).collect();
+    let lengths: Vec<Option<i32>> = nearest
+        .iter()
+        .map(|neighbour| neighbour.anchor.map(|anchor| anchor.end.saturating_sub(anchor.start) as i32))
+        .collect();
+
+    let sql = format!(
+        r#"
+        SELECT e.id, e.case_id, e.title,
+            CASE WHEN n.chunk_start IS NULL
+                THEN left(coalesce(nullif(e.description, ''), e.extracted_text, ''), 240)
+                ELSE coalesce(substr(e.extracted_text, n.chunk_start + 1, least(n.chunk_length, 240)), '')
+            END AS excerpt
+        FROM unnest($1::INTEGER[], $4::INTEGER[], $5::INTEGER[]) AS n(id, chunk_start, chunk_length)
+        JOIN evidence e ON e.id = n.id
+        LEFT JOIN cases c ON c.id = e.case_id
+        WHERE ($3::INTEGER IS NULL OR e.case_id = $3)
+          AND (e.case_id IS NULL OR (c.archived = false AND {}))
+        "#,
+        case_visibility_clause(viewer, 2)
+    );
+    let mut visible: HashMap<i32, VisibleEvidence> = query_as::<_, VisibleEvidence>(&sql)
+        .bind(&ids)
+        .bind(viewer.user_id)
+        .bind(case_id)
+        .bind(&starts)
+        .bind(&lengths)
+        .fetch_all(db.as_ref())
+        .await?
+        .into_iter()
+        .map(|evidence| (evidence.id, evidence))
+        .collect();
+
+    Ok(nearest
+        .into_iter()
+        .filter_map(|neighbour| visible.remove(&neighbour.evidence_id).map(|evidence| (evidence, neighbour)))
+        .take(candidates as usize)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(retriever: Retriever, weight: f32, ids: &[i32]) -> RankedList {
+        RankedList {
+            retriever,
+            weight,
+            hits: ids.iter().map(|&id| (SearchKind::Evidence, id, 0.5)).collect(),
+        }
+    }
+
+    fn order(hits: &[FusedHit]) -> Vec<i32> {
+        hits.iter().map(|hit| hit.id).collect()
+    }
+
+    #[test]
+    fn test_fusion_rewards_agreement() {
+        let hits = fuse(&[
+            list(Retriever::Lexical, 1.0, &[1, 2, 3]),
+            list(Retriever::Vector, 1.0, &[3, 4, 1]),
+        ]);
+        // 1 and 3 are found by both retrievers; 1 ranks better overall
+        assert_eq!(order(&hits), vec![1, 3, 2, 4]);
+
+        let first = &hits[0];
+        assert_eq!(first.sources.len(), 2);
+        assert_eq!((first.sources[0].retriever, first.sources[0].rank), (Retriever::Lexical, 1));
+        assert_eq!((first.sources[1].retriever, first.sources[1].rank), (Retriever::Vector, 3));
+        let expected = 1.0 / (RRF_K + 1.0) + 1.0 / (RRF_K + 3.0);
+        assert!((first.score - expected).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_fusion_weights() {
+        let lists = |lexical, vector| [list(Retriever::Lexical, lexical, &[1, 2]), list(Retriever::Vector, vector, &[2, 1])];
+        // Symmetric lists tie on score; the better single rank (lexical's first) wins the tie
+        assert_eq!(order(&fuse(&lists(1.0, 1.0))), vec![1, 2]);
+        assert_eq!(order(&fuse(&lists(1.0, 3.0))), vec![2, 1]);
+
+        // A zero weight removes the retriever from scores and explanations
+        let hits = fuse(&lists(0.0, 1.0));
+        assert_eq!(order(&hits), vec![2, 1]);
+        assert!(hits.iter().all(|hit| hit.sources.iter().all(|source| source.retriever == Retriever::Vector)));
+    }
+
+    #[test]
+    fn test_fusion_keeps_kinds_apart() {
+        let lexical = RankedList {
+            retriever: Retriever::Lexical,
+            weight: 1.0,
+            hits: vec![(SearchKind::Case, 7, 0.3), (SearchKind::Evidence, 7, 0.2), (SearchKind::Evidence, 7, 0.1)],
+        };
+        let hits = fuse(&[lexical]);
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].kind, SearchKind::Case);
+        assert_eq!(hits[1].sources[0].rank, 2);
+    }
+
+    #[test]
+    fn test_weights_are_validated() {
+        assert_eq!(weight(None).unwrap(), 1.0);
+        assert!(weight(Some(-1.0)).is_err());
+        assert!(weight(Some(f32::NAN)).is_err());
+    }
+}
@@ -0,0 +1,206 @@
+// Evidence integrity checks for prosecutor-core
+// Uploads store the SHA-256 of the file as received. The sweep re-hashes every stored object
+// through the evidence store and reports evidence whose file has disappeared or no longer matches its recorded digest.
+
+use anyhow::Result;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{sync::Arc, time::Duration};
+
+use crate::{database::DbConnection, evidence_store::EvidenceStore, utils::to_hex};
+
+/// Incremental SHA-256 over an upload as its chunks arrive
+#[derive(Default)]
+pub struct UploadHasher {
+    hasher: Sha256,
+    bytes: u64,
+}
+
+impl UploadHasher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, chunk: &[u8]) {
+        self.hasher.update(chunk);
+        self.bytes += chunk.len() as u64;
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.bytes
+    }
+
+    pub fn finish(self) -> String {
+        to_hex(&self.hasher.finalize())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IntegrityStatus {
+    Ok,
+    Missing,
+    Modified,
+    Unhashed, // Uploaded before digests were recorded
+}
+
+#[derive(Debug, Serialize)]
+pub struct EvidenceIntegrity {
+    pub evidence_id: i32,
+    pub case_id: Option<i32>,
+    pub file_path: String,
+    pub expected_hash: Option<String>,
+    pub actual_hash: Option<String>,
+    pub status: IntegrityStatus,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct IntegrityReport {
+    pub checked: u64,
+    pub ok: u64,
+    pub missing: u64,
+    pub modified: u64,
+    pub unhashed: u64,
+    /// Every item whose status is not `ok`
+    pub problems: Vec<EvidenceIntegrity>,
+}
+
+impl IntegrityReport {
+    fn add(&mut self, item: EvidenceIntegrity) {
+        self.checked += 1;
+        match item.status {
+            IntegrityStatus::Ok => {
+                self.ok += 1;
+                return;
+            }
+            IntegrityStatus::Missing => self.missing += 1,
+            IntegrityStatus::Modified => self.modified += 1,
+            IntegrityStatus::Unhashed => self.unhashed += 1,
+        }
+        self.problems.push(item);
+    }
+}
+
+/// Compare a stored digest with the file's current digest (`None` when the file can't be read)
+pub fn classify(expected: Option<&str>, actual: Option<&str>) -> IntegrityStatus {
+    match (expected, actual) {
+        (_, None) => IntegrityStatus::Missing,
+        (None, Some(_)) => IntegrityStatus::Unhashed,
+        (Some(expected), Some(actual)) if expected.eq_ignore_ascii_case(actual) => IntegrityStatus::Ok,
+        (Some(_), Some(_)) => IntegrityStatus::Modified,
+    }
+}
+
+/// Re-hash every stored evidence file (optionally for one case) and report what changed.
+pub async fn verify_evidence(
+    db: &DbConnection,
+    store: &dyn EvidenceStore,
+    case_id: Option<i32>,
+) -> Result<IntegrityReport> {
+    const BATCH: i64 = 500;
+    let mut report = IntegrityReport::default();
+    let mut last_id = 0i32;
+
+    loop {
+        let batch: Vec<(i32, Option<i32>, String, Option<String>)> = sqlx::query_as(
+            r#"
+            SELECT id, case_id, file_path, hash_sha256 FROM evidence
+            WHERE file_path IS NOT NULL AND id > $1
+              AND ($2::INTEGER IS NULL OR case_id = $2)
+            ORDER BY id
+            LIMIT $3
+            "#
+        )
+        .bind(last_id)
+        .bind(case_id)
+        .bind(BATCH)
+        .fetch_all(db.as_ref())
+        .await?;
+
+        let Some(last) = batch.last() else {
+            break;
+        };
+        last_id = last.0;
+
+        for (evidence_id, case_id, file_path, expected_hash) in batch {
+            let actual_hash = match store.sha256(&file_path).await {
+                Ok(hash) => hash,
+                Err(e) => {
+                    tracing::warn!("Could not read evidence {} from the store: {}", evidence_id, e);
+                    None
+                }
+            };
+            let status = classify(expected_hash.as_deref(), actual_hash.as_deref());
+            if status != IntegrityStatus::Ok {
+                tracing::warn!("Evidence {} failed integrity check: {:?} ({})", evidence_id, status, file_path);
+            }
+            report.add(EvidenceIntegrity {
+                evidence_id,
+                case_id,
+                file_path,
+                expected_hash,
+                actual_hash,
+                status,
+            });
+        }
+    }
+
+    Ok(report)
+}
+
+/// Run the sweep in the background every `interval`, logging a summary of each pass.
+pub fn spawn_integrity_sweep(
+    db: DbConnection,
+    store: Arc<dyn EvidenceStore>,
+    interval: Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.tick().await; // The first tick fires immediately; wait a full interval after startup
+        loop {
+            ticker.tick().await;
+            match verify_evidence(&db, store.as_ref(), None).await {
+                Ok(report) if report.problems.is_empty() => {
+                    tracing::info!("Evidence integrity sweep: {} files verified", report.checked);
+                }
+                Ok(report) => tracing::error!(
+                    "Evidence integrity sweep: {} of {} files failed ({} missing, {} modified, {} unhashed)",
+                    report.problems.len(),
+                    report.checked,
+                    report.missing,
+                    report.modified,
+                    report.unhashed
+                ),
+                Err(e) => tracing::error!("Evidence integrity sweep failed: {}", e),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::sha256_file;
+
+    #[test]
+    fn test_upload_hasher_matches_file_hash() {
+        let path = std::env::temp_dir().join(format!("integrity-{}.bin", uuid::Uuid::new_v4()));
+        std::fs::write(&path, b"exhibit A contents").unwrap();
+
+        let mut hasher = UploadHasher::new();
+        hasher.update(b"exhibit A ");
+        hasher.update(b"contents");
+        assert_eq!(hasher.bytes(), 18);
+        assert_eq!(hasher.finish(), sha256_file(path.to_str().unwrap()).unwrap());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(classify(Some("ab"), Some("AB")), IntegrityStatus::Ok);
+        assert_eq!(classify(Some("ab"), Some("cd")), IntegrityStatus::Modified);
+        assert_eq!(classify(Some("ab"), None), IntegrityStatus::Missing);
+        assert_eq!(classify(None, Some("cd")), IntegrityStatus::Unhashed);
+    }
+}
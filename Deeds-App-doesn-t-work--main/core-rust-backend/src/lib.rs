@@ -2,32 +2,82 @@
 // This library provides the core functionality for the prosecutor case management system
 // and can be used by web (Vercel), desktop (Tauri), and mobile (Flutter) applications.
 
+pub mod audit;
 pub mod auth_simple;
+pub mod chunker;
 pub mod config;
+pub mod custody;
 pub mod database;
+pub mod download;
+pub mod embedder;
+pub mod embeddings;
+#[cfg(feature = "encryption")]
+pub mod encryption;
+pub mod evidence_store;
+pub mod extraction;
+pub mod file_processor;
 pub mod handlers;
+pub mod hybrid;
+pub mod integrity;
+pub mod llm_backend;
+pub mod llm_stream;
 pub mod middleware;
+pub mod migrations;
 pub mod models;
+pub mod password;
+pub mod permissions;
+pub mod rag;
+pub mod repository;
+pub mod resumable;
+pub mod search;
+pub mod upload;
 pub mod utils;
+pub mod vector_collections;
+pub mod watermark;
 
 // AI modules
+pub mod llm;
 pub mod qdrant;
 
 // Re-export commonly used types
 pub use auth_simple::*;
 pub use config::Config;
 pub use database::*;
+// `models::evidence` and `handlers::evidence` share a name; both stay reachable by path
+#[allow(ambiguous_glob_reexports)]
 pub use models::*;
 
-use database::DbConnection;
+use embedder::EmbeddingProvider;
+use evidence_store::EvidenceStore;
+use file_processor::FileProcessor;
+use llm_backend::LlmBackend;
 use qdrant::QdrantClient;
+use repository::{AuditRepository, CaseRepository, EvidenceRepository, UserRepository};
+use std::sync::Arc;
+use tokio::sync::Semaphore;
 
 /// Application state that can be shared across different deployment targets
 #[derive(Clone)]
 pub struct AppState {
     pub config: Config,
     pub db: DbConnection,
+    pub cases: Arc<dyn CaseRepository>,
+    pub evidence: Arc<dyn EvidenceRepository>,
+    pub users: Arc<dyn UserRepository>,
+    pub audit: Arc<dyn AuditRepository>,
     pub qdrant: QdrantClient,
+    pub embedder: Option<Arc<dyn EmbeddingProvider>>,
+    /// Whether `embedder`'s vectors fit the pgvector column, so text queries can search it
+    pub pgvector_search: bool,
+    pub llm: Option<Arc<dyn LlmBackend>>,
+    /// Slots for background extraction and summary generations on `llm`
+    pub llm_permits: Arc<Semaphore>,
+    pub file_processor: FileProcessor,
+    pub evidence_store: Arc<dyn EvidenceStore>,
+    pub signing_keys: SigningKeys,
+    /// Long-lived keys for custody signatures, independent of token key rotation
+    pub custody_keys: SigningKeys,
+    pub revoked_tokens: RevocationList,
 }
 
 impl AppState {
@@ -36,19 +86,87 @@ impl AppState {
         
         let db = database::create_connection(&config.database_url).await?;
         
-        // Initialize schema
-        database::init_schema(&db).await?;
+        // Bring the schema up to this build's version, or refuse to start if it cannot be used
+        if config.auto_migrate {
+            let applied = migrations::run(&db).await?;
+            if !applied.is_empty() {
+                tracing::info!("Applied migrations: {:?}", applied);
+            }
+        } else {
+            migrations::ensure_current(&db).await?;
+        }
         
         // Test connection
         database::test_connection(&db).await?;
         
-        // Initialize Qdrant
-        let qdrant = qdrant::QdrantClient::new(&config.qdrant_url, "prosecutor_cases").await?;
+        // Data access shared by the HTTP handlers and the app bindings
+        let cases: Arc<dyn CaseRepository> = Arc::new(repository::PgCaseRepository::new(db.clone()));
+        let evidence: Arc<dyn EvidenceRepository> = Arc::new(repository::PgEvidenceRepository::new(db.clone()));
+        let users: Arc<dyn UserRepository> = Arc::new(repository::PgUserRepository::new(db.clone()));
+        let audit: Arc<dyn AuditRepository> = Arc::new(repository::PgAuditRepository::new(db.clone()));
+
+        // Token signing keys and the server-side revocation list
+        let signing_keys = SigningKeys::from_config(&config);
+        let custody_keys = SigningKeys::custody_from_config(&config);
+        if config.custody_signing_key.is_none() {
+            tracing::warn!("CUSTODY_SIGNING_KEY is not set; custody records are signed with the JWT key");
+        }
+        let revoked_tokens = RevocationList::load(&db).await?;
+        
+        // Text embeddings. Qdrant collections are sized per model; the pgvector column has a fixed
+        // size, so a provider of another size only loses text queries against it.
+        let embedder = embedder::from_config(&config).await?;
+        let mut pgvector_search = false;
+        if let Some(embedder) = &embedder {
+            match embeddings::vector_dimensions(&db).await {
+                Ok(column) => match embedder::check_dimensions(embedder.as_ref(), column, "embeddings.embedding_vector") {
+                    Ok(()) => pgvector_search = true,
+                    Err(e) => tracing::warn!("pgvector text search disabled: {}", e),
+                },
+                Err(e) => tracing::warn!("Embedding search unavailable: {}", e),
+            }
+        }
+        
+        // Initialize Qdrant, and finish (or start) filling a collection for the current model
+        let qdrant = qdrant::QdrantClient::new(&config.qdrant_url, config.qdrant_api_key.clone(), &config.qdrant_collection).await?;
+        let build = vector_collections::bootstrap(&db, &qdrant, embedder.as_deref()).await?;
+        if let (Some(collection), Some(embedder)) = (build, &embedder) {
+            vector_collections::spawn_reembed(db.clone(), qdrant.clone(), embedder.clone(), collection);
+        }
+
+        // Language model for case Q&A
+        let llm = llm_backend::from_config(&config)?;
+        let llm_permits = Arc::new(Semaphore::new(config.llm_max_concurrency));
+
+        // Text/metadata extraction for uploaded files; resumable uploads may exceed max_file_size
+        let file_processor = FileProcessor::new(
+            config.upload_dir.clone(),
+            false,
+            true,
+            config.max_file_size.max(config.max_resumable_upload_size as usize),
+        );
+
+        // Evidence files: local sharded directory or an S3-compatible bucket, optionally encrypted
+        let evidence_store = evidence_store::from_config(&config, &db)?;
+        tracing::info!("Evidence store: {}", evidence_store.name());
         
         Ok(Self {
             config,
             db,
+            cases,
+            evidence,
+            users,
+            audit,
             qdrant,
+            embedder,
+            pgvector_search,
+            llm,
+            llm_permits,
+            file_processor,
+            evidence_store,
+            signing_keys,
+            custody_keys,
+            revoked_tokens,
         })
     }
 }
@@ -1,214 +1,74 @@
-use anyhow::{anyhow, Result};
-use candle_core::{DType, Device, Tensor};
-use candle_nn::VarBuilder;
-use candle_transformers::models::llama::{Llama, LlamaConfig};
-use std::path::Path;
-use tokenizers::Tokenizer;
-use tracing::{debug, info, warn};
+// Evidence tags and summaries from the configured LLM backend
+// `LLMService` asks whichever `LlmBackend` is configured (see llm_backend.rs). Without one it falls
+// back to keyword tags and a truncated summary, so callers never need to check first.
 
-#[derive(Clone)]
-pub struct LocalLLM {
-    model: Llama,
-    tokenizer: Tokenizer,
-    device: Device,
-    config: LlamaConfig,
-}
-
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
-pub struct LLMRequest {
-    pub prompt: String,
-    pub max_tokens: Option<usize>,
-    pub temperature: Option<f32>,
-    pub system_prompt: Option<String>,
-}
-
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
-pub struct LLMResponse {
-    pub text: String,
-    pub tokens_used: usize,
-    pub processing_time_ms: u64,
-}
+use anyhow::Result;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tracing::info;
 
-impl LocalLLM {
-    pub async fn new(model_path: &str) -> Result<Self> {
-        info!("Loading local LLM from: {}", model_path);
-        
-        let device = Device::Cpu; // Use CPU for compatibility, can add GPU support later
-        
-        // Load tokenizer (you'll need to download this with the model)
-        let tokenizer_path = format!("{}/tokenizer.json", Path::new(model_path).parent().unwrap().display());
-        let tokenizer = Tokenizer::from_file(&tokenizer_path)
-            .map_err(|e| anyhow!("Failed to load tokenizer: {}", e))?;
-
-        // Load model config
-        let config_path = format!("{}/config.json", Path::new(model_path).parent().unwrap().display());
-        let config: LlamaConfig = serde_json::from_str(
-            &std::fs::read_to_string(&config_path)
-                .map_err(|e| anyhow!("Failed to read config: {}", e))?
-        )?;
-
-        // Load model weights
-        let model = Self::load_model(model_path, &config, &device).await?;
-
-        info!("Local LLM loaded successfully");
-        
-        Ok(Self {
-            model,
-            tokenizer,
-            device,
-            config,
-        })
-    }
+use crate::{
+    extraction::{self, Extraction},
+    llm_backend::{GenerationRequest, LlmBackend},
+    llm_stream::{self, StreamEvent},
+};
 
-    async fn load_model(model_path: &str, config: &LlamaConfig, device: &Device) -> Result<Llama> {
-        // This is a simplified version - you'll need to implement proper GGUF loading
-        // For now, this creates a placeholder that can be extended
-        warn!("Model loading is a placeholder - implement GGUF loading for production");
-        
-        // In a real implementation, you'd load the GGUF file here
-        // and convert it to candle tensors
-        let dummy_weights = std::collections::HashMap::new();
-        let var_builder = VarBuilder::from_tensors(dummy_weights, DType::F32, device);
-        
-        Llama::load(&var_builder, config)
-            .map_err(|e| anyhow!("Failed to load model: {}", e))
-    }
+const ANALYSIS_SYSTEM_PROMPT: &str =
+    "You are a legal document analysis AI supporting prosecutor case management. Be precise and concise.";
 
-    pub async fn generate(&self, request: LLMRequest) -> Result<LLMResponse> {
-        let start_time = std::time::Instant::now();
-        
-        debug!("Processing LLM request: {}", request.prompt);
-
-        // Build the full prompt with system message
-        let full_prompt = if let Some(system) = &request.system_prompt {
-            format!("System: {}\n\nUser: {}\n\nAssistant:", system, request.prompt)
-        } else {
-            format!("User: {}\n\nAssistant:", request.prompt)
-        };
-
-        // Tokenize input
-        let encoding = self.tokenizer.encode(&full_prompt, true)
-            .map_err(|e| anyhow!("Tokenization failed: {}", e))?;
-        
-        let input_tokens = encoding.get_ids();
-        debug!("Input tokens: {}", input_tokens.len());
-
-        // For now, return a placeholder response
-        // In production, you'd run inference through the model here
-        let response_text = self.mock_inference(&request).await?;
-        
-        let processing_time = start_time.elapsed();
-        
-        Ok(LLMResponse {
-            text: response_text,
-            tokens_used: input_tokens.len() + 50, // Mock token count
-            processing_time_ms: processing_time.as_millis() as u64,
-        })
-    }
+#[derive(Clone)]
+pub struct LLMService {
+    pub backend: Option<Arc<dyn LlmBackend>>,
+}
 
-    async fn mock_inference(&self, request: &LLMRequest) -> Result<String> {
-        // This is a placeholder for actual model inference
-        // Replace with real candle-based inference
-        
-        if request.prompt.contains("tag") || request.prompt.contains("category") {
-            Ok("evidence, document, legal, criminal-case, high-priority".to_string())
-        } else if request.prompt.contains("summary") {
-            Ok("This document contains legal evidence related to a criminal investigation. Key points include witness statements, physical evidence documentation, and case procedural notes.".to_string())
-        } else {
-            Ok("I'm a local AI assistant helping with case management and evidence analysis.".to_string())
+impl LLMService {
+    pub fn new(backend: Option<Arc<dyn LlmBackend>>) -> Self {
+        match &backend {
+            Some(backend) => info!("LLM service using {}", backend.name()),
+            None => info!("LLM service disabled; using rule-based tags and summaries"),
         }
+        Self { backend }
     }
 
-    pub async fn generate_tags(&self, content: &str) -> Result<Vec<String>> {
-        let request = LLMRequest {
-            prompt: format!(
-                "Analyze this legal document and generate relevant tags for categorization:\n\n{}\n\nProvide only comma-separated tags:",
-                content
-            ),
-            max_tokens: Some(50),
-            temperature: Some(0.3),
-            system_prompt: Some("You are a legal document analysis AI. Generate precise, relevant tags for evidence categorization.".to_string()),
-        };
-
-        let response = self.generate(request).await?;
-        
-        let tags: Vec<String> = response.text
-            .split(',')
-            .map(|tag| tag.trim().to_lowercase())
-            .filter(|tag| !tag.is_empty())
-            .collect();
-
-        Ok(tags)
+    pub async fn is_available(&self) -> bool {
+        self.backend.is_some()
     }
 
-    pub async fn summarize_content(&self, content: &str) -> Result<String> {
-        let request = LLMRequest {
-            prompt: format!(
-                "Provide a concise summary of this legal document:\n\n{}\n\nSummary:",
-                content
-            ),
-            max_tokens: Some(200),
-            temperature: Some(0.5),
-            system_prompt: Some("You are a legal document analysis AI. Provide clear, concise summaries for prosecutor case management.".to_string()),
-        };
-
-        let response = self.generate(request).await?;
-        Ok(response.text)
+    /// Tags from the structured extraction (see extraction.rs)
+    pub async fn process_for_tags(&self, content: &str) -> Result<Vec<String>> {
+        Ok(self.extract(content).await.fields.tags)
     }
-}
-
-#[derive(Clone)]
-pub struct LLMService {
-    pub llm: Option<LocalLLM>,
-    pub enabled: bool,
-}
 
-impl LLMService {
-    pub async fn new(model_path: Option<String>, enabled: bool) -> Result<Self> {
-        let llm = if enabled && model_path.is_some() {
-            match LocalLLM::new(&model_path.unwrap()).await {
-                Ok(llm) => {
-                    info!("Local LLM service initialized successfully");
-                    Some(llm)
-                }
-                Err(e) => {
-                    warn!("Failed to initialize LLM, running without AI features: {}", e);
-                    None
-                }
-            }
-        } else {
-            info!("LLM service disabled");
-            None
-        };
-
-        Ok(Self { llm, enabled })
+    /// Tags, charges, persons, dates and locations, validated against the extraction schema
+    pub async fn extract(&self, content: &str) -> Extraction {
+        extraction::extract(self.backend.as_deref(), content).await
     }
 
-    pub async fn is_available(&self) -> bool {
-        self.enabled && self.llm.is_some()
+    pub async fn process_for_summary(&self, content: &str) -> Result<String> {
+        let Some(backend) = &self.backend else { return Ok(Self::create_basic_summary(content)) };
+        Ok(backend.generate(&Self::summary_request(content)).await?.text.trim().to_string())
     }
 
-    pub async fn process_for_tags(&self, content: &str) -> Result<Vec<String>> {
-        if let Some(llm) = &self.llm {
-            llm.generate_tags(content).await
-        } else {
-            // Fallback to basic keyword extraction
-            Ok(Self::extract_basic_tags(content))
+    /// The summary as it is generated; without a backend, the basic summary in one piece
+    pub fn stream_summary(&self, content: &str) -> mpsc::Receiver<StreamEvent> {
+        match &self.backend {
+            Some(backend) => llm_stream::stream_generation(backend.clone(), Self::summary_request(content)),
+            None => llm_stream::completed(Self::create_basic_summary(content), "rule-based"),
         }
     }
 
-    pub async fn process_for_summary(&self, content: &str) -> Result<String> {
-        if let Some(llm) = &self.llm {
-            llm.summarize_content(content).await
-        } else {
-            // Fallback to truncated content
-            Ok(Self::create_basic_summary(content))
+    fn summary_request(content: &str) -> GenerationRequest {
+        GenerationRequest {
+            system: Some(ANALYSIS_SYSTEM_PROMPT.to_string()),
+            prompt: format!("Provide a concise summary of this legal document:\n\n{}\n\nSummary:", content),
+            max_tokens: Some(200),
+            temperature: Some(0.5),
         }
     }
 
-    fn extract_basic_tags(content: &str) -> Vec<String> {
+    pub fn extract_basic_tags(content: &str) -> Vec<String> {
         let legal_keywords = [
-            "evidence", "witness", "statement", "testimony", "document", 
+            "evidence", "witness", "statement", "testimony", "document",
             "case", "criminal", "civil", "court", "trial", "hearing",
             "investigation", "police", "detective", "forensic", "DNA",
             "fingerprint", "weapon", "drug", "theft", "assault", "murder"
@@ -217,12 +77,12 @@ impl LLMService {
         let content_lower = content.to_lowercase();
         legal_keywords
             .iter()
-            .filter(|&&keyword| content_lower.contains(keyword))
+            .filter(|&&keyword| content_lower.contains(&keyword.to_lowercase()))
             .map(|&keyword| keyword.to_string())
             .collect()
     }
 
-    fn create_basic_summary(content: &str) -> String {
+    pub fn create_basic_summary(content: &str) -> String {
         let words: Vec<&str> = content.split_whitespace().collect();
         if words.len() > 50 {
             format!("{}...", words[..50].join(" "))
@@ -231,3 +91,21 @@ impl LLMService {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm_backend::MockLlmBackend;
+
+    #[tokio::test]
+    async fn test_tags_from_backend_or_keywords() {
+        let reply = r#"{"tags": ["Witness", "van"], "charges": ["arson"], "persons": [], "dates": [], "locations": []}"#;
+        let service = LLMService::new(Some(Arc::new(MockLlmBackend::with_reply(reply))));
+        assert_eq!(service.process_for_tags("anything").await.unwrap(), vec!["witness", "van"]);
+
+        let fallback = LLMService::new(None);
+        assert!(!fallback.is_available().await);
+        assert_eq!(fallback.process_for_tags("Police found DNA on the weapon").await.unwrap(), vec!["police", "dna", "weapon"]);
+        assert_eq!(fallback.process_for_summary("short text").await.unwrap(), "short text");
+    }
+}
@@ -0,0 +1,577 @@
+// Text generation backends for prosecutor-core
+// Everything that needs a language model (case Q&A, summaries, tagging, the desktop commands) goes
+// through `LlmBackend` instead of a particular runtime. The HTTP backend speaks the OpenAI
+// `/chat/completions` API, which the llama.cpp server, Ollama, vLLM and OpenAI itself all serve;
+// pointed at a llama.cpp server it also uses that server's own `/tokenize` and `/props`. The mock
+// backend is deterministic and needs nothing running, for tests and development.
+//
+// Streaming hands each piece of text to an mpsc channel as it arrives. Dropping the receiver
+// cancels the generation: the backend stops reading and returns what it has so far.
+
+use anyhow::{anyhow, Result};
+use axum::async_trait;
+use serde::{Deserialize, Serialize};
+use std::{sync::Arc, time::Duration};
+use tokio::sync::mpsc;
+
+use crate::config::Config;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GenerationRequest {
+    pub system: Option<String>,
+    pub prompt: String,
+    pub max_tokens: Option<u32>,
+    pub temperature: Option<f32>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub prompt_tokens: u32,
+    pub completion_tokens: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Generation {
+    pub text: String,
+    /// The model that answered, as the backend reports it
+    pub model: String,
+    pub usage: TokenUsage,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ModelInfo {
+    pub backend: String,
+    pub model: String,
+    /// Tokens of prompt and completion the model can hold, when the server says
+    pub context_length: Option<u32>,
+}
+
+#[async_trait]
+pub trait LlmBackend: Send + Sync {
+    /// Backend and model, for logs and responses (e.g. `llamacpp:mistral-7b-instruct`)
+    fn name(&self) -> String;
+
+    async fn model_info(&self) -> Result<ModelInfo>;
+
+    /// The model's token ids for `text`
+    async fn tokenize(&self, text: &str) -> Result<Vec<u32>>;
+
+    /// Complete one prompt
+    async fn generate(&self, request: &GenerationRequest) -> Result<Generation>;
+
+    /// Complete one prompt, sending the text to `pieces` as it is generated. The returned
+    /// generation holds all of it. When the receiver goes away generation stops early.
+    async fn stream(&self, request: &GenerationRequest, pieces: mpsc::Sender<String>) -> Result<Generation>;
+}
+
+/// The configured backend, or `None` when LLM_BACKEND is `none`
+pub fn from_config(config: &Config) -> Result<Option<Arc<dyn LlmBackend>>> {
+    let backend: Arc<dyn LlmBackend> = match config.llm_backend.as_str() {
+        "none" => return Ok(None),
+        "openai" | "llamacpp" => {
+            let url = config
+                .llm_url
+                .clone()
+                .ok_or_else(|| anyhow!("LLM_URL is required when LLM_BACKEND={}", config.llm_backend))?;
+            let flavor = if config.llm_backend == "llamacpp" { HttpFlavor::LlamaCpp } else { HttpFlavor::OpenAi };
+            Arc::new(HttpLlmBackend::new(HttpLlmConfig {
+                url,
+                model: config.llm_model.clone(),
+                api_key: config.llm_api_key.clone(),
+                flavor,
+            })?)
+        }
+        "mock" => Arc::new(MockLlmBackend::new()),
+        other => {
+            return Err(anyhow!("Unknown LLM_BACKEND '{}' (expected 'none', 'openai', 'llamacpp' or 'mock')", other))
+        }
+    };
+    tracing::info!("LLM backend: {}", backend.name());
+    Ok(Some(backend))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HttpFlavor {
+    /// Only the OpenAI API: no tokenizer endpoint, and model info from `/models`
+    OpenAi,
+    /// A llama.cpp server, which adds `/tokenize` and `/props` next to its OpenAI API
+    LlamaCpp,
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpLlmConfig {
+    /// Base URL of the OpenAI API, e.g. `http://localhost:8080/v1` for the llama.cpp server
+    pub url: String,
+    pub model: String,
+    pub api_key: Option<String>,
+    pub flavor: HttpFlavor,
+}
+
+#[derive(Serialize)]
+struct ChatMessage<'a> {
+    role: &'a str,
+    content: &'a str,
+}
+
+#[derive(Serialize)]
+struct ChatRequest<'a> {
+    model: &'a str,
+    messages: Vec<ChatMessage<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    stream: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stream_options: Option<StreamOptions>,
+}
+
+#[derive(Serialize)]
+struct StreamOptions {
+    include_usage: bool,
+}
+
+#[derive(Deserialize)]
+struct ChatResponse {
+    model: Option<String>,
+    choices: Vec<ChatChoice>,
+    usage: Option<TokenUsage>,
+}
+
+#[derive(Deserialize)]
+struct ChatChoice {
+    message: ChatChoiceMessage,
+}
+
+#[derive(Deserialize)]
+struct ChatChoiceMessage {
+    content: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChatChunk {
+    model: Option<String>,
+    #[serde(default)]
+    choices: Vec<ChatChunkChoice>,
+    usage: Option<TokenUsage>,
+}
+
+#[derive(Deserialize)]
+struct ChatChunkChoice {
+    delta: ChatChoiceMessage,
+}
+
+#[derive(Deserialize)]
+struct TokenizeResponse {
+    tokens: Vec<u32>,
+}
+
+#[derive(Deserialize)]
+struct ModelList {
+    data: Vec<ModelEntry>,
+}
+
+#[derive(Deserialize)]
+struct ModelEntry {
+    id: String,
+}
+
+/// Longest a stream may go without a byte before it is abandoned; covers prompt processing before the first token
+const STREAM_IDLE_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// An OpenAI-compatible chat completions server
+pub struct HttpLlmBackend {
+    http: reqwest::Client,
+    /// Without a total timeout: a stream may run as long as it keeps producing
+    streaming: reqwest::Client,
+    config: HttpLlmConfig,
+}
+
+impl HttpLlmBackend {
+    pub fn new(config: HttpLlmConfig) -> Result<Self> {
+        // Local models on CPU can take minutes over a long prompt
+        let http = reqwest::Client::builder().timeout(Duration::from_secs(300)).build()?;
+        let streaming = reqwest::Client::builder().connect_timeout(Duration::from_secs(10)).build()?;
+        Ok(Self { http, streaming, config })
+    }
+
+    fn api_url(&self, path: &str) -> String {
+        format!("{}{}", self.config.url.trim_end_matches('/'), path)
+    }
+
+    /// llama.cpp serves its own endpoints at the root, beside `/v1`
+    fn server_url(&self, path: &str) -> String {
+        let base = self.config.url.trim_end_matches('/');
+        format!("{}{}", base.strip_suffix("/v1").unwrap_or(base), path)
+    }
+
+    fn request(&self, method: reqwest::Method, url: &str) -> reqwest::RequestBuilder {
+        self.authorize(self.http.request(method, url))
+    }
+
+    fn authorize(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.config.api_key {
+            Some(api_key) => request.bearer_auth(api_key),
+            None => request,
+        }
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder, url: &str) -> Result<reqwest::Response> {
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(anyhow!("LLM request to {} failed with {}: {}", url, status, body.trim()));
+        }
+        Ok(response)
+    }
+
+    fn chat_request<'a>(&'a self, request: &'a GenerationRequest, stream: bool) -> ChatRequest<'a> {
+        let mut messages = Vec::with_capacity(2);
+        if let Some(system) = &request.system {
+            messages.push(ChatMessage { role: "system", content: system });
+        }
+        messages.push(ChatMessage { role: "user", content: &request.prompt });
+        ChatRequest {
+            model: &self.config.model,
+            messages,
+            max_tokens: request.max_tokens,
+            temperature: request.temperature,
+            stream,
+            stream_options: stream.then_some(StreamOptions { include_usage: true }),
+        }
+    }
+}
+
+#[async_trait]
+impl LlmBackend for HttpLlmBackend {
+    fn name(&self) -> String {
+        match self.config.flavor {
+            HttpFlavor::OpenAi => format!("openai:{}", self.config.model),
+            HttpFlavor::LlamaCpp => format!("llamacpp:{}", self.config.model),
+        }
+    }
+
+    async fn model_info(&self) -> Result<ModelInfo> {
+        match self.config.flavor {
+            HttpFlavor::OpenAi => {
+                let url = self.api_url("/models");
+                let models: ModelList = self.send(self.request(reqwest::Method::GET, &url), &url).await?.json().await?;
+                if !models.data.iter().any(|model| model.id == self.config.model) {
+                    return Err(anyhow!("{} does not serve model '{}'", self.config.url, self.config.model));
+                }
+                Ok(ModelInfo { backend: self.name(), model: self.config.model.clone(), context_length: None })
+            }
+            HttpFlavor::LlamaCpp => {
+                let url = self.server_url("/props");
+                let props: serde_json::Value = self.send(self.request(reqwest::Method::GET, &url), &url).await?.json().await?;
+                // The server runs whatever file it was started with; the configured name is only a label
+                let model = props["model_path"]
+                    .as_str()
+                    .and_then(|path| std::path::Path::new(path).file_name())
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| self.config.model.clone());
+                let context_length = props["default_generation_settings"]["n_ctx"].as_u64().map(|n| n as u32);
+                Ok(ModelInfo { backend: self.name(), model, context_length })
+            }
+        }
+    }
+
+    async fn tokenize(&self, text: &str) -> Result<Vec<u32>> {
+        if self.config.flavor == HttpFlavor::OpenAi {
+            return Err(anyhow!("{} has no tokenizer endpoint", self.name()));
+        }
+        let url = self.server_url("/tokenize");
+        let request = self.request(reqwest::Method::POST, &url).json(&serde_json::json!({ "content": text }));
+        let response: TokenizeResponse = self.send(request, &url).await?.json().await?;
+        Ok(response.tokens)
+    }
+
+    async fn generate(&self, request: &GenerationRequest) -> Result<Generation> {
+        let url = self.api_url("/chat/completions");
+        let http_request = self.request(reqwest::Method::POST, &url).json(&self.chat_request(request, false));
+        let response: ChatResponse = self.send(http_request, &url).await?.json().await?;
+        let text = response
+            .choices
+            .into_iter()
+            .next()
+            .and_then(|choice| choice.message.content)
+            .ok_or_else(|| anyhow!("{} returned no completion", self.name()))?;
+        Ok(Generation {
+            text,
+            model: response.model.unwrap_or_else(|| self.config.model.clone()),
+            usage: response.usage.unwrap_or_default(),
+        })
+    }
+
+    async fn stream(&self, request: &GenerationRequest, pieces: mpsc::Sender<String>) -> Result<Generation> {
+        let url = self.api_url("/chat/completions");
+        let http_request = self.authorize(self.streaming.post(&url)).json(&self.chat_request(request, true));
+        let mut response = tokio::time::timeout(STREAM_IDLE_TIMEOUT, self.send(http_request, &url))
+            .await
+            .map_err(|_| anyhow!("{} did not answer within {:?}", self.name(), STREAM_IDLE_TIMEOUT))??;
+
+        let mut generation = Generation { text: String::new(), model: self.config.model.clone(), usage: TokenUsage::default() };
+        let mut chunks = 0;
+        let mut usage = None;
+        // Raw bytes: a network chunk may end inside a UTF-8 character, so only whole lines are decoded
+        let mut buffer: Vec<u8> = Vec::new();
+        // Server-sent events: `data: {chunk}` lines, ending with `data: [DONE]`
+        'read: loop {
+            let bytes = tokio::time::timeout(STREAM_IDLE_TIMEOUT, response.chunk())
+                .await
+                .map_err(|_| anyhow!("{} sent nothing for {:?}", self.name(), STREAM_IDLE_TIMEOUT))??;
+            let Some(bytes) = bytes else { break };
+            buffer.extend_from_slice(&bytes);
+            while let Some(newline) = buffer.iter().position(|&byte| byte == b'\n') {
+                let line: Vec<u8> = buffer.drain(..=newline).collect();
+                let line = std::str::from_utf8(&line)?.trim();
+                let Some(data) = line.strip_prefix("data:").map(str::trim) else { continue };
+                if data == "[DONE]" {
+                    break 'read;
+                }
+                let chunk: ChatChunk = serde_json::from_str(data)?;
+                if let Some(model) = chunk.model {
+                    generation.model = model;
+                }
+                usage = chunk.usage.or(usage);
+                let Some(piece) = chunk.choices.into_iter().next().and_then(|choice| choice.delta.content) else { continue };
+                if piece.is_empty() {
+                    continue;
+                }
+                chunks += 1;
+                generation.text.push_str(&piece);
+                if pieces.send(piece).await.is_err() {
+                    // Nobody is listening any more; dropping the response closes the connection
+                    break 'read;
+                }
+            }
+        }
+        // Servers that do not report usage on a stream send about one token per chunk
+        generation.usage = usage.unwrap_or(TokenUsage { prompt_tokens: 0, completion_tokens: chunks });
+        Ok(generation)
+    }
+}
+
+pub const DEFAULT_MOCK_REPLY: &str = "This is a mock completion from the prosecutor-core test backend.";
+
+/// Deterministic backend: replies with fixed text, a word per token, and tokenizes by words with
+/// ids taken from a hash of each word
+pub struct MockLlmBackend {
+    reply: String,
+}
+
+impl MockLlmBackend {
+    pub fn new() -> Self {
+        Self::with_reply(DEFAULT_MOCK_REPLY)
+    }
+
+    pub fn with_reply(reply: impl Into<String>) -> Self {
+        Self { reply: reply.into() }
+    }
+
+    /// The reply split into tokens, cut to `max_tokens`
+    fn pieces(&self, max_tokens: Option<u32>) -> Vec<&str> {
+        let pieces = self.reply.split_inclusive(' ');
+        pieces.take(max_tokens.map_or(usize::MAX, |max| max as usize)).collect()
+    }
+
+    fn token_id(word: &str) -> u32 {
+        // FNV-1a
+        word.bytes().fold(0x811c_9dc5, |hash: u32, byte| (hash ^ byte as u32).wrapping_mul(0x0100_0193))
+    }
+
+    fn usage(&self, request: &GenerationRequest, completion_tokens: usize) -> TokenUsage {
+        let system = request.system.as_deref().unwrap_or_default();
+        let prompt_tokens = system.split_whitespace().count() + request.prompt.split_whitespace().count();
+        TokenUsage { prompt_tokens: prompt_tokens as u32, completion_tokens: completion_tokens as u32 }
+    }
+}
+
+impl Default for MockLlmBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl LlmBackend for MockLlmBackend {
+    fn name(&self) -> String {
+        "mock".to_string()
+    }
+
+    async fn model_info(&self) -> Result<ModelInfo> {
+        Ok(ModelInfo { backend: self.name(), model: "mock".to_string(), context_length: Some(4096) })
+    }
+
+    async fn tokenize(&self, text: &str) -> Result<Vec<u32>> {
+        Ok(text.split_whitespace().map(Self::token_id).collect())
+    }
+
+    async fn generate(&self, request: &GenerationRequest) -> Result<Generation> {
+        let pieces = self.pieces(request.max_tokens);
+        Ok(Generation { text: pieces.concat(), model: "mock".to_string(), usage: self.usage(request, pieces.len()) })
+    }
+
+    async fn stream(&self, request: &GenerationRequest, sender: mpsc::Sender<String>) -> Result<Generation> {
+        let mut text = String::new();
+        let mut sent = 0;
+        for piece in self.pieces(request.max_tokens) {
+            if sender.send(piece.to_string()).await.is_err() {
+                break;
+            }
+            text.push_str(piece);
+            sent += 1;
+        }
+        Ok(Generation { text, model: "mock".to_string(), usage: self.usage(request, sent) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn http_backend(url: String, flavor: HttpFlavor) -> HttpLlmBackend {
+        HttpLlmBackend::new(HttpLlmConfig { url, model: "test-model".to_string(), api_key: Some("secret".to_string()), flavor })
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_http_backend_sends_chat_and_reads_usage() {
+        let mut server = mockito::Server::new_async().await;
+        let completion = server
+            .mock("POST", "/v1/chat/completions")
+            .match_header("authorization", "Bearer secret")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+                "model": "test-model",
+                "messages": [{"role": "system", "content": "Be brief."}, {"role": "user", "content": "Who?"}],
+                "max_tokens": 16,
+                "stream": false,
+            })))
+            .with_body(r#"{"model": "test-model-q4", "choices": [{"message": {"role": "assistant", "content": "The driver."}}], "usage": {"prompt_tokens": 9, "completion_tokens": 3}}"#)
+            .create_async()
+            .await;
+        server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({"messages": [{"role": "user", "content": "down"}]})))
+            .with_status(503)
+            .with_body("loading model")
+            .create_async()
+            .await;
+
+        let backend = http_backend(format!("{}/v1/", server.url()), HttpFlavor::OpenAi);
+        let request = GenerationRequest {
+            system: Some("Be brief.".to_string()),
+            prompt: "Who?".to_string(),
+            max_tokens: Some(16),
+            temperature: None,
+        };
+        let generation = backend.generate(&request).await.unwrap();
+        assert_eq!(generation.text, "The driver.");
+        assert_eq!(generation.model, "test-model-q4");
+        assert_eq!(generation.usage, TokenUsage { prompt_tokens: 9, completion_tokens: 3 });
+        completion.assert_async().await;
+
+        let down = GenerationRequest { prompt: "down".to_string(), ..Default::default() };
+        let error = backend.generate(&down).await.unwrap_err().to_string();
+        assert!(error.contains("503") && error.contains("loading model"), "{}", error);
+        assert!(backend.tokenize("no endpoint").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_http_backend_streams_server_sent_events() {
+        let mut server = mockito::Server::new_async().await;
+        let events = [
+            r#"data: {"model": "test-model", "choices": [{"delta": {"role": "assistant"}}]}"#,
+            r#"data: {"choices": [{"delta": {"content": "The "}}]}"#,
+            r#"data: {"choices": [{"delta": {"content": "driver."}}]}"#,
+            "data: [DONE]",
+        ];
+        server
+            .mock("POST", "/v1/chat/completions")
+            .match_body(mockito::Matcher::PartialJson(serde_json::json!({"stream": true, "stream_options": {"include_usage": true}})))
+            .with_header("content-type", "text/event-stream")
+            .with_body(events.join("\n\n") + "\n\n")
+            .create_async()
+            .await;
+
+        let backend = http_backend(format!("{}/v1", server.url()), HttpFlavor::OpenAi);
+        let (sender, mut receiver) = mpsc::channel(8);
+        let request = GenerationRequest { prompt: "Who?".to_string(), ..Default::default() };
+        let generation = backend.stream(&request, sender).await.unwrap();
+
+        let mut pieces = Vec::new();
+        while let Some(piece) = receiver.recv().await {
+            pieces.push(piece);
+        }
+        assert_eq!(pieces, vec!["The ", "driver."]);
+        assert_eq!(generation.text, "The driver.");
+        // No usage on the stream: counted from the chunks
+        assert_eq!(generation.usage.completion_tokens, 2);
+    }
+
+    #[tokio::test]
+    async fn test_http_backend_stream_keeps_characters_split_across_chunks() {
+        let mut server = mockito::Server::new_async().await;
+        let event = "data: {\"choices\": [{\"delta\": {\"content\": \"Café\"}}]}\n\ndata: [DONE]\n\n".as_bytes().to_vec();
+        // Cut inside the two bytes of 'é'
+        let split = event.iter().position(|&byte| byte == 0xC3).unwrap() + 1;
+        server
+            .mock("POST", "/v1/chat/completions")
+            .with_header("content-type", "text/event-stream")
+            .with_chunked_body(move |writer| {
+                writer.write_all(&event[..split])?;
+                writer.flush()?;
+                std::thread::sleep(Duration::from_millis(50));
+                writer.write_all(&event[split..])
+            })
+            .create_async()
+            .await;
+
+        let backend = http_backend(format!("{}/v1", server.url()), HttpFlavor::OpenAi);
+        let (sender, _receiver) = mpsc::channel(8);
+        let generation = backend.stream(&GenerationRequest::default(), sender).await.unwrap();
+        assert_eq!(generation.text, "Café");
+    }
+
+    #[tokio::test]
+    async fn test_llamacpp_backend_tokenizes_and_reads_props() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("POST", "/tokenize")
+            .match_body(mockito::Matcher::Json(serde_json::json!({"content": "white van"})))
+            .with_body(r#"{"tokens": [4796, 16037]}"#)
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/props")
+            .with_body(r#"{"model_path": "/models/mistral-7b-instruct.Q4_K_M.gguf", "default_generation_settings": {"n_ctx": 8192}}"#)
+            .create_async()
+            .await;
+
+        let backend = http_backend(format!("{}/v1", server.url()), HttpFlavor::LlamaCpp);
+        assert_eq!(backend.name(), "llamacpp:test-model");
+        assert_eq!(backend.tokenize("white van").await.unwrap(), vec![4796, 16037]);
+        let info = backend.model_info().await.unwrap();
+        assert_eq!(info.model, "mistral-7b-instruct.Q4_K_M.gguf");
+        assert_eq!(info.context_length, Some(8192));
+    }
+
+    #[tokio::test]
+    async fn test_mock_backend_is_deterministic() {
+        let backend = MockLlmBackend::with_reply("one two three four");
+        let request = GenerationRequest { prompt: "a b".to_string(), max_tokens: Some(3), ..Default::default() };
+        let generation = backend.generate(&request).await.unwrap();
+        assert_eq!(generation.text, "one two three ");
+        assert_eq!(generation.usage, TokenUsage { prompt_tokens: 2, completion_tokens: 3 });
+        assert_eq!(backend.tokenize("van van car").await.unwrap()[0], backend.tokenize("van").await.unwrap()[0]);
+
+        // A dropped receiver stops the stream after the piece in flight
+        let (sender, mut receiver) = mpsc::channel(1);
+        let streaming = tokio::spawn(async move { backend.stream(&GenerationRequest::default(), sender).await });
+        assert_eq!(receiver.recv().await.unwrap(), "one ");
+        drop(receiver);
+        let generation = streaming.await.unwrap().unwrap();
+        assert!(generation.usage.completion_tokens < 4, "{:?}", generation.usage);
+    }
+}
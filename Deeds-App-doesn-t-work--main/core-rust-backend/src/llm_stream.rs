@@ -0,0 +1,154 @@
+// Streaming generations as events
+// `stream_generation` runs an `LlmBackend::stream` in the background and turns it into a channel of
+// events: each piece of text as it arrives, then one `Done` carrying the same figures the desktop's
+// `InferenceResponse` reports, or an `Error`. The SSE endpoint and the desktop's Tauri events both
+// forward this channel. Dropping the receiver cancels the generation; so does the `stop` future of
+// `stream_generation_until`, which still ends the stream with a `Done` for what was generated.
+
+use serde::Serialize;
+use std::{future::Future, sync::Arc, time::{Duration, Instant}};
+use tokio::sync::mpsc;
+
+use crate::llm_backend::{Generation, GenerationRequest, LlmBackend};
+
+/// Events buffered between the backend and a slow reader
+const EVENT_BUFFER: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StreamEvent {
+    Token { text: String },
+    Done(StreamDone),
+    Error { error: String },
+}
+
+/// The finished generation and its usage
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StreamDone {
+    pub text: String,
+    pub tokens_generated: u32,
+    pub tokens_per_second: f32,
+    pub processing_time_ms: u64,
+    pub model_used: String,
+}
+
+impl StreamDone {
+    pub fn new(generation: Generation, elapsed: Duration) -> Self {
+        let tokens_generated = generation.usage.completion_tokens;
+        let seconds = elapsed.as_secs_f32();
+        Self {
+            text: generation.text,
+            tokens_generated,
+            tokens_per_second: if seconds > 0.0 { tokens_generated as f32 / seconds } else { 0.0 },
+            processing_time_ms: elapsed.as_millis() as u64,
+            model_used: generation.model,
+        }
+    }
+}
+
+/// Start `request` on `backend`; the events end with exactly one `Done` or `Error` unless the
+/// receiver is dropped first, which stops the backend at its next piece
+pub fn stream_generation(backend: Arc<dyn LlmBackend>, request: GenerationRequest) -> mpsc::Receiver<StreamEvent> {
+    stream_generation_until(backend, request, std::future::pending())
+}
+
+/// As `stream_generation`, also stopping once `stop` completes
+pub fn stream_generation_until(
+    backend: Arc<dyn LlmBackend>,
+    request: GenerationRequest,
+    stop: impl Future<Output = ()> + Send + 'static,
+) -> mpsc::Receiver<StreamEvent> {
+    let (events, receiver) = mpsc::channel(EVENT_BUFFER);
+    tokio::spawn(async move {
+        let started = Instant::now();
+        // No slack between backend and forwarder, so a stop takes effect within a piece
+        let (pieces, mut piece_receiver) = mpsc::channel(1);
+        let forward_events = events.clone();
+        let forward = async move {
+            tokio::pin!(stop);
+            loop {
+                let text = tokio::select! {
+                    biased;
+                    _ = &mut stop => break,
+                    piece = piece_receiver.recv() => match piece {
+                        Some(text) => text,
+                        None => break,
+                    },
+                };
+                if forward_events.send(StreamEvent::Token { text }).await.is_err() {
+                    break;
+                }
+            }
+            // Dropping `piece_receiver` here tells the backend to stop
+        };
+        let (result, ()) = tokio::join!(backend.stream(&request, pieces), forward);
+
+        let event = match result {
+            Ok(generation) => StreamEvent::Done(StreamDone::new(generation, started.elapsed())),
+            Err(e) => {
+                tracing::warn!("Streaming generation on {} failed: {}", backend.name(), e);
+                StreamEvent::Error { error: e.to_string() }
+            }
+        };
+        let _ = events.send(event).await;
+    });
+    receiver
+}
+
+/// Events for text that is already complete, e.g. a rule-based fallback
+pub fn completed(text: String, model: &str) -> mpsc::Receiver<StreamEvent> {
+    let (events, receiver) = mpsc::channel(2);
+    let done = StreamDone {
+        text: text.clone(),
+        tokens_generated: 0,
+        tokens_per_second: 0.0,
+        processing_time_ms: 0,
+        model_used: model.to_string(),
+    };
+    // Both fit in the buffer, so neither send can fail while `receiver` is alive
+    let _ = events.try_send(StreamEvent::Token { text });
+    let _ = events.try_send(StreamEvent::Done(done));
+    receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm_backend::MockLlmBackend;
+
+    #[tokio::test]
+    async fn test_stream_ends_with_usage() {
+        let backend = Arc::new(MockLlmBackend::with_reply("The white van."));
+        let mut events = stream_generation(backend, GenerationRequest { prompt: "Who?".to_string(), ..Default::default() });
+
+        let mut streamed = String::new();
+        let done = loop {
+            match events.recv().await.unwrap() {
+                StreamEvent::Token { text } => streamed.push_str(&text),
+                StreamEvent::Done(done) => break done,
+                StreamEvent::Error { error } => panic!("{}", error),
+            }
+        };
+        assert_eq!(streamed, "The white van.");
+        assert_eq!(done.text, streamed);
+        assert_eq!(done.tokens_generated, 3);
+        assert_eq!(done.model_used, "mock");
+        assert!(events.recv().await.is_none());
+
+        let json = serde_json::to_value(StreamEvent::Done(done)).unwrap();
+        assert_eq!(json["type"], "done");
+        assert_eq!(json["tokens_generated"], 3);
+    }
+
+    #[tokio::test]
+    async fn test_stop_ends_with_partial_generation() {
+        let backend = Arc::new(MockLlmBackend::with_reply("one two three four"));
+        let mut events = stream_generation_until(backend, GenerationRequest::default(), async {});
+
+        match events.recv().await.unwrap() {
+            StreamEvent::Done(done) => assert!(done.tokens_generated < 4, "{:?}", done),
+            other => panic!("expected done, got {:?}", other),
+        }
+        assert!(events.recv().await.is_none());
+    }
+}
@@ -1,47 +1,61 @@
 use axum::{
-    extract::Extension,
-    http::StatusCode,
+    extract::{DefaultBodyLimit, Extension},
     middleware as axum_middleware,
-    response::Json,
     routing::{delete, get, post, put},
     Router,
 };
-use std::net::SocketAddr;
+use std::{net::SocketAddr, time::Duration};
 use tower_http::cors::CorsLayer;
-use tracing_subscriber;
-
-mod auth_simple;
-mod config;
-mod database;
-mod handlers;
-mod middleware;
-mod models;
-mod utils;
-// mod auth;  // Commented out due to jsonwebtoken dependency
-// mod llm;  // Commented out for now due to compilation issues
-// mod file_processor;  // Commented out for now
-// mod qdrant;  // Commented out for now
-
-use config::Config;
-use handlers::{auth as auth_handlers, cases, evidence, embeddings, health};
-// use llm::LLMService;  // Commented out for now
-// use file_processor::FileProcessor;  // Commented out for now
-// use qdrant::QdrantService;  // Commented out for now
-
-// Import the AppState from lib.rs
-use prosecutor_core::AppState;
+// The server is a thin shell over the library: routes, startup and the migrate command
+use prosecutor_core::{
+    config::Config,
+    database,
+    handlers::{audit as audit_handlers, auth as auth_handlers, cases, custody as custody_handlers, evidence, embeddings as embedding_handlers, health, search as search_handlers, uploads},
+    integrity,
+    middleware,
+    migrations,
+    resumable,
+    AppState,
+};
+#[cfg(feature = "encryption")]
+use prosecutor_core::encryption;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     // Initialize tracing
-    tracing_subscriber::init();
+    tracing_subscriber::fmt::init();
     
+    // `prosecutor-core migrate [status | up | down <version>]` manages the schema and exits
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("migrate") {
+        return migrate_command(&args[1..]).await;
+    }
+
     tracing::info!("🦀 Starting Prosecutor Backend Server");
     
     // Initialize application state (includes database setup)
     let state = AppState::new().await?;
     tracing::info!("✅ Application state initialized");
 
+    if state.config.integrity_sweep_interval_hours > 0 {
+        let interval = Duration::from_secs(state.config.integrity_sweep_interval_hours * 3600);
+        integrity::spawn_integrity_sweep(state.db.clone(), state.evidence_store.clone(), interval);
+    }
+
+    // Data keys still wrapped under a retired master key move to the current one
+    #[cfg(feature = "encryption")]
+    if state.config.evidence_encryption {
+        let master_keys = encryption::MasterKeys::from_config(&state.config)?;
+        encryption::spawn_rewrap_data_keys(state.db.clone(), master_keys, Duration::from_secs(30));
+    }
+
+    // Abandoned resumable uploads hold partial files; clear expired sessions hourly
+    resumable::spawn_session_cleanup(
+        state.db.clone(),
+        resumable::UploadSettings::from_config(&state.config),
+        Duration::from_secs(3600),
+    );
+
     // Build our application with routes
     let app = create_router(state);
 
@@ -55,6 +69,36 @@ async fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+async fn migrate_command(args: &[String]) -> anyhow::Result<()> {
+    let config = Config::from_env()?;
+    let db = database::create_connection(&config.database_url).await?;
+
+    match args.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+        [] | ["status"] => {
+            let applied = migrations::status(&db).await?;
+            for migration in migrations::MIGRATIONS {
+                match applied.iter().find(|record| record.version == migration.version) {
+                    Some(record) => println!("{:>4}  {:<32} applied {}", migration.version, migration.name, record.applied_at),
+                    None => println!("{:>4}  {:<32} pending", migration.version, migration.name),
+                }
+            }
+            // Reports unknown, edited or out-of-order history
+            migrations::pending(migrations::MIGRATIONS, &applied)?;
+        }
+        ["up"] => {
+            let applied = migrations::run(&db).await?;
+            println!("Applied {} migration(s): {:?}", applied.len(), applied);
+        }
+        ["down", target] => {
+            let target: i64 = target.parse().map_err(|_| anyhow::anyhow!("invalid target version {:?}", target))?;
+            let reverted = migrations::revert_to(&db, target).await?;
+            println!("Reverted {} migration(s): {:?}", reverted.len(), reverted);
+        }
+        _ => anyhow::bail!("usage: prosecutor-core migrate [status | up | down <version>]"),
+    }
+    Ok(())
+}
+
 fn create_router(state: AppState) -> Router {
     Router::new()
         // Health check
@@ -63,20 +107,53 @@ fn create_router(state: AppState) -> Router {
         // Authentication routes
         .route("/api/auth/register", post(auth_handlers::register))
         .route("/api/auth/login", post(auth_handlers::login))
+        .route("/api/auth/refresh", post(auth_handlers::refresh))
+        .route("/api/auth/logout", post(auth_handlers::logout))
         .route("/api/auth/me", get(auth_handlers::me))
+        .route("/api/auth/sessions", get(auth_handlers::list_sessions).delete(auth_handlers::revoke_other_sessions))
+        .route("/api/auth/sessions/:id", delete(auth_handlers::revoke_session))
         
         // Protected routes (require authentication)
         .route("/api/cases", get(cases::list_cases).post(cases::create_case))
         .route("/api/cases/:id", get(cases::get_case).put(cases::update_case).delete(cases::delete_case))
+        .route("/api/cases/:id/ask", post(cases::ask_case))
+        .route("/api/cases/:id/collaborators", get(cases::list_collaborators).post(cases::add_collaborator))
+        .route("/api/cases/:id/collaborators/:user_id", delete(cases::remove_collaborator))
         
-        .route("/api/evidence", post(evidence::upload_evidence))
+        // The upload handler streams to disk and enforces max_file_size itself
+        .route(
+            "/api/evidence",
+            post(evidence::upload_evidence).layer(DefaultBodyLimit::max(state.config.max_file_size + 1024 * 1024)),
+        )
+        .route("/api/evidence/verify", get(evidence::verify_evidence_integrity))
         .route("/api/evidence/:id", get(evidence::get_evidence).delete(evidence::delete_evidence))
+        .route("/api/evidence/:id/content", get(evidence::get_evidence_content))
+        .route("/api/evidence/:id/summary/stream", get(evidence::stream_evidence_summary))
+        .route("/api/evidence/:id/extract", post(evidence::extract_evidence))
+        .route("/api/evidence/:id/access", get(evidence::get_evidence_access_log))
+        .route("/api/evidence/:id/custody", get(custody_handlers::get_custody_timeline).post(custody_handlers::record_custody_event))
+        
+        // Resumable chunked uploads
+        .route("/api/uploads", post(uploads::create_upload))
+        .route("/api/uploads/:id", get(uploads::get_upload).delete(uploads::abort_upload))
+        .route("/api/uploads/:id/chunks/:index", put(uploads::put_chunk))
+        .route("/api/uploads/:id/finalize", post(uploads::finalize_upload))
+        
+        // Full-text search over cases and evidence
+        .route("/api/search", get(search_handlers::search))
+        .route("/api/search/hybrid", post(search_handlers::hybrid_search))
+        
+        // Audit log
+        .route("/api/audit", get(audit_handlers::list_audit))
+        .route("/api/audit/verify", get(audit_handlers::verify_audit))
         
         // Content embeddings routes
-        .route("/api/embeddings", post(embeddings::create_embedding))
-        .route("/api/embeddings/:content_id/:content_type", get(embeddings::get_embedding))
-        .route("/api/embeddings/:content_id", get(embeddings::get_content_embeddings))
-        .route("/api/embeddings/search", post(embeddings::search_embeddings))
+        .route("/api/embeddings", post(embedding_handlers::create_embedding))
+        .route("/api/embeddings/:content_id/:content_type", get(embedding_handlers::get_embedding))
+        .route("/api/embeddings/:content_id", get(embedding_handlers::get_content_embeddings))
+        .route("/api/embeddings/search", post(embedding_handlers::search_embeddings))
+        .route("/api/vector-collections", get(embedding_handlers::list_vector_collections))
+        .route("/api/vector-collections/reembed", post(embedding_handlers::reembed))
         
         // Apply authentication middleware to protected routes
         .layer(axum_middleware::from_fn_with_state(
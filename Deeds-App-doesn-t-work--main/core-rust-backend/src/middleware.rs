@@ -4,10 +4,17 @@ use axum::{
     middleware::Next,
     response::Response,
 };
-use uuid::Uuid;
 
 use crate::{auth_simple::verify_jwt, AppState};
 
+/// Routes reachable without a bearer token
+const PUBLIC_PATHS: &[&str] = &[
+    "/health",
+    "/api/auth/register",
+    "/api/auth/login",
+    "/api/auth/refresh",
+];
+
 pub async fn auth_middleware(
     Extension(state): Extension<AppState>,
     mut request: Request,
@@ -15,7 +22,7 @@ pub async fn auth_middleware(
 ) -> Result<Response, StatusCode> {
     // Skip authentication for certain routes
     let path = request.uri().path();
-    if path.starts_with("/api/auth/") || path == "/health" {
+    if PUBLIC_PATHS.contains(&path) {
         return Ok(next.run(request).await);
     }
 
@@ -30,17 +37,17 @@ pub async fn auth_middleware(
         .strip_prefix("Bearer ")
         .ok_or(StatusCode::UNAUTHORIZED)?;
 
-    // Verify JWT token
-    let claims = verify_jwt(token, &state.config.jwt_secret)
-        .map_err(|_| StatusCode::UNAUTHORIZED)?
-        .claims;
-
-    // Parse user ID
-    let user_id = Uuid::parse_str(&claims.sub)
-        .map_err(|_| StatusCode::UNAUTHORIZED)?;
-
-    // Add user ID to request extensions
-    request.extensions_mut().insert(user_id);
+    // Verify signature, key id and expiry, then the revocation list
+    let token = verify_jwt(token, &state.signing_keys)
+        .and_then(|token| state.revoked_tokens.check(&token).map(|_| token))
+        .map_err(|e| {
+            tracing::warn!(reason = e.reason(), path = %path, "Rejected bearer token: {}", e);
+            StatusCode::UNAUTHORIZED
+        })?;
+
+    // Add user ID and the verified token to request extensions
+    request.extensions_mut().insert(token.user_id);
+    request.extensions_mut().insert(token);
 
     Ok(next.run(request).await)
 }
@@ -0,0 +1,307 @@
+// Versioned schema migrations for prosecutor-core
+// Migrations live in `migrations/NNNN_name.{up,down}.sql` and are compiled into the binary. Each
+// applied migration is recorded in `schema_migrations` with a checksum of its up script, so an
+// edited migration, a database migrated by a newer build, or a gap in the history stops startup
+// instead of running against a schema the code does not expect.
+
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use sqlx::{pool::PoolConnection, Connection, Executor, FromRow, PgConnection, Postgres};
+
+use crate::{database::DbConnection, utils::to_hex};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub up: &'static str,
+    pub down: &'static str,
+}
+
+impl Migration {
+    pub fn checksum(&self) -> String {
+        to_hex(&Sha256::digest(self.up.as_bytes()))
+    }
+}
+
+/// Every migration this build knows about, in version order
+pub static MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial_schema",
+        up: include_str!("../migrations/0001_initial_schema.up.sql"),
+        down: include_str!("../migrations/0001_initial_schema.down.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "full_text_search",
+        up: include_str!("../migrations/0002_full_text_search.up.sql"),
+        down: include_str!("../migrations/0002_full_text_search.down.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "vector_collections",
+        up: include_str!("../migrations/0003_vector_collections.up.sql"),
+        down: include_str!("../migrations/0003_vector_collections.down.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "evidence_ai_extraction",
+        up: include_str!("../migrations/0004_evidence_ai_extraction.up.sql"),
+        down: include_str!("../migrations/0004_evidence_ai_extraction.down.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "session_previous_refresh_hash",
+        up: include_str!("../migrations/0005_session_previous_refresh_hash.up.sql"),
+        down: include_str!("../migrations/0005_session_previous_refresh_hash.down.sql"),
+    },
+];
+
+#[derive(Debug, Clone, FromRow)]
+pub struct AppliedMigration {
+    pub version: i64,
+    pub name: String,
+    pub checksum: String,
+    pub applied_at: DateTime<Utc>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MigrationError {
+    #[error("database schema is at version {database} but this build only knows up to {binary}; refusing to start")]
+    DatabaseAhead { database: i64, binary: i64 },
+    #[error("migration {version} ({name}) was modified after it was applied")]
+    ChecksumMismatch { version: i64, name: String },
+    #[error("migration {0} is recorded in the database but missing from this build")]
+    Unknown(i64),
+    #[error("migration {version} has not been applied but {latest} has; it cannot be applied out of order")]
+    OutOfOrder { version: i64, latest: i64 },
+    #[error("{0} pending migration(s); run `prosecutor-core migrate up` or enable AUTO_MIGRATE")]
+    Pending(usize),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+/// Highest version this build can migrate to
+pub fn latest_version(available: &[Migration]) -> i64 {
+    available.last().map_or(0, |migration| migration.version)
+}
+
+/// Compare the recorded history with the migrations in this build and return the ones still to
+/// apply, or why the database cannot be used as it is.
+pub fn pending<'a>(available: &'a [Migration], applied: &[AppliedMigration]) -> Result<Vec<&'a Migration>, MigrationError> {
+    let latest_applied = applied.iter().map(|migration| migration.version).max().unwrap_or(0);
+    let latest_known = latest_version(available);
+    if latest_applied > latest_known {
+        return Err(MigrationError::DatabaseAhead { database: latest_applied, binary: latest_known });
+    }
+
+    for record in applied {
+        let migration = available
+            .iter()
+            .find(|migration| migration.version == record.version)
+            .ok_or(MigrationError::Unknown(record.version))?;
+        if migration.checksum() != record.checksum {
+            return Err(MigrationError::ChecksumMismatch { version: record.version, name: record.name.clone() });
+        }
+    }
+
+    let pending: Vec<&Migration> = available
+        .iter()
+        .filter(|migration| !applied.iter().any(|record| record.version == migration.version))
+        .collect();
+    if let Some(migration) = pending.iter().find(|migration| migration.version < latest_applied) {
+        return Err(MigrationError::OutOfOrder { version: migration.version, latest: latest_applied });
+    }
+    Ok(pending)
+}
+
+// Held for the duration of a migration run so concurrently starting instances take turns
+const MIGRATION_LOCK_ID: i64 = 0x0070_726f_736d_6967; // "prosmig"
+
+async fn ensure_table(conn: &mut PgConnection) -> Result<(), MigrationError> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_migrations (
+            version BIGINT PRIMARY KEY,
+            name VARCHAR(255) NOT NULL,
+            checksum VARCHAR(64) NOT NULL,
+            applied_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+            execution_ms BIGINT NOT NULL
+        )",
+    )
+    .await?;
+    Ok(())
+}
+
+async fn load_applied(conn: &mut PgConnection) -> Result<Vec<AppliedMigration>, MigrationError> {
+    let applied = sqlx::query_as::<_, AppliedMigration>(
+        "SELECT version, name, checksum, applied_at FROM schema_migrations ORDER BY version"
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    Ok(applied)
+}
+
+/// Take the migration lock on a dedicated connection and make sure the history table exists
+async fn lock(db: &DbConnection) -> Result<PoolConnection<Postgres>, MigrationError> {
+    let mut conn = db.acquire().await?;
+    sqlx::query("SELECT pg_advisory_lock($1)").bind(MIGRATION_LOCK_ID).execute(&mut *conn).await?;
+    if let Err(e) = ensure_table(&mut conn).await {
+        return unlock(conn, Err(e)).await;
+    }
+    Ok(conn)
+}
+
+async fn unlock<T>(mut conn: PoolConnection<Postgres>, result: Result<T, MigrationError>) -> Result<T, MigrationError> {
+    sqlx::query("SELECT pg_advisory_unlock($1)").bind(MIGRATION_LOCK_ID).execute(&mut *conn).await?;
+    result
+}
+
+/// Apply all pending migrations, each in its own transaction. Returns the versions applied.
+pub async fn run(db: &DbConnection) -> Result<Vec<i64>, MigrationError> {
+    let mut conn = lock(db).await?;
+    let result = apply_pending(&mut conn).await;
+    unlock(conn, result).await
+}
+
+async fn apply_pending(conn: &mut PgConnection) -> Result<Vec<i64>, MigrationError> {
+    let applied = load_applied(conn).await?;
+    let mut versions = Vec::new();
+    for migration in pending(MIGRATIONS, &applied)? {
+        tracing::info!("Applying migration {} ({})", migration.version, migration.name);
+        let started = std::time::Instant::now();
+
+        let mut tx = conn.begin().await?;
+        (&mut *tx).execute(migration.up).await?;
+        sqlx::query(
+            "INSERT INTO schema_migrations (version, name, checksum, execution_ms) VALUES ($1, $2, $3, $4)"
+        )
+        .bind(migration.version)
+        .bind(migration.name)
+        .bind(migration.checksum())
+        .bind(started.elapsed().as_millis() as i64)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+
+        versions.push(migration.version);
+    }
+    Ok(versions)
+}
+
+/// Revert applied migrations newer than `target`, newest first. Returns the versions reverted.
+pub async fn revert_to(db: &DbConnection, target: i64) -> Result<Vec<i64>, MigrationError> {
+    let mut conn = lock(db).await?;
+    let result = revert_applied(&mut conn, target).await;
+    unlock(conn, result).await
+}
+
+async fn revert_applied(conn: &mut PgConnection, target: i64) -> Result<Vec<i64>, MigrationError> {
+    let applied = load_applied(conn).await?;
+    // Only revert a history this build fully recognises
+    pending(MIGRATIONS, &applied)?;
+
+    let mut versions = Vec::new();
+    for migration in MIGRATIONS.iter().rev().filter(|migration| migration.version > target) {
+        if !applied.iter().any(|record| record.version == migration.version) {
+            continue;
+        }
+        tracing::info!("Reverting migration {} ({})", migration.version, migration.name);
+
+        let mut tx = conn.begin().await?;
+        (&mut *tx).execute(migration.down).await?;
+        sqlx::query("DELETE FROM schema_migrations WHERE version = $1")
+            .bind(migration.version)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        versions.push(migration.version);
+    }
+    Ok(versions)
+}
+
+/// Applied migrations, oldest first
+pub async fn status(db: &DbConnection) -> Result<Vec<AppliedMigration>, MigrationError> {
+    let mut conn = lock(db).await?;
+    let result = load_applied(&mut conn).await;
+    unlock(conn, result).await
+}
+
+/// Startup check when migrations are not applied automatically: the schema must match this build
+/// exactly.
+pub async fn ensure_current(db: &DbConnection) -> Result<(), MigrationError> {
+    let applied = status(db).await?;
+    match pending(MIGRATIONS, &applied)?.len() {
+        0 => Ok(()),
+        count => Err(MigrationError::Pending(count)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AVAILABLE: &[Migration] = &[
+        Migration { version: 1, name: "first", up: "CREATE TABLE a ()", down: "DROP TABLE a" },
+        Migration { version: 2, name: "second", up: "CREATE TABLE b ()", down: "DROP TABLE b" },
+        Migration { version: 3, name: "third", up: "CREATE TABLE c ()", down: "DROP TABLE c" },
+    ];
+
+    fn record(migration: &Migration) -> AppliedMigration {
+        AppliedMigration {
+            version: migration.version,
+            name: migration.name.to_string(),
+            checksum: migration.checksum(),
+            applied_at: Utc::now(),
+        }
+    }
+
+    fn versions(migrations: Vec<&Migration>) -> Vec<i64> {
+        migrations.iter().map(|migration| migration.version).collect()
+    }
+
+    #[test]
+    fn test_bundled_migrations_are_ordered() {
+        assert!(!MIGRATIONS.is_empty());
+        assert!(MIGRATIONS.windows(2).all(|pair| pair[0].version < pair[1].version));
+        assert!(MIGRATIONS.iter().all(|migration| !migration.up.trim().is_empty() && !migration.down.trim().is_empty()));
+    }
+
+    #[test]
+    fn test_pending_migrations() {
+        assert_eq!(versions(pending(AVAILABLE, &[]).unwrap()), vec![1, 2, 3]);
+        assert_eq!(versions(pending(AVAILABLE, &[record(&AVAILABLE[0])]).unwrap()), vec![2, 3]);
+        let all: Vec<_> = AVAILABLE.iter().map(record).collect();
+        assert!(pending(AVAILABLE, &all).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_database_ahead_is_refused() {
+        let all: Vec<_> = AVAILABLE.iter().map(record).collect();
+        match pending(&AVAILABLE[..2], &all) {
+            Err(MigrationError::DatabaseAhead { database: 3, binary: 2 }) => {}
+            other => panic!("expected DatabaseAhead, got {:?}", other.map(versions)),
+        }
+    }
+
+    #[test]
+    fn test_modified_missing_and_out_of_order_migrations_are_refused() {
+        let mut edited = record(&AVAILABLE[0]);
+        edited.checksum = "0".repeat(64);
+        assert!(matches!(
+            pending(AVAILABLE, &[edited]),
+            Err(MigrationError::ChecksumMismatch { version: 1, .. })
+        ));
+
+        let missing = [AVAILABLE[0], AVAILABLE[2]];
+        let applied: Vec<_> = AVAILABLE.iter().map(record).collect();
+        assert!(matches!(pending(&missing, &applied), Err(MigrationError::Unknown(2))));
+
+        let applied = [record(&AVAILABLE[0]), record(&AVAILABLE[2])];
+        assert!(matches!(
+            pending(AVAILABLE, &applied),
+            Err(MigrationError::OutOfOrder { version: 2, latest: 3 })
+        ));
+    }
+}
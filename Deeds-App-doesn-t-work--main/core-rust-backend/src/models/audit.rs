@@ -0,0 +1,25 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub actor_id: Uuid,
+    pub action: String,
+    pub entity_type: String, // "case", "evidence"
+    pub entity_id: String,
+    pub case_id: Option<i32>,
+    pub changes: serde_json::Value, // { field: { before, after } }
+    pub created_at: DateTime<Utc>,
+    pub prev_hash: String,
+    pub entry_hash: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    pub case_id: Option<i32>,
+    pub before_id: Option<i64>, // Page backwards from this entry id
+    pub limit: Option<i64>,
+}
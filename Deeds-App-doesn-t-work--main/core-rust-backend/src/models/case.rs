@@ -3,7 +3,7 @@ use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use uuid::Uuid;
 
-#[derive(Debug, Serialize, Deserialize, FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct Case {
     pub id: i32,
     pub case_number: String,
@@ -67,6 +67,27 @@ pub struct UpdateCaseRequest {
     pub notes: Option<String>,
 }
 
+impl UpdateCaseRequest {
+    /// True when the request would change nothing
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none()
+            && self.description.is_none()
+            && self.status.is_none()
+            && self.priority.is_none()
+            && self.assigned_to.is_none()
+            && self.court_date.is_none()
+            && self.court_location.is_none()
+            && self.judge_assigned.is_none()
+            && self.case_type.is_none()
+            && self.jurisdiction.is_none()
+            && self.estimated_duration.is_none()
+            && self.case_value.is_none()
+            && self.statute_of_limitations.is_none()
+            && self.tags.is_none()
+            && self.notes.is_none()
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CaseResponse {
     pub id: i32,
@@ -94,6 +115,34 @@ pub struct CaseResponse {
     pub evidence_count: i64,
 }
 
+/// A case with the related data shown in listings
+#[derive(Debug, Clone, FromRow)]
+pub struct CaseListing {
+    #[sqlx(flatten)]
+    pub case: Case,
+    pub created_by_user: Option<String>,
+    pub assigned_to_user: Option<String>,
+    pub evidence_count: i64,
+}
+
+impl From<CaseListing> for CaseResponse {
+    fn from(listing: CaseListing) -> Self {
+        Self {
+            created_by_user: listing.created_by_user,
+            assigned_to_user: listing.assigned_to_user,
+            evidence_count: listing.evidence_count,
+            ..listing.case.into()
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CaseListResponse {
+    pub items: Vec<CaseResponse>,
+    pub total: i64, // All matching cases, across pages
+    pub next_cursor: Option<String>, // Absent on the last page
+}
+
 impl From<Case> for CaseResponse {
     fn from(case: Case) -> Self {
         let tags: Vec<String> = case.tags.as_array()
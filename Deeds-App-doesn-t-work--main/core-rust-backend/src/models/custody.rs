@@ -0,0 +1,47 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct CustodyEvent {
+    pub id: i64,
+    pub evidence_id: i32,
+    pub event_type: String, // collected, transferred, checked_out, returned, sealed
+    pub holder: String,
+    pub location: Option<String>,
+    pub reason: Option<String>,
+    pub file_hash: Option<String>, // SHA-256 of the stored file when the event was recorded
+    pub recorded_by: Uuid,
+    pub occurred_at: DateTime<Utc>,
+    pub signing_kid: String,
+    pub signature: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecordCustodyRequest {
+    pub event_type: String,
+    pub holder: String,
+    pub location: Option<String>,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CustodyEventView {
+    #[serde(flatten)]
+    pub event: CustodyEvent,
+    pub signature_valid: Option<bool>, // None when the signing key has been retired
+    pub hash_changed: bool, // File hash differs from the previous hashed event
+}
+
+#[derive(Debug, Serialize)]
+pub struct CustodyTimeline {
+    pub evidence_id: i32,
+    pub current_holder: Option<String>,
+    pub checked_out: bool,
+    pub sealed: bool,
+    pub current_file_hash: Option<String>,
+    pub integrity_ok: bool,
+    pub issues: Vec<String>,
+    pub events: Vec<CustodyEventView>,
+}
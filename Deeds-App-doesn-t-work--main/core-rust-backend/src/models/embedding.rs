@@ -0,0 +1,41 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// A stored embedding, without its vector
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct Embedding {
+    pub id: Uuid,
+    pub content_id: String,
+    pub content_type: String, // "evidence", "case", "note", etc.
+    pub content_text: String,
+    pub metadata: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateEmbeddingRequest {
+    pub content_id: String,
+    pub content_type: String,
+    pub content_text: String,
+    pub embedding_vector: Vec<f32>,
+    pub metadata: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchEmbeddingsRequest {
+    pub query_embedding: Option<Vec<f32>>,
+    pub query_text: Option<String>, // Embedded server-side when no vector is given
+    pub content_type: Option<String>,
+    pub metadata: Option<serde_json::Value>, // Rows whose metadata contains this object
+    pub limit: Option<i64>,
+    pub min_similarity: Option<f32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct EmbeddingMatch {
+    #[sqlx(flatten)]
+    pub embedding: Embedding,
+    pub similarity: f64, // Cosine similarity, 1 for identical direction
+}
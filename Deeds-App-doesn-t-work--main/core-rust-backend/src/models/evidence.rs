@@ -1,9 +1,10 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use sqlx::FromRow;
+use serde_json::Value;
+use sqlx::{types::Json, FromRow};
 use uuid::Uuid;
 
-#[derive(Debug, Serialize, Deserialize, FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct Evidence {
     pub id: i32,
     pub case_id: Option<i32>,
@@ -14,8 +15,28 @@ pub struct Evidence {
     pub file_path: Option<String>,
     pub file_size: Option<i64>,
     pub file_type: Option<String>,
+    pub hash_sha256: Option<String>, // SHA-256 of the file as uploaded
     pub uploaded_by: Uuid,
     pub created_at: DateTime<Utc>,
+    pub ai_tags: Option<Json<Vec<String>>>, // Set by structured extraction (extraction.rs)
+    pub ai_summary: Option<String>,
+    pub ai_extraction: Option<Value>,
+    pub ai_extracted_at: Option<DateTime<Utc>>,
+}
+
+/// One download of an evidence file
+#[derive(Debug, Serialize, Deserialize, FromRow)]
+pub struct EvidenceAccessEvent {
+    pub id: i64,
+    pub evidence_id: i32,
+    pub case_id: Option<i32>,
+    pub user_id: Uuid,
+    pub accessed_at: DateTime<Utc>,
+    pub range_start: Option<i64>, // Half-open byte range, when only part of the file was served
+    pub range_end: Option<i64>,
+    pub bytes_served: i64,
+    pub watermarked: bool,
+    pub user_agent: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -38,9 +59,14 @@ pub struct EvidenceResponse {
     pub file_path: Option<String>,
     pub file_size: Option<i64>,
     pub file_type: Option<String>,
+    pub hash_sha256: Option<String>,
     pub created_at: DateTime<Utc>,
     pub uploaded_by_user: Option<String>, // User name
     pub case_title: Option<String>, // Case title if linked
+    pub ai_tags: Option<Vec<String>>,
+    pub ai_summary: Option<String>,
+    pub ai_extraction: Option<Value>, // The whole extraction: charges, persons, dates, locations
+    pub ai_extracted_at: Option<DateTime<Utc>>,
 }
 
 impl From<Evidence> for EvidenceResponse {
@@ -55,9 +81,14 @@ impl From<Evidence> for EvidenceResponse {
             file_path: evidence.file_path,
             file_size: evidence.file_size,
             file_type: evidence.file_type,
+            hash_sha256: evidence.hash_sha256,
             created_at: evidence.created_at,
             uploaded_by_user: None, // Will be populated by query
             case_title: None, // Will be populated by query
+            ai_tags: evidence.ai_tags.map(|tags| tags.0),
+            ai_summary: evidence.ai_summary,
+            ai_extraction: evidence.ai_extraction,
+            ai_extracted_at: evidence.ai_extracted_at,
         }
     }
 }
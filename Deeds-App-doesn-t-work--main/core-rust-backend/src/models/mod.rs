@@ -1,7 +1,17 @@
+pub mod audit;
 pub mod case;
+pub mod custody;
+pub mod embedding;
 pub mod evidence;
+pub mod session;
+pub mod upload;
 pub mod user;
 
+pub use audit::*;
 pub use case::*;
+pub use custody::*;
+pub use embedding::*;
 pub use evidence::*;
+pub use session::*;
+pub use upload::*;
 pub use user::*;
@@ -0,0 +1,70 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct UploadSession {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub case_id: Option<i32>,
+    pub criminal_id: Option<i32>,
+    pub title: String,
+    pub description: Option<String>,
+    pub evidence_type: String,
+    pub file_name: String,
+    pub content_type: Option<String>, // As declared by the client
+    pub total_size: i64,
+    pub chunk_size: i32,
+    pub expected_sha256: Option<String>,
+    pub status: String, // open, finalizing, completed, aborted
+    pub evidence_id: Option<i32>, // Set once finalization has created the evidence row
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct UploadChunk {
+    pub session_id: Uuid,
+    pub chunk_index: i32,
+    pub size: i32,
+    pub sha256: String,
+    pub received_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateUploadRequest {
+    pub case_id: Option<i32>,
+    pub criminal_id: Option<i32>,
+    pub title: String,
+    pub description: Option<String>,
+    pub evidence_type: String,
+    pub file_name: String,
+    pub content_type: Option<String>,
+    pub total_size: i64,
+    pub chunk_size: Option<i32>, // Server default when omitted
+    pub sha256: Option<String>, // Whole-file digest, checked at finalization
+}
+
+/// Half-open byte range `[start, end)` the server already holds
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UploadSessionResponse {
+    pub id: Uuid,
+    pub status: String,
+    pub file_name: String,
+    pub total_size: i64,
+    pub chunk_size: i32,
+    pub total_chunks: u32,
+    pub received_bytes: u64,
+    pub received: Vec<ByteRange>,
+    pub missing_chunks: Vec<u32>,
+    pub evidence_id: Option<i32>,
+    pub expires_at: DateTime<Utc>,
+}
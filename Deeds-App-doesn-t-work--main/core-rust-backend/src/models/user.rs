@@ -3,7 +3,7 @@ use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 use uuid::Uuid;
 
-#[derive(Debug, Serialize, Deserialize, FromRow)]
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct User {
     pub id: Uuid,
     pub email: String,
@@ -43,7 +43,9 @@ pub struct LoginRequest {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AuthResponse {
-    pub token: String,
+    pub token: String, // Short-lived access token
+    pub refresh_token: String,
+    pub expires_in: i64, // Access token lifetime in seconds
     pub user: UserResponse,
 }
 
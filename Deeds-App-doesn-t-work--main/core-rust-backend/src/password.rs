@@ -0,0 +1,124 @@
+// Password storage for prosecutor-core
+// New hashes are bcrypt (`$2b$<cost>$<salt><digest>`), which carries its own per-user salt
+// and cost. Hashes written by the old SHA-256 scheme are still accepted and flagged for upgrade.
+
+use anyhow::{anyhow, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use sha2::{Digest, Sha256};
+
+/// Cost used by the legacy SHA-256 scheme when verifying (it always assumed 12)
+const LEGACY_DEFAULT_COST: u32 = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HashScheme {
+    Bcrypt { cost: u32 },
+    LegacySha256,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PasswordCheck {
+    pub valid: bool,
+    /// True when the stored hash should be replaced with a fresh one at the configured cost
+    pub needs_rehash: bool,
+}
+
+pub fn hash_password(password: &str, cost: u32) -> Result<String> {
+    bcrypt::hash(password, cost).map_err(|e| anyhow!("Failed to hash password: {}", e))
+}
+
+pub fn identify(stored_hash: &str) -> Option<HashScheme> {
+    if let Some(rest) = stored_hash
+        .strip_prefix("$2b$")
+        .or_else(|| stored_hash.strip_prefix("$2a$"))
+        .or_else(|| stored_hash.strip_prefix("$2y$"))
+    {
+        return rest
+            .split('$')
+            .next()
+            .and_then(|cost| cost.parse().ok())
+            .map(|cost| HashScheme::Bcrypt { cost });
+    }
+
+    // Legacy hashes are a bare base64 SHA-256 digest
+    match STANDARD.decode(stored_hash) {
+        Ok(digest) if digest.len() == 32 => Some(HashScheme::LegacySha256),
+        _ => None,
+    }
+}
+
+/// Verify a password and report whether the stored hash is due for an upgrade to `target_cost`.
+pub fn verify_password(password: &str, stored_hash: &str, target_cost: u32) -> Result<PasswordCheck> {
+    match identify(stored_hash) {
+        Some(HashScheme::Bcrypt { cost }) => {
+            let valid = bcrypt::verify(password, stored_hash)
+                .map_err(|e| anyhow!("Failed to verify password: {}", e))?;
+            Ok(PasswordCheck {
+                valid,
+                needs_rehash: valid && cost != target_cost,
+            })
+        }
+        Some(HashScheme::LegacySha256) => {
+            // The old scheme salted with the configured cost but verified with 12, so accept either
+            let valid = [target_cost, LEGACY_DEFAULT_COST]
+                .iter()
+                .any(|&cost| constant_time_eq(legacy_hash(password, cost).as_bytes(), stored_hash.as_bytes()));
+            Ok(PasswordCheck {
+                valid,
+                needs_rehash: valid,
+            })
+        }
+        None => Err(anyhow!("Unrecognized password hash format")),
+    }
+}
+
+fn legacy_hash(password: &str, cost: u32) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(password.as_bytes());
+    hasher.update(format!("prosecutor_salt_{}", cost).as_bytes());
+    STANDARD.encode(hasher.finalize())
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bcrypt_roundtrip_uses_unique_salts() {
+        let first = hash_password("correct horse", 4).unwrap();
+        let second = hash_password("correct horse", 4).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(identify(&first), Some(HashScheme::Bcrypt { cost: 4 }));
+
+        let check = verify_password("correct horse", &first, 4).unwrap();
+        assert!(check.valid && !check.needs_rehash);
+        assert!(!verify_password("wrong", &first, 4).unwrap().valid);
+    }
+
+    #[test]
+    fn test_cost_change_requests_rehash() {
+        let stored = hash_password("correct horse", 4).unwrap();
+        let check = verify_password("correct horse", &stored, 5).unwrap();
+        assert!(check.valid && check.needs_rehash);
+    }
+
+    #[test]
+    fn test_legacy_hash_is_accepted_and_upgraded() {
+        let stored = legacy_hash("correct horse", 12);
+        assert_eq!(identify(&stored), Some(HashScheme::LegacySha256));
+
+        let check = verify_password("correct horse", &stored, 10).unwrap();
+        assert!(check.valid && check.needs_rehash);
+
+        let check = verify_password("wrong", &stored, 10).unwrap();
+        assert!(!check.valid && !check.needs_rehash);
+    }
+
+    #[test]
+    fn test_unknown_format_is_an_error() {
+        assert!(verify_password("anything", "plaintext", 12).is_err());
+    }
+}
@@ -0,0 +1,313 @@
+// Role-based access control for prosecutor-core
+// Roles come from `users.role` (carried in the access token). Handlers demand a permission by
+// taking an `Authorized<P>` extractor; case visibility is further limited to the creator,
+// the assignee and explicit collaborators unless the role can see every case.
+
+use anyhow::Result;
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{request::Parts, StatusCode},
+    response::{IntoResponse, Json, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt, marker::PhantomData, str::FromStr};
+use uuid::Uuid;
+
+use crate::{auth_simple::SimpleToken, database::DbConnection, repository::CaseRepository};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Prosecutor,
+    Paralegal,
+    Investigator,
+    Supervisor,
+    Admin,
+}
+
+impl FromStr for Role {
+    type Err = ();
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "prosecutor" => Ok(Self::Prosecutor),
+            "paralegal" => Ok(Self::Paralegal),
+            "investigator" => Ok(Self::Investigator),
+            "supervisor" => Ok(Self::Supervisor),
+            "admin" => Ok(Self::Admin),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    ViewAllCases,
+    CreateCase,
+    EditCase,
+    ArchiveCase,
+    ManageCollaborators,
+    UploadEvidence,
+    EditEvidence,
+    DeleteEvidence,
+    RecordCustody,
+    ManageSearchIndex,
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Permission::ViewAllCases => "view_all_cases",
+            Permission::CreateCase => "create_case",
+            Permission::EditCase => "edit_case",
+            Permission::ArchiveCase => "archive_case",
+            Permission::ManageCollaborators => "manage_collaborators",
+            Permission::UploadEvidence => "upload_evidence",
+            Permission::EditEvidence => "edit_evidence",
+            Permission::DeleteEvidence => "delete_evidence",
+            Permission::RecordCustody => "record_custody",
+            Permission::ManageSearchIndex => "manage_search_index",
+        };
+        f.write_str(name)
+    }
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Prosecutor => "prosecutor",
+            Role::Paralegal => "paralegal",
+            Role::Investigator => "investigator",
+            Role::Supervisor => "supervisor",
+            Role::Admin => "admin",
+        }
+    }
+
+    pub fn can(&self, permission: Permission) -> bool {
+        use Permission::*;
+        match self {
+            Role::Admin | Role::Supervisor => true,
+            Role::Prosecutor => matches!(
+                permission,
+                CreateCase
+                    | EditCase
+                    | ArchiveCase
+                    | ManageCollaborators
+                    | UploadEvidence
+                    | EditEvidence
+                    | DeleteEvidence
+                    | RecordCustody
+            ),
+            Role::Paralegal => matches!(permission, EditCase | UploadEvidence | EditEvidence | RecordCustody),
+            Role::Investigator => matches!(permission, UploadEvidence | RecordCustody),
+        }
+    }
+}
+
+/// The authenticated caller, taken from the token the auth middleware verified.
+#[derive(Debug, Clone)]
+pub struct CurrentUser {
+    pub user_id: Uuid,
+    pub role: Option<Role>, // None for roles this build does not know about
+}
+
+impl CurrentUser {
+    pub fn can(&self, permission: Permission) -> bool {
+        self.role.map(|role| role.can(permission)).unwrap_or(false)
+    }
+
+    pub fn require(&self, permission: Permission) -> Result<(), PermissionDenied> {
+        if self.can(permission) {
+            Ok(())
+        } else {
+            Err(PermissionDenied::new(format!(
+                "role '{}' lacks permission '{}'",
+                self.role.map(|role| role.as_str()).unwrap_or("unknown"),
+                permission
+            )))
+        }
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for CurrentUser {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let token = parts
+            .extensions
+            .get::<SimpleToken>()
+            .ok_or(StatusCode::UNAUTHORIZED)?;
+
+        Ok(Self {
+            user_id: token.user_id,
+            role: token.role.parse().ok(),
+        })
+    }
+}
+
+/// A permission a handler can demand through `Authorized<P>`.
+pub trait RequiredPermission: Send + Sync {
+    const PERMISSION: Permission;
+}
+
+macro_rules! required_permission {
+    ($($name:ident => $permission:ident),* $(,)?) => {
+        $(
+            pub struct $name;
+
+            impl RequiredPermission for $name {
+                const PERMISSION: Permission = Permission::$permission;
+            }
+        )*
+    };
+}
+
+required_permission! {
+    CanCreateCase => CreateCase,
+    CanEditCase => EditCase,
+    CanArchiveCase => ArchiveCase,
+    CanManageCollaborators => ManageCollaborators,
+    CanUploadEvidence => UploadEvidence,
+    CanEditEvidence => EditEvidence,
+    CanDeleteEvidence => DeleteEvidence,
+    CanRecordCustody => RecordCustody,
+    CanManageSearchIndex => ManageSearchIndex,
+}
+
+/// Extractor that only succeeds when the caller's role grants `P`; otherwise responds 403 with a reason.
+pub struct Authorized<P: RequiredPermission> {
+    pub user: CurrentUser,
+    _permission: PhantomData<P>,
+}
+
+impl<P: RequiredPermission> Authorized<P> {
+    pub fn user_id(&self) -> Uuid {
+        self.user.user_id
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync, P: RequiredPermission> FromRequestParts<S> for Authorized<P> {
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let user = CurrentUser::from_request_parts(parts, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        user.require(P::PERMISSION).map_err(|denied| {
+            tracing::warn!("Denied {} to user {}: {}", P::PERMISSION, user.user_id, denied.reason);
+            denied.into_response()
+        })?;
+
+        Ok(Self {
+            user,
+            _permission: PhantomData,
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PermissionDenied {
+    pub error: &'static str,
+    pub reason: String,
+}
+
+impl PermissionDenied {
+    pub fn new(reason: impl Into<String>) -> Self {
+        Self {
+            error: "forbidden",
+            reason: reason.into(),
+        }
+    }
+}
+
+impl IntoResponse for PermissionDenied {
+    fn into_response(self) -> Response {
+        (StatusCode::FORBIDDEN, Json(self)).into_response()
+    }
+}
+
+/// SQL predicate restricting `cases` (aliased `c`) to those visible to the user bound at `$param`.
+/// Always true for roles that may see every case (the parameter is still referenced so binds line up).
+pub fn case_visibility_clause(user: &CurrentUser, param: usize) -> String {
+    if user.can(Permission::ViewAllCases) {
+        format!("${}::UUID IS NOT NULL", param)
+    } else {
+        format!(
+            "(c.created_by = ${p} OR c.assigned_to = ${p} OR EXISTS (
+                SELECT 1 FROM case_collaborators cc WHERE cc.case_id = c.id AND cc.user_id = ${p}
+            ))",
+            p = param
+        )
+    }
+}
+
+/// Whether `user` may see the case; archived cases are visible to nobody.
+pub async fn can_access_case(db: &DbConnection, user: &CurrentUser, case_id: i32) -> Result<bool> {
+    let sql = format!(
+        "SELECT EXISTS (SELECT 1 FROM cases c WHERE c.id = $1 AND c.archived = false AND {})",
+        case_visibility_clause(user, 2)
+    );
+    let (visible,): (bool,) = sqlx::query_as(&sql)
+        .bind(case_id)
+        .bind(user.user_id)
+        .fetch_one(db.as_ref())
+        .await?;
+    Ok(visible)
+}
+
+/// Map case visibility to a handler status: hidden cases look the same as missing ones.
+pub async fn ensure_case_access(cases: &dyn CaseRepository, user: &CurrentUser, case_id: i32) -> Result<(), StatusCode> {
+    match cases.is_visible(user, case_id).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            tracing::error!("Case access check failed for case {}: {}", case_id, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(role: &str) -> CurrentUser {
+        CurrentUser {
+            user_id: Uuid::new_v4(),
+            role: role.parse().ok(),
+        }
+    }
+
+    #[test]
+    fn test_role_matrix() {
+        assert!(user("prosecutor").can(Permission::ArchiveCase));
+        assert!(!user("prosecutor").can(Permission::ViewAllCases));
+        assert!(user("paralegal").can(Permission::EditCase));
+        assert!(!user("paralegal").can(Permission::DeleteEvidence));
+        assert!(user("paralegal").can(Permission::EditEvidence));
+        assert!(!user("investigator").can(Permission::EditEvidence));
+        assert!(user("investigator").can(Permission::UploadEvidence));
+        assert!(!user("investigator").can(Permission::CreateCase));
+        assert!(user("supervisor").can(Permission::ViewAllCases));
+        assert!(user("Admin").can(Permission::DeleteEvidence));
+        assert!(user("supervisor").can(Permission::ManageSearchIndex));
+        assert!(!user("prosecutor").can(Permission::ManageSearchIndex));
+    }
+
+    #[test]
+    fn test_unknown_role_has_no_permissions() {
+        let denied = user("user").require(Permission::UploadEvidence).unwrap_err();
+        assert_eq!(denied.reason, "role 'unknown' lacks permission 'upload_evidence'");
+    }
+
+    #[test]
+    fn test_visibility_clause() {
+        assert_eq!(case_visibility_clause(&user("supervisor"), 1), "$1::UUID IS NOT NULL");
+        assert!(case_visibility_clause(&user("paralegal"), 3).contains("cc.user_id = $3"));
+    }
+}
@@ -1,19 +1,39 @@
-use anyhow::Result;
-use qdrant_client::{
-    prelude::*,
-    qdrant::{
-        vectors::VectorsOptions, CreateCollection, SearchPoints, UpsertPoints, VectorParams,
-        VectorsConfig, Distance, CollectionOperationResponse, SearchResponse,
+// Qdrant vector index, over its REST API
+// Queries and writes go through an alias (QDRANT_COLLECTION, default `prosecutor_cases`) that
+// points at one versioned collection; vector_collections.rs creates those collections and moves
+// the alias. The client remembers the vector size behind the alias so a query embedded by a
+// different model fails with a clear error instead of a Qdrant one.
+// Evidence with extracted text is indexed a chunk at a time, each point carrying the chunk's
+// anchor so a hit can point at the page it came from; all of an evidence row's points share its
+// `evidence_id` in the payload.
+
+use anyhow::{anyhow, Result};
+use axum::async_trait;
+use reqwest::{Method, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
     },
+    time::Duration,
+};
+
+use crate::{
+    chunker::ChunkAnchor,
+    hybrid::{Neighbour, VectorIndex},
 };
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use uuid::Uuid;
 
 #[derive(Debug, Clone)]
 pub struct QdrantClient {
-    client: qdrant_client::client::QdrantClient,
-    collection_name: String,
+    http: reqwest::Client,
+    url: String,
+    api_key: Option<String>,
+    alias: String,
+    // Vector size of the collection behind the alias; 0 until known
+    serving_dimensions: Arc<AtomicUsize>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -23,96 +43,210 @@ pub struct EvidenceVector {
     pub title: String,
     pub description: Option<String>,
     pub evidence_type: String,
-    pub tags: Vec<String>,
+    /// The chunk of extracted text embedded; `None` for the title and description
+    pub chunk: Option<ChunkAnchor>,
     pub embedding: Vec<f32>,
 }
 
+impl EvidenceVector {
+    /// Evidence id in the high bits and chunk number + 1 in the low 24, so re-indexing a row
+    /// overwrites its points in place
+    pub fn point_id(&self) -> u64 {
+        let chunk = self.chunk.map_or(0, |anchor| anchor.chunk as u64 + 1);
+        ((self.evidence_id as u64) << 24) | (chunk & 0xff_ffff)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SearchResult {
     pub evidence_id: i32,
     pub score: f32,
+    pub anchor: Option<ChunkAnchor>,
     pub metadata: HashMap<String, String>,
 }
 
+#[derive(Deserialize)]
+struct ApiResponse<T> {
+    result: T,
+}
+
+#[derive(Deserialize)]
+struct ApiError {
+    status: Option<ApiErrorStatus>,
+}
+
+#[derive(Deserialize)]
+struct ApiErrorStatus {
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct AliasList {
+    aliases: Vec<AliasEntry>,
+}
+
+#[derive(Deserialize)]
+struct AliasEntry {
+    alias_name: String,
+    collection_name: String,
+}
+
+#[derive(Deserialize)]
+struct ScoredPoint {
+    score: f32,
+    payload: Option<serde_json::Map<String, Value>>,
+}
+
 impl QdrantClient {
-    pub async fn new(url: &str, collection_name: &str) -> Result<Self> {
-        let client = qdrant_client::client::QdrantClient::from_url(url).build()?;
-        
-        let qdrant_client = Self {
-            client,
-            collection_name: collection_name.to_string(),
+    /// Connect to Qdrant and look up the collection behind `alias`
+    pub async fn new(url: &str, api_key: Option<String>, alias: &str) -> Result<Self> {
+        let client = Self {
+            http: reqwest::Client::builder().timeout(Duration::from_secs(30)).build()?,
+            url: url.trim_end_matches('/').to_string(),
+            api_key,
+            alias: alias.to_string(),
+            serving_dimensions: Arc::new(AtomicUsize::new(0)),
         };
+        client.refresh_serving_dimensions().await?;
+        Ok(client)
+    }
 
-        // Initialize collection if it doesn't exist
-        qdrant_client.ensure_collection_exists().await?;
+    /// The name queries go through
+    pub fn alias(&self) -> &str {
+        &self.alias
+    }
 
-        Ok(qdrant_client)
+    /// Vector size of the collection currently serving queries
+    pub fn serving_dimensions(&self) -> Option<usize> {
+        Some(self.serving_dimensions.load(Ordering::Relaxed)).filter(|dimensions| *dimensions > 0)
     }
 
-    async fn ensure_collection_exists(&self) -> Result<()> {
-        // Check if collection exists
-        let collections = self.client.list_collections().await?;
-        
-        let collection_exists = collections
-            .collections
-            .iter()
-            .any(|c| c.name == self.collection_name);
-
-        if !collection_exists {
-            tracing::info!("🔍 Creating Qdrant collection: {}", self.collection_name);
-            
-            // Create collection with 1536 dimensions (OpenAI ada-002 embedding size)
-            let create_collection = CreateCollection {
-                collection_name: self.collection_name.clone(),
-                vectors_config: Some(VectorsConfig {
-                    config: Some(qdrant_client::qdrant::vectors_config::Config::Params(
-                        VectorParams {
-                            size: 1536, // OpenAI ada-002 embedding dimensions
-                            distance: Distance::Cosine.into(),
-                            hnsw_config: None,
-                            quantization_config: None,
-                            on_disk: Some(true), // Store on disk for better memory usage
-                        },
-                    )),
-                }),
-                ..Default::default()
-            };
-
-            self.client.create_collection(&create_collection).await?;
-            tracing::info!("✅ Qdrant collection created successfully");
-        } else {
-            tracing::info!("✅ Qdrant collection already exists");
+    /// Re-read the vector size behind the alias, e.g. after another instance switched it
+    pub async fn refresh_serving_dimensions(&self) -> Result<Option<usize>> {
+        let dimensions = self.collection_dimensions(&self.alias).await?;
+        self.serving_dimensions.store(dimensions.unwrap_or(0), Ordering::Relaxed);
+        Ok(dimensions)
+    }
+
+    fn request(&self, method: Method, path: &str) -> reqwest::RequestBuilder {
+        let request = self.http.request(method, format!("{}{}", self.url, path));
+        match &self.api_key {
+            Some(api_key) => request.header("api-key", api_key),
+            None => request,
         }
+    }
 
+    /// Send a request and unwrap Qdrant's `{"result": ...}` envelope. `None` on 404.
+    async fn call<T: DeserializeOwned>(&self, request: reqwest::RequestBuilder) -> Result<Option<T>> {
+        let response = request.send().await?;
+        let status = response.status();
+        if status == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            let message = serde_json::from_str::<ApiError>(&body)
+                .ok()
+                .and_then(|error| error.status)
+                .and_then(|status| status.error)
+                .unwrap_or(body);
+            return Err(anyhow!("Qdrant request failed with {}: {}", status, message.trim()));
+        }
+        Ok(Some(response.json::<ApiResponse<T>>().await?.result))
+    }
+
+    async fn call_required<T: DeserializeOwned>(&self, request: reqwest::RequestBuilder, what: &str) -> Result<T> {
+        self.call(request).await?.ok_or_else(|| anyhow!("Qdrant: {} not found", what))
+    }
+
+    /// The collection an alias points at, if the alias exists
+    pub async fn resolve_alias(&self, alias: &str) -> Result<Option<String>> {
+        let list: AliasList = self.call_required(self.request(Method::GET, "/aliases"), "aliases").await?;
+        Ok(list
+            .aliases
+            .into_iter()
+            .find(|entry| entry.alias_name == alias)
+            .map(|entry| entry.collection_name))
+    }
+
+    /// Vector size of a collection (or alias), `None` if it does not exist
+    pub async fn collection_dimensions(&self, name: &str) -> Result<Option<usize>> {
+        let info: Option<Value> = self.call(self.request(Method::GET, &format!("/collections/{}", name))).await?;
+        let Some(info) = info else { return Ok(None) };
+        info.pointer("/config/params/vectors/size")
+            .and_then(Value::as_u64)
+            .map(|size| Some(size as usize))
+            .ok_or_else(|| anyhow!("collection {} does not have a single unnamed vector", name))
+    }
+
+    pub async fn create_collection(&self, name: &str, dimensions: usize) -> Result<()> {
+        let body = json!({
+            "vectors": { "size": dimensions, "distance": "Cosine", "on_disk": true },
+        });
+        let _: Value = self
+            .call_required(self.request(Method::PUT, &format!("/collections/{}", name)).json(&body), name)
+            .await?;
+        tracing::info!("Created Qdrant collection {} ({} dimensions)", name, dimensions);
         Ok(())
     }
 
-    pub async fn upsert_evidence(&self, evidence: &EvidenceVector) -> Result<()> {
-        let points = vec![PointStruct::new(
-            evidence.evidence_id as u64,
-            evidence.embedding.clone(),
-            [
-                ("evidence_id", evidence.evidence_id.into()),
-                ("case_id", evidence.case_id.unwrap_or(0).into()),
-                ("title", evidence.title.clone().into()),
-                ("description", evidence.description.clone().unwrap_or_default().into()),
-                ("evidence_type", evidence.evidence_type.clone().into()),
-                ("tags", serde_json::to_string(&evidence.tags).unwrap_or_default().into()),
-            ]
-        )];
-
-        let upsert_points = UpsertPoints {
-            collection_name: self.collection_name.clone(),
-            points,
-            ..Default::default()
-        };
+    pub async fn delete_collection(&self, name: &str) -> Result<()> {
+        let _: Option<Value> = self.call(self.request(Method::DELETE, &format!("/collections/{}", name))).await?;
+        Ok(())
+    }
 
-        self.client.upsert_points(upsert_points).await?;
-        tracing::debug!("📝 Upserted evidence {} to Qdrant", evidence.evidence_id);
+    /// Point the alias at `collection`. Removing the old target and adding the new one is a
+    /// single Qdrant request, so queries never see the alias missing.
+    pub async fn switch_alias(&self, collection: &str) -> Result<()> {
+        let mut actions = Vec::new();
+        if self.resolve_alias(&self.alias).await?.is_some() {
+            actions.push(json!({ "delete_alias": { "alias_name": self.alias } }));
+        } else if self.collection_dimensions(&self.alias).await?.is_some() {
+            // A collection from before versioning holds the alias name; it has to go first
+            tracing::warn!("Deleting unversioned Qdrant collection {} to make way for its alias", self.alias);
+            self.delete_collection(&self.alias).await?;
+        }
+        actions.push(json!({ "create_alias": { "collection_name": collection, "alias_name": self.alias } }));
+
+        let _: Value = self
+            .call_required(
+                self.request(Method::POST, "/collections/aliases").json(&json!({ "actions": actions })),
+                "aliases",
+            )
+            .await?;
+        self.refresh_serving_dimensions().await?;
+        tracing::info!("Qdrant alias {} now points at {}", self.alias, collection);
+        Ok(())
+    }
 
+    /// Write evidence vectors to a collection; pass `alias()` for the serving one
+    pub async fn upsert_evidence(&self, collection: &str, evidence: &[EvidenceVector]) -> Result<()> {
+        let points: Vec<Value> = evidence
+            .iter()
+            .map(|evidence| {
+                json!({
+                    "id": evidence.point_id(),
+                    "vector": evidence.embedding,
+                    "payload": {
+                        "evidence_id": evidence.evidence_id,
+                        "case_id": evidence.case_id,
+                        "title": evidence.title,
+                        "description": evidence.description,
+                        "evidence_type": evidence.evidence_type,
+                        "anchor": evidence.chunk,
+                    },
+                })
+            })
+            .collect();
+        let path = format!("/collections/{}/points?wait=true", collection);
+        let _: Value = self
+            .call_required(self.request(Method::PUT, &path).json(&json!({ "points": points })), collection)
+            .await?;
+        tracing::debug!("📝 Upserted {} evidence vectors to {}", evidence.len(), collection);
         Ok(())
     }
 
+    /// Nearest evidence in the serving collection. `filters` must match payload values exactly.
     pub async fn search_similar_evidence(
         &self,
         query_vector: Vec<f32>,
@@ -120,93 +254,181 @@ impl QdrantClient {
         score_threshold: Option<f32>,
         filters: Option<HashMap<String, String>>,
     ) -> Result<Vec<SearchResult>> {
-        let mut search_points = SearchPoints {
-            collection_name: self.collection_name.clone(),
-            vector: query_vector,
-            limit,
-            score_threshold,
-            with_payload: Some(true.into()),
-            ..Default::default()
-        };
+        let must = filters
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(key, value)| json!({ "key": key, "match": { "value": value } }))
+            .collect();
+        self.search_points(query_vector, limit, score_threshold, must).await
+    }
+
+    /// Nearest points, restricted by Qdrant `must` conditions
+    async fn search_points(
+        &self,
+        query_vector: Vec<f32>,
+        limit: u64,
+        score_threshold: Option<f32>,
+        must: Vec<Value>,
+    ) -> Result<Vec<SearchResult>> {
+        if let Some(dimensions) = self.serving_dimensions() {
+            if query_vector.len() != dimensions {
+                return Err(anyhow!(
+                    "query vector has {} dimensions but {} holds {}; re-embed the collection with the current model",
+                    query_vector.len(),
+                    self.alias,
+                    dimensions
+                ));
+            }
+        }
 
-        // Apply filters if provided
-        if let Some(filters) = filters {
-            // Convert filters to Qdrant filter format
-            // This is a simplified version - you can extend it for more complex filtering
-            tracing::debug!("🔍 Applying filters: {:?}", filters);
+        let mut body = json!({
+            "vector": query_vector,
+            "limit": limit,
+            "with_payload": true,
+        });
+        if let Some(score_threshold) = score_threshold {
+            body["score_threshold"] = json!(score_threshold);
+        }
+        if !must.is_empty() {
+            body["filter"] = json!({ "must": must });
         }
 
-        let search_result = self.client.search_points(&search_points).await?;
+        let path = format!("/collections/{}/points/search", self.alias);
+        let points: Vec<ScoredPoint> = self.call_required(self.request(Method::POST, &path).json(&body), &self.alias).await?;
 
-        let results = search_result
-            .result
+        let results = points
             .into_iter()
-            .map(|scored_point| {
-                let mut metadata = HashMap::new();
-                
-                if let Some(payload) = scored_point.payload {
-                    for (key, value) in payload {
-                        if let Some(string_value) = value.as_str() {
-                            metadata.insert(key, string_value.to_string());
-                        }
-                    }
-                }
-
-                SearchResult {
-                    evidence_id: scored_point.id.unwrap().point_id_options.unwrap().try_into().unwrap_or(0),
-                    score: scored_point.score,
-                    metadata,
-                }
+            .filter_map(|point| {
+                let payload = point.payload.unwrap_or_default();
+                let evidence_id = payload.get("evidence_id").and_then(Value::as_i64)? as i32;
+                let anchor = payload.get("anchor").and_then(|anchor| ChunkAnchor::deserialize(anchor).ok());
+                let metadata = payload
+                    .into_iter()
+                    .filter_map(|(key, value)| match value {
+                        Value::String(value) => Some((key, value)),
+                        Value::Number(value) => Some((key, value.to_string())),
+                        _ => None,
+                    })
+                    .collect();
+                Some(SearchResult { evidence_id, score: point.score, anchor, metadata })
             })
             .collect();
-
         Ok(results)
     }
 
-    pub async fn search_by_tags(&self, tags: Vec<String>, limit: u64) -> Result<Vec<SearchResult>> {
-        // This would implement tag-based filtering
-        // For now, we'll use a simple approach - in a real implementation,
-        // you'd want to use Qdrant's filtering capabilities
-        
-        tracing::info!("🏷️  Searching by tags: {:?}", tags);
-        
-        // Create a filter for tags
-        let filters = tags.iter()
-            .map(|tag| ("tags".to_string(), tag.clone()))
-            .collect();
+    /// Remove every point of an evidence row from a collection; pass `alias()` for the serving one
+    pub async fn delete_evidence(&self, collection: &str, evidence_id: i32) -> Result<()> {
+        let path = format!("/collections/{}/points/delete?wait=true", collection);
+        let body = json!({ "filter": { "must": [{ "key": "evidence_id", "match": { "value": evidence_id } }] } });
+        let _: Option<Value> = self.call(self.request(Method::POST, &path).json(&body)).await?;
+        tracing::debug!("🗑️  Deleted evidence {} from {}", evidence_id, collection);
+        Ok(())
+    }
 
-        // For semantic search, we'd need to convert tags to embeddings
-        // This is a placeholder - implement based on your embedding strategy
-        let query_vector = vec![0.0; 1536]; // Placeholder vector
+    pub async fn get_collection_info(&self) -> Result<String> {
+        let info: Value = self
+            .call_required(self.request(Method::GET, &format!("/collections/{}", self.alias)), &self.alias)
+            .await?;
+        Ok(format!("Collection: {} - Points: {}", self.alias, info["points_count"]))
+    }
+}
 
-        self.search_similar_evidence(query_vector, limit, Some(0.7), Some(filters)).await
+#[async_trait]
+impl VectorIndex for QdrantClient {
+    async fn nearest_evidence(&self, vector: Vec<f32>, limit: u64, case_id: Option<i32>) -> Result<Vec<Neighbour>> {
+        // `case_id` is stored as a number, which the string filters of search_similar_evidence miss
+        let must = case_id.map(|case_id| json!({ "key": "case_id", "match": { "value": case_id } }));
+        let results = self.search_points(vector, limit, None, must.into_iter().collect()).await?;
+        Ok(results
+            .into_iter()
+            .map(|result| Neighbour { evidence_id: result.evidence_id, similarity: result.score, anchor: result.anchor })
+            .collect())
     }
+}
 
-    pub async fn delete_evidence(&self, evidence_id: i32) -> Result<()> {
-        let points_selector = PointsSelector {
-            points_selector_one_of: Some(
-                qdrant_client::qdrant::points_selector::PointsSelectorOneOf::Points(
-                    PointsIdsList {
-                        ids: vec![PointId::from(evidence_id as u64)],
-                    }
-                )
-            ),
-        };
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::Matcher;
 
-        let delete_points = qdrant_client::qdrant::DeletePoints {
-            collection_name: self.collection_name.clone(),
-            points: Some(points_selector),
-            ..Default::default()
-        };
+    fn collection_info(size: usize) -> String {
+        json!({ "result": { "points_count": 3, "config": { "params": { "vectors": { "size": size, "distance": "Cosine" } } } } })
+            .to_string()
+    }
 
-        self.client.delete_points(&delete_points).await?;
-        tracing::debug!("🗑️  Deleted evidence {} from Qdrant", evidence_id);
+    #[tokio::test]
+    async fn test_switch_alias_replaces_the_old_target_in_one_request() {
+        let mut server = mockito::Server::new_async().await;
+        server
+            .mock("GET", "/collections/cases")
+            .with_body(collection_info(3))
+            .expect_at_least(1)
+            .create_async()
+            .await;
+        server
+            .mock("GET", "/aliases")
+            .with_body(json!({ "result": { "aliases": [{ "alias_name": "cases", "collection_name": "cases__hash__3d_v1" }] } }).to_string())
+            .create_async()
+            .await;
+        let switch = server
+            .mock("POST", "/collections/aliases")
+            .match_body(Matcher::Json(json!({ "actions": [
+                { "delete_alias": { "alias_name": "cases" } },
+                { "create_alias": { "collection_name": "cases__hash__3d_v2", "alias_name": "cases" } },
+            ] })))
+            .with_body(r#"{"result": true, "status": "ok"}"#)
+            .create_async()
+            .await;
 
-        Ok(())
+        let client = QdrantClient::new(&server.url(), None, "cases").await.unwrap();
+        assert_eq!(client.serving_dimensions(), Some(3));
+        client.switch_alias("cases__hash__3d_v2").await.unwrap();
+        switch.assert_async().await;
     }
 
-    pub async fn get_collection_info(&self) -> Result<String> {
-        let info = self.client.collection_info(&self.collection_name).await?;
-        Ok(format!("Collection: {} - Points: {:?}", self.collection_name, info.result))
+    #[tokio::test]
+    async fn test_search_checks_dimensions_and_reads_payload_ids() {
+        let mut server = mockito::Server::new_async().await;
+        server.mock("GET", "/collections/cases").with_body(collection_info(2)).create_async().await;
+        server
+            .mock("POST", "/collections/cases/points/search")
+            .match_header("api-key", "secret")
+            .match_body(Matcher::PartialJson(json!({ "limit": 5, "filter": { "must": [{ "key": "evidence_type", "match": { "value": "document" } }] } })))
+            .with_body(
+                json!({ "result": [
+                    { "id": 117440513, "score": 0.9, "payload": {
+                        "evidence_id": 7,
+                        "title": "Statement",
+                        "anchor": { "kind": "text_chunk", "chunk": 0, "page": 3, "start": 0, "end": 80 },
+                    } },
+                    { "id": "0b6c", "score": 0.5, "payload": {} },
+                ] })
+                .to_string(),
+            )
+            .create_async()
+            .await;
+
+        let client = QdrantClient::new(&server.url(), Some("secret".to_string()), "cases").await.unwrap();
+        let mismatch = client.nearest_evidence(vec![0.1, 0.2, 0.3], 5, None).await.unwrap_err();
+        assert!(mismatch.to_string().contains("re-embed"), "{}", mismatch);
+
+        let filters = HashMap::from([("evidence_type".to_string(), "document".to_string())]);
+        let results = client.search_similar_evidence(vec![0.1, 0.2], 5, None, Some(filters)).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].evidence_id, 7);
+        assert_eq!(results[0].anchor, Some(ChunkAnchor { chunk: 0, page: 3, start: 0, end: 80 }));
+        assert_eq!(results[0].metadata["title"], "Statement");
+
+        // Case ids are matched as numbers, the way they are stored
+        let in_case = server
+            .mock("POST", "/collections/cases/points/search")
+            .match_body(Matcher::PartialJson(json!({ "filter": { "must": [{ "key": "case_id", "match": { "value": 3 } }] } })))
+            .with_body(json!({ "result": [{ "id": 1, "score": 0.4, "payload": { "evidence_id": 2, "case_id": 3 } }] }).to_string())
+            .create_async()
+            .await;
+        let neighbours = client.nearest_evidence(vec![0.1, 0.2], 5, Some(3)).await.unwrap();
+        assert_eq!(neighbours.len(), 1);
+        assert_eq!((neighbours[0].evidence_id, neighbours[0].anchor), (2, None));
+        in_case.assert_async().await;
     }
 }
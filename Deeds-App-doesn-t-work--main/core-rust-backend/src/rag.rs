@@ -0,0 +1,348 @@
+// Retrieval-augmented case Q&A
+// A question about a case is embedded and the nearest evidence chunks of that case are pulled from
+// the vector index. The chunks go to the LLM as numbered excerpts with instructions to answer only
+// from them and to cite each statement as [n]. Markers that name a real excerpt become citations
+// (evidence id, page, snippet). When nothing relevant is retrieved, or the model declines or cites
+// nothing, the answer says the evidence does not support one instead of passing on a guess.
+
+use serde::{Deserialize, Serialize};
+use sqlx::{query_as, FromRow};
+use std::collections::{BTreeSet, HashSet};
+
+use crate::{
+    chunker::ChunkAnchor,
+    database::DbConnection,
+    embedder::{embed_one, EmbeddingProvider},
+    hybrid::VectorIndex,
+    llm_backend::{GenerationRequest, LlmBackend, TokenUsage},
+};
+
+/// What the model is told to reply when the excerpts do not answer the question
+pub const NO_ANSWER_MARKER: &str = "NO_ANSWER";
+
+pub const UNSUPPORTED_ANSWER: &str =
+    "The evidence indexed for this case does not contain enough to answer this question.";
+
+/// Cosine similarity below which a chunk is not worth showing the model
+pub const DEFAULT_MIN_SCORE: f32 = 0.2;
+
+const SNIPPET_CHARS: usize = 240;
+
+const SYSTEM_PROMPT: &str = "You assist prosecutors by answering questions about a single criminal case. \
+Use only the numbered evidence excerpts you are given, never outside knowledge. After every statement, cite \
+the excerpts it rests on by number in square brackets, like [2] or [1][3]. If the excerpts do not answer the \
+question, reply with exactly NO_ANSWER and nothing else.";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AskRequest {
+    pub question: String,
+    /// Excerpts given to the model, default 6
+    pub limit: Option<u32>,
+    /// Minimum similarity for an excerpt, default `DEFAULT_MIN_SCORE`
+    pub min_score: Option<f32>,
+    pub max_tokens: Option<u32>,
+}
+
+/// A retrieved excerpt, numbered from 1 in the prompt
+#[derive(Debug, Clone, PartialEq)]
+pub struct Source {
+    pub evidence_id: i32,
+    pub title: String,
+    pub anchor: Option<ChunkAnchor>,
+    pub text: String,
+    pub score: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Citation {
+    /// The `[n]` used in the answer text
+    pub marker: usize,
+    pub evidence_id: i32,
+    pub title: String,
+    pub page: Option<usize>,
+    pub anchor: Option<ChunkAnchor>,
+    pub snippet: String,
+    pub score: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CaseAnswer {
+    pub answer: String,
+    /// False when no excerpt supports an answer; `answer` then says so
+    pub supported: bool,
+    pub citations: Vec<Citation>,
+    /// Excerpts retrieved, cited or not
+    pub sources_considered: usize,
+    pub model: Option<String>,
+    pub usage: Option<TokenUsage>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AskError {
+    #[error("invalid request: {0}")]
+    Invalid(&'static str),
+    #[error("unavailable: {0}")]
+    Unavailable(&'static str),
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+#[derive(FromRow)]
+struct SourceRow {
+    position: i64,
+    id: i32,
+    title: String,
+    text: String,
+}
+
+/// Answer `request` from the evidence of `case_id`. Case access must already have been checked.
+pub async fn ask(
+    db: &DbConnection,
+    index: &dyn VectorIndex,
+    embedder: Option<&dyn EmbeddingProvider>,
+    llm: Option<&dyn LlmBackend>,
+    case_id: i32,
+    request: AskRequest,
+) -> Result<CaseAnswer, AskError> {
+    let question = request.question.trim();
+    if question.is_empty() || question.chars().count() > 1000 {
+        return Err(AskError::Invalid("question must be between 1 and 1000 characters"));
+    }
+    let min_score = request.min_score.unwrap_or(DEFAULT_MIN_SCORE);
+    if !min_score.is_finite() {
+        return Err(AskError::Invalid("min_score must be finite"));
+    }
+    let embedder = embedder.ok_or(AskError::Unavailable("no embedding provider is configured"))?;
+    let llm = llm.ok_or(AskError::Unavailable("no LLM backend is configured"))?;
+    let limit = request.limit.unwrap_or(6).clamp(1, 20) as usize;
+
+    let sources = retrieve(db, index, embedder, case_id, question, limit, min_score).await?;
+    Ok(answer(llm, question, &sources, request.max_tokens).await?)
+}
+
+/// The case's evidence chunks nearest to `question`, best first
+pub async fn retrieve(
+    db: &DbConnection,
+    index: &dyn VectorIndex,
+    embedder: &dyn EmbeddingProvider,
+    case_id: i32,
+    question: &str,
+    limit: usize,
+    min_score: f32,
+) -> anyhow::Result<Vec<Source>> {
+    let embedding = embed_one(embedder, question).await?;
+    // A row's title point and its chunks are separate neighbours; only exact repeats are dropped
+    let mut seen = HashSet::new();
+    let nearest: Vec<_> = index
+        .nearest_evidence(embedding, limit as u64 * 2, Some(case_id))
+        .await?
+        .into_iter()
+        .filter(|neighbour| neighbour.similarity >= min_score)
+        .filter(|neighbour| seen.insert((neighbour.evidence_id, neighbour.anchor.map(|anchor| anchor.chunk))))
+        .take(limit)
+        .collect();
+    if nearest.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let ids: Vec<i32> = nearest.iter().map(|neighbour| neighbour.evidence_id).collect();
+    let starts: Vec<Option<i32>> = nearest.iter().map(|neighbour| neighbour.anchor.map(|anchor| anchor.start as i32)).collect();
+    let lengths: Vec<Option<i32>> = nearest
+        .iter()
+        .map(|neighbour| neighbour.anchor.map(|anchor| anchor.end.saturating_sub(anchor.start) as i32))
+        .collect();
+
+    // The case is checked again here: the index payload is only as current as the last re-embed
+    let rows = query_as::<_, SourceRow>(
+        r#"
+        SELECT n.position, e.id, e.title,
+            CASE WHEN n.chunk_start IS NULL
+                THEN concat_ws(E'\n\n', e.title, nullif(e.description, ''))
+                ELSE coalesce(substr(e.extracted_text, n.chunk_start + 1, n.chunk_length), '')
+            END AS text
+        FROM unnest($1::INTEGER[], $2::INTEGER[], $3::INTEGER[]) WITH ORDINALITY AS n(id, chunk_start, chunk_length, position)
+        JOIN evidence e ON e.id = n.id
+        WHERE e.case_id = $4
+        ORDER BY n.position
+        "#,
+    )
+    .bind(&ids)
+    .bind(&starts)
+    .bind(&lengths)
+    .bind(case_id)
+    .fetch_all(db.as_ref())
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .filter(|row| !row.text.trim().is_empty())
+        .map(|row| {
+            let neighbour = &nearest[row.position as usize - 1];
+            Source { evidence_id: row.id, title: row.title, anchor: neighbour.anchor, text: row.text, score: neighbour.similarity }
+        })
+        .collect())
+}
+
+/// Ask the model to answer from `sources` and attach the citations it used
+pub async fn answer(
+    llm: &dyn LlmBackend,
+    question: &str,
+    sources: &[Source],
+    max_tokens: Option<u32>,
+) -> anyhow::Result<CaseAnswer> {
+    if sources.is_empty() {
+        return Ok(unsupported(0, None, None));
+    }
+    let mut request = build_prompt(question, sources);
+    request.max_tokens = max_tokens.or(request.max_tokens);
+    let generation = llm.generate(&request).await?;
+
+    let text = generation.text.trim();
+    let citations = citations(text, sources);
+    if citations.is_empty() || text.contains(NO_ANSWER_MARKER) {
+        return Ok(unsupported(sources.len(), Some(generation.model), Some(generation.usage)));
+    }
+    Ok(CaseAnswer {
+        answer: text.to_string(),
+        supported: true,
+        citations,
+        sources_considered: sources.len(),
+        model: Some(generation.model),
+        usage: Some(generation.usage),
+    })
+}
+
+fn unsupported(sources_considered: usize, model: Option<String>, usage: Option<TokenUsage>) -> CaseAnswer {
+    CaseAnswer {
+        answer: UNSUPPORTED_ANSWER.to_string(),
+        supported: false,
+        citations: Vec::new(),
+        sources_considered,
+        model,
+        usage,
+    }
+}
+
+/// The grounded prompt: numbered excerpts, each labelled with its evidence and page, then the question
+pub fn build_prompt(question: &str, sources: &[Source]) -> GenerationRequest {
+    let mut prompt = String::from("Evidence excerpts:\n\n");
+    for (i, source) in sources.iter().enumerate() {
+        let page = source.anchor.map(|anchor| format!(", page {}", anchor.page)).unwrap_or_default();
+        prompt.push_str(&format!(
+            "[{}] {} (evidence {}{})\n{}\n\n",
+            i + 1,
+            source.title,
+            source.evidence_id,
+            page,
+            source.text.trim()
+        ));
+    }
+    prompt.push_str(&format!("Question: {}\nAnswer:", question));
+
+    GenerationRequest {
+        system: Some(SYSTEM_PROMPT.to_string()),
+        prompt,
+        max_tokens: Some(512),
+        temperature: Some(0.1),
+    }
+}
+
+/// Citations for the `[n]` markers in `answer` that name one of `sources`, by marker. Markers may
+/// list several excerpts (`[1, 3]`); anything else in brackets is ignored.
+pub fn citations(answer: &str, sources: &[Source]) -> Vec<Citation> {
+    let mut markers = BTreeSet::new();
+    let mut rest = answer;
+    while let Some(open) = rest.find('[') {
+        rest = &rest[open + 1..];
+        let Some(close) = rest.find(']') else { break };
+        let numbers: Option<Vec<usize>> = rest[..close].split(',').map(|part| part.trim().parse().ok()).collect();
+        markers.extend(numbers.unwrap_or_default().into_iter().filter(|&n| n >= 1 && n <= sources.len()));
+        rest = &rest[close + 1..];
+    }
+
+    markers
+        .into_iter()
+        .map(|marker| {
+            let source = &sources[marker - 1];
+            Citation {
+                marker,
+                evidence_id: source.evidence_id,
+                title: source.title.clone(),
+                page: source.anchor.map(|anchor| anchor.page),
+                anchor: source.anchor,
+                snippet: snippet(&source.text),
+                score: source.score,
+            }
+        })
+        .collect()
+}
+
+fn snippet(text: &str) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    match text.char_indices().nth(SNIPPET_CHARS) {
+        Some((cut, _)) => format!("{}…", &text[..cut]),
+        None => text,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm_backend::MockLlmBackend;
+
+    fn sources() -> Vec<Source> {
+        vec![
+            Source {
+                evidence_id: 4,
+                title: "Witness statement".to_string(),
+                anchor: Some(ChunkAnchor { chunk: 2, page: 7, start: 900, end: 1000 }),
+                text: "The witness saw  Mr. Doe\ndriving a white van.".to_string(),
+                score: 0.81,
+            },
+            Source { evidence_id: 9, title: "Photo log".to_string(), anchor: None, text: "Photo log\n\nVan at the harbor".to_string(), score: 0.44 },
+        ]
+    }
+
+    #[test]
+    fn test_prompt_numbers_and_labels_excerpts() {
+        let request = build_prompt("Who drove the van?", &sources());
+        assert!(request.system.unwrap().contains(NO_ANSWER_MARKER));
+        assert!(request.prompt.contains("[1] Witness statement (evidence 4, page 7)\nThe witness saw"));
+        assert!(request.prompt.contains("[2] Photo log (evidence 9)\n"));
+    }
+
+    #[test]
+    fn test_citations_follow_valid_markers() {
+        let cited = citations("Mr. Doe drove [1, 2]. It was white [1][7] (see [note]).", &sources());
+        assert_eq!(cited.iter().map(|citation| citation.marker).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(cited[0].evidence_id, 4);
+        assert_eq!(cited[0].page, Some(7));
+        assert_eq!(cited[0].snippet, "The witness saw Mr. Doe driving a white van.");
+        assert_eq!(cited[1].page, None);
+
+        assert!(citations("No brackets at all", &sources()).is_empty());
+        assert_eq!(snippet(&"word ".repeat(100)).chars().count(), SNIPPET_CHARS + 1);
+    }
+
+    #[tokio::test]
+    async fn test_answers_without_support_say_so() {
+        let question = "Who drove the van?";
+        let supported = answer(&MockLlmBackend::with_reply("Mr. Doe drove it [1]."), question, &sources(), None).await.unwrap();
+        assert!(supported.supported);
+        assert_eq!(supported.answer, "Mr. Doe drove it [1].");
+        assert_eq!(supported.citations.len(), 1);
+
+        for reply in ["NO_ANSWER", "Probably Mr. Doe.", "Mr. Doe [5]."] {
+            let declined = answer(&MockLlmBackend::with_reply(reply), question, &sources(), None).await.unwrap();
+            assert!(!declined.supported, "{}", reply);
+            assert_eq!(declined.answer, UNSUPPORTED_ANSWER);
+            assert!(declined.citations.is_empty());
+            assert_eq!(declined.sources_considered, 2);
+        }
+
+        // Nothing retrieved: the model is not asked at all
+        let empty = answer(&MockLlmBackend::new(), question, &[], None).await.unwrap();
+        assert!(!empty.supported);
+        assert_eq!(empty.model, None);
+    }
+}
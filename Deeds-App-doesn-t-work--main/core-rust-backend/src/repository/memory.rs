@@ -0,0 +1,719 @@
+// In-memory repositories. They follow the Postgres implementations' rules (visibility, archived
+// cases, duplicate files, refresh token rotation) but keep no audit trail or blob reference counts.
+
+use axum::async_trait;
+use chrono::Utc;
+use sqlx::types::Json;
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+};
+use uuid::Uuid;
+
+use super::{
+    display_name, AuditRepository, CaseCursor, CaseFilter, CasePage, CaseRepository, EvidenceExtraction, EvidenceRepository,
+    NewEvidence, NewSession, NewUser, RepositoryError, RepositoryResult, RevokedSession, SortDirection,
+    UserRepository,
+};
+use crate::{
+    audit::ChainVerification,
+    models::{AuditEntry, Case, CaseListing, CreateCaseRequest, Evidence, Session, UpdateCaseRequest, User},
+    permissions::{CurrentUser, Permission},
+};
+
+#[derive(Default)]
+struct CaseTables {
+    next_id: i32,
+    cases: BTreeMap<i32, Case>,
+    collaborators: Vec<(i32, Uuid)>, // In the order they were added
+}
+
+#[derive(Default)]
+pub struct InMemoryCaseRepository {
+    tables: Mutex<CaseTables>,
+}
+
+impl InMemoryCaseRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+fn visible(tables: &CaseTables, viewer: &CurrentUser, case: &Case) -> bool {
+    viewer.can(Permission::ViewAllCases)
+        || case.created_by == viewer.user_id
+        || case.assigned_to == Some(viewer.user_id)
+        || tables.collaborators.contains(&(case.id, viewer.user_id))
+}
+
+#[async_trait]
+impl CaseRepository for InMemoryCaseRepository {
+    async fn get(&self, id: i32) -> RepositoryResult<Option<Case>> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables.cases.get(&id).filter(|case| !case.archived).cloned())
+    }
+
+    async fn list(&self, viewer: &CurrentUser, filter: &CaseFilter) -> RepositoryResult<CasePage> {
+        let tables = self.tables.lock().unwrap();
+        let search = filter.search.as_ref().map(|search| search.to_lowercase());
+        let mut cases: Vec<&Case> = tables
+            .cases
+            .values()
+            .filter(|case| !case.archived && visible(&tables, viewer, case))
+            .filter(|case| filter.status.iter().all(|status| &case.status == status))
+            .filter(|case| filter.priority.iter().all(|priority| &case.priority == priority))
+            .filter(|case| filter.assigned_to.iter().all(|user_id| case.assigned_to == Some(*user_id)))
+            .filter(|case| filter.case_type.iter().all(|case_type| case.case_type.as_ref() == Some(case_type)))
+            .filter(|case| filter.jurisdiction.iter().all(|jurisdiction| case.jurisdiction.as_ref() == Some(jurisdiction)))
+            .filter(|case| filter.court_date_from.iter().all(|from| case.court_date.is_some_and(|date| date >= *from)))
+            .filter(|case| filter.court_date_to.iter().all(|to| case.court_date.is_some_and(|date| date <= *to)))
+            .filter(|case| {
+                let tags = case.tags.as_array();
+                filter.tags.iter().all(|tag| tags.is_some_and(|tags| tags.iter().any(|t| t.as_str() == Some(tag))))
+            })
+            .filter(|case| {
+                search.iter().all(|search| {
+                    case.title.to_lowercase().contains(search)
+                        || case.description.as_ref().is_some_and(|d| d.to_lowercase().contains(search))
+                })
+            })
+            .collect();
+        let total = cases.len() as i64;
+
+        let position = |case: &Case| (filter.sort.key(case), case.id);
+        cases.sort_by_key(|case| position(case));
+        if filter.direction == SortDirection::Desc {
+            cases.reverse();
+        }
+        if let Some(after) = &filter.after {
+            let after = (after.key.clone(), after.id);
+            cases.retain(|case| match filter.direction {
+                SortDirection::Asc => position(case) > after,
+                SortDirection::Desc => position(case) < after,
+            });
+        }
+
+        let limit = filter.limit.max(0) as usize;
+        let next_cursor = (cases.len() > limit)
+            .then(|| cases[..limit].last().map(|last| CaseCursor::after(last, filter.sort, filter.direction)))
+            .flatten();
+        // Names and evidence counts live in the other repositories
+        let cases = cases
+            .into_iter()
+            .take(limit)
+            .map(|case| CaseListing {
+                case: case.clone(),
+                created_by_user: None,
+                assigned_to_user: None,
+                evidence_count: 0,
+            })
+            .collect();
+        Ok(CasePage { cases, total, next_cursor })
+    }
+
+    async fn is_visible(&self, viewer: &CurrentUser, id: i32) -> RepositoryResult<bool> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables.cases.get(&id).is_some_and(|case| !case.archived && visible(&tables, viewer, case)))
+    }
+
+    async fn create(&self, actor: Uuid, request: CreateCaseRequest) -> RepositoryResult<Case> {
+        let mut tables = self.tables.lock().unwrap();
+        if tables.cases.values().any(|case| case.case_number == request.case_number) {
+            return Err(RepositoryError::Conflict("case number already exists".to_string()));
+        }
+
+        tables.next_id += 1;
+        let now = Utc::now();
+        let case = Case {
+            id: tables.next_id,
+            case_number: request.case_number,
+            title: request.title,
+            description: request.description,
+            status: request.status.unwrap_or_else(|| "open".to_string()),
+            priority: request.priority.unwrap_or_else(|| "medium".to_string()),
+            created_by: actor,
+            assigned_to: request.assigned_to,
+            created_at: now,
+            updated_at: now,
+            court_date: request.court_date,
+            court_location: request.court_location,
+            judge_assigned: request.judge_assigned,
+            case_type: request.case_type,
+            jurisdiction: request.jurisdiction,
+            estimated_duration: request.estimated_duration,
+            case_value: request.case_value,
+            statute_of_limitations: request.statute_of_limitations,
+            tags: serde_json::json!(request.tags.unwrap_or_default()),
+            notes: request.notes,
+            archived: false,
+        };
+        tables.cases.insert(case.id, case.clone());
+        Ok(case)
+    }
+
+    async fn update(&self, _actor: Uuid, id: i32, request: UpdateCaseRequest) -> RepositoryResult<Case> {
+        let mut tables = self.tables.lock().unwrap();
+        let case = tables
+            .cases
+            .get_mut(&id)
+            .filter(|case| !case.archived)
+            .ok_or(RepositoryError::NotFound)?;
+
+        if let Some(title) = request.title {
+            case.title = title;
+        }
+        if let Some(status) = request.status {
+            case.status = status;
+        }
+        if let Some(priority) = request.priority {
+            case.priority = priority;
+        }
+        if let Some(tags) = request.tags {
+            case.tags = serde_json::json!(tags);
+        }
+        case.description = request.description.or(case.description.take());
+        case.assigned_to = request.assigned_to.or(case.assigned_to);
+        case.court_date = request.court_date.or(case.court_date);
+        case.court_location = request.court_location.or(case.court_location.take());
+        case.judge_assigned = request.judge_assigned.or(case.judge_assigned.take());
+        case.case_type = request.case_type.or(case.case_type.take());
+        case.jurisdiction = request.jurisdiction.or(case.jurisdiction.take());
+        case.estimated_duration = request.estimated_duration.or(case.estimated_duration);
+        case.case_value = request.case_value.or(case.case_value);
+        case.statute_of_limitations = request.statute_of_limitations.or(case.statute_of_limitations);
+        case.notes = request.notes.or(case.notes.take());
+        case.updated_at = Utc::now();
+        Ok(case.clone())
+    }
+
+    async fn archive(&self, _actor: Uuid, id: i32) -> RepositoryResult<Case> {
+        let mut tables = self.tables.lock().unwrap();
+        let case = tables.cases.get_mut(&id).ok_or(RepositoryError::NotFound)?;
+        case.archived = true;
+        case.updated_at = Utc::now();
+        Ok(case.clone())
+    }
+
+    async fn collaborators(&self, case_id: i32) -> RepositoryResult<Vec<Uuid>> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables
+            .collaborators
+            .iter()
+            .filter(|(id, _)| *id == case_id)
+            .map(|(_, user_id)| *user_id)
+            .collect())
+    }
+
+    async fn add_collaborator(&self, case_id: i32, user_id: Uuid, _added_by: Uuid) -> RepositoryResult<()> {
+        let mut tables = self.tables.lock().unwrap();
+        if !tables.collaborators.contains(&(case_id, user_id)) {
+            tables.collaborators.push((case_id, user_id));
+        }
+        Ok(())
+    }
+
+    async fn remove_collaborator(&self, case_id: i32, user_id: Uuid) -> RepositoryResult<bool> {
+        let mut tables = self.tables.lock().unwrap();
+        let before = tables.collaborators.len();
+        tables.collaborators.retain(|entry| *entry != (case_id, user_id));
+        Ok(tables.collaborators.len() < before)
+    }
+}
+
+#[derive(Default)]
+struct EvidenceTable {
+    next_id: i32,
+    rows: BTreeMap<i32, Evidence>,
+    texts: HashMap<i32, String>,
+}
+
+#[derive(Default)]
+pub struct InMemoryEvidenceRepository {
+    table: Mutex<EvidenceTable>,
+}
+
+impl InMemoryEvidenceRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl EvidenceRepository for InMemoryEvidenceRepository {
+    async fn get(&self, id: i32) -> RepositoryResult<Option<Evidence>> {
+        Ok(self.table.lock().unwrap().rows.get(&id).cloned())
+    }
+
+    async fn list_for_case(&self, case_id: i32) -> RepositoryResult<Vec<Evidence>> {
+        let table = self.table.lock().unwrap();
+        Ok(table.rows.values().filter(|row| row.case_id == Some(case_id)).cloned().collect())
+    }
+
+    async fn extracted_text(&self, id: i32) -> RepositoryResult<Option<String>> {
+        let table = self.table.lock().unwrap();
+        if !table.rows.contains_key(&id) {
+            return Err(RepositoryError::NotFound);
+        }
+        Ok(table.texts.get(&id).cloned())
+    }
+
+    async fn find_by_hash(&self, case_id: i32, sha256: &str) -> RepositoryResult<Option<i32>> {
+        let table = self.table.lock().unwrap();
+        Ok(table
+            .rows
+            .values()
+            .find(|row| row.case_id == Some(case_id) && row.hash_sha256.as_deref() == Some(sha256))
+            .map(|row| row.id))
+    }
+
+    async fn create(&self, _actor: Uuid, new: NewEvidence) -> RepositoryResult<Evidence> {
+        let mut table = self.table.lock().unwrap();
+        let duplicate = new.case_id.is_some()
+            && new.hash_sha256.is_some()
+            && table
+                .rows
+                .values()
+                .any(|row| row.case_id == new.case_id && row.hash_sha256 == new.hash_sha256);
+        if duplicate {
+            return Err(RepositoryError::Conflict("this file in the case already exists".to_string()));
+        }
+
+        table.next_id += 1;
+        let evidence = Evidence {
+            id: table.next_id,
+            case_id: new.case_id,
+            criminal_id: new.criminal_id,
+            title: new.title,
+            description: new.description,
+            evidence_type: new.evidence_type,
+            file_path: new.file_path,
+            file_size: new.file_size,
+            file_type: new.file_type,
+            hash_sha256: new.hash_sha256,
+            uploaded_by: new.uploaded_by,
+            created_at: Utc::now(),
+            ai_tags: None,
+            ai_summary: None,
+            ai_extraction: None,
+            ai_extracted_at: None,
+        };
+        table.rows.insert(evidence.id, evidence.clone());
+        if let Some(text) = new.extracted_text {
+            table.texts.insert(evidence.id, text);
+        }
+        Ok(evidence)
+    }
+
+    async fn delete(&self, _actor: Uuid, id: i32) -> RepositoryResult<Evidence> {
+        let mut table = self.table.lock().unwrap();
+        table.texts.remove(&id);
+        table.rows.remove(&id).ok_or(RepositoryError::NotFound)
+    }
+
+    async fn save_extraction(&self, _actor: Uuid, id: i32, extraction: EvidenceExtraction) -> RepositoryResult<Evidence> {
+        let mut table = self.table.lock().unwrap();
+        let evidence = table.rows.get_mut(&id).ok_or(RepositoryError::NotFound)?;
+        evidence.ai_tags = Some(Json(extraction.tags));
+        if let Some(summary) = extraction.summary {
+            evidence.ai_summary = Some(summary);
+        }
+        evidence.ai_extraction = Some(extraction.extraction);
+        evidence.ai_extracted_at = Some(Utc::now());
+        Ok(evidence.clone())
+    }
+}
+
+/// The in-memory repositories do not audit their changes, so this log stays empty
+#[derive(Default)]
+pub struct InMemoryAuditRepository;
+
+impl InMemoryAuditRepository {
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+#[async_trait]
+impl AuditRepository for InMemoryAuditRepository {
+    async fn list(&self, _case_id: Option<i32>, _before_id: Option<i64>, _limit: i64) -> RepositoryResult<Vec<AuditEntry>> {
+        Ok(Vec::new())
+    }
+
+    async fn verify(&self) -> RepositoryResult<ChainVerification> {
+        Ok(ChainVerification { entries_checked: 0, valid: true, first_broken_link: None })
+    }
+}
+
+#[derive(Default)]
+struct UserTables {
+    users: HashMap<Uuid, User>,
+    sessions: HashMap<Uuid, Session>,
+}
+
+#[derive(Default)]
+pub struct InMemoryUserRepository {
+    tables: Mutex<UserTables>,
+}
+
+impl InMemoryUserRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+fn revoked(session: &Session) -> RevokedSession {
+    RevokedSession {
+        session_id: session.id,
+        user_id: session.user_id,
+        expires_at: session.expires_at.timestamp(),
+    }
+}
+
+#[async_trait]
+impl UserRepository for InMemoryUserRepository {
+    async fn get(&self, id: Uuid) -> RepositoryResult<Option<User>> {
+        Ok(self.tables.lock().unwrap().users.get(&id).cloned())
+    }
+
+    async fn find_by_email(&self, email: &str) -> RepositoryResult<Option<User>> {
+        let tables = self.tables.lock().unwrap();
+        Ok(tables.users.values().find(|user| user.email == email).cloned())
+    }
+
+    async fn create(&self, new: NewUser) -> RepositoryResult<User> {
+        let mut tables = self.tables.lock().unwrap();
+        if tables.users.values().any(|user| user.email == new.email) {
+            return Err(RepositoryError::Conflict("a user with this email already exists".to_string()));
+        }
+
+        let now = Utc::now();
+        let user = User {
+            id: Uuid::new_v4(),
+            name: display_name(new.first_name.as_deref(), new.last_name.as_deref()),
+            email: new.email,
+            email_verified: None,
+            hashed_password: Some(new.hashed_password),
+            role: new.role,
+            is_active: true,
+            created_at: now,
+            updated_at: now,
+            first_name: new.first_name,
+            last_name: new.last_name,
+            title: new.title,
+            department: new.department,
+            phone: None,
+            office_address: None,
+            avatar: None,
+            bio: None,
+            specializations: serde_json::json!([]),
+        };
+        tables.users.insert(user.id, user.clone());
+        Ok(user)
+    }
+
+    async fn set_password_hash(&self, id: Uuid, hashed_password: &str) -> RepositoryResult<()> {
+        if let Some(user) = self.tables.lock().unwrap().users.get_mut(&id) {
+            user.hashed_password = Some(hashed_password.to_string());
+            user.updated_at = Utc::now();
+        }
+        Ok(())
+    }
+
+    async fn create_session(&self, new: NewSession) -> RepositoryResult<()> {
+        let now = Utc::now();
+        let session = Session {
+            id: new.id,
+            user_id: new.user_id,
+            refresh_token_hash: new.refresh_token_hash,
+            previous_refresh_token_hash: None,
+            user_agent: new.user_agent,
+            ip_address: new.ip_address,
+            created_at: now,
+            last_used_at: now,
+            expires_at: new.expires_at,
+            revoked_at: None,
+        };
+        self.tables.lock().unwrap().sessions.insert(session.id, session);
+        Ok(())
+    }
+
+    async fn active_sessions(&self, user_id: Uuid) -> RepositoryResult<Vec<Session>> {
+        let tables = self.tables.lock().unwrap();
+        let now = Utc::now();
+        let mut sessions: Vec<Session> = tables
+            .sessions
+            .values()
+            .filter(|session| session.user_id == user_id && session.revoked_at.is_none() && session.expires_at > now)
+            .cloned()
+            .collect();
+        sessions.sort_by_key(|session| Reverse(session.last_used_at));
+        Ok(sessions)
+    }
+
+    async fn rotate_refresh_token(
+        &self,
+        session_id: Uuid,
+        presented_hash: &str,
+        new_hash: &str,
+    ) -> RepositoryResult<Option<Session>> {
+        let mut tables = self.tables.lock().unwrap();
+        let now = Utc::now();
+        Ok(tables
+            .sessions
+            .get_mut(&session_id)
+            .filter(|session| {
+                session.refresh_token_hash == presented_hash && session.revoked_at.is_none() && session.expires_at > now
+            })
+            .map(|session| {
+                session.previous_refresh_token_hash =
+                    Some(std::mem::replace(&mut session.refresh_token_hash, new_hash.to_string()));
+                session.last_used_at = now;
+                session.clone()
+            }))
+    }
+
+    async fn find_rotated_out(&self, session_id: Uuid, presented_hash: &str) -> RepositoryResult<Option<Session>> {
+        let tables = self.tables.lock().unwrap();
+        let now = Utc::now();
+        Ok(tables
+            .sessions
+            .get(&session_id)
+            .filter(|session| {
+                session.previous_refresh_token_hash.as_deref() == Some(presented_hash)
+                    && session.revoked_at.is_none()
+                    && session.expires_at > now
+            })
+            .cloned())
+    }
+
+    async fn revoke_session(&self, session_id: Uuid, owner: Option<Uuid>) -> RepositoryResult<Option<RevokedSession>> {
+        let mut tables = self.tables.lock().unwrap();
+        Ok(tables
+            .sessions
+            .get_mut(&session_id)
+            .filter(|session| session.revoked_at.is_none() && owner.iter().all(|owner| session.user_id == *owner))
+            .map(|session| {
+                session.revoked_at = Some(Utc::now());
+                revoked(session)
+            }))
+    }
+
+    async fn revoke_other_sessions(&self, user_id: Uuid, keep: Uuid) -> RepositoryResult<Vec<RevokedSession>> {
+        let mut tables = self.tables.lock().unwrap();
+        let now = Utc::now();
+        Ok(tables
+            .sessions
+            .values_mut()
+            .filter(|session| session.user_id == user_id && session.id != keep && session.revoked_at.is_none())
+            .map(|session| {
+                session.revoked_at = Some(now);
+                revoked(session)
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{permissions::Role, repository::CaseSort};
+    use chrono::Duration;
+
+    fn viewer(user_id: Uuid, role: Role) -> CurrentUser {
+        CurrentUser { user_id, role: Some(role) }
+    }
+
+    fn new_case(case_number: &str) -> CreateCaseRequest {
+        serde_json::from_value(serde_json::json!({ "case_number": case_number, "title": case_number })).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_case_visibility_and_filters() {
+        let repo = InMemoryCaseRepository::new();
+        let (owner, collaborator, outsider) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+
+        let first = repo.create(owner, new_case("CR-1")).await.unwrap();
+        let mut urgent = new_case("CR-2");
+        urgent.priority = Some("high".to_string());
+        let second = repo.create(owner, urgent).await.unwrap();
+        assert!(matches!(repo.create(owner, new_case("CR-1")).await, Err(RepositoryError::Conflict(_))));
+
+        repo.add_collaborator(first.id, collaborator, owner).await.unwrap();
+        let all = CaseFilter { limit: 10, ..Default::default() };
+
+        let ids = |page: CasePage| page.cases.into_iter().map(|listing| listing.case.id).collect::<Vec<_>>();
+        assert_eq!(ids(repo.list(&viewer(owner, Role::Prosecutor), &all).await.unwrap()), vec![second.id, first.id]);
+        assert_eq!(ids(repo.list(&viewer(collaborator, Role::Paralegal), &all).await.unwrap()), vec![first.id]);
+        assert!(repo.list(&viewer(outsider, Role::Paralegal), &all).await.unwrap().cases.is_empty());
+        assert_eq!(repo.list(&viewer(outsider, Role::Supervisor), &all).await.unwrap().total, 2);
+        assert!(!repo.is_visible(&viewer(outsider, Role::Investigator), first.id).await.unwrap());
+
+        let high = CaseFilter { priority: Some("high".to_string()), ..all.clone() };
+        assert_eq!(ids(repo.list(&viewer(owner, Role::Prosecutor), &high).await.unwrap()), vec![second.id]);
+
+        // Archived cases drop out of reads and can no longer be edited
+        repo.archive(owner, second.id).await.unwrap();
+        assert!(repo.get(second.id).await.unwrap().is_none());
+        assert!(!repo.is_visible(&viewer(owner, Role::Prosecutor), second.id).await.unwrap());
+        let update: UpdateCaseRequest = serde_json::from_value(serde_json::json!({ "title": "renamed" })).unwrap();
+        assert!(matches!(repo.update(owner, second.id, update).await, Err(RepositoryError::NotFound)));
+
+        assert!(repo.remove_collaborator(first.id, collaborator).await.unwrap());
+        assert!(!repo.remove_collaborator(first.id, collaborator).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_case_listing_sorts_and_pages_by_cursor() {
+        let repo = InMemoryCaseRepository::new();
+        let owner = Uuid::new_v4();
+        let user = viewer(owner, Role::Prosecutor);
+        for (number, title, priority, tags) in [
+            ("CR-1", "Harbor theft", "low", vec!["theft"]),
+            ("CR-2", "Arson on 5th", "critical", vec!["arson", "felony"]),
+            ("CR-3", "Warehouse theft", "high", vec!["theft", "felony"]),
+            ("CR-4", "Bike theft", "medium", vec!["theft"]),
+        ] {
+            let mut request = new_case(number);
+            request.title = title.to_string();
+            request.priority = Some(priority.to_string());
+            request.tags = Some(tags.into_iter().map(String::from).collect());
+            repo.create(owner, request).await.unwrap();
+        }
+
+        fn numbers(page: &CasePage) -> Vec<&str> {
+            page.cases.iter().map(|listing| listing.case.case_number.as_str()).collect()
+        }
+        let by_priority = CaseFilter { sort: CaseSort::Priority, limit: 3, ..Default::default() };
+        let first = repo.list(&user, &by_priority).await.unwrap();
+        assert_eq!((numbers(&first), first.total), (vec!["CR-2", "CR-3", "CR-4"], 4));
+
+        // The cursor survives the round trip to the client and picks up where the page ended
+        let cursor = CaseCursor::decode(&first.next_cursor.unwrap().encode()).unwrap();
+        let rest = repo.list(&user, &CaseFilter { after: Some(cursor), ..by_priority.clone() }).await.unwrap();
+        assert_eq!((numbers(&rest), rest.total), (vec!["CR-1"], 4));
+        assert!(rest.next_cursor.is_none());
+
+        let felony_theft = CaseFilter {
+            tags: vec!["theft".to_string(), "felony".to_string()],
+            limit: 10,
+            ..Default::default()
+        };
+        assert_eq!(numbers(&repo.list(&user, &felony_theft).await.unwrap()), vec!["CR-3"]);
+
+        let search = CaseFilter {
+            search: Some("THEFT".to_string()),
+            sort: CaseSort::Title,
+            direction: SortDirection::Asc,
+            limit: 10,
+            ..Default::default()
+        };
+        assert_eq!(numbers(&repo.list(&user, &search).await.unwrap()), vec!["CR-4", "CR-1", "CR-3"]);
+        assert!(CaseCursor::decode("not a cursor").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_case_update_keeps_absent_fields() {
+        let repo = InMemoryCaseRepository::new();
+        let owner = Uuid::new_v4();
+        let mut request = new_case("CR-9");
+        request.notes = Some("initial notes".to_string());
+        let case = repo.create(owner, request).await.unwrap();
+
+        let update: UpdateCaseRequest =
+            serde_json::from_value(serde_json::json!({ "status": "closed", "tags": ["fraud"] })).unwrap();
+        let updated = repo.update(owner, case.id, update).await.unwrap();
+        assert_eq!(updated.status, "closed");
+        assert_eq!(updated.tags, serde_json::json!(["fraud"]));
+        assert_eq!(updated.notes.as_deref(), Some("initial notes"));
+        assert_eq!(updated.title, "CR-9");
+    }
+
+    #[tokio::test]
+    async fn test_evidence_duplicates_per_case() {
+        let repo = InMemoryEvidenceRepository::new();
+        let new = |case_id| NewEvidence {
+            case_id: Some(case_id),
+            criminal_id: None,
+            title: "Bodycam".to_string(),
+            description: None,
+            evidence_type: "video".to_string(),
+            file_path: Some(format!("sha256/{}", "a".repeat(64))),
+            file_size: Some(10),
+            file_type: Some("video/mp4".to_string()),
+            hash_sha256: Some("a".repeat(64)),
+            uploaded_by: Uuid::nil(),
+            extracted_text: None,
+            chunk_anchors: Vec::new(),
+        };
+
+        let first = repo.create(Uuid::nil(), new(1)).await.unwrap();
+        assert_eq!(repo.extracted_text(first.id).await.unwrap(), None);
+        assert!(matches!(repo.create(Uuid::nil(), new(1)).await, Err(RepositoryError::Conflict(_))));
+        repo.create(Uuid::nil(), new(2)).await.unwrap();
+        assert_eq!(repo.find_by_hash(1, &"a".repeat(64)).await.unwrap(), Some(first.id));
+
+        // A failed summary keeps the one stored before
+        let extraction = |summary: Option<&str>| EvidenceExtraction {
+            tags: vec!["video".to_string()],
+            summary: summary.map(str::to_string),
+            extraction: serde_json::json!({"extractor": "rule-based"}),
+        };
+        repo.save_extraction(Uuid::nil(), first.id, extraction(Some("Bodycam of the stop"))).await.unwrap();
+        let updated = repo.save_extraction(Uuid::nil(), first.id, extraction(None)).await.unwrap();
+        assert_eq!(updated.ai_tags.map(|tags| tags.0), Some(vec!["video".to_string()]));
+        assert_eq!(updated.ai_summary.as_deref(), Some("Bodycam of the stop"));
+
+        assert_eq!(repo.delete(Uuid::nil(), first.id).await.unwrap().id, first.id);
+        assert!(matches!(repo.delete(Uuid::nil(), first.id).await, Err(RepositoryError::NotFound)));
+        assert!(matches!(repo.extracted_text(first.id).await, Err(RepositoryError::NotFound)));
+        assert!(repo.list_for_case(1).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_sessions_rotate_and_revoke() {
+        let repo = InMemoryUserRepository::new();
+        let user = repo
+            .create(NewUser {
+                email: "ada@da.example".to_string(),
+                hashed_password: "hash".to_string(),
+                role: "prosecutor".to_string(),
+                first_name: Some("Ada".to_string()),
+                last_name: Some("Lovelace".to_string()),
+                title: None,
+                department: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(user.name.as_deref(), Some("Ada Lovelace"));
+
+        let (current, other) = (Uuid::new_v4(), Uuid::new_v4());
+        for (id, hash) in [(current, "h1"), (other, "h2")] {
+            repo.create_session(NewSession {
+                id,
+                user_id: user.id,
+                refresh_token_hash: hash.to_string(),
+                user_agent: None,
+                ip_address: None,
+                expires_at: Utc::now() + Duration::days(1),
+            })
+            .await
+            .unwrap();
+        }
+
+        // A refresh token only works once
+        assert!(repo.rotate_refresh_token(current, "h1", "h3").await.unwrap().is_some());
+        assert!(repo.rotate_refresh_token(current, "h1", "h4").await.unwrap().is_none());
+        assert!(repo.find_rotated_out(current, "h1").await.unwrap().is_some());
+        assert!(repo.find_rotated_out(current, "forged").await.unwrap().is_none());
+
+        // Another user cannot revoke the session
+        assert!(repo.revoke_session(current, Some(Uuid::new_v4())).await.unwrap().is_none());
+
+        let revoked = repo.revoke_other_sessions(user.id, current).await.unwrap();
+        assert_eq!(revoked.iter().map(|session| session.session_id).collect::<Vec<_>>(), vec![other]);
+        assert_eq!(repo.active_sessions(user.id).await.unwrap().len(), 1);
+        assert!(repo.revoke_session(current, Some(user.id)).await.unwrap().is_some());
+        assert!(repo.active_sessions(user.id).await.unwrap().is_empty());
+    }
+}
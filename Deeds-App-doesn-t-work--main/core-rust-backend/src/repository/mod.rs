@@ -0,0 +1,334 @@
+// Data access for prosecutor-core
+// Handlers, the Tauri commands and the mobile bindings go through these traits instead of writing
+// SQL. `postgres` is the production implementation (mutations are audited in the same
+// transaction); `memory` keeps everything in process for tests and offline use.
+
+pub mod memory;
+pub mod postgres;
+
+pub use memory::{InMemoryAuditRepository, InMemoryCaseRepository, InMemoryEvidenceRepository, InMemoryUserRepository};
+pub use postgres::{PgAuditRepository, PgCaseRepository, PgEvidenceRepository, PgUserRepository};
+
+use axum::{async_trait, http::StatusCode};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    audit::ChainVerification,
+    chunker::ChunkAnchor,
+    models::{AuditEntry, Case, CaseListing, CreateCaseRequest, Evidence, Session, UpdateCaseRequest, User},
+    permissions::CurrentUser,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum RepositoryError {
+    #[error("not found")]
+    NotFound,
+    #[error("conflict: {0}")]
+    Conflict(String),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+impl RepositoryError {
+    /// Unique violations become `Conflict`; everything else stays a database error
+    pub(crate) fn from_insert(e: sqlx::Error, what: &str) -> Self {
+        match e.as_database_error().and_then(|db_err| db_err.code()) {
+            Some(code) if code == "23505" => Self::Conflict(format!("{} already exists", what)),
+            _ => Self::Database(e),
+        }
+    }
+}
+
+impl From<RepositoryError> for StatusCode {
+    fn from(e: RepositoryError) -> Self {
+        match e {
+            RepositoryError::NotFound => StatusCode::NOT_FOUND,
+            RepositoryError::Conflict(_) => StatusCode::CONFLICT,
+            e => {
+                tracing::error!("Repository error: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+}
+
+pub type RepositoryResult<T> = Result<T, RepositoryError>;
+
+/// Filters for listing the cases a user can see. `None` and empty fields do not filter.
+#[derive(Debug, Clone, Default)]
+pub struct CaseFilter {
+    pub status: Option<String>,
+    pub priority: Option<String>,
+    pub assigned_to: Option<Uuid>,
+    pub case_type: Option<String>,
+    pub jurisdiction: Option<String>,
+    pub court_date_from: Option<DateTime<Utc>>,
+    pub court_date_to: Option<DateTime<Utc>>,
+    /// Cases carrying all of these tags
+    pub tags: Vec<String>,
+    /// Case-insensitive substring of the title or description
+    pub search: Option<String>,
+    pub sort: CaseSort,
+    pub direction: SortDirection,
+    /// Continue after this case; it must come from a listing with the same sort and direction
+    pub after: Option<CaseCursor>,
+    pub limit: i64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaseSort {
+    #[default]
+    CreatedAt,
+    UpdatedAt,
+    CourtDate,
+    Priority,
+    Title,
+    CaseNumber,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortDirection {
+    Asc,
+    #[default]
+    Desc,
+}
+
+// Cases without a court date sort as if it were this far in the future
+pub(crate) const NO_COURT_DATE: &str = "9999-12-31T00:00:00.000000Z";
+
+impl CaseSort {
+    /// Dates newest first and priority highest first; text columns alphabetically. Court dates
+    /// are the exception: the next hearing first.
+    pub fn default_direction(self) -> SortDirection {
+        match self {
+            Self::CreatedAt | Self::UpdatedAt | Self::Priority => SortDirection::Desc,
+            Self::CourtDate | Self::Title | Self::CaseNumber => SortDirection::Asc,
+        }
+    }
+
+    /// The value a case is ordered by, as text that orders the same way for dates and priorities
+    pub fn key(self, case: &Case) -> String {
+        let timestamp = |at: DateTime<Utc>| at.to_rfc3339_opts(SecondsFormat::Micros, true);
+        match self {
+            Self::CreatedAt => timestamp(case.created_at),
+            Self::UpdatedAt => timestamp(case.updated_at),
+            Self::CourtDate => case.court_date.map_or_else(|| NO_COURT_DATE.to_string(), timestamp),
+            Self::Priority => priority_rank(&case.priority).to_string(),
+            Self::Title => case.title.clone(),
+            Self::CaseNumber => case.case_number.clone(),
+        }
+    }
+}
+
+/// Unknown priorities rank below `low`
+pub fn priority_rank(priority: &str) -> i32 {
+    match priority {
+        "critical" => 4,
+        "high" => 3,
+        "medium" => 2,
+        "low" => 1,
+        _ => 0,
+    }
+}
+
+/// Position in a case listing: the sort key and id of the last case returned
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CaseCursor {
+    pub sort: CaseSort,
+    pub direction: SortDirection,
+    pub key: String,
+    pub id: i32,
+}
+
+impl CaseCursor {
+    pub fn after(case: &Case, sort: CaseSort, direction: SortDirection) -> Self {
+        Self { sort, direction, key: sort.key(case), id: case.id }
+    }
+
+    /// Opaque form handed to clients
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).expect("cursor serializes"))
+    }
+
+    pub fn decode(encoded: &str) -> Option<Self> {
+        let bytes = URL_SAFE_NO_PAD.decode(encoded).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+}
+
+/// One page of a case listing. `total` counts every match, not just this page.
+#[derive(Debug, Clone)]
+pub struct CasePage {
+    pub cases: Vec<CaseListing>,
+    pub total: i64,
+    pub next_cursor: Option<CaseCursor>,
+}
+
+#[async_trait]
+pub trait CaseRepository: Send + Sync {
+    /// A case that has not been archived
+    async fn get(&self, id: i32) -> RepositoryResult<Option<Case>>;
+
+    /// Cases visible to `viewer` that match the filter, in the filter's order
+    async fn list(&self, viewer: &CurrentUser, filter: &CaseFilter) -> RepositoryResult<CasePage>;
+
+    /// Whether `viewer` may see the case (creator, assignee, collaborator, or a see-all role); archived cases are hidden
+    async fn is_visible(&self, viewer: &CurrentUser, id: i32) -> RepositoryResult<bool>;
+
+    async fn create(&self, actor: Uuid, request: CreateCaseRequest) -> RepositoryResult<Case>;
+
+    /// Apply the fields present in `request`; archived cases are `NotFound`
+    async fn update(&self, actor: Uuid, id: i32, request: UpdateCaseRequest) -> RepositoryResult<Case>;
+
+    async fn archive(&self, actor: Uuid, id: i32) -> RepositoryResult<Case>;
+
+    async fn collaborators(&self, case_id: i32) -> RepositoryResult<Vec<Uuid>>;
+
+    async fn add_collaborator(&self, case_id: i32, user_id: Uuid, added_by: Uuid) -> RepositoryResult<()>;
+
+    /// Returns false if the user was not a collaborator
+    async fn remove_collaborator(&self, case_id: i32, user_id: Uuid) -> RepositoryResult<bool>;
+}
+
+/// Everything needed to create an evidence row. `file_path` is the store key of the file.
+#[derive(Debug, Clone)]
+pub struct NewEvidence {
+    pub case_id: Option<i32>,
+    pub criminal_id: Option<i32>,
+    pub title: String,
+    pub description: Option<String>,
+    pub evidence_type: String,
+    pub file_path: Option<String>,
+    pub file_size: Option<i64>,
+    pub file_type: Option<String>,
+    pub hash_sha256: Option<String>,
+    pub uploaded_by: Uuid,
+    /// Text extracted from the file, for search
+    pub extracted_text: Option<String>,
+    /// Where each chunk of `extracted_text` lies, stored in `anchor_points`
+    pub chunk_anchors: Vec<ChunkAnchor>,
+}
+
+/// What a structured extraction run stores on an evidence row
+#[derive(Debug, Clone)]
+pub struct EvidenceExtraction {
+    pub tags: Vec<String>,
+    /// `None` keeps the current summary, so a failed summary does not erase an earlier one
+    pub summary: Option<String>,
+    pub extraction: serde_json::Value,
+}
+
+#[async_trait]
+pub trait EvidenceRepository: Send + Sync {
+    async fn get(&self, id: i32) -> RepositoryResult<Option<Evidence>>;
+
+    async fn list_for_case(&self, case_id: i32) -> RepositoryResult<Vec<Evidence>>;
+
+    /// Text extracted from the evidence file at upload, if any
+    async fn extracted_text(&self, id: i32) -> RepositoryResult<Option<String>>;
+
+    /// Id of the evidence in `case_id` whose file has this hash, if any
+    async fn find_by_hash(&self, case_id: i32, sha256: &str) -> RepositoryResult<Option<i32>>;
+
+    /// Insert the row and take a reference on its content-addressed file. The same file attached
+    /// to the same case twice is a `Conflict`.
+    async fn create(&self, actor: Uuid, evidence: NewEvidence) -> RepositoryResult<Evidence>;
+
+    /// Delete the row and drop its file reference, returning what was deleted. Removing the file
+    /// from the store is left to the caller, after this returns.
+    async fn delete(&self, actor: Uuid, id: i32) -> RepositoryResult<Evidence>;
+
+    /// Store an extraction's tags, summary and full result, returning the updated row
+    async fn save_extraction(&self, actor: Uuid, id: i32, extraction: EvidenceExtraction) -> RepositoryResult<Evidence>;
+}
+
+#[derive(Debug, Clone)]
+pub struct NewUser {
+    pub email: String,
+    pub hashed_password: String,
+    pub role: String,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    pub title: Option<String>,
+    pub department: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NewSession {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub refresh_token_hash: String,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// A session that was revoked, with its expiry as a unix timestamp for the revocation list
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RevokedSession {
+    pub session_id: Uuid,
+    pub user_id: Uuid,
+    pub expires_at: i64,
+}
+
+#[async_trait]
+pub trait UserRepository: Send + Sync {
+    async fn get(&self, id: Uuid) -> RepositoryResult<Option<User>>;
+
+    async fn find_by_email(&self, email: &str) -> RepositoryResult<Option<User>>;
+
+    /// An existing email is a `Conflict`
+    async fn create(&self, user: NewUser) -> RepositoryResult<User>;
+
+    async fn set_password_hash(&self, id: Uuid, hashed_password: &str) -> RepositoryResult<()>;
+
+    async fn create_session(&self, session: NewSession) -> RepositoryResult<()>;
+
+    /// Live (unrevoked, unexpired) sessions, most recently used first
+    async fn active_sessions(&self, user_id: Uuid) -> RepositoryResult<Vec<Session>>;
+
+    /// Swap the refresh token hash of a live session, but only if `presented_hash` is still the
+    /// current one, so concurrent refreshes with the same token cannot both succeed.
+    async fn rotate_refresh_token(
+        &self,
+        session_id: Uuid,
+        presented_hash: &str,
+        new_hash: &str,
+    ) -> RepositoryResult<Option<Session>>;
+
+    /// The live session whose last rotation replaced `presented_hash`, if any
+    async fn find_rotated_out(&self, session_id: Uuid, presented_hash: &str) -> RepositoryResult<Option<Session>>;
+
+    /// Revoke a session, optionally only if it belongs to `owner`
+    async fn revoke_session(&self, session_id: Uuid, owner: Option<Uuid>) -> RepositoryResult<Option<RevokedSession>>;
+
+    /// Revoke every session of the user except `keep`
+    async fn revoke_other_sessions(&self, user_id: Uuid, keep: Uuid) -> RepositoryResult<Vec<RevokedSession>>;
+}
+
+#[async_trait]
+pub trait AuditRepository: Send + Sync {
+    /// Entries newest first, for one case or all of them, continuing below `before_id`
+    async fn list(&self, case_id: Option<i32>, before_id: Option<i64>, limit: i64) -> RepositoryResult<Vec<AuditEntry>>;
+
+    /// Walk the whole hash chain and report the first broken link
+    async fn verify(&self) -> RepositoryResult<ChainVerification>;
+}
+
+/// Display name from the first and last name, whichever are present
+pub fn display_name(first_name: Option<&str>, last_name: Option<&str>) -> Option<String> {
+    match (first_name, last_name) {
+        (Some(first), Some(last)) => Some(format!("{} {}", first, last)),
+        (Some(first), None) => Some(first.to_string()),
+        (None, Some(last)) => Some(last.to_string()),
+        (None, None) => None,
+    }
+}
+
@@ -0,0 +1,712 @@
+use axum::async_trait;
+use chrono::Utc;
+use sqlx::{postgres::PgArguments, query, query::QueryAs, query_as, types::Json, Postgres};
+use uuid::Uuid;
+
+use super::{
+    display_name, AuditRepository, CaseCursor, CaseFilter, CasePage, CaseRepository, CaseSort, EvidenceExtraction,
+    EvidenceRepository, NewEvidence, NewSession, NewUser, RepositoryError, RepositoryResult, RevokedSession,
+    SortDirection, UserRepository, NO_COURT_DATE,
+};
+use crate::{
+    audit::{self, AuditAction, ChainVerification, NewAuditEntry},
+    chunker,
+    database::DbConnection,
+    evidence_store::{self, parse_content_key},
+    models::{AuditEntry, Case, CaseListing, CreateCaseRequest, Evidence, Session, UpdateCaseRequest, User},
+    permissions::{can_access_case, case_visibility_clause, CurrentUser},
+};
+
+pub struct PgCaseRepository {
+    db: DbConnection,
+}
+
+impl PgCaseRepository {
+    pub fn new(db: DbConnection) -> Self {
+        Self { db }
+    }
+}
+
+/// WHERE clause for a case listing. $1 is the viewer and the filters that are set follow in the
+/// order `bind_filter` binds them; returns the clause and the last parameter number used.
+fn filter_clause(viewer: &CurrentUser, filter: &CaseFilter) -> (String, usize) {
+    let mut clause = format!("c.archived = false AND {}", case_visibility_clause(viewer, 1));
+    let mut param = 1;
+    let conditions = [
+        (filter.status.is_some(), "c.status = $?"),
+        (filter.priority.is_some(), "c.priority = $?"),
+        (filter.assigned_to.is_some(), "c.assigned_to = $?"),
+        (filter.case_type.is_some(), "c.case_type = $?"),
+        (filter.jurisdiction.is_some(), "c.jurisdiction = $?"),
+        (filter.court_date_from.is_some(), "c.court_date >= $?"),
+        (filter.court_date_to.is_some(), "c.court_date <= $?"),
+        (!filter.tags.is_empty(), "c.tags @> $?"),
+        (filter.search.is_some(), "(c.title ILIKE $? OR c.description ILIKE $?)"),
+    ];
+    for (_, condition) in conditions.iter().filter(|(present, _)| *present) {
+        param += 1;
+        clause.push_str(" AND ");
+        clause.push_str(&condition.replace("$?", &format!("${}", param)));
+    }
+    (clause, param)
+}
+
+fn bind_filter<'q, O>(
+    query: QueryAs<'q, Postgres, O, PgArguments>,
+    viewer: &CurrentUser,
+    filter: &'q CaseFilter,
+) -> QueryAs<'q, Postgres, O, PgArguments> {
+    let mut query = query.bind(viewer.user_id);
+    if let Some(status) = &filter.status {
+        query = query.bind(status);
+    }
+    if let Some(priority) = &filter.priority {
+        query = query.bind(priority);
+    }
+    if let Some(assigned_to) = filter.assigned_to {
+        query = query.bind(assigned_to);
+    }
+    if let Some(case_type) = &filter.case_type {
+        query = query.bind(case_type);
+    }
+    if let Some(jurisdiction) = &filter.jurisdiction {
+        query = query.bind(jurisdiction);
+    }
+    if let Some(from) = filter.court_date_from {
+        query = query.bind(from);
+    }
+    if let Some(to) = filter.court_date_to {
+        query = query.bind(to);
+    }
+    if !filter.tags.is_empty() {
+        query = query.bind(serde_json::json!(filter.tags));
+    }
+    if let Some(search) = &filter.search {
+        query = query.bind(like_pattern(search));
+    }
+    query
+}
+
+/// Substring pattern for ILIKE, with the wildcards in `text` matched literally
+fn like_pattern(text: &str) -> String {
+    let escaped = text.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+/// Ordering expression for a sort, and the type its cursor key is cast to. Must order the same
+/// way as `CaseSort::key`.
+fn sort_column(sort: CaseSort) -> (String, &'static str) {
+    match sort {
+        CaseSort::CreatedAt => ("c.created_at".to_string(), "TIMESTAMPTZ"),
+        CaseSort::UpdatedAt => ("c.updated_at".to_string(), "TIMESTAMPTZ"),
+        CaseSort::CourtDate => (format!("COALESCE(c.court_date, '{}'::TIMESTAMPTZ)", NO_COURT_DATE), "TIMESTAMPTZ"),
+        CaseSort::Priority => (
+            "CASE c.priority WHEN 'critical' THEN 4 WHEN 'high' THEN 3 WHEN 'medium' THEN 2 WHEN 'low' THEN 1 ELSE 0 END"
+                .to_string(),
+            "INTEGER",
+        ),
+        CaseSort::Title => ("c.title".to_string(), "TEXT"),
+        CaseSort::CaseNumber => ("c.case_number".to_string(), "TEXT"),
+    }
+}
+
+#[async_trait]
+impl CaseRepository for PgCaseRepository {
+    async fn get(&self, id: i32) -> RepositoryResult<Option<Case>> {
+        let case = query_as::<_, Case>("SELECT * FROM cases WHERE id = $1 AND archived = false")
+            .bind(id)
+            .fetch_optional(self.db.as_ref())
+            .await?;
+        Ok(case)
+    }
+
+    async fn list(&self, viewer: &CurrentUser, filter: &CaseFilter) -> RepositoryResult<CasePage> {
+        let (clause, last_param) = filter_clause(viewer, filter);
+
+        let count_sql = format!("SELECT COUNT(*) FROM cases c WHERE {}", clause);
+        let (total,): (i64,) = bind_filter(query_as(&count_sql), viewer, filter)
+            .fetch_one(self.db.as_ref())
+            .await?;
+
+        let (order_by, key_type) = sort_column(filter.sort);
+        let (comparison, direction) = match filter.direction {
+            SortDirection::Asc => (">", "ASC"),
+            SortDirection::Desc => ("<", "DESC"),
+        };
+        let mut sql = format!(
+            r#"
+            SELECT c.*,
+                COALESCE(cu.name, cu.email) AS created_by_user,
+                COALESCE(au.name, au.email) AS assigned_to_user,
+                (SELECT COUNT(*) FROM evidence e WHERE e.case_id = c.id) AS evidence_count
+            FROM cases c
+            LEFT JOIN users cu ON cu.id = c.created_by
+            LEFT JOIN users au ON au.id = c.assigned_to
+            WHERE {}
+            "#,
+            clause
+        );
+        let mut param = last_param;
+        if filter.after.is_some() {
+            sql.push_str(&format!(
+                " AND ({}, c.id) {} (${}::{}, ${})",
+                order_by, comparison, param + 1, key_type, param + 2
+            ));
+            param += 2;
+        }
+        sql.push_str(&format!(" ORDER BY {o} {d}, c.id {d} LIMIT ${}", param + 1, o = order_by, d = direction));
+
+        let mut listing = bind_filter(query_as::<_, CaseListing>(&sql), viewer, filter);
+        if let Some(after) = &filter.after {
+            listing = listing.bind(&after.key).bind(after.id);
+        }
+        // One extra row tells whether there is another page
+        let mut cases = listing.bind(filter.limit + 1).fetch_all(self.db.as_ref()).await?;
+
+        let next_cursor = if cases.len() as i64 > filter.limit {
+            cases.truncate(filter.limit as usize);
+            cases.last().map(|last| CaseCursor::after(&last.case, filter.sort, filter.direction))
+        } else {
+            None
+        };
+        Ok(CasePage { cases, total, next_cursor })
+    }
+
+    async fn is_visible(&self, viewer: &CurrentUser, id: i32) -> RepositoryResult<bool> {
+        Ok(can_access_case(&self.db, viewer, id).await?)
+    }
+
+    async fn create(&self, actor: Uuid, request: CreateCaseRequest) -> RepositoryResult<Case> {
+        let now = Utc::now();
+        let tags = serde_json::to_value(request.tags.unwrap_or_default()).map_err(anyhow::Error::from)?;
+
+        let mut tx = self.db.begin().await?;
+
+        let case = query_as::<_, Case>(
+            r#"
+            INSERT INTO cases (
+                case_number, title, description, status, priority, created_by, assigned_to,
+                created_at, updated_at, court_date, court_location, judge_assigned,
+                case_type, jurisdiction, estimated_duration, case_value,
+                statute_of_limitations, tags, notes, archived
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20)
+            RETURNING *
+            "#
+        )
+        .bind(&request.case_number)
+        .bind(&request.title)
+        .bind(&request.description)
+        .bind(request.status.unwrap_or_else(|| "open".to_string()))
+        .bind(request.priority.unwrap_or_else(|| "medium".to_string()))
+        .bind(actor)
+        .bind(request.assigned_to)
+        .bind(now)
+        .bind(now)
+        .bind(request.court_date)
+        .bind(&request.court_location)
+        .bind(&request.judge_assigned)
+        .bind(&request.case_type)
+        .bind(&request.jurisdiction)
+        .bind(request.estimated_duration)
+        .bind(request.case_value)
+        .bind(request.statute_of_limitations)
+        .bind(&tags)
+        .bind(&request.notes)
+        .bind(false)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| RepositoryError::from_insert(e, "case number"))?;
+
+        audit::record(
+            &mut tx,
+            NewAuditEntry::new(actor, AuditAction::Create, "case", case.id, Some(case.id), None, Some(&case)),
+        )
+        .await?;
+
+        tx.commit().await?;
+        Ok(case)
+    }
+
+    async fn update(&self, actor: Uuid, id: i32, request: UpdateCaseRequest) -> RepositoryResult<Case> {
+        let tags = request
+            .tags
+            .as_ref()
+            .map(serde_json::to_value)
+            .transpose()
+            .map_err(anyhow::Error::from)?;
+
+        let mut tx = self.db.begin().await?;
+
+        let before = query_as::<_, Case>("SELECT * FROM cases WHERE id = $1 AND archived = false FOR UPDATE")
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or(RepositoryError::NotFound)?;
+
+        let case = query_as::<_, Case>(
+            r#"
+            UPDATE cases SET
+                title = COALESCE($1, title),
+                description = COALESCE($2, description),
+                status = COALESCE($3, status),
+                priority = COALESCE($4, priority),
+                assigned_to = COALESCE($5, assigned_to),
+                court_date = COALESCE($6, court_date),
+                court_location = COALESCE($7, court_location),
+                judge_assigned = COALESCE($8, judge_assigned),
+                case_type = COALESCE($9, case_type),
+                jurisdiction = COALESCE($10, jurisdiction),
+                estimated_duration = COALESCE($11, estimated_duration),
+                case_value = COALESCE($12, case_value),
+                statute_of_limitations = COALESCE($13, statute_of_limitations),
+                tags = COALESCE($14, tags),
+                notes = COALESCE($15, notes),
+                updated_at = $16
+            WHERE id = $17
+            RETURNING *
+            "#
+        )
+        .bind(&request.title)
+        .bind(&request.description)
+        .bind(&request.status)
+        .bind(&request.priority)
+        .bind(request.assigned_to)
+        .bind(request.court_date)
+        .bind(&request.court_location)
+        .bind(&request.judge_assigned)
+        .bind(&request.case_type)
+        .bind(&request.jurisdiction)
+        .bind(request.estimated_duration)
+        .bind(request.case_value)
+        .bind(request.statute_of_limitations)
+        .bind(&tags)
+        .bind(&request.notes)
+        .bind(Utc::now())
+        .bind(id)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        audit::record(
+            &mut tx,
+            NewAuditEntry::new(actor, AuditAction::Update, "case", case.id, Some(case.id), Some(&before), Some(&case)),
+        )
+        .await?;
+
+        tx.commit().await?;
+        Ok(case)
+    }
+
+    async fn archive(&self, actor: Uuid, id: i32) -> RepositoryResult<Case> {
+        let mut tx = self.db.begin().await?;
+
+        let before = query_as::<_, Case>("SELECT * FROM cases WHERE id = $1 FOR UPDATE")
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or(RepositoryError::NotFound)?;
+
+        let archived = query_as::<_, Case>("UPDATE cases SET archived = true, updated_at = $2 WHERE id = $1 RETURNING *")
+            .bind(id)
+            .bind(Utc::now())
+            .fetch_one(&mut *tx)
+            .await?;
+
+        audit::record(
+            &mut tx,
+            NewAuditEntry::new(actor, AuditAction::Delete, "case", id, Some(id), Some(&before), Some(&archived)),
+        )
+        .await?;
+
+        tx.commit().await?;
+        Ok(archived)
+    }
+
+    async fn collaborators(&self, case_id: i32) -> RepositoryResult<Vec<Uuid>> {
+        let collaborators: Vec<(Uuid,)> =
+            query_as("SELECT user_id FROM case_collaborators WHERE case_id = $1 ORDER BY added_at")
+                .bind(case_id)
+                .fetch_all(self.db.as_ref())
+                .await?;
+        Ok(collaborators.into_iter().map(|(user_id,)| user_id).collect())
+    }
+
+    async fn add_collaborator(&self, case_id: i32, user_id: Uuid, added_by: Uuid) -> RepositoryResult<()> {
+        query(
+            r#"
+            INSERT INTO case_collaborators (case_id, user_id, added_by)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (case_id, user_id) DO NOTHING
+            "#
+        )
+        .bind(case_id)
+        .bind(user_id)
+        .bind(added_by)
+        .execute(self.db.as_ref())
+        .await?;
+        Ok(())
+    }
+
+    async fn remove_collaborator(&self, case_id: i32, user_id: Uuid) -> RepositoryResult<bool> {
+        let result = query("DELETE FROM case_collaborators WHERE case_id = $1 AND user_id = $2")
+            .bind(case_id)
+            .bind(user_id)
+            .execute(self.db.as_ref())
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+pub struct PgEvidenceRepository {
+    db: DbConnection,
+}
+
+impl PgEvidenceRepository {
+    pub fn new(db: DbConnection) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl EvidenceRepository for PgEvidenceRepository {
+    async fn get(&self, id: i32) -> RepositoryResult<Option<Evidence>> {
+        let evidence = query_as::<_, Evidence>("SELECT * FROM evidence WHERE id = $1")
+            .bind(id)
+            .fetch_optional(self.db.as_ref())
+            .await?;
+        Ok(evidence)
+    }
+
+    async fn list_for_case(&self, case_id: i32) -> RepositoryResult<Vec<Evidence>> {
+        let evidence = query_as::<_, Evidence>("SELECT * FROM evidence WHERE case_id = $1 ORDER BY created_at, id")
+            .bind(case_id)
+            .fetch_all(self.db.as_ref())
+            .await?;
+        Ok(evidence)
+    }
+
+    async fn extracted_text(&self, id: i32) -> RepositoryResult<Option<String>> {
+        let text: Option<(Option<String>,)> = query_as("SELECT extracted_text FROM evidence WHERE id = $1")
+            .bind(id)
+            .fetch_optional(self.db.as_ref())
+            .await?;
+        text.map(|(text,)| text).ok_or(RepositoryError::NotFound)
+    }
+
+    async fn find_by_hash(&self, case_id: i32, sha256: &str) -> RepositoryResult<Option<i32>> {
+        let existing: Option<(i32,)> = query_as("SELECT id FROM evidence WHERE case_id = $1 AND hash_sha256 = $2")
+            .bind(case_id)
+            .bind(sha256)
+            .fetch_optional(self.db.as_ref())
+            .await?;
+        Ok(existing.map(|(id,)| id))
+    }
+
+    async fn create(&self, actor: Uuid, new: NewEvidence) -> RepositoryResult<Evidence> {
+        let mut tx = self.db.begin().await?;
+
+        let evidence = query_as::<_, Evidence>(
+            r#"
+            INSERT INTO evidence (
+                case_id, criminal_id, title, description, evidence_type,
+                file_path, file_size, file_type, hash_sha256, uploaded_by, created_at, extracted_text,
+                anchor_points
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+            RETURNING *
+            "#
+        )
+        .bind(new.case_id)
+        .bind(new.criminal_id)
+        .bind(&new.title)
+        .bind(&new.description)
+        .bind(&new.evidence_type)
+        .bind(&new.file_path)
+        .bind(new.file_size)
+        .bind(&new.file_type)
+        .bind(&new.hash_sha256)
+        .bind(new.uploaded_by)
+        .bind(Utc::now())
+        .bind(&new.extracted_text)
+        .bind(chunker::anchors_json(&new.chunk_anchors))
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(|e| RepositoryError::from_insert(e, "this file in the case"))?;
+
+        if let (Some(hash), Some(size)) = (new.file_path.as_deref().and_then(parse_content_key), new.file_size) {
+            evidence_store::retain_blob(&mut tx, hash, size).await?;
+        }
+
+        audit::record(
+            &mut tx,
+            NewAuditEntry::new(actor, AuditAction::Create, "evidence", evidence.id, evidence.case_id, None, Some(&evidence)),
+        )
+        .await?;
+
+        tx.commit().await?;
+        Ok(evidence)
+    }
+
+    async fn delete(&self, actor: Uuid, id: i32) -> RepositoryResult<Evidence> {
+        let mut tx = self.db.begin().await?;
+
+        let evidence = query_as::<_, Evidence>("DELETE FROM evidence WHERE id = $1 RETURNING *")
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or(RepositoryError::NotFound)?;
+
+        // Content-addressed files are shared; the caller collects the object once nothing uses it
+        if let Some(hash) = evidence.file_path.as_deref().and_then(parse_content_key) {
+            evidence_store::release_blob(&mut tx, hash).await?;
+        }
+
+        audit::record(
+            &mut tx,
+            NewAuditEntry::new(actor, AuditAction::Delete, "evidence", evidence.id, evidence.case_id, Some(&evidence), None),
+        )
+        .await?;
+
+        tx.commit().await?;
+        Ok(evidence)
+    }
+
+    async fn save_extraction(&self, actor: Uuid, id: i32, extraction: EvidenceExtraction) -> RepositoryResult<Evidence> {
+        let mut tx = self.db.begin().await?;
+
+        let before = query_as::<_, Evidence>("SELECT * FROM evidence WHERE id = $1 FOR UPDATE")
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or(RepositoryError::NotFound)?;
+
+        let evidence = query_as::<_, Evidence>(
+            "UPDATE evidence
+             SET ai_tags = $2, ai_summary = coalesce($3, ai_summary), ai_extraction = $4, ai_extracted_at = NOW()
+             WHERE id = $1
+             RETURNING *",
+        )
+        .bind(id)
+        .bind(Json(&extraction.tags))
+        .bind(&extraction.summary)
+        .bind(&extraction.extraction)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        audit::record(
+            &mut tx,
+            NewAuditEntry::new(actor, AuditAction::Update, "evidence", evidence.id, evidence.case_id, Some(&before), Some(&evidence)),
+        )
+        .await?;
+
+        tx.commit().await?;
+        Ok(evidence)
+    }
+}
+
+pub struct PgAuditRepository {
+    db: DbConnection,
+}
+
+impl PgAuditRepository {
+    pub fn new(db: DbConnection) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl AuditRepository for PgAuditRepository {
+    async fn list(&self, case_id: Option<i32>, before_id: Option<i64>, limit: i64) -> RepositoryResult<Vec<AuditEntry>> {
+        let entries = query_as::<_, AuditEntry>(
+            r#"
+            SELECT * FROM audit_log
+            WHERE ($1::INTEGER IS NULL OR case_id = $1)
+              AND ($2::BIGINT IS NULL OR id < $2)
+            ORDER BY id DESC
+            LIMIT $3
+            "#
+        )
+        .bind(case_id)
+        .bind(before_id)
+        .bind(limit)
+        .fetch_all(self.db.as_ref())
+        .await?;
+        Ok(entries)
+    }
+
+    async fn verify(&self) -> RepositoryResult<ChainVerification> {
+        Ok(audit::verify_chain(&self.db).await?)
+    }
+}
+
+pub struct PgUserRepository {
+    db: DbConnection,
+}
+
+impl PgUserRepository {
+    pub fn new(db: DbConnection) -> Self {
+        Self { db }
+    }
+}
+
+#[async_trait]
+impl UserRepository for PgUserRepository {
+    async fn get(&self, id: Uuid) -> RepositoryResult<Option<User>> {
+        let user = query_as::<_, User>("SELECT * FROM users WHERE id = $1")
+            .bind(id)
+            .fetch_optional(self.db.as_ref())
+            .await?;
+        Ok(user)
+    }
+
+    async fn find_by_email(&self, email: &str) -> RepositoryResult<Option<User>> {
+        let user = query_as::<_, User>("SELECT * FROM users WHERE email = $1")
+            .bind(email)
+            .fetch_optional(self.db.as_ref())
+            .await?;
+        Ok(user)
+    }
+
+    async fn create(&self, new: NewUser) -> RepositoryResult<User> {
+        let now = Utc::now();
+        let name = display_name(new.first_name.as_deref(), new.last_name.as_deref());
+
+        let user = query_as::<_, User>(
+            r#"
+            INSERT INTO users (
+                id, email, hashed_password, role, is_active, created_at, updated_at,
+                first_name, last_name, name, title, department, specializations
+            ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+            RETURNING *
+            "#
+        )
+        .bind(Uuid::new_v4())
+        .bind(&new.email)
+        .bind(&new.hashed_password)
+        .bind(&new.role)
+        .bind(true)
+        .bind(now)
+        .bind(now)
+        .bind(&new.first_name)
+        .bind(&new.last_name)
+        .bind(&name)
+        .bind(&new.title)
+        .bind(&new.department)
+        .bind(serde_json::json!([]))
+        .fetch_one(self.db.as_ref())
+        .await
+        .map_err(|e| RepositoryError::from_insert(e, "a user with this email"))?;
+        Ok(user)
+    }
+
+    async fn set_password_hash(&self, id: Uuid, hashed_password: &str) -> RepositoryResult<()> {
+        query("UPDATE users SET hashed_password = $1, updated_at = $2 WHERE id = $3")
+            .bind(hashed_password)
+            .bind(Utc::now())
+            .bind(id)
+            .execute(self.db.as_ref())
+            .await?;
+        Ok(())
+    }
+
+    async fn create_session(&self, session: NewSession) -> RepositoryResult<()> {
+        query(
+            r#"
+            INSERT INTO sessions (id, user_id, refresh_token_hash, user_agent, ip_address, expires_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#
+        )
+        .bind(session.id)
+        .bind(session.user_id)
+        .bind(&session.refresh_token_hash)
+        .bind(&session.user_agent)
+        .bind(&session.ip_address)
+        .bind(session.expires_at)
+        .execute(self.db.as_ref())
+        .await?;
+        Ok(())
+    }
+
+    async fn active_sessions(&self, user_id: Uuid) -> RepositoryResult<Vec<Session>> {
+        let sessions = query_as::<_, Session>(
+            r#"
+            SELECT * FROM sessions
+            WHERE user_id = $1 AND revoked_at IS NULL AND expires_at > NOW()
+            ORDER BY last_used_at DESC
+            "#
+        )
+        .bind(user_id)
+        .fetch_all(self.db.as_ref())
+        .await?;
+        Ok(sessions)
+    }
+
+    async fn rotate_refresh_token(
+        &self,
+        session_id: Uuid,
+        presented_hash: &str,
+        new_hash: &str,
+    ) -> RepositoryResult<Option<Session>> {
+        let session = query_as::<_, Session>(
+            r#"
+            UPDATE sessions
+            SET previous_refresh_token_hash = refresh_token_hash, refresh_token_hash = $1, last_used_at = NOW()
+            WHERE id = $2 AND refresh_token_hash = $3 AND revoked_at IS NULL AND expires_at > NOW()
+            RETURNING *
+            "#
+        )
+        .bind(new_hash)
+        .bind(session_id)
+        .bind(presented_hash)
+        .fetch_optional(self.db.as_ref())
+        .await?;
+        Ok(session)
+    }
+
+    async fn find_rotated_out(&self, session_id: Uuid, presented_hash: &str) -> RepositoryResult<Option<Session>> {
+        let session = query_as::<_, Session>(
+            r#"
+            SELECT * FROM sessions
+            WHERE id = $1 AND previous_refresh_token_hash = $2 AND revoked_at IS NULL AND expires_at > NOW()
+            "#
+        )
+        .bind(session_id)
+        .bind(presented_hash)
+        .fetch_optional(self.db.as_ref())
+        .await?;
+        Ok(session)
+    }
+
+    async fn revoke_session(&self, session_id: Uuid, owner: Option<Uuid>) -> RepositoryResult<Option<RevokedSession>> {
+        let revoked: Option<(Uuid, i64)> = query_as(
+            r#"
+            UPDATE sessions SET revoked_at = NOW()
+            WHERE id = $1 AND ($2::UUID IS NULL OR user_id = $2) AND revoked_at IS NULL
+            RETURNING user_id, EXTRACT(EPOCH FROM expires_at)::BIGINT
+            "#
+        )
+        .bind(session_id)
+        .bind(owner)
+        .fetch_optional(self.db.as_ref())
+        .await?;
+
+        Ok(revoked.map(|(user_id, expires_at)| RevokedSession { session_id, user_id, expires_at }))
+    }
+
+    async fn revoke_other_sessions(&self, user_id: Uuid, keep: Uuid) -> RepositoryResult<Vec<RevokedSession>> {
+        let revoked: Vec<(Uuid, i64)> = query_as(
+            r#"
+            UPDATE sessions SET revoked_at = NOW()
+            WHERE user_id = $1 AND id <> $2 AND revoked_at IS NULL
+            RETURNING id, EXTRACT(EPOCH FROM expires_at)::BIGINT
+            "#
+        )
+        .bind(user_id)
+        .bind(keep)
+        .fetch_all(self.db.as_ref())
+        .await?;
+
+        Ok(revoked
+            .into_iter()
+            .map(|(session_id, expires_at)| RevokedSession { session_id, user_id, expires_at })
+            .collect())
+    }
+}
@@ -0,0 +1,784 @@
+// Resumable chunked uploads for prosecutor-core
+// A client opens an upload session, PUTs numbered fixed-size chunks in any order (re-sending a
+// chunk overwrites it), asks which byte ranges the server holds, and finalizes. Partial chunks
+// live under `upload_dir/.resumable/<session>/`. Finalization assembles them through the same
+// staging path as direct uploads, hands the file to `FileProcessor::process_file`, and creates
+// the evidence row through the repository like a direct upload. The `finalizing` claim makes sure
+// that happens once; the row is taken back out if the file cannot be stored or the claim was lost.
+
+use anyhow::anyhow;
+use chrono::{Duration as ChronoDuration, Utc};
+use sha2::{Digest, Sha256};
+use sqlx::{query, query_as};
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::{fs, io::AsyncReadExt};
+use uuid::Uuid;
+
+use crate::{
+    chunker::{self, ChunkOptions},
+    config::Config,
+    database::DbConnection,
+    evidence_store::{self, content_key, EvidenceStore},
+    file_processor::FileProcessor,
+    models::{ByteRange, CreateUploadRequest, Evidence, UploadChunk, UploadSession, UploadSessionResponse},
+    permissions::{can_access_case, CurrentUser},
+    repository::{EvidenceRepository, NewEvidence, RepositoryError},
+    search,
+    upload::{StagedUpload, StagingWriter, UploadError},
+    utils::to_hex,
+};
+
+pub const MIN_CHUNK_SIZE: i32 = 256 * 1024;
+pub const MAX_CHUNK_SIZE: i32 = 64 * 1024 * 1024;
+
+/// A session left in `finalizing` this long is assumed to belong to a request that died
+const FINALIZE_TIMEOUT_MINUTES: i64 = 15;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ResumableError {
+    #[error("upload session not found")]
+    NotFound,
+    #[error("invalid upload: {0}")]
+    Invalid(String),
+    #[error("upload session is {0}")]
+    Closed(String),
+    #[error("upload session is being finalized")]
+    Busy,
+    #[error("upload is incomplete: {0} chunks missing")]
+    Incomplete(usize),
+    #[error("file digest {actual} does not match expected {expected}")]
+    ChecksumMismatch { expected: String, actual: String },
+    #[error("file is already attached to this case")]
+    Duplicate(Option<i32>), // The existing evidence id, when known
+    #[error(transparent)]
+    Upload(#[from] UploadError),
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+impl From<sqlx::Error> for ResumableError {
+    fn from(e: sqlx::Error) -> Self {
+        ResumableError::Internal(e.into())
+    }
+}
+
+impl From<RepositoryError> for ResumableError {
+    fn from(e: RepositoryError) -> Self {
+        match e {
+            RepositoryError::NotFound => ResumableError::NotFound,
+            RepositoryError::Conflict(_) => ResumableError::Duplicate(None),
+            e => ResumableError::Internal(e.into()),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UploadSettings {
+    pub upload_dir: PathBuf,
+    pub default_chunk_size: i32,
+    pub max_upload_size: u64,
+    pub session_ttl: ChronoDuration,
+}
+
+impl UploadSettings {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            upload_dir: PathBuf::from(&config.upload_dir),
+            default_chunk_size: config.upload_chunk_size.clamp(MIN_CHUNK_SIZE, MAX_CHUNK_SIZE),
+            max_upload_size: config.max_resumable_upload_size,
+            session_ttl: ChronoDuration::hours(config.upload_session_ttl_hours),
+        }
+    }
+
+    fn session_dir(&self, session_id: Uuid) -> PathBuf {
+        self.upload_dir.join(".resumable").join(session_id.to_string())
+    }
+}
+
+fn chunk_path(session_dir: &Path, index: u32) -> PathBuf {
+    session_dir.join(format!("{:08}.chunk", index))
+}
+
+pub fn total_chunks(total_size: i64, chunk_size: i32) -> u32 {
+    if total_size <= 0 || chunk_size <= 0 {
+        return 0;
+    }
+    ((total_size + chunk_size as i64 - 1) / chunk_size as i64) as u32
+}
+
+/// Length chunk `index` must have: `chunk_size` for all but the last chunk, which holds the rest.
+pub fn expected_chunk_len(total_size: i64, chunk_size: i32, index: u32) -> Option<u64> {
+    if index >= total_chunks(total_size, chunk_size) {
+        return None;
+    }
+    let start = index as u64 * chunk_size as u64;
+    Some((total_size as u64 - start).min(chunk_size as u64))
+}
+
+/// Byte ranges covered by the received chunks, merged where chunks are adjacent.
+pub fn received_ranges(chunks: &[UploadChunk], chunk_size: i32) -> Vec<ByteRange> {
+    let mut indexed: Vec<&UploadChunk> = chunks.iter().collect();
+    indexed.sort_by_key(|chunk| chunk.chunk_index);
+
+    let mut ranges: Vec<ByteRange> = Vec::new();
+    for chunk in indexed {
+        let start = chunk.chunk_index as u64 * chunk_size as u64;
+        let end = start + chunk.size as u64;
+        match ranges.last_mut() {
+            Some(last) if last.end == start => last.end = end,
+            _ => ranges.push(ByteRange { start, end }),
+        }
+    }
+    ranges
+}
+
+pub fn missing_chunks(chunks: &[UploadChunk], total: u32) -> Vec<u32> {
+    let mut present = vec![false; total as usize];
+    for chunk in chunks {
+        if let Some(slot) = present.get_mut(chunk.chunk_index as usize) {
+            *slot = true;
+        }
+    }
+    (0..total).filter(|&index| !present[index as usize]).collect()
+}
+
+pub fn session_response(session: &UploadSession, chunks: &[UploadChunk]) -> UploadSessionResponse {
+    let total = total_chunks(session.total_size, session.chunk_size);
+    let received = received_ranges(chunks, session.chunk_size);
+
+    UploadSessionResponse {
+        id: session.id,
+        status: session.status.clone(),
+        file_name: session.file_name.clone(),
+        total_size: session.total_size,
+        chunk_size: session.chunk_size,
+        total_chunks: total,
+        received_bytes: received.iter().map(|range| range.end - range.start).sum(),
+        received,
+        missing_chunks: missing_chunks(chunks, total),
+        evidence_id: session.evidence_id,
+        expires_at: session.expires_at,
+    }
+}
+
+fn ensure_open(session: &UploadSession) -> Result<(), ResumableError> {
+    if session.status != "open" {
+        return Err(ResumableError::Closed(session.status.clone()));
+    }
+    if session.expires_at <= Utc::now() {
+        return Err(ResumableError::Closed("expired".to_string()));
+    }
+    Ok(())
+}
+
+pub async fn create_session(
+    db: &DbConnection,
+    settings: &UploadSettings,
+    user_id: Uuid,
+    request: CreateUploadRequest,
+) -> Result<UploadSession, ResumableError> {
+    if request.title.trim().is_empty() || request.evidence_type.trim().is_empty() || request.file_name.trim().is_empty() {
+        return Err(ResumableError::Invalid("title, evidence_type and file_name are required".to_string()));
+    }
+    if request.total_size <= 0 || request.total_size as u64 > settings.max_upload_size {
+        return Err(ResumableError::Invalid(format!(
+            "total_size must be between 1 and {} bytes",
+            settings.max_upload_size
+        )));
+    }
+
+    let chunk_size = request.chunk_size.unwrap_or(settings.default_chunk_size);
+    if !(MIN_CHUNK_SIZE..=MAX_CHUNK_SIZE).contains(&chunk_size) {
+        return Err(ResumableError::Invalid(format!(
+            "chunk_size must be between {} and {} bytes",
+            MIN_CHUNK_SIZE, MAX_CHUNK_SIZE
+        )));
+    }
+
+    let expected_sha256 = request.sha256.map(|digest| digest.to_lowercase());
+    if let Some(digest) = &expected_sha256 {
+        if digest.len() != 64 || !digest.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(ResumableError::Invalid("sha256 must be 64 hex characters".to_string()));
+        }
+    }
+
+    let session = query_as::<_, UploadSession>(
+        r#"
+        INSERT INTO upload_sessions (
+            id, user_id, case_id, criminal_id, title, description, evidence_type, file_name,
+            content_type, total_size, chunk_size, expected_sha256, status, expires_at
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, 'open', $13)
+        RETURNING *
+        "#
+    )
+    .bind(Uuid::new_v4())
+    .bind(user_id)
+    .bind(request.case_id)
+    .bind(request.criminal_id)
+    .bind(request.title.trim())
+    .bind(&request.description)
+    .bind(request.evidence_type.trim())
+    .bind(request.file_name.trim())
+    .bind(&request.content_type)
+    .bind(request.total_size)
+    .bind(chunk_size)
+    .bind(&expected_sha256)
+    .bind(Utc::now() + settings.session_ttl)
+    .fetch_one(db.as_ref())
+    .await?;
+
+    fs::create_dir_all(settings.session_dir(session.id))
+        .await
+        .map_err(UploadError::from)?;
+
+    Ok(session)
+}
+
+/// Load a session owned by `user_id`; other users' sessions look missing.
+pub async fn load_session(db: &DbConnection, session_id: Uuid, user_id: Uuid) -> Result<UploadSession, ResumableError> {
+    query_as::<_, UploadSession>("SELECT * FROM upload_sessions WHERE id = $1 AND user_id = $2")
+        .bind(session_id)
+        .bind(user_id)
+        .fetch_optional(db.as_ref())
+        .await?
+        .ok_or(ResumableError::NotFound)
+}
+
+pub async fn load_chunks(db: &DbConnection, session_id: Uuid) -> Result<Vec<UploadChunk>, ResumableError> {
+    let chunks = query_as::<_, UploadChunk>(
+        "SELECT * FROM upload_chunks WHERE session_id = $1 ORDER BY chunk_index"
+    )
+    .bind(session_id)
+    .fetch_all(db.as_ref())
+    .await?;
+    Ok(chunks)
+}
+
+/// Store chunk `index`, checking its length and (when the client sent one) its SHA-256.
+pub async fn write_chunk(
+    db: &DbConnection,
+    settings: &UploadSettings,
+    session: &UploadSession,
+    index: u32,
+    data: &[u8],
+    claimed_sha256: Option<&str>,
+) -> Result<UploadChunk, ResumableError> {
+    ensure_open(session)?;
+
+    let expected_len = expected_chunk_len(session.total_size, session.chunk_size, index)
+        .ok_or_else(|| ResumableError::Invalid(format!("chunk index {} is out of range", index)))?;
+    if data.len() as u64 != expected_len {
+        return Err(ResumableError::Invalid(format!(
+            "chunk {} must be {} bytes, got {}",
+            index,
+            expected_len,
+            data.len()
+        )));
+    }
+
+    let digest = to_hex(&Sha256::digest(data));
+    if let Some(claimed) = claimed_sha256 {
+        if !claimed.eq_ignore_ascii_case(&digest) {
+            return Err(ResumableError::Invalid(format!("chunk {} failed its checksum", index)));
+        }
+    }
+
+    // Write beside the final name and rename, so a chunk file is never half-written
+    let session_dir = settings.session_dir(session.id);
+    fs::create_dir_all(&session_dir).await.map_err(UploadError::from)?;
+    let final_path = chunk_path(&session_dir, index);
+    let temp_path = final_path.with_extension(format!("{}.tmp", Uuid::new_v4()));
+    fs::write(&temp_path, data).await.map_err(UploadError::from)?;
+
+    // Touching the row locks it, so finalize cannot claim the session until this chunk is in
+    // place, and a session already being finalized gets nothing written into its directory.
+    // Concurrent chunks queue on the lock; a share lock followed by this update would deadlock.
+    let mut tx = db.begin().await?;
+    let status: Option<(String,)> =
+        query_as("UPDATE upload_sessions SET updated_at = NOW() WHERE id = $1 RETURNING status")
+            .bind(session.id)
+            .fetch_optional(&mut *tx)
+            .await?;
+    let status = match status {
+        Some((status,)) if status == "open" => Ok(()),
+        Some((status,)) if status == "finalizing" => Err(ResumableError::Busy),
+        Some((status,)) => Err(ResumableError::Closed(status)),
+        None => Err(ResumableError::NotFound),
+    };
+    if let Err(e) = status {
+        let _ = fs::remove_file(&temp_path).await;
+        return Err(e);
+    }
+
+    if let Err(e) = fs::rename(&temp_path, &final_path).await {
+        let _ = fs::remove_file(&temp_path).await;
+        return Err(UploadError::from(e).into());
+    }
+
+    let chunk = query_as::<_, UploadChunk>(
+        r#"
+        INSERT INTO upload_chunks (session_id, chunk_index, size, sha256, received_at)
+        VALUES ($1, $2, $3, $4, NOW())
+        ON CONFLICT (session_id, chunk_index)
+        DO UPDATE SET size = EXCLUDED.size, sha256 = EXCLUDED.sha256, received_at = EXCLUDED.received_at
+        RETURNING *
+        "#
+    )
+    .bind(session.id)
+    .bind(index as i32)
+    .bind(data.len() as i32)
+    .bind(&digest)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(chunk)
+}
+
+/// Concatenate the session's chunk files into a staged upload (hashed and sniffed on the way).
+pub async fn assemble_chunks(
+    settings: &UploadSettings,
+    session: &UploadSession,
+) -> Result<StagedUpload, UploadError> {
+    let session_dir = settings.session_dir(session.id);
+    let mut writer = StagingWriter::create(
+        &settings.upload_dir,
+        Some(&session.file_name),
+        settings.max_upload_size as usize,
+    )
+    .await?;
+
+    let mut buffer = vec![0u8; 64 * 1024];
+    for index in 0..total_chunks(session.total_size, session.chunk_size) {
+        let mut file = fs::File::open(chunk_path(&session_dir, index)).await?;
+        loop {
+            let read = file.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+            writer.write(&buffer[..read]).await?;
+        }
+    }
+
+    writer
+        .finish(Some(session.file_name.clone()), session.content_type.clone())
+        .await
+}
+
+/// Turn a complete session into an evidence row. Retrying a finalize that already succeeded
+/// returns the same evidence instead of creating another.
+pub async fn finalize(
+    db: &DbConnection,
+    evidence_repo: &dyn EvidenceRepository,
+    settings: &UploadSettings,
+    processor: &FileProcessor,
+    store: &dyn EvidenceStore,
+    session_id: Uuid,
+    user: &CurrentUser,
+) -> Result<Evidence, ResumableError> {
+    let claimed = query_as::<_, UploadSession>(
+        r#"
+        UPDATE upload_sessions SET status = 'finalizing', updated_at = NOW()
+        WHERE id = $1 AND user_id = $2 AND expires_at > NOW()
+          AND (status = 'open' OR (status = 'finalizing' AND updated_at < NOW() - make_interval(mins => $3)))
+        RETURNING *
+        "#
+    )
+    .bind(session_id)
+    .bind(user.user_id)
+    .bind(FINALIZE_TIMEOUT_MINUTES as i32)
+    .fetch_optional(db.as_ref())
+    .await?;
+
+    let session = match claimed {
+        Some(session) => session,
+        None => {
+            let session = load_session(db, session_id, user.user_id).await?;
+            return match (session.status.as_str(), session.evidence_id) {
+                ("completed", Some(evidence_id)) => evidence_repo.get(evidence_id).await?.ok_or(ResumableError::NotFound),
+                ("finalizing", _) => Err(ResumableError::Busy),
+                _ => {
+                    ensure_open(&session)?;
+                    Err(ResumableError::Busy)
+                }
+            };
+        }
+    };
+
+    match finalize_claimed(db, evidence_repo, settings, processor, store, &session, user).await {
+        Ok(evidence) => {
+            let _ = fs::remove_dir_all(settings.session_dir(session.id)).await;
+            Ok(evidence)
+        }
+        Err(e) => {
+            // Hand the session back so the client can fix the problem and retry
+            query("UPDATE upload_sessions SET status = 'open', updated_at = NOW() WHERE id = $1 AND status = 'finalizing'")
+                .bind(session.id)
+                .execute(db.as_ref())
+                .await?;
+            Err(e)
+        }
+    }
+}
+
+async fn finalize_claimed(
+    db: &DbConnection,
+    evidence_repo: &dyn EvidenceRepository,
+    settings: &UploadSettings,
+    processor: &FileProcessor,
+    store: &dyn EvidenceStore,
+    session: &UploadSession,
+    user: &CurrentUser,
+) -> Result<Evidence, ResumableError> {
+    let chunks = load_chunks(db, session.id).await?;
+    let missing = missing_chunks(&chunks, total_chunks(session.total_size, session.chunk_size));
+    if !missing.is_empty() {
+        return Err(ResumableError::Incomplete(missing.len()));
+    }
+
+    // Access may have been withdrawn while the upload was in flight
+    if let Some(case_id) = session.case_id {
+        if !can_access_case(db, user, case_id).await? {
+            return Err(ResumableError::NotFound);
+        }
+    }
+
+    let staged = assemble_chunks(settings, session).await?;
+    if staged.size != session.total_size as u64 {
+        return Err(ResumableError::Invalid(format!(
+            "assembled {} bytes, expected {}",
+            staged.size, session.total_size
+        )));
+    }
+    if let Some(expected) = &session.expected_sha256 {
+        if *expected != staged.sha256 {
+            return Err(ResumableError::ChecksumMismatch {
+                expected: expected.clone(),
+                actual: staged.sha256.clone(),
+            });
+        }
+    }
+
+    if let Some(case_id) = session.case_id {
+        if let Some(existing_id) = evidence_repo.find_by_hash(case_id, &staged.sha256).await? {
+            return Err(ResumableError::Duplicate(Some(existing_id)));
+        }
+    }
+
+    // Text is only for search: a file it cannot be extracted from is still evidence. Prefer the
+    // sniffed type; fall back to what processing infers from the extension.
+    let mut mime_type = staged.mime_type.clone();
+    let mut extracted_text = None;
+    match processor.process_file(&staged.temp_path().to_string_lossy(), &session.file_name).await {
+        Ok(processed) => {
+            tracing::info!(
+                "Processed upload {} ({:?}, {} bytes, {} chars of text)",
+                session.id,
+                processed.file_type,
+                processed.metadata.size_bytes,
+                processed.extracted_text.len()
+            );
+            if staged.mime_type == mime::APPLICATION_OCTET_STREAM.as_ref() {
+                mime_type = processed.metadata.mime_type;
+            }
+            extracted_text = search::indexable_text(&processed.extracted_text);
+        }
+        Err(e) => tracing::warn!("Could not process upload {} ({:?}): {}", session.id, session.file_name, e),
+    }
+    let storage_key = content_key(&staged.sha256);
+    let chunk_anchors = extracted_text
+        .as_deref()
+        .map(|text| chunker::chunk_anchors(text, &ChunkOptions::default()))
+        .unwrap_or_default();
+
+    let sha256 = staged.sha256.clone();
+    let evidence = evidence_repo
+        .create(
+            user.user_id,
+            NewEvidence {
+                case_id: session.case_id,
+                criminal_id: session.criminal_id,
+                title: session.title.clone(),
+                description: session.description.clone(),
+                evidence_type: session.evidence_type.clone(),
+                file_path: Some(storage_key),
+                file_size: Some(staged.size as i64),
+                file_type: Some(mime_type),
+                hash_sha256: Some(sha256.clone()),
+                uploaded_by: user.user_id,
+                extracted_text,
+                chunk_anchors,
+            },
+        )
+        .await?;
+
+    if let Err(e) = evidence_store::store_staged(store, staged).await {
+        remove_evidence(evidence_repo, user, evidence.id).await;
+        return Err(anyhow!("Failed to store upload {}: {}", session.id, e).into());
+    }
+
+    // Only the request holding the claim can complete the session
+    let completed = query(
+        "UPDATE upload_sessions SET status = 'completed', evidence_id = $2, updated_at = NOW()
+         WHERE id = $1 AND status = 'finalizing'"
+    )
+    .bind(session.id)
+    .bind(evidence.id)
+    .execute(db.as_ref())
+    .await;
+    match completed {
+        Ok(result) if result.rows_affected() > 0 => Ok(evidence),
+        completed => {
+            // The claim went stale and another request took over; its row is the one that counts
+            remove_evidence(evidence_repo, user, evidence.id).await;
+            if let Err(e) = evidence_store::collect_blob(db, store, &sha256).await {
+                tracing::warn!("Failed to remove stored object {}: {}", sha256, e);
+            }
+            completed?;
+            Err(ResumableError::Busy)
+        }
+    }
+}
+
+/// Take back a row created by a finalization that could not complete
+async fn remove_evidence(evidence_repo: &dyn EvidenceRepository, user: &CurrentUser, evidence_id: i32) {
+    if let Err(e) = evidence_repo.delete(user.user_id, evidence_id).await {
+        tracing::error!("Failed to remove evidence {} from an unfinished upload: {}", evidence_id, e);
+    }
+}
+
+pub async fn abort(db: &DbConnection, settings: &UploadSettings, session_id: Uuid, user_id: Uuid) -> Result<(), ResumableError> {
+    let aborted = query(
+        "UPDATE upload_sessions SET status = 'aborted', updated_at = NOW()
+         WHERE id = $1 AND user_id = $2 AND status = 'open'"
+    )
+    .bind(session_id)
+    .bind(user_id)
+    .execute(db.as_ref())
+    .await?;
+
+    if aborted.rows_affected() == 0 {
+        let session = load_session(db, session_id, user_id).await?;
+        return Err(ResumableError::Closed(session.status));
+    }
+
+    let _ = fs::remove_dir_all(settings.session_dir(session_id)).await;
+    Ok(())
+}
+
+/// Abort expired sessions and delete their partial chunks. Returns the number cleaned up.
+pub async fn cleanup_expired(db: &DbConnection, settings: &UploadSettings) -> Result<usize, ResumableError> {
+    let expired: Vec<(Uuid,)> = query_as(
+        "UPDATE upload_sessions SET status = 'aborted', updated_at = NOW()
+         WHERE status = 'open' AND expires_at < NOW()
+         RETURNING id"
+    )
+    .fetch_all(db.as_ref())
+    .await?;
+
+    for (session_id,) in &expired {
+        let _ = fs::remove_dir_all(settings.session_dir(*session_id)).await;
+    }
+    Ok(expired.len())
+}
+
+pub fn spawn_session_cleanup(db: DbConnection, settings: UploadSettings, interval: Duration) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match cleanup_expired(&db, &settings).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("Removed {} expired upload sessions", count),
+                Err(e) => tracing::error!("Upload session cleanup failed: {}", e),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(index: i32, size: i32) -> UploadChunk {
+        UploadChunk {
+            session_id: Uuid::nil(),
+            chunk_index: index,
+            size,
+            sha256: String::new(),
+            received_at: Utc::now(),
+        }
+    }
+
+    fn session(total_size: i64, chunk_size: i32) -> UploadSession {
+        UploadSession {
+            id: Uuid::new_v4(),
+            user_id: Uuid::nil(),
+            case_id: None,
+            criminal_id: None,
+            title: "Body cam".to_string(),
+            description: None,
+            evidence_type: "video".to_string(),
+            file_name: "bodycam.mp4".to_string(),
+            content_type: None,
+            total_size,
+            chunk_size,
+            expected_sha256: None,
+            status: "open".to_string(),
+            evidence_id: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            expires_at: Utc::now() + ChronoDuration::hours(1),
+        }
+    }
+
+    #[test]
+    fn test_chunk_geometry() {
+        assert_eq!(total_chunks(10, 4), 3);
+        assert_eq!(total_chunks(8, 4), 2);
+        assert_eq!(total_chunks(0, 4), 0);
+        assert_eq!(expected_chunk_len(10, 4, 1), Some(4));
+        assert_eq!(expected_chunk_len(10, 4, 2), Some(2));
+        assert_eq!(expected_chunk_len(10, 4, 3), None);
+    }
+
+    #[test]
+    fn test_received_ranges_merge_adjacent_chunks() {
+        let chunks = vec![chunk(3, 2), chunk(0, 4), chunk(1, 4)];
+        assert_eq!(
+            received_ranges(&chunks, 4),
+            vec![ByteRange { start: 0, end: 8 }, ByteRange { start: 12, end: 14 }]
+        );
+        assert_eq!(missing_chunks(&chunks, 4), vec![2]);
+    }
+
+    #[test]
+    fn test_closed_sessions_reject_chunks() {
+        let mut completed = session(10, 4);
+        completed.status = "completed".to_string();
+        assert!(matches!(ensure_open(&completed), Err(ResumableError::Closed(status)) if status == "completed"));
+
+        let mut expired = session(10, 4);
+        expired.expires_at = Utc::now() - ChronoDuration::minutes(1);
+        assert!(matches!(ensure_open(&expired), Err(ResumableError::Closed(status)) if status == "expired"));
+    }
+
+    /// A migrated database from `TEST_DATABASE_URL`; tests that need one are skipped without it
+    async fn test_db() -> Option<DbConnection> {
+        let url = std::env::var("TEST_DATABASE_URL").ok()?;
+        let db = crate::database::create_connection(&url).await.unwrap();
+        crate::migrations::run(&db).await.unwrap();
+        Some(db)
+    }
+
+    /// Store `session` as an open upload of a new user, returning the user's id
+    async fn insert_session(db: &DbConnection, session: &UploadSession) -> Uuid {
+        let (user_id,): (Uuid,) = query_as("INSERT INTO users (email) VALUES ($1) RETURNING id")
+            .bind(format!("{}@example.test", session.id))
+            .fetch_one(db.as_ref())
+            .await
+            .unwrap();
+        query(
+            "INSERT INTO upload_sessions (id, user_id, title, evidence_type, file_name, total_size, chunk_size, expires_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+        )
+        .bind(session.id)
+        .bind(user_id)
+        .bind(&session.title)
+        .bind(&session.evidence_type)
+        .bind(&session.file_name)
+        .bind(session.total_size)
+        .bind(session.chunk_size)
+        .bind(session.expires_at)
+        .execute(db.as_ref())
+        .await
+        .unwrap();
+        user_id
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_chunks_to_one_session() {
+        let Some(db) = test_db().await else { return };
+        let upload_dir = std::env::temp_dir().join(format!("resumable-{}", Uuid::new_v4()));
+        let settings = UploadSettings {
+            upload_dir: upload_dir.clone(),
+            default_chunk_size: 4,
+            max_upload_size: 1024,
+            session_ttl: ChronoDuration::hours(1),
+        };
+        let mut session = session(16, 4);
+        session.user_id = insert_session(&db, &session).await;
+
+        // Clients upload chunks in parallel; none may fail on the others' locks
+        let results = tokio::join!(
+            write_chunk(&db, &settings, &session, 0, b"abcd", None),
+            write_chunk(&db, &settings, &session, 1, b"efgh", None),
+            write_chunk(&db, &settings, &session, 2, b"ijkl", None),
+            write_chunk(&db, &settings, &session, 3, b"mnop", None),
+        );
+        for result in [results.0, results.1, results.2, results.3] {
+            result.unwrap();
+        }
+        assert!(missing_chunks(&load_chunks(&db, session.id).await.unwrap(), 4).is_empty());
+
+        query("DELETE FROM users WHERE id = $1").bind(session.user_id).execute(db.as_ref()).await.unwrap();
+        std::fs::remove_dir_all(upload_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_finalize_keeps_files_without_extractable_text() {
+        let Some(db) = test_db().await else { return };
+        let upload_dir = std::env::temp_dir().join(format!("resumable-{}", Uuid::new_v4()));
+        let settings = UploadSettings {
+            upload_dir: upload_dir.clone(),
+            default_chunk_size: 4,
+            max_upload_size: 1024,
+            session_ttl: ChronoDuration::hours(1),
+        };
+        let mut session = session(4, 4);
+        session.file_name = "notes.txt".to_string();
+        session.user_id = insert_session(&db, &session).await;
+        let user = CurrentUser { user_id: session.user_id, role: Some(crate::permissions::Role::Investigator) };
+
+        // Not UTF-8, so the text extractor fails
+        write_chunk(&db, &settings, &session, 0, b"\xff\xfe\xfd\xfc", None).await.unwrap();
+        let evidence_repo = crate::repository::PgEvidenceRepository::new(db.clone());
+        let processor = FileProcessor::new(upload_dir.to_string_lossy().into_owned(), false, true, 1024);
+        let store = crate::evidence_store::LocalEvidenceStore::new(upload_dir.join("store"));
+        let evidence = finalize(&db, &evidence_repo, &settings, &processor, &store, session.id, &user).await.unwrap();
+        assert_eq!(evidence.file_size, Some(4));
+        assert_eq!(evidence_repo.extracted_text(evidence.id).await.unwrap(), None);
+
+        evidence_repo.delete(session.user_id, evidence.id).await.unwrap();
+        query("DELETE FROM users WHERE id = $1").bind(session.user_id).execute(db.as_ref()).await.unwrap();
+        std::fs::remove_dir_all(upload_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_assemble_chunks_in_order() {
+        let upload_dir = std::env::temp_dir().join(format!("resumable-{}", Uuid::new_v4()));
+        let settings = UploadSettings {
+            upload_dir: upload_dir.clone(),
+            default_chunk_size: 4,
+            max_upload_size: 1024,
+            session_ttl: ChronoDuration::hours(1),
+        };
+        let session = session(10, 4);
+        let session_dir = settings.session_dir(session.id);
+        std::fs::create_dir_all(&session_dir).unwrap();
+        for (index, bytes) in [&b"\x00\x00\x00\x18"[..], b"ftyp", b"mp"].iter().enumerate().rev() {
+            std::fs::write(chunk_path(&session_dir, index as u32), bytes).unwrap();
+        }
+
+        let staged = assemble_chunks(&settings, &session).await.unwrap();
+        assert_eq!(staged.size, 10);
+        assert_eq!(staged.mime_type, "video/mp4");
+        assert_eq!(std::fs::read(staged.temp_path()).unwrap(), b"\x00\x00\x00\x18ftypmp");
+
+        drop(staged);
+        std::fs::remove_dir_all(upload_dir).unwrap();
+    }
+}
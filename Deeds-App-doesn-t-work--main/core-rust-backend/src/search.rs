@@ -0,0 +1,294 @@
+// Full-text search over cases and evidence
+// Cases are indexed on title, description and notes; evidence on title, description and the text
+// extracted from its file at upload (migration 0002). Queries accept plain words, "quoted
+// phrases", prefixes (`burgl*`), exclusions (`-alibi`) and `OR`; everything else is ANDed.
+
+use serde::{Deserialize, Serialize};
+use sqlx::{query_as, FromRow};
+
+use crate::{
+    database::DbConnection,
+    permissions::{case_visibility_clause, CurrentUser},
+};
+
+// ts_headline wraps matches in these; they are turned into <mark> after the snippet is escaped
+const MATCH_START: char = '\u{2}';
+const MATCH_END: char = '\u{3}';
+const HEADLINE_OPTIONS: &str =
+    "StartSel=\u{2}, StopSel=\u{3}, MaxWords=35, MinWords=15, MaxFragments=2, FragmentDelimiter=\" … \"";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchKind {
+    Case,
+    Evidence,
+}
+
+impl SearchKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SearchKind::Case => "case",
+            SearchKind::Evidence => "evidence",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SearchRequest {
+    pub query: String,
+    /// Only hits of this kind
+    pub kind: Option<SearchKind>,
+    /// Only the case itself and its evidence
+    pub case_id: Option<i32>,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    pub kind: SearchKind,
+    pub id: i32,
+    pub case_id: Option<i32>,
+    pub title: String,
+    /// HTML-escaped excerpt with matches wrapped in `<mark>`
+    pub snippet: String,
+    /// `ts_rank_cd` normalised to 0..1, comparable between cases and evidence
+    pub rank: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchResults {
+    pub hits: Vec<SearchHit>,
+    pub total: i64,
+}
+
+/// A hit on the requested page, or a lone row carrying only `total` when the page is empty
+#[derive(FromRow)]
+struct HitRow {
+    total: i64,
+    kind: Option<String>,
+    id: Option<i32>,
+    case_id: Option<i32>,
+    title: Option<String>,
+    snippet: Option<String>,
+    rank: Option<f32>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Operator {
+    And,
+    Or,
+}
+
+/// Turn a user query into `to_tsquery` syntax. Words are reduced to letters and digits and quoted,
+/// so the result is always valid whatever was typed. `None` when nothing searchable is left.
+pub fn parse_query(input: &str) -> Option<String> {
+    let mut clauses: Vec<(Operator, String)> = Vec::new();
+    let mut next_operator = Operator::And;
+    let mut chars = input.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let negated = c == '-';
+        if negated {
+            chars.next();
+        }
+        let quoted = chars.peek() == Some(&'"');
+        let raw: String = if quoted {
+            chars.next();
+            chars.by_ref().take_while(|&c| c != '"').collect()
+        } else {
+            chars.by_ref().take_while(|c| !c.is_whitespace()).collect()
+        };
+
+        if !quoted && !negated && raw == "OR" {
+            if !clauses.is_empty() {
+                next_operator = Operator::Or;
+            }
+            continue;
+        }
+
+        let mut words: Vec<String> = raw
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(|word| format!("'{}'", word.to_lowercase()))
+            .collect();
+        let Some(last) = words.last_mut() else { continue };
+        if !quoted && raw.ends_with('*') {
+            last.push_str(":*");
+        }
+
+        // Several words in one term (a phrase, or something like "5th-street") must be adjacent
+        let mut clause = if words.len() > 1 { format!("({})", words.join(" <-> ")) } else { words.remove(0) };
+        if negated {
+            clause = format!("!{}", clause);
+        }
+        clauses.push((std::mem::replace(&mut next_operator, Operator::And), clause));
+    }
+
+    let mut clauses = clauses.into_iter();
+    let (_, mut query) = clauses.next()?;
+    for (operator, clause) in clauses {
+        query = match operator {
+            // OR binds tighter than the implicit AND, as in web search syntax
+            Operator::Or => match query.rsplit_once(" & ") {
+                Some((rest, last)) => format!("{} & ({} | {})", rest, last, clause),
+                None => format!("({} | {})", query, clause),
+            },
+            Operator::And => format!("{} & {}", query, clause),
+        };
+    }
+    Some(query)
+}
+
+/// Extracted file text as it is stored for indexing: Postgres text cannot hold NUL bytes, and
+/// files with no text store nothing
+pub fn indexable_text(extracted: &str) -> Option<String> {
+    let text = extracted.replace('\0', "");
+    (!text.trim().is_empty()).then_some(text)
+}
+
+/// Escape a ts_headline result for HTML, turn its match markers into `<mark>` tags and put it on
+/// one line
+pub fn render_snippet(headline: &str) -> String {
+    let mut html = String::with_capacity(headline.len());
+    for c in headline.trim().chars() {
+        match c {
+            c if c.is_whitespace() => {
+                if !html.ends_with(' ') {
+                    html.push(' ');
+                }
+            }
+            MATCH_START => html.push_str("<mark>"),
+            MATCH_END => html.push_str("</mark>"),
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            c => html.push(c),
+        }
+    }
+    html
+}
+
+/// Cases and evidence visible to `viewer` that match the query, best first. Evidence that is not
+/// attached to a case is visible to everyone, as it is for `GET /api/evidence/:id`.
+pub async fn search(db: &DbConnection, viewer: &CurrentUser, request: &SearchRequest) -> anyhow::Result<SearchResults> {
+    let Some(tsquery) = parse_query(&request.query) else {
+        return Ok(SearchResults { hits: Vec::new(), total: 0 });
+    };
+
+    let visible = case_visibility_clause(viewer, 2);
+    let sql = format!(
+        r#"
+        WITH query AS (SELECT to_tsquery('english', $1) AS q),
+        hits AS (
+            SELECT 'case' AS kind, c.id, c.id AS case_id, ts_rank_cd(c.search_vector, query.q, 32) AS rank
+            FROM cases c, query
+            WHERE ($3::TEXT IS NULL OR $3 = 'case')
+              AND c.search_vector @@ query.q
+              AND c.archived = false
+              AND ($4::INTEGER IS NULL OR c.id = $4)
+              AND {visible}
+            UNION ALL
+            SELECT 'evidence', e.id, e.case_id, ts_rank_cd(e.search_vector, query.q, 32)
+            FROM evidence e LEFT JOIN cases c ON c.id = e.case_id, query
+            WHERE ($3::TEXT IS NULL OR $3 = 'evidence')
+              AND e.search_vector @@ query.q
+              AND ($4::INTEGER IS NULL OR e.case_id = $4)
+              AND (e.case_id IS NULL OR (c.archived = false AND {visible}))
+        ),
+        page AS (
+            SELECT * FROM hits ORDER BY rank DESC, kind, id LIMIT $5 OFFSET $6
+        ),
+        counted AS (SELECT COUNT(*) AS total FROM hits)
+        SELECT counted.total, page.kind, page.id, page.case_id, page.rank,
+            COALESCE(c.title, e.title) AS title,
+            ts_headline(
+                'english',
+                CASE page.kind
+                    WHEN 'case' THEN concat_ws(E'\n', c.title, c.description, c.notes)
+                    ELSE concat_ws(E'\n', e.title, e.description, left(e.extracted_text, 500000))
+                END,
+                query.q,
+                $7
+            ) AS snippet
+        FROM counted
+        CROSS JOIN query
+        LEFT JOIN page ON true
+        LEFT JOIN cases c ON page.kind = 'case' AND c.id = page.id
+        LEFT JOIN evidence e ON page.kind = 'evidence' AND e.id = page.id
+        ORDER BY page.rank DESC, page.kind, page.id
+        "#,
+        visible = visible
+    );
+
+    let rows = query_as::<_, HitRow>(&sql)
+        .bind(&tsquery)
+        .bind(viewer.user_id)
+        .bind(request.kind.map(|kind| kind.as_str()))
+        .bind(request.case_id)
+        .bind(request.limit)
+        .bind(request.offset)
+        .bind(HEADLINE_OPTIONS)
+        .fetch_all(db.as_ref())
+        .await?;
+
+    // Always at least the counted row, so a page past the end still reports the total
+    let total = rows.first().map_or(0, |row| row.total);
+    let hits = rows
+        .into_iter()
+        .filter_map(|row| {
+            Some(SearchHit {
+                kind: if row.kind? == "case" { SearchKind::Case } else { SearchKind::Evidence },
+                id: row.id?,
+                case_id: row.case_id,
+                title: row.title.unwrap_or_default(),
+                snippet: render_snippet(&row.snippet.unwrap_or_default()),
+                rank: row.rank.unwrap_or_default(),
+            })
+        })
+        .collect();
+    Ok(SearchResults { hits, total })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_query() {
+        assert_eq!(parse_query("harbor theft").as_deref(), Some("'harbor' & 'theft'"));
+        assert_eq!(parse_query("\"white van\" burgl*").as_deref(), Some("('white' <-> 'van') & 'burgl':*"));
+        assert_eq!(parse_query("theft -\"bike shed\"").as_deref(), Some("'theft' & !('bike' <-> 'shed')"));
+        assert_eq!(parse_query("arson OR fire damage").as_deref(), Some("('arson' | 'fire') & 'damage'"));
+        assert_eq!(parse_query("damage arson OR fire").as_deref(), Some("'damage' & ('arson' | 'fire')"));
+        assert_eq!(parse_query("5th-street*").as_deref(), Some("('5th' <-> 'street':*)"));
+    }
+
+    #[test]
+    fn test_parse_query_neutralises_tsquery_syntax() {
+        assert_eq!(parse_query("o'brien & (a | !b)").as_deref(), Some("('o' <-> 'brien') & 'a' & 'b'"));
+        assert_eq!(parse_query("OR theft").as_deref(), Some("'theft'"));
+        assert_eq!(parse_query("  \"\" - * !& ").as_deref(), None);
+    }
+
+    #[test]
+    fn test_indexable_text() {
+        assert_eq!(indexable_text("page\0one").as_deref(), Some("pageone"));
+        assert_eq!(indexable_text(" \n\0 "), None);
+    }
+
+    #[test]
+    fn test_render_snippet_escapes_html() {
+        assert_eq!(
+            render_snippet("Harbor\n<script> \u{2}theft\u{3}  & \"more\"\n"),
+            "Harbor &lt;script&gt; <mark>theft</mark> &amp; &quot;more&quot;"
+        );
+    }
+}
@@ -0,0 +1,252 @@
+// Streaming evidence uploads for prosecutor-core
+// Multipart file fields are written chunk by chunk to a temp file under `upload_dir/.staging`,
+// enforcing the size limit, hashing and sniffing the MIME type as bytes arrive. The caller hands
+// the file to the evidence store only after the evidence row has committed; the temp file is
+// removed when the staged upload is dropped.
+
+use axum::extract::multipart::Field;
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+use tokio::{fs, io::AsyncWriteExt};
+use uuid::Uuid;
+
+use crate::integrity::UploadHasher;
+
+/// Bytes kept from the start of the file for MIME sniffing
+const SNIFF_LEN: usize = 512;
+
+#[derive(Debug, thiserror::Error)]
+pub enum UploadError {
+    #[error("upload exceeds the {0} byte limit")]
+    TooLarge(usize),
+    #[error("malformed multipart body: {0}")]
+    Malformed(String),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+/// A fully received upload waiting in the staging directory
+#[derive(Debug)]
+pub struct StagedUpload {
+    temp_path: Option<PathBuf>,
+    pub size: u64,
+    pub sha256: String,
+    pub mime_type: String,
+    pub original_name: Option<String>,
+}
+
+impl StagedUpload {
+    pub fn staging_dir(upload_dir: &Path) -> PathBuf {
+        upload_dir.join(".staging")
+    }
+
+    /// Stream a multipart field to a temp file in `upload_dir/.staging`.
+    pub async fn from_field(
+        mut field: Field<'_>,
+        upload_dir: &Path,
+        max_size: usize,
+    ) -> Result<Self, UploadError> {
+        let original_name = field.file_name().map(str::to_string);
+        let declared_type = field.content_type().map(str::to_string);
+
+        let mut writer = StagingWriter::create(upload_dir, original_name.as_deref(), max_size).await?;
+        while let Some(chunk) = field
+            .chunk()
+            .await
+            .map_err(|e| UploadError::Malformed(e.to_string()))?
+        {
+            writer.write(&chunk).await?;
+        }
+        writer.finish(original_name, declared_type).await
+    }
+
+    pub fn temp_path(&self) -> &Path {
+        self.temp_path.as_deref().expect("staged upload already released")
+    }
+}
+
+impl Drop for StagedUpload {
+    fn drop(&mut self) {
+        if let Some(path) = self.temp_path.take() {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// Incremental writer behind `StagedUpload`; also usable for bodies that aren't multipart fields.
+pub struct StagingWriter {
+    file: Option<fs::File>,
+    temp_path: PathBuf,
+    max_size: usize,
+    hasher: UploadHasher,
+    head: Vec<u8>,
+}
+
+impl StagingWriter {
+    /// The temp file keeps the original extension so file processing can tell its type.
+    pub async fn create(upload_dir: &Path, original_name: Option<&str>, max_size: usize) -> Result<Self, UploadError> {
+        let staging_dir = StagedUpload::staging_dir(upload_dir);
+        fs::create_dir_all(&staging_dir).await?;
+
+        let temp_path = staging_dir.join(format!("{}.{}", Uuid::new_v4(), safe_extension(original_name)));
+        let file = fs::File::create(&temp_path).await?;
+
+        Ok(Self {
+            file: Some(file),
+            temp_path,
+            max_size,
+            hasher: UploadHasher::new(),
+            head: Vec::with_capacity(SNIFF_LEN),
+        })
+    }
+
+    pub async fn write(&mut self, chunk: &[u8]) -> Result<(), UploadError> {
+        if self.hasher.bytes() as usize + chunk.len() > self.max_size {
+            return Err(UploadError::TooLarge(self.max_size));
+        }
+
+        let file = self.file.as_mut().expect("staging writer already finished");
+        file.write_all(chunk).await?;
+        self.hasher.update(chunk);
+
+        let wanted = SNIFF_LEN.saturating_sub(self.head.len()).min(chunk.len());
+        self.head.extend_from_slice(&chunk[..wanted]);
+        Ok(())
+    }
+
+    pub async fn finish(
+        mut self,
+        original_name: Option<String>,
+        declared_type: Option<String>,
+    ) -> Result<StagedUpload, UploadError> {
+        let mut file = self.file.take().expect("staging writer already finished");
+        file.flush().await?;
+        file.sync_all().await?;
+        drop(file);
+
+        let hasher = std::mem::take(&mut self.hasher);
+        let size = hasher.bytes();
+        let mime_type = sniff_mime(&self.head)
+            .map(str::to_string)
+            .or(declared_type)
+            .unwrap_or_else(|| mime::APPLICATION_OCTET_STREAM.to_string());
+
+        Ok(StagedUpload {
+            temp_path: Some(std::mem::take(&mut self.temp_path)),
+            size,
+            sha256: hasher.finish(),
+            mime_type,
+            original_name,
+        })
+    }
+}
+
+impl Drop for StagingWriter {
+    fn drop(&mut self) {
+        // Only an unfinished writer still owns its temp file
+        if self.file.is_some() {
+            let _ = std::fs::remove_file(&self.temp_path);
+        }
+    }
+}
+
+/// Lowercased extension of `name` if it is short and alphanumeric, otherwise "bin"
+pub fn safe_extension(name: Option<&str>) -> String {
+    name.and_then(|name| Path::new(name).extension())
+        .and_then(|ext| ext.to_str())
+        .filter(|ext| !ext.is_empty() && ext.len() <= 10 && ext.chars().all(|c| c.is_ascii_alphanumeric()))
+        .unwrap_or("bin")
+        .to_lowercase()
+}
+
+/// MIME type from the leading bytes of a file, for the formats evidence usually arrives in.
+/// Client-declared types are only a fallback since they are easy to get wrong or spoof.
+pub fn sniff_mime(head: &[u8]) -> Option<&'static str> {
+    const SIGNATURES: &[(&[u8], &str)] = &[
+        (b"%PDF-", "application/pdf"),
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"II*\x00", "image/tiff"),
+        (b"MM\x00*", "image/tiff"),
+        (b"\x1a\x45\xdf\xa3", "video/webm"),
+        (b"OggS", "audio/ogg"),
+        (b"ID3", "audio/mpeg"),
+        (b"fLaC", "audio/flac"),
+        (b"PK\x03\x04", "application/zip"),
+    ];
+
+    if let Some((_, mime)) = SIGNATURES.iter().find(|(magic, _)| head.starts_with(magic)) {
+        return Some(mime);
+    }
+
+    // Container formats identify themselves a few bytes in
+    match (head.get(0..4), head.get(4..8), head.get(8..12)) {
+        (Some(b"RIFF"), _, Some(b"WAVE")) => Some("audio/wav"),
+        (Some(b"RIFF"), _, Some(b"AVI ")) => Some("video/x-msvideo"),
+        (Some(b"RIFF"), _, Some(b"WEBP")) => Some("image/webp"),
+        (_, Some(b"ftyp"), Some(b"qt  ")) => Some("video/quicktime"),
+        (_, Some(b"ftyp"), Some(b"M4A ")) => Some("audio/mp4"),
+        (_, Some(b"ftyp"), _) => Some("video/mp4"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sniff_mime() {
+        assert_eq!(sniff_mime(b"%PDF-1.7\n..."), Some("application/pdf"));
+        assert_eq!(sniff_mime(b"\xff\xd8\xff\xe0\x00\x10JFIF"), Some("image/jpeg"));
+        assert_eq!(sniff_mime(b"\x00\x00\x00\x18ftypmp42"), Some("video/mp4"));
+        assert_eq!(sniff_mime(b"RIFF\x24\x08\x00\x00WAVEfmt "), Some("audio/wav"));
+        assert_eq!(sniff_mime(b"plain text"), None);
+    }
+
+    #[test]
+    fn test_safe_extension() {
+        assert_eq!(safe_extension(Some("bodycam.MP4")), "mp4");
+        assert_eq!(safe_extension(Some("../../etc/passwd")), "bin");
+        assert_eq!(safe_extension(Some("notes.t x t")), "bin");
+        assert_eq!(safe_extension(None), "bin");
+    }
+
+    #[tokio::test]
+    async fn test_staging_writer_enforces_limit_and_cleans_up() {
+        let upload_dir = std::env::temp_dir().join(format!("upload-{}", Uuid::new_v4()));
+        let mut writer = StagingWriter::create(&upload_dir, None, 8).await.unwrap();
+        let temp_path = writer.temp_path.clone();
+
+        writer.write(b"12345").await.unwrap();
+        assert!(matches!(writer.write(b"6789").await, Err(UploadError::TooLarge(8))));
+        drop(writer);
+        assert!(!temp_path.exists());
+
+        std::fs::remove_dir_all(upload_dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_staged_upload_is_hashed_and_cleaned_up() {
+        let upload_dir = std::env::temp_dir().join(format!("upload-{}", Uuid::new_v4()));
+        let mut writer = StagingWriter::create(&upload_dir, Some("Statement.PDF"), 1024).await.unwrap();
+        writer.write(b"%PDF-1.4 ").await.unwrap();
+        writer.write(b"statement").await.unwrap();
+
+        let staged = writer.finish(Some("Statement.PDF".to_string()), None).await.unwrap();
+        assert_eq!(staged.size, 18);
+        assert_eq!(staged.temp_path().extension().unwrap(), "pdf");
+        assert_eq!(staged.mime_type, "application/pdf");
+        assert_eq!(staged.sha256, crate::utils::sha256_file(staged.temp_path().to_str().unwrap()).unwrap());
+
+        let temp_path = staged.temp_path().to_path_buf();
+        drop(staged);
+        assert!(!temp_path.exists());
+
+        std::fs::remove_dir_all(upload_dir).unwrap();
+    }
+}
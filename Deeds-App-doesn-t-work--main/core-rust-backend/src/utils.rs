@@ -1,4 +1,5 @@
-use std::fs;
+use sha2::{Digest, Sha256};
+use std::{fs, io};
 
 pub fn ensure_directory_exists(path: &str) -> Result<(), std::io::Error> {
     if !std::path::Path::new(path).exists() {
@@ -13,3 +14,15 @@ pub fn generate_case_number() -> String {
     let random_suffix: u32 = rand::random::<u32>() % 1000;
     format!("CASE-{}-{:03}", timestamp, random_suffix)
 }
+
+/// Lowercase hex SHA-256 of a file, read in chunks so large evidence files are not loaded into memory
+pub fn sha256_file(path: &str) -> Result<String, std::io::Error> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(to_hex(&hasher.finalize()))
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -0,0 +1,562 @@
+// Versioned vector collections and re-embedding
+// A collection is named after the alias, the embedding model and its dimensions
+// (`prosecutor_cases__openai_nomic_embed_text__768d_v2`) and tracked in `vector_collections`
+// (migration 0003). Changing the embedding model means starting a re-embed: a new collection is
+// filled from Postgres in the background while the alias keeps serving the old one, and the alias
+// moves over only once every evidence row has been embedded. Evidence is walked in id order and
+// progress is saved after each batch, so an interrupted build resumes where it stopped. Rows stored
+// during a build are also written to it directly, and a last pass after the switch catches any
+// that landed between the final batch and the alias move.
+// Each row is embedded as its title and description plus one point per chunk of extracted text
+// (see chunker.rs); rows stored before chunking have their anchors saved on the way through.
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{query, query_as, FromRow};
+use std::sync::Arc;
+
+use crate::{
+    chunker::{self, ChunkAnchor, ChunkOptions, TextChunk},
+    database::DbConnection,
+    embedder::EmbeddingProvider,
+    qdrant::{EvidenceVector, QdrantClient},
+};
+
+/// Evidence rows read per batch, and texts per provider call and Qdrant write
+pub const REEMBED_BATCH_SIZE: i64 = 32;
+
+/// Text sent to the embedding model per point, in characters
+const MAX_EMBED_CHARS: usize = 8000;
+
+const EVIDENCE_TEXT_COLUMNS: &str = "id, case_id, title, description, evidence_type, extracted_text, anchor_points";
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct VectorCollection {
+    pub name: String,
+    pub alias: String,
+    pub model: String,
+    pub dimensions: i32,
+    pub status: String, // building, ready, active, retired or failed
+    pub indexed_count: i32,
+    pub last_evidence_id: i32,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub activated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReembedError {
+    #[error("a re-embed into {0} is already running")]
+    AlreadyRunning(String),
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+#[derive(FromRow)]
+struct EvidenceText {
+    id: i32,
+    case_id: Option<i32>,
+    title: String,
+    description: Option<String>,
+    evidence_type: String,
+    extracted_text: Option<String>,
+    anchor_points: serde_json::Value,
+}
+
+/// `alias__model__NNNd_vN`, with the model reduced to lowercase letters, digits and underscores
+pub fn collection_name(alias: &str, model: &str, dimensions: usize, version: u32) -> String {
+    format!("{}{}", collection_prefix(alias, model, dimensions), version)
+}
+
+// Everything but the version number
+fn collection_prefix(alias: &str, model: &str, dimensions: usize) -> String {
+    let mut slug = String::with_capacity(model.len());
+    for c in model.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('_') {
+            slug.push('_');
+        }
+    }
+    format!("{}__{}__{}d_v", alias, slug.trim_end_matches('_'), dimensions)
+}
+
+/// The chunks of a row's text, from its stored anchors or, for rows stored before chunking,
+/// freshly cut. The flag is set when the anchors still need saving.
+fn text_chunks(evidence: &EvidenceText) -> (Vec<TextChunk>, bool) {
+    let Some(text) = evidence.extracted_text.as_deref() else { return (Vec::new(), false) };
+    let anchors = chunker::read_anchors(&evidence.anchor_points);
+    if !anchors.is_empty() {
+        return (chunker::chunks_from_anchors(text, &anchors), false);
+    }
+    let chunks = chunker::chunk_text(text, &ChunkOptions::default());
+    let unsaved = !chunks.is_empty();
+    (chunks, unsaved)
+}
+
+/// What gets embedded for a row: its title and description, unless there is no description and
+/// the text chunks stand in for it, then each chunk under the title
+fn embed_texts(evidence: &EvidenceText, chunks: &[TextChunk]) -> Vec<(Option<ChunkAnchor>, String)> {
+    let description = evidence.description.as_deref().filter(|description| !description.trim().is_empty());
+    let mut texts = Vec::with_capacity(chunks.len() + 1);
+    if description.is_some() || chunks.is_empty() {
+        let head = [Some(evidence.title.as_str()), description].into_iter().flatten().collect::<Vec<_>>().join("\n\n");
+        texts.push((None, truncate(head)));
+    }
+    for chunk in chunks {
+        texts.push((Some(chunk.anchor), truncate(format!("{}\n\n{}", evidence.title, chunk.text))));
+    }
+    texts
+}
+
+fn truncate(text: String) -> String {
+    match text.char_indices().nth(MAX_EMBED_CHARS) {
+        Some((cut, _)) => text[..cut].to_string(),
+        None => text,
+    }
+}
+
+/// Embed rows into `collection`, returning the number of points written
+async fn index_rows(
+    db: &DbConnection,
+    qdrant: &QdrantClient,
+    embedder: &dyn EmbeddingProvider,
+    collection: &str,
+    rows: &[EvidenceText],
+) -> Result<usize> {
+    let mut owners = Vec::new();
+    let mut texts = Vec::new();
+    for evidence in rows {
+        let (chunks, unsaved) = text_chunks(evidence);
+        if unsaved {
+            let anchors: Vec<ChunkAnchor> = chunks.iter().map(|chunk| chunk.anchor).collect();
+            query("UPDATE evidence SET anchor_points = anchor_points || $2 WHERE id = $1")
+                .bind(evidence.id)
+                .bind(chunker::anchors_json(&anchors))
+                .execute(db.as_ref())
+                .await?;
+        }
+        for (anchor, text) in embed_texts(evidence, &chunks) {
+            owners.push((evidence, anchor));
+            texts.push(text);
+        }
+    }
+
+    let batch_size = REEMBED_BATCH_SIZE as usize;
+    for (owners, texts) in owners.chunks(batch_size).zip(texts.chunks(batch_size)) {
+        let vectors = embedder.embed(texts).await?;
+        let points: Vec<EvidenceVector> = owners
+            .iter()
+            .zip(vectors)
+            .map(|(&(evidence, chunk), embedding)| EvidenceVector {
+                evidence_id: evidence.id,
+                case_id: evidence.case_id,
+                title: evidence.title.clone(),
+                description: evidence.description.clone(),
+                evidence_type: evidence.evidence_type.clone(),
+                chunk,
+                embedding,
+            })
+            .collect();
+        qdrant.upsert_evidence(collection, &points).await?;
+    }
+    Ok(texts.len())
+}
+
+pub async fn list(db: &DbConnection, alias: &str) -> Result<Vec<VectorCollection>> {
+    let collections = query_as::<_, VectorCollection>(
+        "SELECT * FROM vector_collections WHERE alias = $1 ORDER BY created_at DESC",
+    )
+    .bind(alias)
+    .fetch_all(db.as_ref())
+    .await?;
+    Ok(collections)
+}
+
+async fn find(db: &DbConnection, name: &str) -> Result<Option<VectorCollection>> {
+    let collection = query_as::<_, VectorCollection>("SELECT * FROM vector_collections WHERE name = $1")
+        .bind(name)
+        .fetch_optional(db.as_ref())
+        .await?;
+    Ok(collection)
+}
+
+async fn set_status(db: &DbConnection, name: &str, status: &str, error: Option<&str>) -> Result<()> {
+    query("UPDATE vector_collections SET status = $2, error = $3, updated_at = NOW() WHERE name = $1")
+        .bind(name)
+        .bind(status)
+        .bind(error)
+        .execute(db.as_ref())
+        .await?;
+    Ok(())
+}
+
+/// Record `name` as the alias target and retire whatever was active before
+async fn mark_active(db: &DbConnection, alias: &str, name: &str) -> Result<()> {
+    let mut tx = db.begin().await?;
+    query("UPDATE vector_collections SET status = 'retired', updated_at = NOW() WHERE alias = $1 AND status = 'active' AND name <> $2")
+        .bind(alias)
+        .bind(name)
+        .execute(&mut *tx)
+        .await?;
+    query(
+        "UPDATE vector_collections SET status = 'active', error = NULL, activated_at = COALESCE(activated_at, NOW()), updated_at = NOW()
+         WHERE name = $1",
+    )
+    .bind(name)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Reconcile the table with Qdrant at startup. Returns a build for the caller to run: one that was
+/// interrupted, or the first collection when nothing has been indexed yet.
+pub async fn bootstrap(
+    db: &DbConnection,
+    qdrant: &QdrantClient,
+    embedder: Option<&dyn EmbeddingProvider>,
+) -> Result<Option<VectorCollection>> {
+    let alias = qdrant.alias();
+    match qdrant.resolve_alias(alias).await? {
+        Some(target) => {
+            // The alias may have moved without this database seeing it (another instance, or by hand)
+            if find(db, &target).await?.is_none() {
+                let dimensions = qdrant.serving_dimensions().unwrap_or_default() as i32;
+                query(
+                    "INSERT INTO vector_collections (name, alias, model, dimensions, status, activated_at)
+                     VALUES ($1, $2, 'unknown', $3, 'active', NOW()) ON CONFLICT (name) DO NOTHING",
+                )
+                .bind(&target)
+                .bind(alias)
+                .bind(dimensions.max(1))
+                .execute(db.as_ref())
+                .await?;
+            }
+            mark_active(db, alias, &target).await?;
+        }
+        None if qdrant.serving_dimensions().is_some() => {
+            tracing::warn!("Qdrant collection {} predates versioned collections; re-embed to move it behind an alias", alias);
+        }
+        None => {}
+    }
+
+    let Some(embedder) = embedder else { return Ok(None) };
+    if qdrant.serving_dimensions().is_some() {
+        if let Some(mismatch) = serving_mismatch(db, qdrant, embedder).await? {
+            tracing::error!("{}; vector search fails until a re-embed completes", mismatch);
+        }
+    }
+
+    match building(db, alias).await? {
+        Some(collection) if collection.model == embedder.name() && collection.dimensions as usize == embedder.dimensions() => {
+            return Ok(Some(collection));
+        }
+        Some(collection) => {
+            // Built for a model that is no longer configured; it can never be finished
+            tracing::warn!("Abandoning re-embed into {}: the embedding model has changed", collection.name);
+            set_status(db, &collection.name, "failed", Some("embedding model changed before the build finished")).await?;
+            qdrant.delete_collection(&collection.name).await?;
+        }
+        None => {}
+    }
+
+    // Nothing indexed yet: build the first collection for the current model
+    if qdrant.serving_dimensions().is_none() {
+        let collection = start_reembed(db, qdrant, embedder).await?;
+        return Ok(Some(collection));
+    }
+    Ok(None)
+}
+
+/// Create the next collection for the current model and record it as building. The caller runs
+/// `reembed` (usually through `spawn_reembed`) to fill it.
+pub async fn start_reembed(
+    db: &DbConnection,
+    qdrant: &QdrantClient,
+    embedder: &dyn EmbeddingProvider,
+) -> Result<VectorCollection, ReembedError> {
+    let alias = qdrant.alias();
+    if let Some(running) = query_as::<_, (String,)>("SELECT name FROM vector_collections WHERE alias = $1 AND status = 'building'")
+        .bind(alias)
+        .fetch_optional(db.as_ref())
+        .await
+        .map_err(anyhow::Error::from)?
+    {
+        return Err(ReembedError::AlreadyRunning(running.0));
+    }
+
+    let prefix = collection_prefix(alias, &embedder.name(), embedder.dimensions());
+    let (previous,): (i64,) = query_as("SELECT COUNT(*) FROM vector_collections WHERE starts_with(name, $1)")
+        .bind(&prefix)
+        .fetch_one(db.as_ref())
+        .await
+        .map_err(anyhow::Error::from)?;
+    let name = collection_name(alias, &embedder.name(), embedder.dimensions(), previous as u32 + 1);
+
+    // The partial unique index settles a race between two requests
+    let collection = query_as::<_, VectorCollection>(
+        "INSERT INTO vector_collections (name, alias, model, dimensions) VALUES ($1, $2, $3, $4) RETURNING *",
+    )
+    .bind(&name)
+    .bind(alias)
+    .bind(embedder.name())
+    .bind(embedder.dimensions() as i32)
+    .fetch_one(db.as_ref())
+    .await
+    .map_err(|e| match e.as_database_error().and_then(|db_err| db_err.code()) {
+        Some(code) if code == "23505" => ReembedError::AlreadyRunning(name.clone()),
+        _ => ReembedError::Other(e.into()),
+    })?;
+
+    if let Err(e) = qdrant.create_collection(&name, embedder.dimensions()).await {
+        set_status(db, &name, "failed", Some(&e.to_string())).await?;
+        return Err(e.into());
+    }
+    Ok(collection)
+}
+
+/// Fill a building collection from Postgres, then point the alias at it and index whatever was
+/// stored after the last batch was read.
+pub async fn reembed(
+    db: &DbConnection,
+    qdrant: &QdrantClient,
+    embedder: &dyn EmbeddingProvider,
+    collection: &VectorCollection,
+) -> Result<()> {
+    if collection.dimensions as usize != embedder.dimensions() {
+        return Err(anyhow!("{} was created for {} dimensions", collection.name, collection.dimensions));
+    }
+
+    let last_id = index_after(db, qdrant, embedder, &collection.name, collection.last_evidence_id).await?;
+
+    set_status(db, &collection.name, "ready", None).await?;
+    qdrant.switch_alias(&collection.name).await?;
+    mark_active(db, &collection.alias, &collection.name).await?;
+
+    // Rows stored from here on are indexed through the alias by `index_evidence`
+    index_after(db, qdrant, embedder, &collection.name, last_id).await?;
+    Ok(())
+}
+
+/// Embed evidence with ids above `last_id` into `collection` in batches, saving progress after
+/// each. Returns the last id indexed.
+async fn index_after(
+    db: &DbConnection,
+    qdrant: &QdrantClient,
+    embedder: &dyn EmbeddingProvider,
+    collection: &str,
+    mut last_id: i32,
+) -> Result<i32> {
+    loop {
+        let sql = format!("SELECT {} FROM evidence WHERE id > $1 ORDER BY id LIMIT $2", EVIDENCE_TEXT_COLUMNS);
+        let batch = query_as::<_, EvidenceText>(&sql)
+            .bind(last_id)
+            .bind(REEMBED_BATCH_SIZE)
+            .fetch_all(db.as_ref())
+            .await?;
+        let Some(last) = batch.last() else { return Ok(last_id) };
+        last_id = last.id;
+
+        index_rows(db, qdrant, embedder, collection, &batch).await?;
+
+        query(
+            "UPDATE vector_collections SET indexed_count = indexed_count + $2, last_evidence_id = $3, updated_at = NOW()
+             WHERE name = $1",
+        )
+        .bind(collection)
+        .bind(batch.len() as i32)
+        .bind(last_id)
+        .execute(db.as_ref())
+        .await?;
+    }
+}
+
+/// Why the serving collection cannot take `embedder`'s vectors: another size, or another (or an
+/// unrecorded) model of the same size, whose vectors would not be comparable. `None` when it can.
+async fn serving_mismatch(
+    db: &DbConnection,
+    qdrant: &QdrantClient,
+    embedder: &dyn EmbeddingProvider,
+) -> Result<Option<String>> {
+    let alias = qdrant.alias();
+    let Some(serving) = qdrant.serving_dimensions() else {
+        return Ok(Some(format!("{} serves no collection", alias)));
+    };
+    if serving != embedder.dimensions() {
+        return Ok(Some(format!(
+            "{} serves {}-dimensional vectors but {} produces {}",
+            alias,
+            serving,
+            embedder.name(),
+            embedder.dimensions()
+        )));
+    }
+    let active = query_as::<_, (String,)>("SELECT model FROM vector_collections WHERE alias = $1 AND status = 'active'")
+        .bind(alias)
+        .fetch_optional(db.as_ref())
+        .await?;
+    match active {
+        Some((model,)) if model == embedder.name() => Ok(None),
+        Some((model,)) => Ok(Some(format!(
+            "{} serves vectors from {} but embeddings now come from {}",
+            alias,
+            model,
+            embedder.name()
+        ))),
+        None => Ok(Some(format!("{} serves vectors from an unrecorded model, not {}", alias, embedder.name()))),
+    }
+}
+
+/// The collection being built for the current alias, if any
+async fn building(db: &DbConnection, alias: &str) -> Result<Option<VectorCollection>> {
+    let collection = query_as::<_, VectorCollection>("SELECT * FROM vector_collections WHERE alias = $1 AND status = 'building'")
+        .bind(alias)
+        .fetch_optional(db.as_ref())
+        .await?;
+    Ok(collection)
+}
+
+/// Embed one newly stored evidence row into the serving collection, and into a collection being
+/// built for this model so the row is there when the alias moves. The serving collection is
+/// skipped when it holds another model's vectors, even of the same size.
+pub async fn index_evidence(
+    db: &DbConnection,
+    qdrant: &QdrantClient,
+    embedder: &dyn EmbeddingProvider,
+    evidence_id: i32,
+) -> Result<usize> {
+    let mut targets = Vec::new();
+    match serving_mismatch(db, qdrant, embedder).await? {
+        None => targets.push(qdrant.alias().to_string()),
+        Some(mismatch) if qdrant.serving_dimensions().is_some() => {
+            tracing::error!("{}; evidence {} is left out of it until a re-embed completes", mismatch, evidence_id);
+        }
+        Some(_) => {}
+    }
+    if let Some(collection) = building(db, qdrant.alias()).await? {
+        if collection.model == embedder.name() && collection.dimensions as usize == embedder.dimensions() {
+            targets.push(collection.name);
+        }
+    }
+    if targets.is_empty() {
+        return Ok(0);
+    }
+
+    let sql = format!("SELECT {} FROM evidence WHERE id = $1", EVIDENCE_TEXT_COLUMNS);
+    let rows = query_as::<_, EvidenceText>(&sql).bind(evidence_id).fetch_all(db.as_ref()).await?;
+    let mut points = 0;
+    for target in &targets {
+        points += index_rows(db, qdrant, embedder, target, &rows).await?;
+    }
+    Ok(points)
+}
+
+/// Remove a deleted evidence row's vectors from the serving collection and any build in progress
+pub async fn remove_evidence(db: &DbConnection, qdrant: &QdrantClient, evidence_id: i32) -> Result<()> {
+    qdrant.delete_evidence(qdrant.alias(), evidence_id).await?;
+    if let Some(collection) = building(db, qdrant.alias()).await? {
+        qdrant.delete_evidence(&collection.name, evidence_id).await?;
+    }
+    Ok(())
+}
+
+/// Run `index_evidence` in the background; a failure only costs the row its vector search hits
+/// until the next re-embed
+pub fn spawn_index_evidence(
+    db: DbConnection,
+    qdrant: QdrantClient,
+    embedder: Arc<dyn EmbeddingProvider>,
+    evidence_id: i32,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        match index_evidence(&db, &qdrant, embedder.as_ref(), evidence_id).await {
+            Ok(points) => tracing::debug!("Indexed evidence {} as {} vectors", evidence_id, points),
+            Err(e) => tracing::warn!("Could not index evidence {}: {}", evidence_id, e),
+        }
+    })
+}
+
+/// Run `reembed` in the background. A failed build is marked `failed` and its collection dropped;
+/// the alias keeps serving the previous one.
+pub fn spawn_reembed(
+    db: DbConnection,
+    qdrant: QdrantClient,
+    embedder: Arc<dyn EmbeddingProvider>,
+    collection: VectorCollection,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        tracing::info!("Re-embedding evidence into {} with {}", collection.name, embedder.name());
+        match reembed(&db, &qdrant, embedder.as_ref(), &collection).await {
+            Ok(()) => tracing::info!("Re-embed into {} finished; {} is live", collection.name, qdrant.alias()),
+            Err(e) => {
+                tracing::error!("Re-embed into {} failed: {}", collection.name, e);
+                if let Err(e) = set_status(&db, &collection.name, "failed", Some(&e.to_string())).await {
+                    tracing::error!("Could not record the failed re-embed: {}", e);
+                }
+                if let Err(e) = qdrant.delete_collection(&collection.name).await {
+                    tracing::warn!("Could not delete collection {}: {}", collection.name, e);
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collection_name() {
+        assert_eq!(
+            collection_name("prosecutor_cases", "openai:nomic-embed-text", 768, 2),
+            "prosecutor_cases__openai_nomic_embed_text__768d_v2"
+        );
+        assert_eq!(collection_name("cases", "local:BAAI/bge-small-en-v1.5", 384, 1), "cases__local_baai_bge_small_en_v1_5__384d_v1");
+    }
+
+    fn texts(evidence: &EvidenceText) -> (Vec<(Option<ChunkAnchor>, String)>, bool) {
+        let (chunks, unsaved) = text_chunks(evidence);
+        (embed_texts(evidence, &chunks), unsaved)
+    }
+
+    #[test]
+    fn test_embed_texts_per_chunk() {
+        let mut evidence = EvidenceText {
+            id: 1,
+            case_id: None,
+            title: "Statement".to_string(),
+            description: Some(" ".to_string()),
+            evidence_type: "document".to_string(),
+            extracted_text: Some("The suspect drove a white van.".to_string()),
+            anchor_points: serde_json::json!([]),
+        };
+        // Cut now and flagged for saving, since nothing was stored at upload
+        let anchor = ChunkAnchor { chunk: 0, page: 1, start: 0, end: 30 };
+        assert_eq!(texts(&evidence), (vec![(Some(anchor), "Statement\n\nThe suspect drove a white van.".to_string())], true));
+
+        // Stored anchors win, and a description gets a point of its own
+        let stored = ChunkAnchor { chunk: 0, page: 1, start: 4, end: 11 };
+        evidence.anchor_points = chunker::anchors_json(&[stored]);
+        evidence.description = Some("Taken at the scene".to_string());
+        assert_eq!(
+            texts(&evidence),
+            (
+                vec![
+                    (None, "Statement\n\nTaken at the scene".to_string()),
+                    (Some(stored), "Statement\n\nsuspect".to_string()),
+                ],
+                false
+            )
+        );
+
+        evidence.extracted_text = None;
+        evidence.description = None;
+        assert_eq!(texts(&evidence), (vec![(None, "Statement".to_string())], false));
+
+        evidence.description = Some("é".repeat(MAX_EMBED_CHARS * 2));
+        assert_eq!(texts(&evidence).0[0].1.chars().count(), MAX_EMBED_CHARS);
+    }
+}
@@ -0,0 +1,395 @@
+// Per-download watermarks for discovery copies
+// The text (who downloaded, when, which item) is burned into the file itself: a banner drawn
+// across the bottom of images, and a header/footer line added to every page of a PDF. The
+// original stored file is never modified.
+
+use chrono::{DateTime, Utc};
+use image::{DynamicImage, ImageFormat, Rgba, RgbaImage};
+use lopdf::{dictionary, Dictionary, Document, Object, Stream};
+use std::io::Cursor;
+
+#[derive(Debug, thiserror::Error)]
+pub enum WatermarkError {
+    #[error("watermarking is not supported for {0}")]
+    Unsupported(String),
+    #[error("could not read the file: {0}")]
+    Malformed(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WatermarkKind {
+    Pdf,
+    Image(ImageFormat),
+}
+
+impl WatermarkKind {
+    pub fn for_content_type(content_type: &mime::Mime) -> Option<Self> {
+        match content_type.essence_str() {
+            "application/pdf" => Some(Self::Pdf),
+            "image/jpeg" => Some(Self::Image(ImageFormat::Jpeg)),
+            "image/png" => Some(Self::Image(ImageFormat::Png)),
+            "image/gif" => Some(Self::Image(ImageFormat::Gif)),
+            "image/bmp" => Some(Self::Image(ImageFormat::Bmp)),
+            "image/tiff" => Some(Self::Image(ImageFormat::Tiff)),
+            _ => None,
+        }
+    }
+}
+
+/// The line stamped onto a discovery copy
+pub fn watermark_text(evidence_id: i32, recipient: &str, at: DateTime<Utc>) -> String {
+    format!(
+        "DISCOVERY COPY - EVIDENCE {} - {} - {}",
+        evidence_id,
+        recipient,
+        at.format("%Y-%m-%d %H:%M:%S UTC")
+    )
+}
+
+pub fn apply(kind: WatermarkKind, bytes: &[u8], text: &str) -> Result<Vec<u8>, WatermarkError> {
+    match kind {
+        WatermarkKind::Pdf => watermark_pdf(bytes, text),
+        WatermarkKind::Image(format) => watermark_image(bytes, format, text),
+    }
+}
+
+fn watermark_image(bytes: &[u8], format: ImageFormat, text: &str) -> Result<Vec<u8>, WatermarkError> {
+    let image = image::load_from_memory_with_format(bytes, format)
+        .map_err(|e| WatermarkError::Malformed(e.to_string()))?;
+    let mut canvas = image.to_rgba8();
+    draw_banner(&mut canvas, text);
+
+    // JPEG has no alpha channel
+    let output = match format {
+        ImageFormat::Jpeg => DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(canvas).to_rgb8()),
+        _ => DynamicImage::ImageRgba8(canvas),
+    };
+    let mut encoded = Cursor::new(Vec::new());
+    output
+        .write_to(&mut encoded, format)
+        .map_err(|e| WatermarkError::Unsupported(format!("{:?} output: {}", format, e)))?;
+    Ok(encoded.into_inner())
+}
+
+/// Darken a strip along the bottom edge and write `text` across it, scaled to the image width.
+fn draw_banner(canvas: &mut RgbaImage, text: &str) {
+    let (width, height) = canvas.dimensions();
+    let glyphs: Vec<[u8; GLYPH_HEIGHT]> = text.chars().map(glyph).collect();
+    let natural_width = (glyphs.len() as u32 * (GLYPH_WIDTH + 1)).max(1);
+    let scale = (width.saturating_sub(8) / natural_width).clamp(1, 6);
+    let padding = 2 * scale;
+    let banner_height = (GLYPH_HEIGHT as u32 * scale + 2 * padding).min(height);
+    let top = height - banner_height;
+
+    for y in top..height {
+        for x in 0..width {
+            let pixel = canvas.get_pixel_mut(x, y);
+            for channel in 0..3 {
+                pixel[channel] /= 4;
+            }
+            pixel[3] = 255;
+        }
+    }
+
+    let white = Rgba([255, 255, 255, 255]);
+    for (index, rows) in glyphs.iter().enumerate() {
+        let origin_x = padding + index as u32 * (GLYPH_WIDTH + 1) * scale;
+        for (row, bits) in rows.iter().enumerate() {
+            for column in 0..GLYPH_WIDTH {
+                if bits & (0b10000 >> column) == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let x = origin_x + column * scale + dx;
+                        let y = top + padding + row as u32 * scale + dy;
+                        if x < width && y < height {
+                            canvas.put_pixel(x, y, white);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: usize = 7;
+
+/// 5x7 bitmap glyphs for the characters watermark text uses; lowercase is drawn as uppercase.
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+    match c.to_ascii_uppercase() {
+        'A' => [0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001],
+        'B' => [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110],
+        'C' => [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110],
+        'D' => [0b11110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b11110],
+        'E' => [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111],
+        'F' => [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000],
+        'G' => [0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111],
+        'H' => [0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001],
+        'I' => [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110],
+        'J' => [0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100],
+        'K' => [0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001],
+        'L' => [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111],
+        'M' => [0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001],
+        'N' => [0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001],
+        'O' => [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110],
+        'P' => [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000],
+        'Q' => [0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101],
+        'R' => [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001],
+        'S' => [0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110],
+        'T' => [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100],
+        'U' => [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110],
+        'V' => [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100],
+        'W' => [0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010],
+        'X' => [0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001],
+        'Y' => [0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100, 0b00100],
+        'Z' => [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111],
+        '0' => [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110],
+        '1' => [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110],
+        '2' => [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111],
+        '3' => [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110],
+        '4' => [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010],
+        '5' => [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110],
+        '6' => [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110],
+        '7' => [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000],
+        '8' => [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110],
+        '9' => [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100],
+        ' ' => [0; GLYPH_HEIGHT],
+        ':' => [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000],
+        '-' => [0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000],
+        '.' => [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100],
+        ',' => [0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b00100, 0b01000],
+        '@' => [0b01110, 0b10001, 0b10111, 0b10101, 0b10111, 0b10000, 0b01110],
+        '/' => [0b00000, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b00000],
+        '_' => [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111],
+        '+' => [0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000],
+        '(' => [0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010],
+        ')' => [0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000],
+        _ => [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100], // '?'
+    }
+}
+
+const PDF_FONT_NAME: &str = "WmHelv";
+
+/// Add the watermark line to the top and bottom of every page. Existing page content is wrapped
+/// in q/Q so whatever graphics state it leaves behind cannot displace the watermark.
+fn watermark_pdf(bytes: &[u8], text: &str) -> Result<Vec<u8>, WatermarkError> {
+    let mut document = Document::load_mem(bytes).map_err(|e| WatermarkError::Malformed(e.to_string()))?;
+    if document.is_encrypted() {
+        return Err(WatermarkError::Unsupported("encrypted PDFs".to_string()));
+    }
+
+    let font_id = document.add_object(dictionary! {
+        "Type" => "Font",
+        "Subtype" => "Type1",
+        "BaseFont" => "Helvetica",
+        "Encoding" => "WinAnsiEncoding",
+    });
+    let literal = pdf_literal(text);
+
+    let pages: Vec<_> = document.get_pages().into_values().collect();
+    if pages.is_empty() {
+        return Err(WatermarkError::Malformed("PDF has no pages".to_string()));
+    }
+
+    for page_id in pages {
+        let page_height = page_height(&document, page_id);
+        add_page_font(&mut document, page_id, font_id).map_err(|e| WatermarkError::Malformed(e.to_string()))?;
+
+        let overlay = format!(
+            "Q\nq\nBT /{font} 8 Tf 0.75 0 0 rg 1 0 0 1 18 {top} Tm {text} Tj ET\n\
+             BT /{font} 8 Tf 0.75 0 0 rg 1 0 0 1 18 12 Tm {text} Tj ET\nQ\n",
+            font = PDF_FONT_NAME,
+            top = (page_height - 18.0).max(12.0),
+            text = literal
+        );
+
+        let existing = match document.get_dictionary(page_id).and_then(|page| page.get(b"Contents")) {
+            Ok(Object::Array(items)) => items.clone(),
+            Ok(Object::Reference(id)) => vec![Object::Reference(*id)],
+            _ => Vec::new(),
+        };
+        let open = document.add_object(Stream::new(Dictionary::new(), b"q\n".to_vec()));
+        let close = document.add_object(Stream::new(Dictionary::new(), overlay.into_bytes()));
+
+        let mut contents = vec![Object::Reference(open)];
+        contents.extend(existing);
+        contents.push(Object::Reference(close));
+        document
+            .get_dictionary_mut(page_id)
+            .map_err(|e| WatermarkError::Malformed(e.to_string()))?
+            .set("Contents", contents);
+    }
+
+    let mut output = Vec::new();
+    document
+        .save_to(&mut output)
+        .map_err(|e| WatermarkError::Malformed(e.to_string()))?;
+    Ok(output)
+}
+
+/// Height of the page MediaBox (inherited from the page tree if needed), defaulting to US Letter
+fn page_height(document: &Document, page_id: lopdf::ObjectId) -> f32 {
+    if let Some(Object::Array(values)) = inherited_attribute(document, page_id, b"MediaBox") {
+        let numbers: Vec<f32> = values.iter().filter_map(|value| value.as_float().ok()).collect();
+        if let [_, y0, _, y1] = numbers[..] {
+            return y1 - y0;
+        }
+    }
+    792.0
+}
+
+/// Make the watermark font available to the page. Pages that inherit their resources get their
+/// own copy so other pages sharing the parent's dictionary are unaffected.
+fn add_page_font(document: &mut Document, page_id: lopdf::ObjectId, font_id: lopdf::ObjectId) -> lopdf::Result<()> {
+    let mut resources = inherited_attribute(document, page_id, b"Resources")
+        .map(|resources| resolve_dictionary(document, resources))
+        .transpose()?
+        .unwrap_or_default();
+
+    let mut fonts = match resources.get(b"Font") {
+        Ok(fonts) => resolve_dictionary(document, fonts)?,
+        Err(_) => Dictionary::new(),
+    };
+    fonts.set(PDF_FONT_NAME, Object::Reference(font_id));
+    resources.set("Font", Object::Dictionary(fonts));
+
+    document.get_dictionary_mut(page_id)?.set("Resources", Object::Dictionary(resources));
+    Ok(())
+}
+
+/// Look up a page attribute, walking up the page tree for inheritable ones
+fn inherited_attribute<'a>(document: &'a Document, page_id: lopdf::ObjectId, key: &[u8]) -> Option<&'a Object> {
+    let mut node = document.get_dictionary(page_id).ok();
+    while let Some(dictionary) = node {
+        if let Ok(value) = dictionary.get(key) {
+            return Some(value);
+        }
+        node = dictionary
+            .get(b"Parent")
+            .and_then(Object::as_reference)
+            .and_then(|parent| document.get_dictionary(parent))
+            .ok();
+    }
+    None
+}
+
+/// Owned copy of a dictionary given inline or by reference
+fn resolve_dictionary(document: &Document, object: &Object) -> lopdf::Result<Dictionary> {
+    match object {
+        Object::Reference(id) => document.get_dictionary(*id).cloned(),
+        object => object.as_dict().cloned(),
+    }
+}
+
+/// PDF literal string with the delimiters escaped; anything outside printable ASCII becomes '?'
+fn pdf_literal(text: &str) -> String {
+    let mut literal = String::with_capacity(text.len() + 2);
+    literal.push('(');
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                literal.push('\\');
+                literal.push(c);
+            }
+            ' '..='~' => literal.push(c),
+            _ => literal.push('?'),
+        }
+    }
+    literal.push(')');
+    literal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lopdf::content::{Content, Operation};
+
+    fn sample_pdf() -> Vec<u8> {
+        let mut document = Document::with_version("1.5");
+        let pages_id = document.new_object_id();
+        let font_id = document.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Courier",
+        });
+        let content = Content {
+            operations: vec![
+                Operation::new("BT", vec![]),
+                Operation::new("Tf", vec!["F1".into(), 12.into()]),
+                Operation::new("Td", vec![100.into(), 600.into()]),
+                Operation::new("Tj", vec![Object::string_literal("Witness statement")]),
+                Operation::new("ET", vec![]),
+            ],
+        };
+        let content_id = document.add_object(Stream::new(Dictionary::new(), content.encode().unwrap()));
+        let page_id = document.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "Contents" => content_id,
+        });
+        document.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![page_id.into()],
+                "Count" => 1,
+                "Resources" => dictionary! { "Font" => dictionary! { "F1" => font_id } },
+                "MediaBox" => vec![0.into(), 0.into(), 595.into(), 842.into()],
+            }),
+        );
+        let catalog_id = document.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        document.trailer.set("Root", catalog_id);
+
+        let mut bytes = Vec::new();
+        document.save_to(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn test_pdf_watermark_keeps_content_and_adds_text() {
+        let text = watermark_text(7, "ada@da.example", "2024-05-01T10:00:00Z".parse().unwrap());
+        let marked = apply(WatermarkKind::Pdf, &sample_pdf(), &text).unwrap();
+
+        let document = Document::load_mem(&marked).unwrap();
+        let page_id = *document.get_pages().values().next().unwrap();
+        let content = String::from_utf8_lossy(&document.get_page_content(page_id).unwrap()).to_string();
+        assert!(content.contains("Witness statement"));
+        assert!(content.contains("(DISCOVERY COPY - EVIDENCE 7 - ada@da.example - 2024-05-01 10:00:00 UTC) Tj"));
+        assert!(content.starts_with("q\n"));
+
+        // The inherited font is still reachable alongside the watermark font
+        let fonts = document.get_page_fonts(page_id).unwrap();
+        assert!(fonts.contains_key(b"F1".as_slice()));
+        assert!(fonts.contains_key(PDF_FONT_NAME.as_bytes()));
+    }
+
+    #[test]
+    fn test_image_watermark_draws_banner() {
+        let original = RgbaImage::from_pixel(300, 120, Rgba([200, 200, 200, 255]));
+        let mut png = Cursor::new(Vec::new());
+        DynamicImage::ImageRgba8(original).write_to(&mut png, ImageFormat::Png).unwrap();
+
+        let marked = apply(WatermarkKind::Image(ImageFormat::Png), png.get_ref(), "DISCOVERY COPY").unwrap();
+        let marked = image::load_from_memory(&marked).unwrap().to_rgba8();
+        assert_eq!(marked.dimensions(), (300, 120));
+
+        // Untouched above the banner, darkened inside it, with white text pixels drawn
+        assert_eq!(marked.get_pixel(150, 10), &Rgba([200, 200, 200, 255]));
+        assert_eq!(marked.get_pixel(299, 119), &Rgba([50, 50, 50, 255]));
+        let bottom_rows = (100..120).flat_map(|y| (0..300).map(move |x| (x, y)));
+        assert!(bottom_rows.into_iter().any(|(x, y)| marked.get_pixel(x, y) == &Rgba([255, 255, 255, 255])));
+    }
+
+    #[test]
+    fn test_unsupported_inputs() {
+        assert_eq!(WatermarkKind::for_content_type(&"video/mp4".parse().unwrap()), None);
+        assert_eq!(
+            WatermarkKind::for_content_type(&"image/jpeg".parse().unwrap()),
+            Some(WatermarkKind::Image(ImageFormat::Jpeg))
+        );
+        assert!(matches!(apply(WatermarkKind::Pdf, b"not a pdf", "x"), Err(WatermarkError::Malformed(_))));
+        assert_eq!(pdf_literal("a(b)\\c\u{e9}"), "(a\\(b\\)\\\\c?)");
+    }
+}
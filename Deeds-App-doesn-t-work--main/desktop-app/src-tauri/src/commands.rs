@@ -5,6 +5,11 @@
 // summarize_case loads user history and generates summary using local LLM
 
 use super::AppState;
+use prosecutor_core::{
+    llm_backend::{GenerationRequest, LlmBackend},
+    permissions::{can_access_case, CurrentUser},
+    rag::{self, AskRequest},
+};
 use tauri::State;
 use serde_json::Value;
 use serde::{Deserialize, Serialize};
@@ -19,10 +24,18 @@ pub struct LLMRequest {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LLMResponse {
     pub response: String,
-    pub confidence: f32,
+    pub model: String,
+    pub tokens_used: u32,
     pub processing_time: u64,
 }
 
+fn llm_backend(state: &AppState) -> Result<&dyn LlmBackend, String> {
+    state
+        .llm
+        .as_deref()
+        .ok_or_else(|| "No LLM backend is configured. Set LLM_BACKEND and LLM_URL.".to_string())
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct EvidenceFile {
     pub filename: String,
@@ -41,10 +54,31 @@ pub async fn save_case(case_id: String, user_id: String, evidence: Value, state:
 
 #[tauri::command]
 pub async fn summarize_case(case_id: String, user_id: String, evidence: Value, state: State<'_, AppState>) -> Result<String, String> {
-    // In a real app, you'd use the LLM client in AppState
-    println!("Summarizing case {} for user {}: {:?}", case_id, user_id, evidence);
-    // Example: state.llm_client.summarize(...).await;
-    Ok("This is a summary of the case.".to_string())
+    let case_id: i32 = case_id.parse().map_err(|_| format!("Invalid case id: {}", case_id))?;
+    let user_id = user_id.parse().map_err(|_| format!("Invalid user id: {}", user_id))?;
+    let user = state
+        .users
+        .get(user_id)
+        .await
+        .map_err(|e| e.to_string())?
+        .ok_or("Unknown user")?;
+    let user = CurrentUser { user_id: user.id, role: user.role.parse().ok() };
+    if !can_access_case(&state.db, &user, case_id).await.map_err(|e| e.to_string())? {
+        return Err("Case not found".to_string());
+    }
+    tracing::debug!("Summarizing case {} ({} evidence items from the client)", case_id, evidence.as_array().map_or(0, Vec::len));
+
+    // Same retrieval and citation rules as /api/cases/:id/ask
+    let request = AskRequest {
+        question: "Summarize the key facts of this case and what the evidence shows.".to_string(),
+        limit: Some(10),
+        min_score: None,
+        max_tokens: None,
+    };
+    let answer = rag::ask(&state.db, &state.qdrant, state.embedder.as_deref(), state.llm.as_deref(), case_id, request)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(answer.answer)
 }
 
 #[tauri::command]
@@ -71,46 +105,38 @@ pub async fn process_evidence(case_id: String, evidence: Value, state: State<'_,
 // Enhanced AI/LLM Commands
 #[tauri::command]
 pub async fn llm_inference(request: LLMRequest, state: State<'_, AppState>) -> Result<LLMResponse, String> {
-    println!("LLM inference request: {:?}", request);
-    
-    // Simulate LLM processing
+    let backend = llm_backend(&state)?;
     let start_time = std::time::Instant::now();
-    
-    // TODO: Implement actual LLM inference
-    // For now, return a mock response
-    let response = if request.prompt.to_lowercase().contains("legal") {
-        "Based on the legal context provided, this appears to be a matter requiring careful consideration of applicable statutes and precedent cases. I recommend consulting with legal experts and reviewing relevant case law."
-    } else if request.prompt.to_lowercase().contains("evidence") {
-        "The evidence analysis suggests several key points for investigation. Consider chain of custody, forensic analysis, and witness testimony correlation."
-    } else {
-        "I understand your question. Based on the available information, here is my analysis..."
-    };
-    
-    let processing_time = start_time.elapsed().as_millis() as u64;
-    
+
+    let generation = backend
+        .generate(&GenerationRequest {
+            system: request.context,
+            prompt: request.prompt,
+            max_tokens: request.max_tokens,
+            temperature: None,
+        })
+        .await
+        .map_err(|e| format!("LLM inference failed: {}", e))?;
+
     Ok(LLMResponse {
-        response: response.to_string(),
-        confidence: 0.85,
-        processing_time,
+        response: generation.text,
+        model: generation.model,
+        tokens_used: generation.usage.prompt_tokens + generation.usage.completion_tokens,
+        processing_time: start_time.elapsed().as_millis() as u64,
     })
 }
 
 #[tauri::command]
 pub async fn ai_search_query(query: String, context: Option<String>, state: State<'_, AppState>) -> Result<LLMResponse, String> {
-    println!("AI search query: {}", query);
-    
-    let enhanced_prompt = format!(
-        "Legal search query: {}\nContext: {}\nProvide a comprehensive legal analysis and relevant information.",
-        query,
-        context.unwrap_or_default()
-    );
-    
     let request = LLMRequest {
-        prompt: enhanced_prompt,
-        context,
+        prompt: format!("Legal search query: {}\nProvide a comprehensive legal analysis and relevant information.", query),
+        context: Some(format!(
+            "You are a legal research assistant for prosecutors.{}",
+            context.map(|context| format!(" Context: {}", context)).unwrap_or_default()
+        )),
         max_tokens: Some(500),
     };
-    
+
     llm_inference(request, state).await
 }
 
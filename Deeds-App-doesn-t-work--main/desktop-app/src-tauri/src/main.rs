@@ -103,7 +103,7 @@ async fn main() {
         .expect("Failed to initialize core backend");
 
     let tauri_state = TauriAppState {
-        core: Arc::new(Mutex::new(core_state)),
+        core: Arc::new(Mutex::new(core_state.clone())),
     };
 
     let pool = sqlx::SqlitePool::connect("sqlite::memory:")
@@ -112,6 +112,8 @@ async fn main() {
 
     tauri::Builder::default()
         .manage(tauri_state)
+        // The commands module reads the core state (LLM backend, repositories) directly
+        .manage(core_state)
         .manage(DatabaseState { pool: Arc::new(pool) })
         .invoke_handler(tauri::generate_handler![
             list_llm_models,
@@ -7,7 +7,15 @@ use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::Mutex;
 use tokio::process::Command as TokioCommand;
+use prosecutor_core::{
+    evidence_store::{content_key, EvidenceStore, LocalEvidenceStore},
+    utils::sha256_file,
+};
+
+// Serialises read-modify-write of the per-case manifests
+static MANIFEST_LOCK: Mutex<()> = Mutex::new(());
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct EvidenceFile {
@@ -48,7 +56,8 @@ pub struct EvidenceMetadata {
     pub bitrate: Option<i32>,
 }
 
-// Get evidence storage directory
+// Get evidence storage directory. Files are kept in the same content-addressed layout the
+// backend uses; `cases/<case_id>.json` records which of them belong to each case.
 fn get_evidence_dir(app: &AppHandle) -> Result<PathBuf, String> {
     let app_data_dir = app.path_resolver()
         .app_data_dir()
@@ -64,12 +73,101 @@ fn get_evidence_dir(app: &AppHandle) -> Result<PathBuf, String> {
     Ok(evidence_dir)
 }
 
+// Case IDs name manifest files, so they must not be able to walk out of the evidence directory
+fn validate_case_id(case_id: &str) -> Result<(), String> {
+    if case_id.is_empty() || !case_id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(format!("Invalid case ID: {}", case_id));
+    }
+    Ok(())
+}
+
+fn manifest_path(evidence_dir: &Path, case_id: &str) -> PathBuf {
+    evidence_dir.join("cases").join(format!("{}.json", case_id))
+}
+
+fn read_manifest(path: &Path) -> Result<Vec<EvidenceFile>, String> {
+    match fs::read_to_string(path) {
+        Ok(json) => serde_json::from_str(&json)
+            .map_err(|e| format!("Failed to parse evidence manifest: {}", e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(format!("Failed to read evidence manifest: {}", e)),
+    }
+}
+
+// Scratch directory for ffmpeg output; whatever is kept is moved into the store afterwards
+fn create_work_dir(evidence_dir: &Path) -> Result<PathBuf, String> {
+    let work_dir = evidence_dir.join(".staging").join(uuid::Uuid::new_v4().to_string());
+    fs::create_dir_all(&work_dir)
+        .map_err(|e| format!("Failed to create working directory: {}", e))?;
+    Ok(work_dir)
+}
+
+// Move a generated file into the evidence store and record it against the case.
+// Returns the path of the stored copy.
+async fn store_output(
+    evidence_dir: &Path,
+    case_id: &str,
+    output: &Path,
+) -> Result<String, String> {
+    let store = LocalEvidenceStore::new(evidence_dir);
+    let file_size = fs::metadata(output)
+        .map_err(|e| format!("Failed to read output metadata: {}", e))?
+        .len();
+    let sha256 = sha256_file(&output.to_string_lossy())
+        .map_err(|e| format!("Failed to hash output: {}", e))?;
+    let key = content_key(&sha256);
+
+    store.put_file(&key, output)
+        .await
+        .map_err(|e| format!("Failed to store evidence: {}", e))?;
+    let stored_path = store.local_path(&key)
+        .ok_or("Evidence store returned no path")?
+        .to_string_lossy()
+        .to_string();
+
+    let _guard = MANIFEST_LOCK.lock().map_err(|_| "Evidence manifest lock poisoned")?;
+    let manifest = manifest_path(evidence_dir, case_id);
+    let mut files = read_manifest(&manifest)?;
+    if !files.iter().any(|file| file.file_path == stored_path) {
+        files.push(EvidenceFile {
+            file_path: stored_path.clone(),
+            case_id: case_id.to_string(),
+            evidence_type: evidence_type_for(output).to_string(),
+            file_size,
+            upload_date: format!("{:?}", std::time::SystemTime::now()),
+            enhanced_versions: Vec::new(), // TODO: Detect enhanced versions
+        });
+        if let Some(parent) = manifest.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create manifest directory: {}", e))?;
+        }
+        let json = serde_json::to_string_pretty(&files)
+            .map_err(|e| format!("Failed to serialise evidence manifest: {}", e))?;
+        fs::write(&manifest, json)
+            .map_err(|e| format!("Failed to write evidence manifest: {}", e))?;
+    }
+
+    Ok(stored_path)
+}
+
+// Determine evidence type from extension
+fn evidence_type_for(path: &Path) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("mp4") | Some("avi") | Some("mov") | Some("mkv") | Some("webm") => "video",
+        Some("jpg") | Some("jpeg") | Some("png") | Some("bmp") | Some("tiff") => "image",
+        Some("mp3") | Some("wav") | Some("m4a") | Some("flac") => "audio",
+        Some("pdf") | Some("txt") | Some("doc") | Some("docx") => "document",
+        _ => "unknown"
+    }
+}
+
 #[command]
 pub async fn process_evidence_file(
     app_handle: AppHandle,
     request: ProcessingRequest,
 ) -> Result<ProcessingResult, String> {
     let evidence_dir = get_evidence_dir(&app_handle)?;
+    validate_case_id(&request.case_id)?;
     let source_path = Path::new(&request.file_path);
     
     if !source_path.exists() {
@@ -179,10 +277,17 @@ async fn process_video_file(
     request: &ProcessingRequest,
     evidence_dir: &Path,
 ) -> Result<ProcessingResult, String> {
-    let case_dir = evidence_dir.join(&request.case_id);
-    fs::create_dir_all(&case_dir)
-        .map_err(|e| format!("Failed to create case directory: {}", e))?;
-    
+    let work_dir = create_work_dir(evidence_dir)?;
+    let result = transcode_video(request, evidence_dir, &work_dir).await;
+    let _ = fs::remove_dir_all(&work_dir);
+    result
+}
+
+async fn transcode_video(
+    request: &ProcessingRequest,
+    evidence_dir: &Path,
+    work_dir: &Path,
+) -> Result<ProcessingResult, String> {
     let mut result = ProcessingResult {
         success: false,
         processed_files: Vec::new(),
@@ -193,7 +298,7 @@ async fn process_video_file(
     
     // Extract frames if requested
     if request.extract_frames {
-        let frames_dir = case_dir.join("frames");
+        let frames_dir = work_dir.join("frames");
         fs::create_dir_all(&frames_dir)
             .map_err(|e| format!("Failed to create frames directory: {}", e))?;
         
@@ -218,7 +323,8 @@ async fn process_video_file(
                 if let Ok(entry) = entry {
                     let path = entry.path();
                     if path.extension().and_then(|ext| ext.to_str()) == Some("png") {
-                        result.frame_extracts.push(path.to_string_lossy().to_string());
+                        let stored = store_output(evidence_dir, &request.case_id, &path).await?;
+                        result.frame_extracts.push(stored);
                     }
                 }
             }
@@ -229,7 +335,7 @@ async fn process_video_file(
     }
     
     // Create optimized copy for processing
-    let processed_file = case_dir.join(format!("processed_{}", 
+    let processed_file = work_dir.join(format!("processed_{}", 
         Path::new(&request.file_path).file_name().unwrap().to_string_lossy()));
     
     // Optimize video for AI processing (lower resolution, consistent format)
@@ -247,7 +353,8 @@ async fn process_video_file(
         .map_err(|e| format!("Failed to optimize video: {}", e))?;
     
     if output.status.success() {
-        result.processed_files.push(processed_file.to_string_lossy().to_string());
+        let stored = store_output(evidence_dir, &request.case_id, &processed_file).await?;
+        result.processed_files.push(stored);
     }
     
     Ok(result)
@@ -255,12 +362,8 @@ async fn process_video_file(
 
 async fn process_image_file(
     request: &ProcessingRequest,
-    evidence_dir: &Path,
+    _evidence_dir: &Path,
 ) -> Result<ProcessingResult, String> {
-    let case_dir = evidence_dir.join(&request.case_id);
-    fs::create_dir_all(&case_dir)
-        .map_err(|e| format!("Failed to create case directory: {}", e))?;
-    
     let result = ProcessingResult {
         success: false,
         processed_files: vec![request.file_path.clone()], // For images, we can process original
@@ -279,10 +382,17 @@ async fn process_audio_file(
     request: &ProcessingRequest,
     evidence_dir: &Path,
 ) -> Result<ProcessingResult, String> {
-    let case_dir = evidence_dir.join(&request.case_id);
-    fs::create_dir_all(&case_dir)
-        .map_err(|e| format!("Failed to create case directory: {}", e))?;
-    
+    let work_dir = create_work_dir(evidence_dir)?;
+    let result = transcode_audio(request, evidence_dir, &work_dir).await;
+    let _ = fs::remove_dir_all(&work_dir);
+    result
+}
+
+async fn transcode_audio(
+    request: &ProcessingRequest,
+    evidence_dir: &Path,
+    work_dir: &Path,
+) -> Result<ProcessingResult, String> {
     let mut result = ProcessingResult {
         success: false,
         processed_files: Vec::new(),
@@ -292,7 +402,7 @@ async fn process_audio_file(
     };
     
     // Convert to WAV for better processing
-    let processed_file = case_dir.join(format!("processed_{}.wav", 
+    let processed_file = work_dir.join(format!("processed_{}.wav", 
         Path::new(&request.file_path).file_stem().unwrap().to_string_lossy()));
     
     let output = TokioCommand::new("ffmpeg")
@@ -309,7 +419,8 @@ async fn process_audio_file(
         .map_err(|e| format!("Failed to process audio: {}", e))?;
     
     if output.status.success() {
-        result.processed_files.push(processed_file.to_string_lossy().to_string());
+        let stored = store_output(evidence_dir, &request.case_id, &processed_file).await?;
+        result.processed_files.push(stored);
     }
     
     Ok(result)
@@ -337,71 +448,28 @@ pub async fn list_evidence_files(
     case_id: Option<String>,
 ) -> Result<Vec<EvidenceFile>, String> {
     let evidence_dir = get_evidence_dir(&app_handle)?;
-    let mut evidence_files = Vec::new();
-    
-    let search_dir = if let Some(case_id) = case_id {
-        evidence_dir.join(case_id)
-    } else {
-        evidence_dir
-    };
+    let _guard = MANIFEST_LOCK.lock().map_err(|_| "Evidence manifest lock poisoned")?;
     
-    if search_dir.exists() {
-        walk_evidence_directory(&search_dir, &mut evidence_files)?;
+    if let Some(case_id) = case_id {
+        validate_case_id(&case_id)?;
+        return read_manifest(&manifest_path(&evidence_dir, &case_id));
     }
     
-    Ok(evidence_files)
-}
-
-fn walk_evidence_directory(dir: &Path, files: &mut Vec<EvidenceFile>) -> Result<(), String> {
-    let entries = fs::read_dir(dir)
-        .map_err(|e| format!("Failed to read directory: {}", e))?;
-    
-    for entry in entries {
-        let entry = entry.map_err(|e| format!("Failed to read entry: {}", e))?;
-        let path = entry.path();
-        
-        if path.is_dir() {
-            // Recursively search subdirectories
-            walk_evidence_directory(&path, files)?;
-        } else if path.is_file() {
-            let metadata = fs::metadata(&path)
-                .map_err(|e| format!("Failed to get file metadata: {}", e))?;
-            
-            let file_name = path.file_name()
-                .and_then(|n| n.to_str())
-                .unwrap_or("unknown")
-                .to_string();
-            
-            // Extract case ID from path structure
-            let case_id = path.parent()
-                .and_then(|p| p.file_name())
-                .and_then(|n| n.to_str())
-                .unwrap_or("unknown")
-                .to_string();
-            
-            // Determine evidence type from extension
-            let evidence_type = match path.extension().and_then(|ext| ext.to_str()) {
-                Some("mp4") | Some("avi") | Some("mov") | Some("mkv") | Some("webm") => "video",
-                Some("jpg") | Some("jpeg") | Some("png") | Some("bmp") | Some("tiff") => "image",
-                Some("mp3") | Some("wav") | Some("m4a") | Some("flac") => "audio",
-                Some("pdf") | Some("txt") | Some("doc") | Some("docx") => "document",
-                _ => "unknown"
-            };
-            
-            files.push(EvidenceFile {
-                file_path: path.to_string_lossy().to_string(),
-                case_id,
-                evidence_type: evidence_type.to_string(),
-                file_size: metadata.len(),
-                upload_date: metadata.created()
-                    .map(|t| format!("{:?}", t))
-                    .unwrap_or_else(|_| "Unknown".to_string()),
-                enhanced_versions: Vec::new(), // TODO: Detect enhanced versions
-            });
+    let mut evidence_files = Vec::new();
+    let cases_dir = evidence_dir.join("cases");
+    if cases_dir.exists() {
+        let entries = fs::read_dir(&cases_dir)
+            .map_err(|e| format!("Failed to read directory: {}", e))?;
+        for entry in entries {
+            let entry = entry.map_err(|e| format!("Failed to read entry: {}", e))?;
+            let path = entry.path();
+            if path.extension().and_then(|ext| ext.to_str()) == Some("json") {
+                evidence_files.extend(read_manifest(&path)?);
+            }
         }
     }
     
-    Ok(())
+    Ok(evidence_files)
 }
 
 #[command]
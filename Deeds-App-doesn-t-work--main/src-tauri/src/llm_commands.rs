@@ -11,7 +11,18 @@ use aes_gcm::{Aes256Gcm, Key, Nonce};
 use aes_gcm::aead::{Aead, NewAead, generic_array::GenericArray};
 use sha2::{Sha256, Digest};
 use rand::{RngCore, rngs::OsRng};
+use std::collections::HashMap;
 use std::env;
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::sync::oneshot;
+use prosecutor_core::{
+    config::Config,
+    llm_backend::{self, GenerationRequest, LlmBackend},
+    llm_stream::{self, StreamEvent},
+};
+
+// Port start_llama_server uses unless told otherwise
+const DEFAULT_LLAMA_PORT: u16 = 8080;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LLMConfig {
@@ -63,6 +74,23 @@ pub struct InferenceResponse {
     pub model_used: String,
 }
 
+// Event every streamed inference reports on; payloads carry their stream_id
+const LLM_STREAM_EVENT: &str = "llm-stream";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LlmStreamPayload {
+    pub stream_id: String,
+    // `type` is "token", "done" (with the InferenceResponse fields) or "error"
+    #[serde(flatten)]
+    pub event: StreamEvent,
+}
+
+// Stop signals for streams still running, by stream_id
+fn active_streams() -> &'static Mutex<HashMap<String, oneshot::Sender<()>>> {
+    static STREAMS: OnceLock<Mutex<HashMap<String, oneshot::Sender<()>>>> = OnceLock::new();
+    STREAMS.get_or_init(|| Mutex::new(HashMap::new()))
+}
+
 // Get the models directory path
 fn get_models_dir(app: &AppHandle) -> Result<PathBuf, String> {
     let app_data_dir = app.path_resolver()
@@ -136,6 +164,20 @@ fn get_or_create_model_key() -> Result<[u8; 32], String> {
     }
 }
 
+// Inference goes through prosecutor-core's LLM backend. LLM_BACKEND and LLM_URL choose the server;
+// when unset it is the llama.cpp server that start_llama_server launches.
+fn inference_backend(model_name: &str) -> Result<Arc<dyn LlmBackend>, String> {
+    let mut config = Config::from_env().map_err(|e| format!("Failed to read configuration: {}", e))?;
+    if config.llm_backend == "none" {
+        config.llm_backend = "llamacpp".to_string();
+        config.llm_url = Some(format!("http://127.0.0.1:{}/v1", DEFAULT_LLAMA_PORT));
+    }
+    config.llm_model = model_name.to_string();
+    llm_backend::from_config(&config)
+        .map_err(|e| format!("Failed to set up LLM backend: {}", e))?
+        .ok_or_else(|| "No LLM backend is configured".to_string())
+}
+
 // Get the active model name from config
 fn get_active_model_name(app_handle: &AppHandle) -> Result<String, String> {
     let config = get_llm_config(app_handle.clone()).await?;
@@ -273,52 +315,22 @@ pub async fn generate_with_local_llm(
     
     let start_time = std::time::Instant::now();
     
-    // For this example, we'll call the Python NLP service
-    // In a full implementation, you might integrate llama.cpp directly via Rust bindings
-    let client = reqwest::Client::new();
-    let python_service_url = "http://localhost:8001";
-    
-    // First, tell the Python service to load this model
-    let load_response = client
-        .post(&format!("{}/models/load", python_service_url))
-        .json(&serde_json::json!({
-            "model_path": model_path.to_string_lossy()
-        }))
-        .send()
+    let backend = inference_backend(&model_to_use)?;
+    let generation = backend
+        .generate(&GenerationRequest {
+            system: request.context,
+            prompt: request.prompt,
+            max_tokens: Some(request.max_tokens.unwrap_or(512)),
+            temperature: Some(request.temperature.unwrap_or(0.7)),
+        })
         .await
-        .map_err(|e| format!("Failed to communicate with NLP service: {}", e))?;
-    
-    if !load_response.status().is_success() {
-        return Err("Failed to load model in NLP service".to_string());
-    }
-    
-    // Now generate with the loaded model
-    let generate_response = client
-        .post(&format!("{}/generate", python_service_url))
-        .json(&serde_json::json!({
-            "prompt": request.prompt,
-            "max_tokens": request.max_tokens.unwrap_or(512),
-            "temperature": request.temperature.unwrap_or(0.7),
-            "context": request.context
-        }))
-        .send()
-        .await
-        .map_err(|e| format!("Failed to generate text: {}", e))?;
-    
-    if !generate_response.status().is_success() {
-        return Err("Text generation failed".to_string());
-    }
-    
-    let response_data: serde_json::Value = generate_response
-        .json()
-        .await
-        .map_err(|e| format!("Failed to parse response: {}", e))?;
+        .map_err(|e| format!("Text generation failed: {}", e))?;
     
     let processing_time = start_time.elapsed().as_millis() as u64;
     
     Ok(LLMResponse {
-        response: response_data["response"].as_str().unwrap_or("").to_string(),
-        tokens_used: response_data["tokens_used"].as_u64().unwrap_or(0) as u32,
+        response: generation.text,
+        tokens_used: generation.usage.prompt_tokens + generation.usage.completion_tokens,
         processing_time_ms: processing_time,
     })
 }
@@ -444,7 +456,7 @@ pub async fn encrypt_model_file(
     Ok(format!("Model encrypted successfully: {}", encrypted_filename))
 }
 
-// Decrypt and load model for inference
+// Run inference against the served model
 #[command]
 pub async fn run_llama_inference(
     app_handle: AppHandle,
@@ -454,101 +466,101 @@ pub async fn run_llama_inference(
     let models_dir = get_models_dir(&app_handle)?;
     let start_time = std::time::Instant::now();
     
-    // Check if model is encrypted
+    // The inference server loads the file itself; only make sure it is one of ours
     let encrypted_path = models_dir.join(&format!("{}.encrypted", model_name));
-    let metadata_path = models_dir.join(&format!("{}.encrypted.meta", model_name));
-    
-    let model_data = if encrypted_path.exists() && metadata_path.exists() {
-        // Decrypt model
-        let key = get_or_create_model_key()?;
-        let cipher = Aes256Gcm::new(Key::from_slice(&key));
-        
-        // Read metadata
-        let metadata_content = fs::read_to_string(&metadata_path)
-            .map_err(|e| format!("Failed to read metadata: {}", e))?;
-        let metadata: ModelEncryptionInfo = serde_json::from_str(&metadata_content)
-            .map_err(|e| format!("Failed to parse metadata: {}", e))?;
-        
-        if !metadata.is_encrypted {
-            return Err("Model metadata indicates unencrypted file".to_string());
-        }
-        
-        let nonce_bytes = metadata.nonce.ok_or("Missing nonce in metadata")?;
-        let nonce = Nonce::from_slice(&nonce_bytes);
-        
-        // Read and decrypt
-        let ciphertext = fs::read(&encrypted_path)
-            .map_err(|e| format!("Failed to read encrypted model: {}", e))?;
-        
-        cipher.decrypt(nonce, ciphertext.as_ref())
-            .map_err(|e| format!("Decryption failed: {}", e))?
-    } else {
-        // Try unencrypted model
-        let model_path = models_dir.join(&model_name);
-        if !model_path.exists() {
-            return Err(format!("Model file not found: {}", model_name));
-        }
-        
-        fs::read(&model_path)
-            .map_err(|e| format!("Failed to read model file: {}", e))?
-    };
+    let model_path = models_dir.join(&model_name);
+    if !encrypted_path.exists() && !model_path.exists() {
+        return Err(format!("Model file not found: {}", model_name));
+    }
     
-    // For now, we'll call an external llama.cpp server
-    // In a full implementation, you could integrate llama.cpp directly via Rust bindings
-    let inference_result = run_external_llama_inference(&request, &model_data).await?;
+    let backend = inference_backend(&model_name)?;
+    let generation = backend
+        .generate(&GenerationRequest {
+            system: request.system_prompt,
+            prompt: request.prompt,
+            max_tokens: Some(request.max_tokens.unwrap_or(512)),
+            temperature: Some(request.temperature.unwrap_or(0.7)),
+        })
+        .await
+        .map_err(|e| format!("Failed to call inference server: {}", e))?;
     
     let processing_time = start_time.elapsed().as_millis() as u64;
+    let tokens_generated = generation.usage.completion_tokens;
     
     Ok(InferenceResponse {
-        text: inference_result.text,
-        tokens_generated: inference_result.tokens_generated,
+        text: generation.text,
+        tokens_generated,
         tokens_per_second: if processing_time > 0 {
-            inference_result.tokens_generated as f32 / (processing_time as f32 / 1000.0)
+            tokens_generated as f32 / (processing_time as f32 / 1000.0)
         } else {
             0.0
         },
         processing_time_ms: processing_time,
-        model_used: model_name,
+        model_used: generation.model,
     })
 }
 
-// External inference helper (replace with direct llama.cpp integration if needed)
-async fn run_external_llama_inference(
-    request: &InferenceRequest,
-    _model_data: &[u8], // For direct integration, this would be used to load the model
-) -> Result<InferenceResponse, String> {
-    let client = reqwest::Client::new();
-    
-    // Call local llama.cpp server
-    let response = client
-        .post("http://localhost:8080/completion")
-        .json(&serde_json::json!({
-            "prompt": request.prompt,
-            "n_predict": request.max_tokens.unwrap_or(512),
-            "temperature": request.temperature.unwrap_or(0.7),
-            "top_p": request.top_p.unwrap_or(0.95),
-            "n_ctx": request.context_window.unwrap_or(2048),
-            "system_prompt": request.system_prompt,
-            "stream": false
-        }))
-        .send()
-        .await
-        .map_err(|e| format!("Failed to call inference server: {}", e))?;
+// Stream inference as `llm-stream` events tagged with the caller's stream_id, chosen by the caller so
+// it can listen before the first event; the last event is "done" with the same figures
+// run_llama_inference returns, or "error"
+#[command]
+pub async fn stream_llama_inference(
+    app_handle: AppHandle,
+    request: InferenceRequest,
+    model_name: String,
+    stream_id: String,
+) -> Result<(), String> {
+    if stream_id.trim().is_empty() {
+        return Err("stream_id is required".to_string());
+    }
+
+    let models_dir = get_models_dir(&app_handle)?;
+    let encrypted_path = models_dir.join(&format!("{}.encrypted", model_name));
+    let model_path = models_dir.join(&model_name);
+    if !encrypted_path.exists() && !model_path.exists() {
+        return Err(format!("Model file not found: {}", model_name));
+    }
     
-    if !response.status().is_success() {
-        return Err(format!("Inference server error: {}", response.status()));
+    let backend = inference_backend(&model_name)?;
+    let (stop, stopped) = oneshot::channel();
+    {
+        let mut streams = active_streams().lock().unwrap();
+        if streams.contains_key(&stream_id) {
+            return Err(format!("Stream {} is already running", stream_id));
+        }
+        streams.insert(stream_id.clone(), stop);
     }
     
-    let result: serde_json::Value = response.json().await
-        .map_err(|e| format!("Failed to parse inference response: {}", e))?;
+    let generation_request = GenerationRequest {
+        system: request.system_prompt,
+        prompt: request.prompt,
+        max_tokens: Some(request.max_tokens.unwrap_or(512)),
+        temperature: Some(request.temperature.unwrap_or(0.7)),
+    };
+    let mut events = llm_stream::stream_generation_until(backend, generation_request, async move {
+        let _ = stopped.await;
+    });
+    
+    tauri::async_runtime::spawn(async move {
+        while let Some(event) = events.recv().await {
+            let payload = LlmStreamPayload { stream_id: stream_id.clone(), event };
+            if let Err(e) = app_handle.emit_all(LLM_STREAM_EVENT, &payload) {
+                eprintln!("Failed to emit {} event: {}", LLM_STREAM_EVENT, e);
+            }
+        }
+        active_streams().lock().unwrap().remove(&stream_id);
+    });
     
-    Ok(InferenceResponse {
-        text: result["content"].as_str().unwrap_or("").to_string(),
-        tokens_generated: result["tokens_evaluated"].as_u64().unwrap_or(0) as u32,
-        tokens_per_second: 0.0, // Will be calculated by caller
-        processing_time_ms: 0, // Will be calculated by caller
-        model_used: "loaded".to_string(),
-    })
+    Ok(())
+}
+
+// Stop a streamed inference; it still finishes with a "done" event for the text so far
+#[command]
+pub async fn stop_llama_inference(stream_id: String) -> Result<bool, String> {
+    match active_streams().lock().unwrap().remove(&stream_id) {
+        Some(stop) => Ok(stop.send(()).is_ok()),
+        None => Ok(false),
+    }
 }
 
 // Start local llama.cpp server with model
@@ -559,7 +571,7 @@ pub async fn start_llama_server(
     port: Option<u16>,
 ) -> Result<String, String> {
     let models_dir = get_models_dir(&app_handle)?;
-    let port = port.unwrap_or(8080);
+    let port = port.unwrap_or(DEFAULT_LLAMA_PORT);
     
     // Check if model exists (encrypted or unencrypted)
     let encrypted_path = models_dir.join(&format!("{}.encrypted", model_name));
@@ -642,6 +654,8 @@ pub fn register_llm_commands<R: tauri::Runtime>() -> tauri::plugin::TauriPlugin<
             check_llm_service_status,
             encrypt_model_file,
             run_llama_inference,
+            stream_llama_inference,
+            stop_llama_inference,
             start_llama_server,
             check_inference_health
         ])